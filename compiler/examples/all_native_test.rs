@@ -1,5 +1,5 @@
 use inkwell::context::Context;
-use pole_compiler::{parse_ir, CodeGen, CompilerArenas};
+use pole_compiler::{parse_ir, CodeGen, CompilerArenas, OptLevel};
 use std::fs;
 use std::process::Command;
 
@@ -82,6 +82,7 @@ fn run_test(test: &TestCase) -> Result<i64, String> {
     let arenas = CompilerArenas::new_default();
     let context = Context::create();
     let mut codegen = CodeGen::new(&context, test.name, &arenas.codegen_arena);
+    codegen.set_opt_level(OptLevel::O2);
 
     codegen
         .compile_program(&program)
@@ -36,7 +36,8 @@ fn main() {
     let llvm_ir2 = codegen2.print_to_string();
     
     assert!(llvm_ir2.contains("extractvalue { i32, i64 } %opt, 0"), "Extract tag");
-    assert!(llvm_ir2.contains("icmp eq i32 %tag, 1"), "Check if Some");
+    assert!(llvm_ir2.contains("switch i32 %tag"), "Branch on tag");
+    assert!(llvm_ir2.contains("i32 1, label %match_Some"), "Check if Some");
     assert!(llvm_ir2.contains("extractvalue { i32, i64 } %opt, 1"), "Extract value");
     assert!(llvm_ir2.contains("phi i64"), "Merge results with phi");
     println!("✓ Extract tag from Option struct");
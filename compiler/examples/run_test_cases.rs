@@ -0,0 +1,49 @@
+use pole_compiler::{parse_ir, run_test_cases};
+use std::fs;
+
+// Run the @test_case annotations of the given .pole-ir files natively, e.g.
+// cargo run --example run_test_cases -- ../examples/81-test-cases.pole-ir
+fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: run_test_cases <file.pole-ir>...");
+        std::process::exit(2);
+    }
+
+    let mut failed = 0;
+    for path in &paths {
+        let source = fs::read_to_string(path).expect("Failed to read file");
+        let program = match parse_ir(&source) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(2);
+            }
+        };
+
+        println!("=== {} ===", path);
+        match run_test_cases(&program) {
+            Ok(report) => {
+                for result in &report.results {
+                    println!("  {}", result);
+                }
+                println!(
+                    "  {} passed, {} failed (compiled in {:.1}ms)",
+                    report.passed(),
+                    report.failed(),
+                    report.compile_time.as_secs_f64() * 1000.0,
+                );
+                failed += report.failed();
+            }
+            Err(e) => {
+                eprintln!("{}: compilation failed: {}", path, e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("\n{} test case(s) failed", failed);
+        std::process::exit(1);
+    }
+}
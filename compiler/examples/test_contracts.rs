@@ -0,0 +1,118 @@
+use std::process::Command;
+
+use bumpalo::Bump;
+use inkwell::context::Context;
+use inkwell::execution_engine::JitFunction;
+use inkwell::OptimizationLevel;
+use pole_compiler::driver::{self, DriverOptions};
+use pole_compiler::{parse_ir, CodeGen, CompilerArenas};
+
+type MainFunc = unsafe extern "C" fn() -> i64;
+
+fn main() {
+    let ir_code = std::fs::read_to_string("../examples/80-contracts.pole-ir")
+        .expect("Failed to read IR file");
+
+    println!("=== Runtime Contracts Test ===\n");
+
+    let program = parse_ir(&ir_code).expect("Failed to parse IR");
+    println!("✓ Parsed: {} functions", program.func_defs.len());
+
+    let arenas = CompilerArenas::new_default();
+    let context = Context::create();
+    let mut codegen = CodeGen::new(&context, "contracts", &arenas.codegen_arena);
+    codegen.set_contract_checks(true);
+
+    codegen.compile_program(&program).expect("Compilation failed");
+    println!("✓ LLVM IR generated");
+
+    // Every clause gets its own failure message
+    let ir = codegen.print_to_string();
+    for clause in [
+        "Contract violation in 'safe_div': requires b != 0",
+        "Contract violation in 'safe_div': ensures result * b <= a",
+        "Contract violation in 'factorial': ensures n == 0 => result == 1",
+        "Contract violation in 'ratio_or_zero': requires b != 0 => a / b >= 0",
+    ] {
+        if !ir.contains(clause) {
+            println!("\n✗ No check for \"{}\"", clause);
+            std::process::exit(1);
+        }
+    }
+    println!("✓ Contract checks emitted");
+
+    let execution_engine = codegen.get_module()
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .expect("Failed to create execution engine");
+
+    unsafe {
+        let main: JitFunction<MainFunc> = execution_engine
+            .get_function("main")
+            .expect("Failed to get main function");
+
+        let result = main.call();
+
+        // safe_div(20, 3) = 6, factorial(5) = 120, ratio_or_zero(7, 0) = 0
+        // without dividing by zero in its guarded `requires`, no contract
+        // violated
+        if result == 126 {
+            println!("✓ main() = {}", result);
+        } else {
+            println!("\n✗ main() = {}, expected 126", result);
+            std::process::exit(1);
+        }
+    }
+
+    check_tailrec_ensures();
+    println!("\n✓✓✓ All contract checks passed ✓✓✓");
+}
+
+// countdown(5, 0) returns 0, violating `result >= n`; only its innermost
+// call, countdown(0, 0), satisfies the clause, so checking just that one
+// with the loop's final arguments would let the program exit normally
+const TAILREC_ENSURES: &str = "\
+@tailrec
+func countdown (n: Int, acc: Int) -> Int
+  ensures result >= n
+:
+  if n == 0 then acc else countdown(n - 1, acc)
+
+func main () -> Int :
+  countdown(5, 0)
+";
+
+fn check_tailrec_ensures() {
+    let dir = std::env::temp_dir().join(format!("pole_contracts_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Failed to create temp dir");
+    let source = dir.join("countdown.pole-ir");
+    std::fs::write(&source, TAILREC_ENSURES).expect("Failed to write source");
+
+    let checked = driver::load_and_check(&source).unwrap_or_else(|e| fail(&e.to_string()));
+    let options = DriverOptions { contract_checks: true, ..DriverOptions::default() };
+    let context = Context::create();
+    let arena = Bump::new();
+    let codegen = driver::compile(&checked, &options, &context, &arena).unwrap_or_else(|e| fail(&e.to_string()));
+
+    // The recursive call stays a call, so each one checks its own `ensures`
+    let ir = codegen.print_to_string();
+    if ir.contains("tailrec_loop") {
+        fail("countdown's tail call was turned into a loop despite its checked ensures clause");
+    }
+    println!("✓ @tailrec function with ensures keeps its calls");
+
+    let executable = dir.join("countdown");
+    driver::build_executable(&checked, &codegen, &executable, &options).unwrap_or_else(|e| fail(&e.to_string()));
+    let output = Command::new(&executable).output().expect("Failed to run the program");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() || !stderr.contains("Contract violation in 'countdown': ensures result >= n") {
+        fail(&format!("countdown(5, 0) exited with {:?}, printing:\n{}", output.status.code(), stderr));
+    }
+    println!("✓ countdown(5, 0) violates its ensures clause");
+}
+
+fn fail(message: &str) -> ! {
+    println!("\n✗ {}", message);
+    std::process::exit(1);
+}
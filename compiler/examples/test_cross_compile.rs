@@ -0,0 +1,58 @@
+use inkwell::context::Context;
+use pole_compiler::{parse_ir, CodeGen, CompilerArenas, TargetSpec};
+
+// Triple, and the machine field of the object file it should produce:
+// e_machine for ELF, the COFF header's Machine for Windows
+const TARGETS: &[(&str, u16)] = &[
+    ("aarch64-linux-gnu", 0xB7),
+    ("riscv64-linux-gnu", 0xF3),
+    ("i686-linux-gnu", 0x03),
+    ("x86_64-windows-gnu", 0x8664),
+];
+
+fn main() {
+    let ir_code = std::fs::read_to_string("../examples/42-list-push-test.pole-ir")
+        .expect("Failed to read IR file");
+
+    println!("=== Cross-Compilation Test ===\n");
+
+    let program = parse_ir(&ir_code).expect("Failed to parse IR");
+    let arenas = CompilerArenas::new_default();
+    let mut failed = false;
+
+    for &(triple, machine) in TARGETS {
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context, "cross", &arenas.codegen_arena);
+        let target = TargetSpec { triple: Some(triple.to_string()), ..TargetSpec::default() };
+        codegen.set_target(target).expect("Failed to set target");
+        codegen.compile_program(&program).expect("Compilation failed");
+
+        // Sizes passed to malloc follow the target's pointer width
+        let ir = codegen.print_to_string();
+        let size_t = if triple.starts_with("i686") { "i32" } else { "i64" };
+        let malloc_ok = !ir.contains("@malloc(") || ir.contains(&format!("@malloc({}", size_t));
+
+        let object = std::env::temp_dir().join(format!("pole_cross_{}.o", triple));
+        codegen.write_object_file(&object).expect("Failed to write object file");
+        let bytes = std::fs::read(&object).expect("Failed to read object file");
+        let _ = std::fs::remove_file(&object);
+
+        let found = if bytes.starts_with(b"\x7fELF") {
+            u16::from_le_bytes([bytes[18], bytes[19]])
+        } else {
+            u16::from_le_bytes([bytes[0], bytes[1]])
+        };
+        if found == machine && malloc_ok {
+            println!("✓ {}: {} bytes, machine {:#x}", triple, bytes.len(), found);
+        } else {
+            println!("✗ {}: machine {:#x}, expected {:#x}; size_t {} {}", triple, found, machine, size_t,
+                if malloc_ok { "ok" } else { "wrong" });
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+    println!("\n✓✓✓ Objects written for {} targets ✓✓✓", TARGETS.len());
+}
@@ -0,0 +1,66 @@
+use std::path::Path;
+
+use inkwell::context::Context;
+use inkwell::execution_engine::JitFunction;
+use inkwell::OptimizationLevel;
+use pole_compiler::{parse_ir, CodeGen, CompilerArenas};
+
+type MainFunc = unsafe extern "C" fn() -> i64;
+
+fn main() {
+    let path = Path::new("../examples/83-tailrec.pole-ir");
+    let ir_code = std::fs::read_to_string(path).expect("Failed to read IR file");
+
+    println!("=== DWARF Debug Info Test ===\n");
+
+    let program = parse_ir(&ir_code).expect("Failed to parse IR");
+    println!("✓ Parsed: {} functions", program.func_defs.len());
+
+    let arenas = CompilerArenas::new_default();
+    let context = Context::create();
+    let mut codegen = CodeGen::new(&context, "tailrec", &arenas.codegen_arena);
+    codegen.enable_debug_info(path, &ir_code).expect("Failed to enable debug info");
+
+    codegen.compile_program(&program).expect("Compilation failed");
+    println!("✓ LLVM IR generated");
+
+    // Subprograms, parameters, `let` bindings and line locations
+    let ir = codegen.print_to_string();
+    for expected in [
+        "!DICompileUnit(",
+        "!DIFile(filename: \"83-tailrec.pole-ir\"",
+        "!DISubprogram(name: \"sum_to\"",
+        "!DILocalVariable(name: \"n\", arg: 1",
+        "!DILocalVariable(name: \"next\"",
+        "!DILocation(line: 8,",
+    ] {
+        if !ir.contains(expected) {
+            println!("\n✗ No {} in the IR", expected);
+            std::process::exit(1);
+        }
+    }
+    println!("✓ Debug info emitted");
+
+    let object = std::env::temp_dir().join("pole_debug_info_test.o");
+    codegen.write_object_file(&object).expect("Failed to write object file");
+    let _ = std::fs::remove_file(&object);
+    println!("✓ Object file written");
+
+    let execution_engine = codegen.get_module()
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .expect("Failed to create execution engine");
+
+    unsafe {
+        let main: JitFunction<MainFunc> = execution_engine
+            .get_function("main")
+            .expect("Failed to get main function");
+
+        let result = main.call();
+        if result == 0 {
+            println!("\n✓✓✓ main() = {} ✓✓✓", result);
+        } else {
+            println!("\n✗ main() = {}, expected 0", result);
+            std::process::exit(1);
+        }
+    }
+}
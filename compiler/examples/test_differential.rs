@@ -0,0 +1,71 @@
+use pole_compiler::{parse_ir, run_differential};
+use std::fs;
+
+// Run every example's @test_case inputs and main through the reference
+// interpreter and the JIT, and fail if any return different values, e.g.
+// cargo run --example test_differential
+// cargo run --example test_differential -- ../examples/44-hashmap-test.pole-ir
+fn main() {
+    // The interpreter recurses on the Rust stack
+    let worker = std::thread::Builder::new()
+        .stack_size(256 << 20)
+        .spawn(run)
+        .expect("Failed to spawn the test thread");
+    std::process::exit(worker.join().unwrap_or(2));
+}
+
+fn run() -> i32 {
+    let mut paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        paths = fs::read_dir("../examples")
+            .expect("Failed to read ../examples")
+            .map(|entry| entry.expect("Failed to read ../examples").path().display().to_string())
+            .filter(|path| path.ends_with(".pole-ir"))
+            .collect();
+        paths.sort();
+    }
+    println!("=== Differential Test: interpreter vs JIT ===\n");
+
+    let (mut agreed, mut mismatched, mut skipped) = (0, 0, 0);
+    for path in &paths {
+        let source = fs::read_to_string(path).expect("Failed to read file");
+        // Some examples are written in syntax the parser does not take yet
+        let program = match parse_ir(&source) {
+            Ok(program) => program,
+            Err(e) => {
+                println!("- {}: {}", path, e);
+                continue;
+            }
+        };
+        let report = match run_differential(&program) {
+            Ok(report) => report,
+            Err(e) => {
+                println!("- {}: {}", path, e);
+                continue;
+            }
+        };
+        if report.cases.is_empty() {
+            continue;
+        }
+
+        println!("=== {} ===", path);
+        for case in &report.cases {
+            println!("  {}", case);
+        }
+        agreed += report.agreed();
+        mismatched += report.mismatched();
+        skipped += report.skipped();
+    }
+
+    println!("\n{} agreed, {} mismatched, {} skipped", agreed, mismatched, skipped);
+    if mismatched > 0 {
+        fail(&format!("{} case(s) give different results in the interpreter and the JIT", mismatched));
+    }
+    println!("✓ The interpreter and the JIT agree on every case both can run");
+    0
+}
+
+fn fail(message: &str) -> ! {
+    println!("\n✗ {}", message);
+    std::process::exit(1);
+}
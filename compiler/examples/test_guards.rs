@@ -0,0 +1,43 @@
+use inkwell::context::Context;
+use inkwell::execution_engine::JitFunction;
+use inkwell::OptimizationLevel;
+use pole_compiler::{parse_ir, CodeGen, CompilerArenas};
+
+type MainFunc = unsafe extern "C" fn() -> i64;
+
+fn main() {
+    let ir_code = std::fs::read_to_string("../examples/77-guards.pole-ir")
+        .expect("Failed to read IR file");
+
+    println!("=== Match Guards Test ===\n");
+
+    let program = parse_ir(&ir_code).expect("Failed to parse IR");
+    println!("✓ Parsed: {} functions", program.func_defs.len());
+
+    let arenas = CompilerArenas::new_default();
+    let context = Context::create();
+    let mut codegen = CodeGen::new(&context, "guards", &arenas.codegen_arena);
+
+    codegen.compile_program(&program).expect("Compilation failed");
+    println!("✓ LLVM IR generated");
+
+    let execution_engine = codegen.get_module()
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .expect("Failed to create execution engine");
+
+    unsafe {
+        let main: JitFunction<MainFunc> = execution_engine
+            .get_function("main")
+            .expect("Failed to get main function");
+
+        let result = main.call();
+
+        // Classes 0 + 1 + 2 + 3, picks 20 + 6 + 0, codes 100 + 200 + 300 + 400
+        if result == 1032 {
+            println!("\n✓✓✓ main() = {} ✓✓✓", result);
+        } else {
+            println!("\n✗ main() = {}, expected 1032", result);
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use inkwell::context::Context;
-use pole_compiler::{parse_ir, CodeGen, CompilerArenas};
+use pole_compiler::{parse_ir, CodeGen, CompilerArenas, OptLevel};
 use std::fs;
 use std::path::Path;
 use std::process::Command;
@@ -24,6 +24,7 @@ fn main() {
     let arenas = CompilerArenas::new_default();
     let context = Context::create();
     let mut codegen = CodeGen::new(&context, "inventory", &arenas.codegen_arena);
+    codegen.set_opt_level(OptLevel::O2);
 
     if let Err(e) = codegen.compile_program(&program) {
         eprintln!("✗ Compilation failed: {}", e);
@@ -58,7 +58,7 @@ fn main() {
         .arg(object_path)
         .arg("-o")
         .arg(executable_path)
-        .arg("-lSDL2")
+        .args(codegen.link_manifest().linker_args())
         .arg("-lm")
         .output()
         .expect("Failed to execute linker");
@@ -1,34 +1,31 @@
 use inkwell::context::Context;
-use pole_compiler::{parse_ir, CodeGen, CompilerArenas};
+use pole_compiler::{link_program, parse_ir, CodeGen, CompilerArenas, FileSource, OptLevel};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn main() {
-    // Concatenate core + inventory + test
-    let core = fs::read_to_string("../games/zomboid/lib/core.pole-ir")
-        .expect("Failed to read core.pole-ir");
-    let inventory = fs::read_to_string("../games/zomboid/lib/inventory.pole-ir")
-        .expect("Failed to read inventory.pole-ir");
-    
+    // Inventory (and the Core module it imports) is loaded from the game library
     let test_code = r#"
+import Inventory
+
 @extern("puts")
 func c_puts(s: String) -> Int
 
 func test_inventory_lib(dummy: Unit) -> Int:
 let _ = c_puts("=== Testing Inventory Library ===") in
-let inv = inventory_create(10) in
+let inv = Inventory.inventory_create(10) in
 let _ = c_puts("Created 10-slot inventory") in
-let _ = inventory_add_item(inv, 10, 1, 5) in
+let _ = Inventory.inventory_add_item(inv, 10, 1, 5) in
 let _ = c_puts("Added item 1 (qty 5)") in
-let _ = inventory_add_item(inv, 10, 1, 3) in
+let _ = Inventory.inventory_add_item(inv, 10, 1, 3) in
 let _ = c_puts("Added item 1 (qty 3) - should stack") in
-let item_id = inventory_get_item_id(inv, 0) in
-let qty = inventory_get_quantity(inv, 0) in
+let item_id = Inventory.inventory_get_item_id(inv, 0) in
+let qty = Inventory.inventory_get_quantity(inv, 0) in
 let _ = c_puts("Item in slot 0 found") in
-let _ = inventory_consume(inv, 0, 2) in
+let _ = Inventory.inventory_consume(inv, 0, 2) in
 let _ = c_puts("Consumed 2 units") in
-let _ = inventory_free(inv) in
+let _ = Inventory.inventory_free(inv) in
 let _ = c_puts("Test complete!") in
 0
 
@@ -36,10 +33,8 @@ func main() -> Int:
 test_inventory_lib(())
 "#;
 
-    let ir_source = format!("{}\n{}\n{}", core, inventory, test_code);
-
     println!("=== Parsing Pole IR ===");
-    let program = match parse_ir(&ir_source) {
+    let program = match parse_ir(test_code) {
         Ok(p) => {
             println!("✓ Parsed successfully");
             p
@@ -50,10 +45,26 @@ test_inventory_lib(())
         }
     };
 
+    println!("\n=== Linking imports ===");
+    let libs = FileSource::new(vec![PathBuf::from("../games/zomboid/lib")]);
+    let program = match link_program(program, &libs) {
+        Ok(p) => {
+            println!("✓ Loaded {} module(s)", p.modules.len());
+            p
+        }
+        Err(errors) => {
+            for e in errors {
+                eprintln!("✗ {}", e);
+            }
+            return;
+        }
+    };
+
     println!("\n=== Compiling to LLVM IR ===");
     let arenas = CompilerArenas::new_default();
     let context = Context::create();
     let mut codegen = CodeGen::new(&context, "test_lib_inventory", &arenas.codegen_arena);
+    codegen.set_opt_level(OptLevel::O2);
 
     if let Err(e) = codegen.compile_program(&program) {
         eprintln!("✗ Compilation failed: {}", e);
@@ -4,7 +4,13 @@ use std::fs;
 
 fn main() {
     let ir = fs::read_to_string("../games/zomboid/main_partial2.pole-ir").unwrap();
-    let program = parse_ir(&ir).unwrap();
+    let program = match parse_ir(&ir) {
+        Ok(program) => program,
+        Err(e) => {
+            println!("✗ Failed: {}", e);
+            return;
+        }
+    };
     let arenas = CompilerArenas::new_default();
     let context = Context::create();
     let mut codegen = CodeGen::new(&context, "test", &arenas.codegen_arena);
@@ -34,6 +34,7 @@ fn main() {
             type_defs: program.type_defs.clone(),
             func_defs: vec![func.clone()],
             extern_funcs: program.extern_funcs.clone(),
+            ..program.clone()
         };
         
         let arenas_test = CompilerArenas::new_default();
@@ -5,7 +5,7 @@ fn main() {
 type Point = { x: Int, y: Int }
 
 func make_point(x: Int, y: Int) -> Point :
-  { x = x, y = y }
+  { x: x, y: y }
 "#;
 
     match parse_ir(ir) {
@@ -0,0 +1,93 @@
+use std::io::Cursor;
+
+use bumpalo::Bump;
+use inkwell::context::Context;
+use pole_compiler::repl::{run_session, Evaluation, Repl};
+
+// Each submission builds on the ones before it
+const SESSION: &str = "\
+type Person = { name: String, age: Int }
+
+func birthday(p: Person) -> Person:
+  { name: p.name, age: p.age + 1 }
+
+func fact(n: Int) -> Int:
+  if n <= 1 then 1 else n * fact(n - 1)
+
+fact(10)
+birthday({ name: \"Ada\", age: 36 })
+List_push(List_push([], fact(3)), fact(4))
+Some(fact(5))
+:type birthday
+birthday
+fact(true)
+func fact(n: Int) -> Int:
+  n
+
+:frobnicate
+fact(3) + 1
+:quit
+fact(1)
+";
+
+const EXPECTED: &[&str] = &[
+    "type Person",
+    "birthday : Person -> Person",
+    "fact : Int -> Int",
+    "3628800 : Int",
+    "{ name: \"Ada\", age: 37 } : Person",
+    "[6, 24] : List<Int>",
+    "Some(120) : Option<Int>",
+    "Person -> Person",
+    "<function> : Person -> Person",
+];
+
+fn main() {
+    println!("=== REPL Test ===\n");
+
+    let context = Context::create();
+    let arena = Bump::new();
+    let mut repl = Repl::new(&context, &arena);
+    let mut output = Vec::new();
+    run_session(&mut repl, &mut Cursor::new(SESSION), &mut output, false).expect("I/O on buffers cannot fail");
+    let output = String::from_utf8(output).expect("The session wrote invalid UTF-8");
+    let lines: Vec<&str> = output.lines().collect();
+
+    for (index, expected) in EXPECTED.iter().enumerate() {
+        if lines.get(index) != Some(expected) {
+            fail(&format!("Line {}: expected '{}', got {:?}\n{}", index + 1, expected, lines.get(index), output));
+        }
+        println!("✓ {}", expected);
+    }
+
+    // Errors leave the session usable, and nothing after :quit runs
+    let rest = &lines[EXPECTED.len()..];
+    let checks = [
+        ("type error", rest.first().is_some_and(|line| line.contains("Function argument type mismatch"))),
+        ("redefinition", rest.get(1) == Some(&"'fact' is already defined")),
+        ("unknown command", rest.get(2).is_some_and(|line| line.starts_with("unknown command ':frobnicate'"))),
+        ("after errors", rest.get(3) == Some(&"7 : Int")),
+        ("quit", rest.len() == 4),
+    ];
+    for (name, passed) in checks {
+        if !passed {
+            fail(&format!("{} check failed:\n{}", name, output));
+        }
+        println!("✓ {}", name);
+    }
+
+    // Values are also available to callers of the library
+    match repl.submit("fact(4) == 24") {
+        Ok(Evaluation::Value(value, ty)) if value.to_string() == "true" && ty.to_string() == "Bool" => {
+            println!("✓ Repl::submit returns the value and its type");
+        }
+        other => fail(&format!("Unexpected result of fact(4) == 24: {:?}", other)),
+    }
+
+    println!("\n✓ All REPL checks passed");
+}
+
+fn fail(message: &str) -> ! {
+    println!("✗ {}", message);
+    std::process::exit(1);
+}
@@ -8,9 +8,11 @@ use std::process::Command;
 fn main() {
     let ir_code = r#"
 @extern("SDL_Init")
+@link("SDL2")
 func SDL_Init(flags: Int) -> Int
 
 @extern("SDL_Quit")
+@link("SDL2")
 func SDL_Quit(dummy: Unit) -> Unit
 
 @extern("puts")
@@ -78,9 +80,9 @@ func main() -> Int :
         .args(&[
             object_path.to_str().unwrap(),
             &format!("-L{}", sdl2_libdir),
-            "-lSDL2",
             "-o", exe_path,
         ])
+        .args(codegen.link_manifest().linker_args())
         .status()
         .expect("Failed to link");
 
@@ -60,9 +60,9 @@ fn main() {
         .args(&[
             object_path.to_str().unwrap(),
             &format!("-L{}", sdl2_libdir),
-            "-lSDL2",
             "-o", exe_path,
         ])
+        .args(codegen.link_manifest().linker_args())
         .status()
         .expect("Failed to link");
 
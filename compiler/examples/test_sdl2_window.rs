@@ -1,5 +1,5 @@
 use inkwell::context::Context;
-use pole_compiler::{parse_ir, CodeGen, CompilerArenas};
+use pole_compiler::{parse_ir, CodeGen, CompilerArenas, OptLevel};
 use std::fs;
 use std::path::Path;
 use std::process::Command;
@@ -28,6 +28,7 @@ fn main() {
     let arenas = CompilerArenas::new_default();
     let context = Context::create();
     let mut codegen = CodeGen::new(&context, "sdl2_window", &arenas.codegen_arena);
+    codegen.set_opt_level(OptLevel::O2);
     
     match codegen.compile_program(&program) {
         Ok(_) => {
@@ -44,7 +45,7 @@ fn main() {
                         .arg(obj_path)
                         .arg("-o")
                         .arg("/tmp/sdl2_window")
-                        .arg("-lSDL2")
+                        .args(codegen.link_manifest().linker_args())
                         .output();
                     
                     match output {
@@ -0,0 +1,25 @@
+use pole_compiler::{parse_ir, run_test_cases};
+
+// @tailrec loops of millions of iterations, one matching on a variant with
+// a payload, must run natively without growing the stack
+fn main() {
+    println!("=== @tailrec Loop Test ===\n");
+
+    let source = std::fs::read_to_string("../examples/83-tailrec.pole-ir").expect("Failed to read IR file");
+    let program = parse_ir(&source).unwrap_or_else(|e| fail(&format!("Parse failed: {}", e)));
+
+    // A case that overflows the stack aborts the process before reporting
+    let report = run_test_cases(&program).unwrap_or_else(|e| fail(&format!("Compilation failed: {}", e)));
+    for result in &report.results {
+        println!("  {}", result);
+    }
+    if !report.all_passed() {
+        fail(&format!("{} of {} test cases failed", report.failed(), report.results.len()));
+    }
+    println!("\n✓ {} @tailrec test cases passed", report.passed());
+}
+
+fn fail(message: &str) -> ! {
+    println!("\n✗ {}", message);
+    std::process::exit(1);
+}
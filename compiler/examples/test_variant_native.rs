@@ -1,5 +1,5 @@
 use inkwell::context::Context;
-use pole_compiler::{parse_ir, codegen::CodeGen, CompilerArenas};
+use pole_compiler::{parse_ir, codegen::CodeGen, CompilerArenas, OptLevel};
 use std::fs;
 use std::path::Path;
 
@@ -12,6 +12,7 @@ fn main() {
     let arenas = CompilerArenas::new_default();
     let context = Context::create();
     let mut codegen = CodeGen::new(&context, "variant_tags", &arenas.codegen_arena);
+    codegen.set_opt_level(OptLevel::O2);
     
     codegen.compile_program(&program).expect("Compilation failed");
     
@@ -0,0 +1,93 @@
+use std::path::Path;
+use std::process::Command;
+
+use bumpalo::Bump;
+use inkwell::context::Context;
+use pole_compiler::driver::{self, DriverOptions};
+
+const EXPECTED_OUTPUT: &str = "\
+Hello from WASI
+print and println write to stdout
+written and read back through WASI
+lists and maps copied byte for byte
+";
+
+fn main() {
+    println!("=== WASI Test ===\n");
+
+    let checked = driver::load_and_check(Path::new("../examples/84-wasi.pole-ir"))
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    let options = DriverOptions { target: Some("wasm32-wasi".to_string()), ..DriverOptions::default() };
+    let context = Context::create();
+    let arena = Bump::new();
+    let codegen = driver::compile(&checked, &options, &context, &arena).unwrap_or_else(|e| fail(&e.to_string()));
+    println!("✓ Compiled for wasm32-wasi");
+
+    // The C functions are defined over WASI imports, and `_start` exits with main's result
+    let ir = codegen.print_to_string();
+    for expected in [
+        "\"wasm-import-module\"=\"wasi_snapshot_preview1\"",
+        "\"wasm-import-name\"=\"fd_write\"",
+        "\"wasm-import-name\"=\"path_open\"",
+        "\"wasm-import-name\"=\"proc_exit\"",
+        "define void @_start()",
+    ] {
+        if !ir.contains(expected) {
+            fail(&format!("No {} in the IR", expected));
+        }
+    }
+    for function in ["write", "malloc", "memcpy", "memset", "fopen", "fputs", "fgets", "fclose", "puts"] {
+        let symbol = format!(" @{}(", function);
+        if !ir.lines().any(|line| line.starts_with("define") && line.contains(&symbol)) {
+            fail(&format!("'{}' is not defined in the module", function));
+        }
+    }
+    // Without bulk memory these intrinsics become calls to memcpy and memset,
+    // so the runtime's own definitions must not use them
+    for intrinsic in ["@llvm.memcpy", "@llvm.memset"] {
+        if ir.contains(intrinsic) {
+            fail(&format!("{} would call back into the runtime", intrinsic));
+        }
+    }
+    println!("✓ Runtime defined over WASI imports");
+
+    let dir = std::env::temp_dir().join(format!("pole_wasi_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Failed to create temp dir");
+    let object = dir.join("wasi.o");
+    driver::emit_object(&codegen, &object).unwrap_or_else(|e| fail(&e.to_string()));
+    let bytes = std::fs::read(&object).expect("Failed to read object file");
+    if !bytes.starts_with(b"\0asm") {
+        fail("The object file is not a wasm module");
+    }
+    println!("✓ Object file written: {} bytes", bytes.len());
+
+    // Linking and running need wasm-ld and a WASI runtime
+    let available = |tool: &str| Command::new(tool).arg("--version").output().is_ok_and(|out| out.status.success());
+    if !available("wasm-ld") || !available("wasmtime") {
+        println!("\n- wasm-ld or wasmtime not found, not running the module");
+        let _ = std::fs::remove_dir_all(&dir);
+        return;
+    }
+    let module = dir.join("wasi.wasm");
+    driver::build_executable(&checked, &codegen, &module, &options).unwrap_or_else(|e| fail(&e.to_string()));
+    let output = Command::new("wasmtime")
+        .arg("--dir=.")
+        .arg(&module)
+        .current_dir(&dir)
+        .output()
+        .expect("Failed to run wasmtime");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if output.status.code() == Some(0) && stdout == EXPECTED_OUTPUT {
+        println!("\n✓✓✓ Module ran under wasmtime ✓✓✓");
+    } else {
+        fail(&format!("wasmtime exited with {:?}, printing:\n{}{}", output.status.code(), stdout,
+            String::from_utf8_lossy(&output.stderr)));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    println!("\n✗ {}", message);
+    std::process::exit(1);
+}
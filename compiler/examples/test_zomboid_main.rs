@@ -1,5 +1,5 @@
 use inkwell::context::Context;
-use pole_compiler::{parse_ir, CodeGen, CompilerArenas};
+use pole_compiler::{parse_ir, CodeGen, CompilerArenas, OptLevel};
 use std::fs;
 
 fn main() {
@@ -27,6 +27,7 @@ fn main() {
     let arenas = CompilerArenas::new_default();
     let context = Context::create();
     let mut codegen = CodeGen::new(&context, "zomboid_main", &arenas.codegen_arena);
+    codegen.set_opt_level(OptLevel::O2);
     
     match codegen.compile_program(&program) {
         Ok(_) => {
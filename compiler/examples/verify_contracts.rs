@@ -0,0 +1,41 @@
+use pole_compiler::{parse_ir, verify_contracts, Verdict};
+use std::fs;
+
+// Statically verify the contracts of the given .pole-ir files
+fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: verify_contracts <file.pole-ir>...");
+        std::process::exit(2);
+    }
+
+    let mut refuted = 0;
+    for path in &paths {
+        let source = fs::read_to_string(path).expect("Failed to read file");
+        let program = match parse_ir(&source) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(2);
+            }
+        };
+
+        println!("=== {} ===", path);
+        for clause in verify_contracts(&program).clauses {
+            let mark = match clause.verdict {
+                Verdict::Proven => "✓",
+                Verdict::Refuted(_) => "✗",
+                Verdict::Unknown(_) => "?",
+            };
+            if matches!(clause.verdict, Verdict::Refuted(_)) {
+                refuted += 1;
+            }
+            println!("  {} {}", mark, clause);
+        }
+    }
+
+    if refuted > 0 {
+        eprintln!("\n{} clause(s) refuted", refuted);
+        std::process::exit(1);
+    }
+}
@@ -2,6 +2,49 @@
 // Mirrors src/pole/runtime/ir_ast.py
 
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// ============================================================================
+// Source Locations
+// ============================================================================
+
+/// Byte range `start..end` into the source text a node was parsed from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// 1-based (line, column) of the span start within `source`
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let offset = self.start.min(source.len());
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|pos| pos + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Source text covered by the span
+    pub fn snippet<'a>(&self, source: &'a str) -> &'a str {
+        let start = self.start.min(source.len());
+        let end = self.end.clamp(start, source.len());
+        &source[start..end]
+    }
+}
 
 // ============================================================================
 // Types
@@ -17,6 +60,8 @@ pub enum Type {
     Record(RecordType),
     Function(FunctionType),
     Pointer(PointerType),
+    /// Unification variable introduced during type inference; never written in source
+    Var(TypeVar),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -55,6 +100,40 @@ pub struct RecordType {
     pub fields: Vec<(String, Type)>,
 }
 
+/// Source syntax for the type: `List<Int>`, `(Int -> Int) -> Bool`; type
+/// variables print as `'t0`, `'t1`, ...
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Basic(basic) => write!(f, "{}", basic.name),
+            Type::Option(option) => write!(f, "Option<{}>", option.inner),
+            Type::Result(result) => write!(f, "Result<{}, {}>", result.ok_type, result.err_type),
+            Type::List(list) => write!(f, "List<{}>", list.element_type),
+            Type::Pointer(pointer) => write!(f, "Ptr<{}>", pointer.pointee_type),
+            Type::Tuple(tuple) => {
+                let elements: Vec<String> = tuple.element_types.iter().map(Type::to_string).collect();
+                write!(f, "({})", elements.join(", "))
+            }
+            Type::Record(record) => {
+                let fields: Vec<String> = record.fields.iter()
+                    .map(|(name, ty)| format!("{}: {}", name, ty))
+                    .collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+            Type::Function(function) => match &*function.param_type {
+                Type::Function(_) => write!(f, "({}) -> {}", function.param_type, function.return_type),
+                param => write!(f, "{} -> {}", param, function.return_type),
+            },
+            Type::Var(var) => write!(f, "'t{}", var.id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TypeVar {
+    pub id: usize,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionType {
     pub param_type: Box<Type>,
@@ -83,10 +162,178 @@ pub enum Expr {
     FieldAccess(FieldAccess),
 }
 
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal(e) => e.span,
+            Expr::Variable(e) => e.span,
+            Expr::Lambda(e) => e.span,
+            Expr::Application(e) => e.span,
+            Expr::Let(e) => e.span,
+            Expr::If(e) => e.span,
+            Expr::Match(e) => e.span,
+            Expr::Constructor(e) => e.span,
+            Expr::BinaryOp(e) => e.span,
+            Expr::UnaryOp(e) => e.span,
+            Expr::Tuple(e) => e.span,
+            Expr::Record(e) => e.span,
+            Expr::FieldAccess(e) => e.span,
+        }
+    }
+
+    pub fn set_span(&mut self, span: Span) {
+        match self {
+            Expr::Literal(e) => e.span = span,
+            Expr::Variable(e) => e.span = span,
+            Expr::Lambda(e) => e.span = span,
+            Expr::Application(e) => e.span = span,
+            Expr::Let(e) => e.span = span,
+            Expr::If(e) => e.span = span,
+            Expr::Match(e) => e.span = span,
+            Expr::Constructor(e) => e.span = span,
+            Expr::BinaryOp(e) => e.span = span,
+            Expr::UnaryOp(e) => e.span = span,
+            Expr::Tuple(e) => e.span = span,
+            Expr::Record(e) => e.span = span,
+            Expr::FieldAccess(e) => e.span = span,
+        }
+    }
+
+    // Binding strength when printed: operands binding less tightly than
+    // their context need parentheses
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Lambda(_) | Expr::Let(_) | Expr::If(_) | Expr::Match(_) => 0,
+            Expr::BinaryOp(binop) => binary_precedence(&binop.op),
+            Expr::UnaryOp(_) => 11,
+            Expr::Application(_) | Expr::FieldAccess(_) => 12,
+            Expr::Literal(Literal { value: LiteralValue::Int(n), .. }) if *n < 0 => 11,
+            Expr::Literal(Literal { value: LiteralValue::Float(x), .. }) if *x < 0.0 => 11,
+            Expr::Literal(_) | Expr::Variable(_) | Expr::Constructor(_) | Expr::Tuple(_) | Expr::Record(_) => 13,
+        }
+    }
+}
+
+fn binary_precedence(op: &str) -> u8 {
+    match op {
+        "=>" => 1,
+        "||" | "or" => 2,
+        "&&" | "and" => 3,
+        "==" | "!=" | "<" | ">" | "<=" | ">=" => 4,
+        "|" => 5,
+        "^" => 6,
+        "&" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        _ => 10,
+    }
+}
+
+// `expr`, parenthesised if it binds less tightly than `precedence`
+struct Operand<'a>(&'a Expr, u8);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.precedence() < self.1 {
+            write!(f, "({})", self.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(T::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Source syntax for the expression on one line, with only the parentheses
+/// precedence needs: `n >= 0 && (n < 10 || big)`
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(lit) => write!(f, "{}", lit.value),
+            Expr::Variable(var) => write!(f, "{}", var.name),
+            Expr::Lambda(lambda) => {
+                write!(f, "\\")?;
+                for (name, ty) in &lambda.params {
+                    match ty {
+                        Some(ty) => write!(f, "({}: {}) ", name, ty)?,
+                        None => write!(f, "{} ", name)?,
+                    }
+                }
+                write!(f, "-> {}", lambda.body)
+            }
+            Expr::Application(_) => {
+                let mut args = vec![];
+                let mut callee = self;
+                while let Expr::Application(app) = callee {
+                    args.push(&*app.arg);
+                    callee = &app.func;
+                }
+                args.reverse();
+                if let [Expr::Literal(Literal { value: LiteralValue::Unit, .. })] = args[..] {
+                    args.clear();
+                }
+                write!(f, "{}({})", Operand(callee, 12), join(&args))
+            }
+            // A let value may be any expression but another let
+            Expr::Let(let_expr) => match &*let_expr.value {
+                Expr::Let(_) => write!(f, "let {} = ({}) in {}", let_expr.var_name, let_expr.value, let_expr.body),
+                value => write!(f, "let {} = {} in {}", let_expr.var_name, value, let_expr.body),
+            },
+            Expr::If(if_expr) => write!(
+                f,
+                "if {} then {} else {}",
+                Operand(&if_expr.condition, 1), if_expr.then_branch, if_expr.else_branch
+            ),
+            Expr::Match(match_expr) => {
+                write!(f, "match {} with", Operand(&match_expr.scrutinee, 1))?;
+                for arm in &match_expr.arms {
+                    write!(f, " | {}", arm.pattern)?;
+                    if let Some(guard) = &arm.guard {
+                        write!(f, " if {}", Operand(guard, 1))?;
+                    }
+                    write!(f, " -> {}", Operand(&arm.body, 1))?;
+                }
+                Ok(())
+            }
+            Expr::Constructor(constructor) if constructor.name == "List" => write!(f, "[{}]", join(&constructor.args)),
+            Expr::Constructor(constructor) if constructor.args.is_empty() => write!(f, "{}", constructor.name),
+            Expr::Constructor(constructor) => write!(f, "{}({})", constructor.name, join(&constructor.args)),
+            Expr::BinaryOp(binop) => {
+                let precedence = binary_precedence(&binop.op);
+                let (left, right) = match binop.op.as_str() {
+                    "=>" => (precedence + 1, precedence),
+                    "==" | "!=" | "<" | ">" | "<=" | ">=" => (precedence + 1, precedence + 1),
+                    _ => (precedence, precedence + 1),
+                };
+                write!(f, "{} {} {}", Operand(&binop.left, left), binop.op, Operand(&binop.right, right))
+            }
+            Expr::UnaryOp(unop) if unop.op == "-" => match &*unop.operand {
+                // `-3` reads back as the literal -3
+                Expr::Literal(Literal { value: LiteralValue::Int(_) | LiteralValue::Float(_), .. }) => {
+                    write!(f, "-({})", unop.operand)
+                }
+                operand => write!(f, "-{}", Operand(operand, 11)),
+            },
+            Expr::UnaryOp(unop) => write!(f, "{} {}", unop.op, Operand(&unop.operand, 11)),
+            Expr::Tuple(tuple) => write!(f, "({})", join(&tuple.elements)),
+            Expr::Record(record) => {
+                let fields: Vec<String> = record.fields.iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Expr::FieldAccess(access) => write!(f, "{}.{}", Operand(&access.record, 12), access.field),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Literal {
     pub value: LiteralValue,
     pub type_name: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -98,21 +345,55 @@ pub enum LiteralValue {
     Unit,
 }
 
+/// Source syntax for the literal: `-3`, `2.5`, `"a\\n"`, `()`
+impl fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiteralValue::Int(n) => write!(f, "{}", n),
+            // Never in exponent form, which the parser does not read
+            LiteralValue::Float(x) if x.is_finite() && x.fract() == 0.0 => write!(f, "{:.1}", x),
+            LiteralValue::Float(x) => write!(f, "{}", x),
+            LiteralValue::Bool(b) => write!(f, "{}", b),
+            LiteralValue::String(text) => {
+                write!(f, "\"")?;
+                for c in text.chars() {
+                    match c {
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\0' => write!(f, "\\0")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '"' => write!(f, "\\\"")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            LiteralValue::Unit => write!(f, "()"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Variable {
     pub name: String,
+    pub span: Span,
 }
 
+/// `\x y -> body` or `\(x: Int) (y: Int) -> body`; unannotated parameter
+/// types come from the context the lambda is used in
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Lambda {
-    pub params: Vec<String>,
+    pub params: Vec<(String, Option<Type>)>,
     pub body: Box<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Application {
     pub func: Box<Expr>,
     pub arg: Box<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -120,6 +401,7 @@ pub struct LetExpr {
     pub var_name: String,
     pub value: Box<Expr>,
     pub body: Box<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -127,18 +409,30 @@ pub struct IfExpr {
     pub condition: Box<Expr>,
     pub then_branch: Box<Expr>,
     pub else_branch: Box<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MatchExpr {
     pub scrutinee: Box<Expr>,
-    pub arms: Vec<(Pattern, Expr)>,
+    pub arms: Vec<MatchArm>,
+    pub span: Span,
+}
+
+/// `| pattern if guard -> body`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    /// Condition the arm also needs to be taken, seeing the pattern's variables
+    pub guard: Option<Expr>,
+    pub body: Expr,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Constructor {
     pub name: String,
     pub args: Vec<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -146,28 +440,33 @@ pub struct BinaryOp {
     pub op: String,
     pub left: Box<Expr>,
     pub right: Box<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UnaryOp {
     pub op: String,
     pub operand: Box<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TupleExpr {
     pub elements: Vec<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RecordExpr {
     pub fields: Vec<(String, Expr)>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FieldAccess {
     pub record: Box<Expr>,
     pub field: String,
+    pub span: Span,
 }
 
 // ============================================================================
@@ -182,36 +481,138 @@ pub enum Pattern {
     Tuple(TuplePattern),
     Record(RecordPattern),
     Wildcard(WildcardPattern),
+    Or(OrPattern),
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Literal(p) => p.span,
+            Pattern::Variable(p) => p.span,
+            Pattern::Constructor(p) => p.span,
+            Pattern::Tuple(p) => p.span,
+            Pattern::Record(p) => p.span,
+            Pattern::Wildcard(p) => p.span,
+            Pattern::Or(p) => p.span,
+        }
+    }
+
+    pub fn set_span(&mut self, span: Span) {
+        match self {
+            Pattern::Literal(p) => p.span = span,
+            Pattern::Variable(p) => p.span = span,
+            Pattern::Constructor(p) => p.span = span,
+            Pattern::Tuple(p) => p.span = span,
+            Pattern::Record(p) => p.span = span,
+            Pattern::Wildcard(p) => p.span = span,
+            Pattern::Or(p) => p.span = span,
+        }
+    }
+
+    /// Append the names this pattern binds, left to right
+    pub fn collect_bindings(&self, out: &mut Vec<String>) {
+        match self {
+            Pattern::Variable(var) => out.push(var.name.clone()),
+            Pattern::Constructor(constructor) => {
+                for arg in &constructor.args {
+                    arg.collect_bindings(out);
+                }
+            }
+            Pattern::Tuple(tuple) => {
+                for element in &tuple.elements {
+                    element.collect_bindings(out);
+                }
+            }
+            Pattern::Record(record) => {
+                for (_, field) in &record.fields {
+                    field.collect_bindings(out);
+                }
+            }
+            // Every alternative binds the same names
+            Pattern::Or(or) => {
+                if let Some(first) = or.alternatives.first() {
+                    first.collect_bindings(out);
+                }
+            }
+            Pattern::Literal(_) | Pattern::Wildcard(_) => {}
+        }
+    }
+}
+
+/// Source syntax for the pattern: `Some((x, Point { y: 0 }))`
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Literal(lit) => write!(f, "{}", lit.value),
+            Pattern::Variable(var) => write!(f, "{}", var.name),
+            Pattern::Constructor(constructor) if constructor.args.is_empty() => write!(f, "{}", constructor.name),
+            Pattern::Constructor(constructor) => write!(f, "{}({})", constructor.name, join(&constructor.args)),
+            Pattern::Tuple(tuple) => write!(f, "({})", join(&tuple.elements)),
+            Pattern::Record(record) => {
+                if let Some(type_name) = &record.type_name {
+                    write!(f, "{} ", type_name)?;
+                }
+                let fields: Vec<String> = record.fields.iter()
+                    .map(|(name, pattern)| match pattern {
+                        Pattern::Variable(var) if &var.name == name => name.clone(),
+                        pattern => format!("{}: {}", name, pattern),
+                    })
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Pattern::Wildcard(_) => write!(f, "_"),
+            Pattern::Or(or) => {
+                let alternatives: Vec<String> = or.alternatives.iter().map(Pattern::to_string).collect();
+                write!(f, "{}", alternatives.join(" | "))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LiteralPattern {
     pub value: LiteralValue,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VariablePattern {
     pub name: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConstructorPattern {
     pub name: String,
     pub args: Vec<Pattern>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TuplePattern {
     pub elements: Vec<Pattern>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RecordPattern {
+    /// `Point` in `Point { x, y: 0 }`
+    pub type_name: Option<String>,
     pub fields: Vec<(String, Pattern)>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct WildcardPattern;
+pub struct WildcardPattern {
+    pub span: Span,
+}
+
+/// `North | South`: matches when any alternative does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrPattern {
+    pub alternatives: Vec<Pattern>,
+    pub span: Span,
+}
 
 // ============================================================================
 // Program Structure
@@ -228,6 +629,7 @@ pub struct TypeDef {
     pub name: String,
     pub definition: TypeDefKind,
     pub annotations: Vec<Annotation>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -240,12 +642,15 @@ pub enum TypeDefKind {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionDef {
     pub name: String,
+    /// `T` in `func id<T>(x: T) -> T`
+    pub type_params: Vec<String>,
     pub params: Vec<(String, Type)>,
     pub return_type: Type,
     pub requires: Vec<Expr>,
     pub ensures: Vec<Expr>,
     pub body: Expr,
     pub annotations: Vec<Annotation>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -255,6 +660,7 @@ pub struct ExternFunctionDecl {
     pub params: Vec<(String, Type)>,
     pub return_type: Type,
     pub annotations: Vec<Annotation>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -262,4 +668,166 @@ pub struct Program {
     pub type_defs: Vec<TypeDef>,
     pub func_defs: Vec<FunctionDef>,
     pub extern_funcs: Vec<ExternFunctionDecl>,
+    pub modules: Vec<ModuleDef>,
+    pub signatures: Vec<SignatureDef>,
+    pub imports: Vec<Import>,
+}
+
+// ============================================================================
+// Modules
+// ============================================================================
+
+/// `module Name [: Signature] : declarations end`
+///
+/// Items are referred to from outside as `Name.item`. Modules loaded through
+/// `import` are represented the same way, named after their module path.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleDef {
+    pub name: String,
+    pub signature: Option<String>,
+    pub body: Program,
+    /// Source file for modules loaded through `import`; `None` for inline modules
+    pub file: Option<String>,
+    pub span: Span,
+}
+
+/// `signature Name<T> : items end`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignatureDef {
+    pub name: String,
+    pub type_params: Vec<String>,
+    pub items: Vec<SignatureItem>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SignatureItem {
+    /// `func name : A -> B -> R`
+    Func { name: String, func_type: Type, span: Span },
+    /// `type Name<T>` (abstract)
+    Type { name: String, type_params: Vec<String>, span: Span },
+}
+
+/// `import Game.Core [as core]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Import {
+    pub path: Vec<String>,
+    pub alias: Option<String>,
+    pub span: Span,
+}
+
+impl Import {
+    /// Dotted module path, e.g. `Game.Core`
+    pub fn module_name(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+// ============================================================================
+// Span-Insensitive Comparison
+// ============================================================================
+
+/// Nodes whose spans can be reset, so that nodes parsed from differently
+/// laid out source compare equal when their structure is the same
+#[cfg(test)]
+pub(crate) trait ClearSpans: Clone {
+    fn clear_spans(&mut self);
+
+    /// Copy of the node with every span in it reset
+    fn without_spans(&self) -> Self {
+        let mut node = self.clone();
+        node.clear_spans();
+        node
+    }
+}
+
+#[cfg(test)]
+impl ClearSpans for Program {
+    fn clear_spans(&mut self) {
+        for type_def in &mut self.type_defs {
+            type_def.span = Span::default();
+        }
+        for function in &mut self.func_defs {
+            function.span = Span::default();
+            for clause in function.requires.iter_mut().chain(&mut function.ensures) {
+                clause.clear_spans();
+            }
+            function.body.clear_spans();
+        }
+        for extern_func in &mut self.extern_funcs {
+            extern_func.span = Span::default();
+        }
+        for module in &mut self.modules {
+            module.span = Span::default();
+            module.body.clear_spans();
+        }
+        for signature in &mut self.signatures {
+            signature.span = Span::default();
+            for item in &mut signature.items {
+                match item {
+                    SignatureItem::Func { span, .. } | SignatureItem::Type { span, .. } => *span = Span::default(),
+                }
+            }
+        }
+        for import in &mut self.imports {
+            import.span = Span::default();
+        }
+    }
+}
+
+#[cfg(test)]
+impl ClearSpans for Expr {
+    fn clear_spans(&mut self) {
+        self.set_span(Span::default());
+        match self {
+            Expr::Literal(_) | Expr::Variable(_) => {}
+            Expr::Lambda(lambda) => lambda.body.clear_spans(),
+            Expr::Application(app) => {
+                app.func.clear_spans();
+                app.arg.clear_spans();
+            }
+            Expr::Let(let_expr) => {
+                let_expr.value.clear_spans();
+                let_expr.body.clear_spans();
+            }
+            Expr::If(if_expr) => {
+                if_expr.condition.clear_spans();
+                if_expr.then_branch.clear_spans();
+                if_expr.else_branch.clear_spans();
+            }
+            Expr::Match(match_expr) => {
+                match_expr.scrutinee.clear_spans();
+                for arm in &mut match_expr.arms {
+                    arm.pattern.clear_spans();
+                    if let Some(guard) = &mut arm.guard {
+                        guard.clear_spans();
+                    }
+                    arm.body.clear_spans();
+                }
+            }
+            Expr::Constructor(constructor) => constructor.args.iter_mut().for_each(Expr::clear_spans),
+            Expr::BinaryOp(binop) => {
+                binop.left.clear_spans();
+                binop.right.clear_spans();
+            }
+            Expr::UnaryOp(unop) => unop.operand.clear_spans(),
+            Expr::Tuple(tuple) => tuple.elements.iter_mut().for_each(Expr::clear_spans),
+            Expr::Record(record) => record.fields.iter_mut().for_each(|(_, value)| value.clear_spans()),
+            Expr::FieldAccess(access) => access.record.clear_spans(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl ClearSpans for Pattern {
+    fn clear_spans(&mut self) {
+        self.set_span(Span::default());
+        match self {
+            Pattern::Literal(_) | Pattern::Variable(_) | Pattern::Wildcard(_) => {}
+            Pattern::Constructor(constructor) => constructor.args.iter_mut().for_each(Pattern::clear_spans),
+            Pattern::Tuple(tuple) => tuple.elements.iter_mut().for_each(Pattern::clear_spans),
+            Pattern::Record(record) => record.fields.iter_mut().for_each(|(_, pattern)| pattern.clear_spans()),
+            Pattern::Or(or) => or.alternatives.iter_mut().for_each(Pattern::clear_spans),
+        }
+    }
 }
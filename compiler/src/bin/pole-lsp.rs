@@ -0,0 +1,17 @@
+// pole-lsp - Language Server Protocol server for .pole-ir files
+
+use std::io;
+use std::process::exit;
+
+use pole_compiler::lsp::run_server;
+
+fn main() {
+    if std::env::args().skip(1).any(|arg| arg == "-h" || arg == "--help") {
+        println!("usage: pole-lsp\n\nServes the Language Server Protocol on stdin and stdout; start it from an editor.");
+        return;
+    }
+    if let Err(e) = run_server(&mut io::stdin().lock(), &mut io::stdout().lock()) {
+        eprintln!("pole-lsp: {}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,216 @@
+// polec - Pole compiler command-line driver
+
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use bumpalo::Bump;
+use inkwell::context::Context;
+use pole_compiler::driver::{self, DriverError, DriverOptions};
+use pole_compiler::repl::{run_session, Repl};
+
+const USAGE: &str = "\
+usage: polec <command> <file.pole-ir> [options]
+       polec repl [file.pole-ir]
+       polec fmt <file.pole-ir> [--check] [-o <path>]
+
+commands:
+  check        parse and type-check the program
+  build        compile and link an executable (default output: the file name without
+               extension, or <file>.wasm for wasm32-wasi)
+  emit-llvm    write LLVM IR (default output: <file>.ll)
+  emit-obj     write an object file (default output: <file>.o)
+  run          compile and run on the JIT, exiting with the entry function's result;
+               for wasm32-wasi, run under $WASI_RUNTIME (default wasmtime)
+  repl         define types and functions and evaluate expressions interactively,
+               starting from the file's definitions if one is given
+  fmt          rewrite the file in canonical layout; comments between declarations
+               are kept, and declarations with comments inside are left as written
+
+options:
+  -o <path>          output path
+  -O<level>          optimization level 0-3, or s for size (default 0)
+  --dump-ir <dir>    write the LLVM IR before and after optimization to
+                     <dir>/<name>.before.ll and <dir>/<name>.after.ll
+  --entry <name>     function the program starts in (default main); emitted
+                     code gets a C main calling it
+  -l <lib>           link a library, also -l<lib>; repeatable
+  -L <dir>           search a directory for libraries, also -L<dir>; repeatable
+  --contracts        check requires/ensures clauses at run time
+  -g                 emit DWARF debug info for stepping through the .pole-ir source
+  --target <triple>  compile for another target, such as aarch64-linux-gnu,
+                     x86_64-windows-gnu or riscv64-linux-gnu; `run` is not
+                     available and `build` links with $CC. wasm32-wasi
+                     modules link with $WASM_LD (default wasm-ld)
+  --cpu <name>       CPU to generate code for (default: the host's, or
+                     generic with --target)
+  --features <list>  CPU features, such as +neon,-sve
+  --check            with fmt, list the file if it is not formatted instead of
+                     rewriting it, and exit with 1
+  -h, --help         show this help
+
+exit codes: 0 success, 1 compile or link errors, 2 usage errors; `run` exits
+with the program's result";
+
+struct Invocation {
+    command: String,
+    // Optional only for `repl`
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    // `fmt --check`
+    check: bool,
+    options: DriverOptions,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let invocation = parse_args(args).unwrap_or_else(|message| {
+        eprintln!("polec: {}\n\n{}", message, USAGE);
+        exit(2);
+    });
+
+    match execute(&invocation) {
+        Ok(code) => exit(code),
+        Err(DriverError::Diagnostics(diagnostics)) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{}", diagnostic);
+            }
+            let input = invocation.input.as_ref().map_or("<repl>".into(), |input| input.display().to_string());
+            eprintln!("polec: {} error(s) in {}", diagnostics.len(), input);
+            exit(1);
+        }
+        Err(DriverError::Failed(message)) => {
+            eprintln!("polec: {}", message);
+            exit(1);
+        }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or("missing command")?;
+    if !matches!(command.as_str(), "check" | "build" | "emit-llvm" | "emit-obj" | "run" | "repl" | "fmt") {
+        return Err(format!("unknown command '{}'", command));
+    }
+
+    let mut input = None;
+    let mut output = None;
+    let mut check = false;
+    let mut options = DriverOptions::default();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or_else(|| format!("{} needs a value", flag));
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(value("-o")?)),
+            "--entry" => options.entry = Some(value("--entry")?),
+            "-l" => options.link_libs.push(value("-l")?),
+            "-L" => options.lib_dirs.push(PathBuf::from(value("-L")?)),
+            "--contracts" => options.contract_checks = true,
+            "-g" => options.debug_info = true,
+            "--target" => options.target = Some(value("--target")?),
+            "--cpu" => options.cpu = Some(value("--cpu")?),
+            "--features" => options.features = Some(value("--features")?),
+            "--dump-ir" => options.dump_ir = Some(PathBuf::from(value("--dump-ir")?)),
+            "--check" if command == "fmt" => check = true,
+            _ if arg.starts_with("-O") => options.opt_level = arg[2..].parse()?,
+            _ if arg.starts_with("-l") => options.link_libs.push(arg[2..].to_string()),
+            _ if arg.starts_with("-L") => options.lib_dirs.push(PathBuf::from(&arg[2..])),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if input.is_some() => return Err(format!("unexpected argument '{}'", arg)),
+            _ => input = Some(PathBuf::from(arg)),
+        }
+    }
+
+    if input.is_none() && command != "repl" {
+        return Err("missing input file".to_string());
+    }
+    Ok(Invocation { command, input, output, check, options })
+}
+
+// Run the command; the exit code on success
+fn execute(invocation: &Invocation) -> Result<i32, DriverError> {
+    let Invocation { command, input, output, check, options } = invocation;
+    if command == "repl" {
+        return run_repl(input.as_deref());
+    }
+    let input = input.as_ref().expect("parse_args requires an input file");
+    if command == "fmt" {
+        return run_fmt(input, output.as_deref(), *check);
+    }
+    let checked = driver::load_and_check(input)?;
+    if command == "check" {
+        println!("{}: ok", input.display());
+        return Ok(0);
+    }
+
+    let context = Context::create();
+    let arena = Bump::new();
+    let codegen = driver::compile(&checked, options, &context, &arena)?;
+    for warning in driver::link_warnings(&codegen) {
+        eprintln!("polec: warning: {}", warning);
+    }
+
+    let output_or = |extension: &str| output.clone().unwrap_or_else(|| default_output(input, extension));
+    let wasi = options.target_spec().is_wasi();
+    match command.as_str() {
+        "build" => driver::build_executable(&checked, &codegen, &output_or(if wasi { "wasm" } else { "" }), options)?,
+        "emit-llvm" => driver::emit_llvm(&codegen, &output_or("ll"))?,
+        "emit-obj" => driver::emit_object(&codegen, &output_or("o"))?,
+        "run" if wasi => return driver::run_wasi(&checked, &codegen, options),
+        "run" => return driver::run_jit(&checked, &codegen, options),
+        _ => unreachable!("commands are validated in parse_args"),
+    }
+    Ok(0)
+}
+
+// Interactive session on stdin, after loading `input`'s definitions
+fn run_repl(input: Option<&Path>) -> Result<i32, DriverError> {
+    let context = Context::create();
+    let arena = Bump::new();
+    let mut repl = Repl::new(&context, &arena);
+    if let Some(input) = input {
+        let checked = driver::load_and_check(input)?;
+        let loaded = repl.load(checked.program, &checked.source).map_err(DriverError::Failed)?;
+        println!("loaded {}", input.display());
+        println!("{}", loaded);
+    }
+
+    let stdin = io::stdin();
+    let prompt = stdin.is_terminal();
+    run_session(&mut repl, &mut stdin.lock(), &mut io::stdout().lock(), prompt)
+        .map_err(|e| DriverError::Failed(e.to_string()))?;
+    Ok(0)
+}
+
+// Rewrite `input` in canonical layout, or to `output`; with `check`, only
+// report whether it already is
+fn run_fmt(input: &Path, output: Option<&Path>, check: bool) -> Result<i32, DriverError> {
+    let (source, formatted) = driver::format_file(input)?;
+    if check {
+        if formatted == source {
+            return Ok(0);
+        }
+        println!("{}: not formatted", input.display());
+        return Ok(1);
+    }
+    let output = output.unwrap_or(input);
+    if output != input || formatted != source {
+        fs::write(output, formatted)
+            .map_err(|e| DriverError::Failed(format!("Cannot write {}: {}", output.display(), e)))?;
+    }
+    Ok(0)
+}
+
+// `game.pole-ir` becomes `game.ll`, `game.o` or `game`, next to the input
+fn default_output(input: &Path, extension: &str) -> PathBuf {
+    let output = input.with_extension(extension);
+    if output == input {
+        input.with_extension("out")
+    } else {
+        output
+    }
+}
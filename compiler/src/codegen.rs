@@ -1,24 +1,306 @@
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::module::Module;
+use inkwell::debug_info::{
+    AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DILocation, DIScope, DIType,
+    DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::{FlagBehavior, Linkage, Module};
+use inkwell::passes::PassBuilderOptions;
 use inkwell::targets::{
-    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetData, TargetMachine, TargetTriple,
 };
-use inkwell::types::{BasicMetadataTypeEnum, BasicType as LLVMBasicType, BasicTypeEnum};
-use inkwell::values::{BasicValueEnum, FunctionValue};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType as LLVMBasicType, BasicTypeEnum, IntType, StructType};
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PhiValue, PointerValue};
 use inkwell::OptimizationLevel;
-use inkwell::{IntPredicate, FloatPredicate};
+use inkwell::{AddressSpace, IntPredicate, FloatPredicate};
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 
 use crate::ast::{
     Application, BasicType as AstBasicType, BinaryOp, Constructor, Expr, ExternFunctionDecl,
-    FieldAccess, FunctionDef, IfExpr, LetExpr, Literal, LiteralValue, MatchExpr, Pattern,
-    Program, RecordExpr, RecordType, Type, TypeDefKind,
+    FieldAccess, FunctionDef, FunctionType, IfExpr, Lambda, LetExpr, Literal, LiteralPattern,
+    LiteralValue, MatchArm, MatchExpr, OptionType, Pattern, Program, RecordExpr, RecordPattern, RecordType,
+    Span, TupleExpr, TupleType, Type, TypeDefKind, UnaryOp, Variable, WildcardPattern,
 };
+use crate::exhaustiveness::pattern_to_string;
+use crate::tailrec::{is_tailrec, recursive_calls};
+use crate::type_checker::{curried_type, substitute_type_params};
 
-use std::collections::HashMap;
+use crate::link_manifest::LinkManifest;
+use crate::linker::{flatten_program, function_files, LinkError};
+use std::collections::{HashMap, HashSet};
 use bumpalo::Bump;
 
+/// Code generation failure, pointing at the innermost expression that failed
+/// (or the enclosing declaration when no expression is involved)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodegenError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl CodegenError {
+    pub fn new(message: impl Into<String>, span: Option<Span>) -> Self {
+        CodegenError {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{} (at {}..{})", self.message, span.start, span.end),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+impl From<LinkError> for CodegenError {
+    fn from(error: LinkError) -> Self {
+        let message = match &error.file {
+            Some(file) => format!("{}: {}", file, error.message),
+            None => error.message,
+        };
+        CodegenError::new(message, error.span)
+    }
+}
+
+/// The first of the errors `flatten_program` reports
+impl From<Vec<LinkError>> for CodegenError {
+    fn from(errors: Vec<LinkError>) -> Self {
+        match errors.into_iter().next() {
+            Some(error) => CodegenError::from(error),
+            None => CodegenError::new("Failed to link the program", None),
+        }
+    }
+}
+
+/// Optimization level: the `default<On>` pipeline of LLVM's new pass
+/// manager, and the matching code generation level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+    /// `O2` that avoids growing code size
+    Os,
+}
+
+impl OptLevel {
+    /// Name in LLVM pass pipelines, `O2`
+    pub fn name(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "O0",
+            OptLevel::O1 => "O1",
+            OptLevel::O2 => "O2",
+            OptLevel::O3 => "O3",
+            OptLevel::Os => "Os",
+        }
+    }
+
+    pub fn llvm(self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+/// `0` to `3` or `s`, as in `-O2`
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(format!("unknown optimization level '{}' (expected 0, 1, 2, 3 or s)", s)),
+        }
+    }
+}
+
+// A pattern variable's name with the value and type it shadowed
+type ShadowedBinding<'ctx> = (String, Option<BasicValueEnum<'ctx>>, Option<Type>);
+
+// A value under test in a match, with its Pole type when known
+#[derive(Clone)]
+struct Occurrence<'ctx> {
+    value: BasicValueEnum<'ctx>,
+    ty: Option<Type>,
+}
+
+/// Machine the generated code runs on: an LLVM target triple, with the CPU
+/// and CPU features to generate code for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetSpec {
+    /// `aarch64-unknown-linux-gnu`, `x86_64-pc-windows-gnu`, ...; `None`
+    /// for the host. Short forms like `aarch64-linux-gnu` are normalized.
+    pub triple: Option<String>,
+    /// `generic`, `cortex-a72`, `znver3`, ...
+    pub cpu: String,
+    /// `+neon,-sve`, or empty for the CPU's defaults
+    pub features: String,
+}
+
+impl TargetSpec {
+    /// The host, with its own CPU and features
+    pub fn host() -> Self {
+        TargetSpec {
+            triple: None,
+            cpu: TargetMachine::get_host_cpu_name().to_string(),
+            features: TargetMachine::get_host_cpu_features().to_string(),
+        }
+    }
+
+    /// Normalized triple, the host's if none is given
+    pub fn target_triple(&self) -> TargetTriple {
+        match &self.triple {
+            Some(triple) => TargetMachine::normalize_triple(&TargetTriple::create(triple)),
+            None => TargetMachine::get_default_triple(),
+        }
+    }
+
+    /// Whether the target is WebAssembly with the WASI system interface,
+    /// such as `wasm32-wasi`
+    pub fn is_wasi(&self) -> bool {
+        let triple = self.target_triple();
+        let triple = triple.as_str().to_string_lossy();
+        triple.starts_with("wasm32") && triple.contains("wasi")
+    }
+
+    /// Whether code for this target runs on the host
+    pub fn is_host(&self) -> bool {
+        self.target_triple().as_str() == TargetMachine::get_default_triple().as_str()
+    }
+}
+
+/// The host triple with the `generic` CPU, so objects run on any machine
+/// of the host's architecture
+impl Default for TargetSpec {
+    fn default() -> Self {
+        TargetSpec {
+            triple: None,
+            cpu: "generic".to_string(),
+            features: String::new(),
+        }
+    }
+}
+
+// Row of a match's clause matrix: patterns for the columns left to test,
+// the variables bound so far, and the arm the row selects
+#[derive(Clone)]
+struct ClauseRow<'ctx> {
+    patterns: Vec<Pattern>,
+    bindings: Vec<(String, Occurrence<'ctx>)>,
+    arm: usize,
+}
+
+// Block of a match arm, and the slots holding the variables it binds
+struct ArmTarget<'ctx> {
+    block: BasicBlock<'ctx>,
+    slots: Vec<(String, PointerValue<'ctx>, BasicTypeEnum<'ctx>, Option<Type>)>,
+}
+
+// The arms of a match being compiled, with the targets leaves created so far
+struct ArmTargets<'a, 'ctx> {
+    arms: &'a [MatchArm],
+    targets: Vec<Option<ArmTarget<'ctx>>>,
+}
+
+// Loop a `@tailrec` function's body runs in: tail calls jump back to
+// `header`, whose phis hold the parameters
+struct TailLoop<'ctx> {
+    header: BasicBlock<'ctx>,
+    params: Vec<(PhiValue<'ctx>, Type)>,
+    calls: Vec<Span>,
+}
+
+// DWARF compile unit of the module and the subprogram being compiled
+struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    compile_unit: DICompileUnit<'ctx>,
+    target_data: TargetData,
+    // The root file first, then the imported files functions come from
+    files: Vec<DebugFile<'ctx>>,
+    // Qualified name -> index into `files`, for functions not in the root file
+    function_files: HashMap<String, usize>,
+    scope: Option<DebugScope<'ctx>>,
+    // Location of the instructions built now. Kept here as LLVM reports an
+    // unset location as an empty node, which is no location to restore.
+    location: Option<DILocation<'ctx>>,
+}
+
+struct DebugFile<'ctx> {
+    file: DIFile<'ctx>,
+    path: String,
+    source: String,
+    // Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl<'ctx> DebugFile<'ctx> {
+    fn new(file: DIFile<'ctx>, path: String, source: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        DebugFile { file, path, source, line_starts }
+    }
+
+    // 1-based line and column of the span start
+    fn line_col(&self, span: Span) -> (u32, u32) {
+        let offset = span.start.min(self.source.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let column = self.source[self.line_starts[line - 1]..offset].chars().count() + 1;
+        (line as u32, column as u32)
+    }
+}
+
+// Subprogram of the function being compiled, and the file it is in
+#[derive(Clone, Copy)]
+struct DebugScope<'ctx> {
+    scope: DIScope<'ctx>,
+    file: usize,
+}
+
+// Scope and location to restore once a nested function is compiled
+type OuterDebugScope<'ctx> = (Option<DebugScope<'ctx>>, Option<DILocation<'ctx>>);
+
+// DWARF base type encodings (DW_ATE_*)
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+const DW_ATE_UNSIGNED: u32 = 0x07;
+
+// C functions the WASI runtime defines for wasm32-wasi modules
+const WASI_RUNTIME_FUNCTIONS: &[&str] = &[
+    "malloc", "free", "memcpy", "memset", "memcmp", "strlen", "strstr", "write", "abort",
+    "puts", "putchar", "fopen", "fclose", "fputs", "fgets",
+];
+// WASI ABI values used by the runtime's `fopen`
+const WASI_PREOPENED_DIR_FD: u64 = 3;
+const WASI_LOOKUPFLAGS_SYMLINK_FOLLOW: u64 = 1;
+const WASI_OFLAGS_CREAT: u64 = 1;
+const WASI_OFLAGS_TRUNC: u64 = 8;
+const WASI_FDFLAGS_APPEND: u64 = 1;
+const WASI_RIGHT_FD_READ: u64 = 1 << 1;
+const WASI_RIGHT_FD_WRITE: u64 = 1 << 6;
+const WASM_PAGE_SIZE: u64 = 65536;
+
 pub struct CodeGen<'ctx, 'arena> {
     context: &'ctx Context,
     module: Module<'ctx>,
@@ -32,6 +314,27 @@ pub struct CodeGen<'ctx, 'arena> {
     extern_func_mapping: HashMap<String, String>,
     extern_func_types: HashMap<String, Type>,
     func_return_types: HashMap<String, Type>,
+    // Parameters of user and extern functions, for using them as values
+    func_params: HashMap<String, Vec<(String, Type)>>,
+    // `func f<T>` definitions, compiled once per instantiation at call sites
+    generic_funcs: HashMap<String, FunctionDef>,
+    lambda_count: usize,
+    error_span: Option<Span>,
+    // Evaluate `requires` on entry and `ensures` on return, aborting on failure
+    check_contracts: bool,
+    // Libraries and headers from `@link`/`@header` on extern declarations
+    link_manifest: LinkManifest,
+    // Pass pipeline run by `optimize` and code generation level for objects
+    opt_level: OptLevel,
+    // Set while compiling the body of a `@tailrec` function
+    tail_loop: Option<TailLoop<'ctx>>,
+    // Set by `enable_debug_info`
+    debug_info: Option<DebugInfo<'ctx>>,
+    target: TargetSpec,
+    // Width of `size_t` on the target, for sizes passed to the C library
+    pointer_bits: u32,
+    // Functions `compile_program` declares without compiling their bodies
+    external_functions: HashSet<String>,
 }
 
 impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
@@ -52,17 +355,123 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
             extern_func_mapping: HashMap::new(),
             extern_func_types: HashMap::new(),
             func_return_types: HashMap::new(),
+            func_params: HashMap::new(),
+            generic_funcs: HashMap::new(),
+            lambda_count: 0,
+            error_span: None,
+            check_contracts: false,
+            link_manifest: LinkManifest::default(),
+            opt_level: OptLevel::default(),
+            tail_loop: None,
+            debug_info: None,
+            target: TargetSpec::default(),
+            pointer_bits: usize::BITS,
+            external_functions: HashSet::new(),
         }
     }
     
+    /// Compile `requires` and `ensures` clauses into runtime checks. A
+    /// violated clause prints the function and the clause to stderr and
+    /// aborts the program. `@tailrec` functions with `ensures` clauses then
+    /// recurse on the stack, so every call's clauses are checked.
+    pub fn set_contract_checks(&mut self, enabled: bool) {
+        self.check_contracts = enabled;
+    }
+    
+    /// Level for `optimize` and for the machine code `write_object_file`
+    /// generates. Defaults to `O0`, as for `polec`.
+    pub fn set_opt_level(&mut self, level: OptLevel) {
+        self.opt_level = level;
+    }
+    
+    pub fn opt_level(&self) -> OptLevel {
+        self.opt_level
+    }
+    
+    /// Generate code for `target` rather than the host: sets the module's
+    /// triple and data layout, so types are laid out for the target, and
+    /// passes sizes to the C library at its pointer width. Call before
+    /// `compile_program`.
+    pub fn set_target(&mut self, target: TargetSpec) -> Result<(), String> {
+        self.target = target;
+        let target_machine = self.target_machine()?;
+        let target_data = target_machine.get_target_data();
+        self.module.set_triple(&target_machine.get_triple());
+        self.module.set_data_layout(&target_data.get_data_layout());
+        self.pointer_bits = target_data.get_pointer_byte_size(None) * 8;
+        Ok(())
+    }
+    
+    /// Emit DWARF debug info locating compiled code in `source`, the text
+    /// of the file at `path`: a subprogram per function, a line and column
+    /// per instruction, and parameters, `let` and match bindings as local
+    /// variables. Call before `compile_program`; functions from imported
+    /// files are located in those files.
+    pub fn enable_debug_info(&mut self, path: &Path, source: &str) -> Result<(), String> {
+        let target_data = self.target_machine()?.get_target_data();
+        let (file_name, directory) = debug_file_name(path);
+        let (builder, compile_unit) = self.module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &file_name,
+            &directory,
+            "polec",
+            self.opt_level != OptLevel::O0,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let i32_type = self.context.i32_type();
+        self.module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, i32_type.const_int(3, false));
+        self.module.add_basic_value_flag("Dwarf Version", FlagBehavior::Warning, i32_type.const_int(4, false));
+        
+        let root = DebugFile::new(compile_unit.get_file(), path.display().to_string(), source.to_string());
+        self.debug_info = Some(DebugInfo {
+            builder,
+            compile_unit,
+            target_data,
+            files: vec![root],
+            function_files: HashMap::new(),
+            scope: None,
+            location: None,
+        });
+        Ok(())
+    }
+    
+    /// Functions defined in another module, such as one a REPL session
+    /// compiled earlier: `compile_program` only declares them, so calls
+    /// link against that module. Names are qualified, as `Module.f`.
+    pub fn set_external_functions(&mut self, names: impl IntoIterator<Item = String>) {
+        self.external_functions = names.into_iter().collect();
+    }
+    
+    /// Libraries and headers the compiled program needs, from the `@link`
+    /// and `@header` annotations of its extern declarations. A library is
+    /// marked used once compiled code calls one of its functions.
+    pub fn link_manifest(&self) -> &LinkManifest {
+        &self.link_manifest
+    }
+    
     fn alloc_temp<T>(&self, value: T) -> &'arena T {
         self.arena.alloc(value)
     }
 
-    pub fn compile_program(&mut self, program: &Program) -> Result<(), String> {
+    pub fn compile_program(&mut self, program: &Program) -> Result<(), CodegenError> {
+        self.add_debug_files(program);
+        
+        // Move module items to the top level under their qualified names
+        let program = &flatten_program(program).map_err(CodegenError::from)?;
+        
         // Declare external functions from @extern declarations
         for extern_func in &program.extern_funcs {
-            self.declare_extern_function(extern_func)?;
+            self.declare_extern_function(extern_func)
+                .map_err(|message| CodegenError::new(message, Some(extern_func.span)))?;
         }
         
         for type_def in &program.type_defs {
@@ -76,9 +485,59 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                 _ => {}
             }
         }
+        for type_def in &program.type_defs {
+            if self.variant_defs.contains_key(&type_def.name) && self.variant_is_recursive(&type_def.name) {
+                return Err(CodegenError::new(
+                    format!("Recursive variant type '{}' is not supported yet", type_def.name),
+                    Some(type_def.span),
+                ));
+            }
+        }
+        
+        for function in program.func_defs.iter().filter(|function| is_tailrec(function)) {
+            if let Some(&span) = recursive_calls(function).non_tail.first() {
+                return Err(CodegenError::new(
+                    format!("Recursive call to '{}' is not in tail position, but the function is @tailrec", function.name),
+                    Some(span),
+                ));
+            }
+        }
+        
+        // Declare every function up front so calls may precede definitions
+        let (generic_funcs, func_defs): (Vec<&FunctionDef>, Vec<&FunctionDef>) = program.func_defs
+            .iter()
+            .partition(|function| !function.type_params.is_empty());
+        for function in generic_funcs {
+            self.generic_funcs.insert(function.name.clone(), function.clone());
+        }
+        for function in &func_defs {
+            let has = |name: &str| function.annotations.iter().any(|ann| ann.name == name);
+            if has("inline") && has("noinline") {
+                return Err(CodegenError::new(
+                    format!("Function '{}' cannot be both @inline and @noinline", function.name),
+                    Some(function.span),
+                ));
+            }
+            self.declare_function(function);
+        }
         
-        for function in &program.func_defs {
-            self.compile_function(function)?;
+        for function in func_defs {
+            if self.external_functions.contains(&function.name) {
+                continue;
+            }
+            self.error_span = None;
+            if let Err(message) = self.compile_function(function) {
+                let span = self.error_span.take().unwrap_or(function.span);
+                return Err(CodegenError::new(message, Some(span)));
+            }
+        }
+        
+        if self.target.is_wasi() {
+            self.define_wasi_runtime();
+        }
+        
+        if let Some(debug) = &self.debug_info {
+            debug.builder.finalize();
         }
         Ok(())
     }
@@ -110,28 +569,32 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         // Create function type
         let fn_type = return_type.fn_type(&param_types, is_variadic);
         
-        // Declare external function with C name
-        self.module.add_function(&extern_func.c_name, fn_type, None);
+        // Declare external function with C name, once even if several
+        // modules declare the same C function
+        if self.module.get_function(&extern_func.c_name).is_none() {
+            self.module.add_function(&extern_func.c_name, fn_type, None);
+        }
+        
+        self.link_manifest.add_extern(extern_func)?;
         
         // Store mapping from Pole name to C name
         self.extern_func_mapping.insert(extern_func.name.clone(), extern_func.c_name.clone());
         
         // Store return type for type inference
         self.extern_func_types.insert(extern_func.name.clone(), extern_func.return_type.clone());
+        self.func_params.insert(extern_func.name.clone(), extern_func.params.clone());
         
         Ok(())
     }
 
-    fn compile_function(&mut self, function: &FunctionDef) -> Result<FunctionValue<'ctx>, String> {
-        self.var_types.clear();
-        self.local_vars.clear();
+    fn declare_function(&mut self, function: &FunctionDef) -> FunctionValue<'ctx> {
+        if let Some(fn_value) = self.module.get_function(&function.name) {
+            return fn_value;
+        }
         
         // Store function return type for type inference
         self.func_return_types.insert(function.name.clone(), function.return_type.clone());
-        
-        for (param_name, param_type) in &function.params {
-            self.var_types.insert(param_name.clone(), param_type.clone());
-        }
+        self.func_params.insert(function.name.clone(), function.params.clone());
         
         let param_types: Vec<BasicMetadataTypeEnum> = function
             .params
@@ -147,13 +610,80 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         for (i, (param_name, _)) in function.params.iter().enumerate() {
             fn_value.get_nth_param(i as u32).unwrap().set_name(param_name);
         }
+        
+        // @inline always inlines, @noinline never does
+        for (annotation, attribute) in [("inline", "alwaysinline"), ("noinline", "noinline")] {
+            if function.annotations.iter().any(|ann| ann.name == annotation) {
+                let kind = Attribute::get_named_enum_kind_id(attribute);
+                fn_value.add_attribute(AttributeLoc::Function, self.context.create_enum_attribute(kind, 0));
+            }
+        }
+        
+        fn_value
+    }
+
+    fn compile_function(&mut self, function: &FunctionDef) -> Result<FunctionValue<'ctx>, String> {
+        self.var_types.clear();
+        self.local_vars.clear();
+        
+        for (param_name, param_type) in &function.params {
+            self.var_types.insert(param_name.clone(), param_type.clone());
+        }
+        
+        let fn_value = self.declare_function(function);
 
         let entry_bb = self.context.append_basic_block(fn_value, "entry");
         self.builder.position_at_end(entry_bb);
+        let file = self.debug_file_of(&function.name);
+        let outer_debug_scope = self.begin_debug_scope(fn_value, &function.name, function.span, file);
+
+        // Tail calls of a @tailrec function jump back to a loop header with
+        // the new arguments, so the recursion runs in constant stack. Not
+        // when `ensures` is checked: each call checks it against its own
+        // arguments after its callee returns, so the calls stay calls.
+        let mut tail_loop = None;
+        let checks_ensures = self.check_contracts && !function.ensures.is_empty();
+        if is_tailrec(function) && !checks_ensures {
+            let header = self.context.append_basic_block(fn_value, "tailrec_loop");
+            self.builder.build_unconditional_branch(header).unwrap();
+            self.builder.position_at_end(header);
+            let mut params = vec![];
+            for (param, (name, ty)) in fn_value.get_param_iter().zip(&function.params) {
+                let phi = self.builder.build_phi(param.get_type(), name).unwrap();
+                phi.add_incoming(&[(&param, entry_bb)]);
+                self.local_vars.insert(name.clone(), phi.as_basic_value());
+                params.push((phi, ty.clone()));
+            }
+            tail_loop = Some(TailLoop { header, params, calls: recursive_calls(function).tail });
+        }
+        let outer_loop = std::mem::replace(&mut self.tail_loop, tail_loop);
+        
+        if self.debug_info.is_some() {
+            for (index, (name, ty)) in function.params.iter().enumerate() {
+                let value = self.compile_variable(name, fn_value)?;
+                self.declare_debug_variable(name, value, Some(ty), function.span, Some(index as u32 + 1), fn_value);
+            }
+        }
+
+        if self.check_contracts {
+            for clause in &function.requires {
+                self.compile_contract_check(clause, &format!("requires {}", clause), &function.name, fn_value)?;
+            }
+        }
 
         self.current_function_return_type = Some(function.return_type.clone());
-        let body_value = self.compile_expr(&function.body, fn_value)?;
+        let body_value = self.compile_expr_with_hint(&function.body, Some(&function.return_type), fn_value)?;
         self.current_function_return_type = None;
+        self.tail_loop = outer_loop;
+
+        if self.check_contracts && !function.ensures.is_empty() {
+            let shadowed = self.bind_local("result".to_string(), body_value, Some(function.return_type.clone()));
+            let checked = function.ensures.iter().try_for_each(|clause| {
+                self.compile_contract_check(clause, &format!("ensures {}", clause), &function.name, fn_value)
+            });
+            self.restore_bindings(vec![shadowed]);
+            checked?;
+        }
 
         // Check if return type is Unit
         let is_unit_return = matches!(&function.return_type, 
@@ -166,6 +696,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         } else {
             self.builder.build_return(Some(&body_value)).unwrap();
         }
+        self.end_debug_scope(fn_value, outer_debug_scope);
 
         if fn_value.verify(true) {
             Ok(fn_value)
@@ -174,21 +705,100 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         }
     }
 
+    // Continue in a new block if `condition` holds; otherwise report the
+    // violated `clause` of `function_name` and abort
+    fn compile_contract_check(
+        &mut self,
+        condition: &Expr,
+        clause: &str,
+        function_name: &str,
+        function: FunctionValue<'ctx>,
+    ) -> Result<(), String> {
+        let holds = self.compile_expr(condition, function)?.into_int_value();
+        let ok_bb = self.context.append_basic_block(function, "contract_ok");
+        let failed_bb = self.context.append_basic_block(function, "contract_failed");
+        self.builder.build_conditional_branch(holds, ok_bb, failed_bb).unwrap();
+        
+        self.builder.position_at_end(failed_bb);
+        let message = format!("Contract violation in '{}': {}\n", function_name, clause);
+        let text = self.builder.build_global_string_ptr(&message, "contract_message").unwrap();
+        let (write_fn, abort_fn) = self.ensure_write_abort();
+        self.builder
+            .build_call(
+                write_fn,
+                &[
+                    self.context.i32_type().const_int(2, false).into(),
+                    text.as_pointer_value().into(),
+                    self.size_type().const_int(message.len() as u64, false).into(),
+                ],
+                "write_result",
+            )
+            .unwrap();
+        self.builder.build_call(abort_fn, &[], "").unwrap();
+        self.builder.build_unreachable().unwrap();
+        
+        self.builder.position_at_end(ok_bb);
+        Ok(())
+    }
+
+    // `write(fd, buf, len)` and `abort()` from the C library
+    fn ensure_write_abort(&mut self) -> (FunctionValue<'ctx>, FunctionValue<'ctx>) {
+        let write_fn = self.module.get_function("write").unwrap_or_else(|| {
+            let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::default());
+            let size_type = self.size_type();
+            let write_type = size_type.fn_type(
+                &[self.context.i32_type().into(), i8_ptr_type.into(), size_type.into()],
+                false
+            );
+            self.module.add_function("write", write_type, None)
+        });
+        let abort_fn = self.module.get_function("abort").unwrap_or_else(|| {
+            let abort_type = self.context.void_type().fn_type(&[], false);
+            self.module.add_function("abort", abort_type, None)
+        });
+        (write_fn, abort_fn)
+    }
+
     fn compile_expr(
         &mut self,
         expr: &Expr,
         function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let outer_location = self.set_debug_location(expr.span());
+        let result = self.compile_expr_inner(expr, function);
+        self.restore_debug_location(outer_location);
+        // Errors propagate outwards, so the first span recorded is the innermost one
+        if result.is_err() && self.error_span.is_none() {
+            self.error_span = Some(expr.span());
+        }
+        result
+    }
+
+    fn compile_expr_inner(
+        &mut self,
+        expr: &Expr,
+        function: FunctionValue<'ctx>,
     ) -> Result<BasicValueEnum<'ctx>, String> {
         match expr {
             Expr::Literal(lit) => self.compile_literal(lit),
-            Expr::Variable(var) => self.compile_variable(&var.name, function),
+            Expr::Variable(var) => {
+                // A top-level function used as a value becomes a closure
+                if self.func_params.contains_key(&var.name) && !self.is_local_value(&var.name, function) {
+                    return self.compile_function_value(&var.name, function);
+                }
+                self.compile_variable(&var.name, function)
+            }
+            Expr::Lambda(lambda) => self.compile_lambda(lambda, None, function),
             Expr::BinaryOp(binop) => self.compile_binary_op(binop, function),
+            Expr::UnaryOp(unop) => self.compile_unary_op(unop, function),
             Expr::If(if_expr) => self.compile_if(if_expr, function),
             Expr::Match(match_expr) => self.compile_match(match_expr, function),
             Expr::Let(let_expr) => self.compile_let(let_expr, function),
             Expr::FieldAccess(field_access) => self.compile_field_access(field_access, function),
             Expr::Record(record_expr) => self.compile_record(record_expr, function),
+            Expr::Tuple(tuple) => self.compile_tuple(tuple, function),
             Expr::Constructor(constructor) => self.compile_constructor(constructor, function),
+            Expr::Application(app) if self.is_tail_call(app) => self.compile_tail_call(app, function),
             Expr::Application(app) => {
                 // Check if this is a builtin function or constructor
                 if let Expr::Variable(var) = &*app.func {
@@ -282,6 +892,19 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                     }
                 }
                 
+                // Calls through function values: function-typed locals and
+                // parameters, lambdas, and computed callees like f(1)(2)
+                let (callee, spine_args) = self.application_spine(app);
+                let is_closure_call = match callee {
+                    Expr::Variable(var) => self.is_local_value(&var.name, function),
+                    _ => true,
+                };
+                if is_closure_call {
+                    let closure_type = self.infer_value_type(callee)?;
+                    let closure = self.compile_expr(callee, function)?;
+                    return self.apply_closure(closure, closure_type, &spine_args, function);
+                }
+                
                 // Regular function call
                 // Collect all args from nested Applications
                 let (func_name, args) = self.flatten_application(app)?;
@@ -399,12 +1022,36 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                     return self.compile_hashmap_size(&args[0], function);
                 }
                 
+                if let Some((type_name, tag, fields)) = self.variant_constructor(&func_name) {
+                    // Rect: Int -> Int -> Shape
+                    if args.len() != fields.len() {
+                        return Err(format!(
+                            "Constructor '{}' expects {} arguments, got {}",
+                            func_name, fields.len(), args.len()
+                        ));
+                    }
+                    let values = args.iter()
+                        .zip(&fields)
+                        .map(|(arg, ty)| self.compile_expr_with_hint(arg, Some(ty), function))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    return Ok(self.build_variant(&type_name, tag, &fields, &values, function));
+                }
+                
+                let func_name = if self.generic_funcs.contains_key(&func_name) {
+                    self.instantiate_generic(&func_name, &args)?
+                } else {
+                    func_name
+                };
+                
                 // Check if this is an extern function (Pole name -> C name)
                 let is_extern = self.extern_func_mapping.contains_key(&func_name);
                 let actual_func_name = self.extern_func_mapping
                     .get(&func_name)
                     .cloned()
                     .unwrap_or_else(|| func_name.clone());
+                if is_extern {
+                    self.link_manifest.mark_used(&actual_func_name);
+                }
                 
                 let callee = self
                     .module
@@ -414,12 +1061,12 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                 // Check if function takes 0 parameters
                 // If so, and we have 1 arg that's Unit, skip it
                 let expected_params = callee.count_params();
-                let args_to_compile = if expected_params == 0 && args.len() == 1 {
+                let args_to_compile = if expected_params == 0 && !args.is_empty() {
                     // Check if the arg is Unit literal
                     if let Expr::Literal(lit) = &args[0] {
                         if matches!(lit.value, LiteralValue::Unit) {
                             // Skip Unit argument for 0-parameter functions
-                            vec![]
+                            args[1..].to_vec()
                         } else {
                             args.clone()
                         }
@@ -430,9 +1077,24 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                     args.clone()
                 };
                 
+                // Arguments beyond the callee's parameters apply the function
+                // value it returns
+                let param_count = callee.count_params() as usize;
+                let (args_to_compile, extra_args) = if !is_extern && args_to_compile.len() > param_count {
+                    let extra = args_to_compile[param_count..].to_vec();
+                    (args_to_compile[..param_count].to_vec(), extra)
+                } else {
+                    (args_to_compile, vec![])
+                };
+                
+                let param_hints: Vec<Type> = self.func_params
+                    .get(&func_name)
+                    .map(|params| params.iter().map(|(_, ty)| ty.clone()).collect())
+                    .unwrap_or_default();
                 let arg_values: Vec<BasicValueEnum> = args_to_compile
                     .iter()
-                    .map(|arg_expr| self.compile_expr(arg_expr, function))
+                    .enumerate()
+                    .map(|(i, arg_expr)| self.compile_expr_with_hint(arg_expr, param_hints.get(i), function))
                     .collect::<Result<Vec<_>, _>>()?;
 
                 // For extern functions, convert String arguments from {i8*, i64} to i8*
@@ -462,10 +1124,20 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                     .build_call(callee, &arg_metadata, "call")
                     .unwrap();
 
-                call_site
+                let result = call_site
                     .try_as_basic_value()
                     .left()
-                    .ok_or_else(|| format!("Function '{}' returned void", func_name))
+                    .ok_or_else(|| format!("Function '{}' returned void", func_name))?;
+                
+                if extra_args.is_empty() {
+                    Ok(result)
+                } else {
+                    let result_type = self.func_return_types
+                        .get(&func_name)
+                        .cloned()
+                        .ok_or_else(|| format!("Function '{}' does not return a function", func_name))?;
+                    self.apply_closure(result, result_type, &extra_args, function)
+                }
             }
             _ => Err(format!("Unsupported expression: {:?}", expr)),
         }
@@ -572,13 +1244,39 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
             .unwrap()
             .into_pointer_value();
         
-        if with_newline {
-            let puts_fn = self.module.get_function("puts").unwrap();
+        if self.target.is_wasi() {
+            // Written to stdout (fd 1) with `write`, which the WASI runtime defines
+            let length = self.builder
+                .build_extract_value(string_struct, 1, "string_len")
+                .unwrap()
+                .into_int_value();
+            let (write_fn, _) = self.ensure_write_abort();
+            let stdout = self.context.i32_type().const_int(1, false);
+            self.builder
+                .build_call(write_fn, &[stdout.into(), string_ptr.into(), self.size_arg(write_fn, 2, length).into()], "write_result")
+                .unwrap();
+            if with_newline {
+                let newline = self.builder.build_global_string_ptr("\n", "newline").unwrap();
+                let one = self.size_type().const_int(1, false);
+                self.builder
+                    .build_call(write_fn, &[stdout.into(), newline.as_pointer_value().into(), one.into()], "write_result")
+                    .unwrap();
+            }
+        } else if with_newline {
+            let puts_fn = self.module.get_function("puts").unwrap_or_else(|| {
+                let i8_ptr_type = self.context.ptr_type(inkwell::AddressSpace::default());
+                let puts_type = self.context.i32_type().fn_type(&[i8_ptr_type.into()], false);
+                self.module.add_function("puts", puts_type, None)
+            });
             self.builder
                 .build_call(puts_fn, &[string_ptr.into()], "puts_result")
                 .unwrap();
         } else {
-            let printf_fn = self.module.get_function("printf").unwrap();
+            let printf_fn = self.module.get_function("printf").unwrap_or_else(|| {
+                let i8_ptr_type = self.context.ptr_type(inkwell::AddressSpace::default());
+                let printf_type = self.context.i32_type().fn_type(&[i8_ptr_type.into()], true);
+                self.module.add_function("printf", printf_type, None)
+            });
             let format_string = self.builder.build_global_string_ptr("%s", "fmt").unwrap();
             self.builder
                 .build_call(printf_fn, &[format_string.as_pointer_value().into(), string_ptr.into()], "printf_result")
@@ -589,21 +1287,32 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         Ok(i8_type.const_int(0, false).into())
     }
 
+    // `size_t` of the target
+    fn size_type(&self) -> IntType<'ctx> {
+        self.context.custom_width_int_type(self.pointer_bits)
+    }
+
+    // An i64 size or length as the `size_t` argument `index` of `callee`,
+    // at the width the callee was declared with: a program's own
+    // `@extern("malloc")` takes an Int whatever the target
+    fn size_arg(&self, callee: FunctionValue<'ctx>, index: usize, size: IntValue<'ctx>) -> IntValue<'ctx> {
+        let param_type = callee.get_type().get_param_types()[index].into_int_type();
+        self.builder.build_int_cast_sign_flag(size, param_type, false, "size").unwrap()
+    }
+
     fn ensure_malloc_memcpy(&mut self) {
         // Ensure malloc is declared
         if self.module.get_function("malloc").is_none() {
-            let i64_type = self.context.i64_type();
             let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::default());
-            let malloc_type = i8_ptr_type.fn_type(&[i64_type.into()], false);
+            let malloc_type = i8_ptr_type.fn_type(&[self.size_type().into()], false);
             self.module.add_function("malloc", malloc_type, None);
         }
         
         // Ensure memcpy is declared
         if self.module.get_function("memcpy").is_none() {
             let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::default());
-            let i64_type = self.context.i64_type();
             let memcpy_type = i8_ptr_type.fn_type(
-                &[i8_ptr_type.into(), i8_ptr_type.into(), i64_type.into()],
+                &[i8_ptr_type.into(), i8_ptr_type.into(), self.size_type().into()],
                 false
             );
             self.module.add_function("memcpy", memcpy_type, None);
@@ -637,10 +1346,10 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         let i32_ptr_type = i32_type.ptr_type(inkwell::AddressSpace::default());
         
         // Step 1: Calculate total length
-        let total_len_ptr = self.builder.build_alloca(i64_type, "total_len").unwrap();
+        let total_len_ptr = self.build_entry_alloca(function, i64_type.into(), "total_len");
         self.builder.build_store(total_len_ptr, i64_type.const_zero()).unwrap();
         
-        let index_ptr = self.builder.build_alloca(i64_type, "i").unwrap();
+        let index_ptr = self.build_entry_alloca(function, i64_type.into(), "i");
         self.builder.build_store(index_ptr, i64_type.const_zero()).unwrap();
         
         let calc_loop = self.context.append_basic_block(function, "calc_loop");
@@ -703,7 +1412,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         
         let malloc_fn = self.module.get_function("malloc").unwrap();
         let result_ptr_i8 = self.builder
-            .build_call(malloc_fn, &[malloc_size.into()], "result_ptr_i8")
+            .build_call(malloc_fn, &[self.size_arg(malloc_fn, 0, malloc_size).into()], "result_ptr_i8")
             .unwrap()
             .try_as_basic_value()
             .left()
@@ -717,7 +1426,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         ).unwrap();
         
         // Step 2: Copy elements
-        let offset_ptr = self.builder.build_alloca(i64_type, "offset").unwrap();
+        let offset_ptr = self.build_entry_alloca(function, i64_type.into(), "offset");
         self.builder.build_store(offset_ptr, i64_type.const_zero()).unwrap();
         self.builder.build_store(index_ptr, i64_type.const_zero()).unwrap();
         
@@ -786,7 +1495,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         
         self.builder.build_call(
             memcpy_fn,
-            &[dest_i8.into(), src_i8.into(), copy_size.into()],
+            &[dest_i8.into(), src_i8.into(), self.size_arg(memcpy_fn, 2, copy_size).into()],
             ""
         ).unwrap();
         
@@ -878,7 +1587,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         } else {
             return Err("List.get: unsupported element type".to_string());
         };
-        let invalid_result = self.builder.build_alloca(element_type, "invalid_result").unwrap();
+        let invalid_result = self.build_entry_alloca(function, element_type, "invalid_result");
         self.builder.build_store(invalid_result, default_val).unwrap();
         let invalid_val = self.builder.build_load(element_type, invalid_result, "invalid_val").unwrap();
         let invalid_bb_end = self.builder.get_insert_block().unwrap();
@@ -1033,7 +1742,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         
         let malloc_fn = self.module.get_function("malloc").unwrap();
         let new_ptr_i8 = self.builder
-            .build_call(malloc_fn, &[total_size.into()], "new_ptr_i8")
+            .build_call(malloc_fn, &[self.size_arg(malloc_fn, 0, total_size).into()], "new_ptr_i8")
             .unwrap()
             .try_as_basic_value()
             .left()
@@ -1054,7 +1763,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         
         self.builder.build_call(
             memcpy_fn,
-            &[dest_i8.into(), src_i8.into(), total_size.into()],
+            &[dest_i8.into(), src_i8.into(), self.size_arg(memcpy_fn, 2, total_size).into()],
             ""
         ).unwrap();
         
@@ -1142,7 +1851,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         
         let malloc_fn = self.module.get_function("malloc").unwrap();
         let new_ptr_i8 = self.builder
-            .build_call(malloc_fn, &[total_size.into()], "new_ptr_i8")
+            .build_call(malloc_fn, &[self.size_arg(malloc_fn, 0, total_size).into()], "new_ptr_i8")
             .unwrap()
             .try_as_basic_value()
             .left()
@@ -1164,7 +1873,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         
         self.builder.build_call(
             memcpy_fn,
-            &[dest_i8.into(), src_i8.into(), old_size.into()],
+            &[dest_i8.into(), src_i8.into(), self.size_arg(memcpy_fn, 2, old_size).into()],
             ""
         ).unwrap();
         
@@ -1228,7 +1937,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         // Allocate buckets array
         let malloc_fn = self.module.get_function("malloc").unwrap();
         let buckets_ptr = self.builder
-            .build_call(malloc_fn, &[total_size.into()], "buckets_ptr")
+            .build_call(malloc_fn, &[self.size_arg(malloc_fn, 0, total_size).into()], "buckets_ptr")
             .unwrap()
             .try_as_basic_value()
             .left()
@@ -1242,7 +1951,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
             let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::default());
             let i32_type = self.context.i32_type();
             let memset_type = self.context.void_type().fn_type(
-                &[i8_ptr_type.into(), i32_type.into(), i64_type.into()],
+                &[i8_ptr_type.into(), i32_type.into(), self.size_type().into()],
                 false
             );
             self.module.add_function("memset", memset_type, None)
@@ -1254,7 +1963,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
             &[
                 buckets_ptr.into(),
                 i32_type.const_int(0, false).into(),
-                total_size.into()
+                self.size_arg(memset_fn, 2, total_size).into()
             ],
             "memset_buckets"
         ).unwrap();
@@ -1461,36 +2170,17 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
             }
         }
         
-        // Check if it's a variant constructor
-        for (_variant_name, constructors) in &self.variant_defs {
-            for (idx, (ctor_name, ctor_args)) in constructors.iter().enumerate() {
-                if ctor_name == name && ctor_args.is_empty() {
-                    // Simple enum constructor (no arguments)
-                    // Represent as i32 tag
-                    let i32_type = self.context.i32_type();
-                    let tag_value = i32_type.const_int(idx as u64, false);
-                    return Ok(tag_value.into());
-                }
+        // Check if it's a variant constructor without arguments
+        if let Some((type_name, tag, fields)) = self.variant_constructor(name) {
+            if fields.is_empty() {
+                return Ok(self.build_variant(&type_name, tag, &fields, &[], function));
             }
         }
         
         // Check if it's None (Option type)
         if name == "None" {
             if let Some(Type::Option(option_type)) = &self.current_function_return_type {
-                // None -> { i32 0, T undef }
-                let i32_type = self.context.i32_type();
-                let tag = i32_type.const_int(0, false);
-                let inner_type = self.compile_type(&option_type.inner);
-                
-                let option_struct_type = self.context.struct_type(
-                    &[i32_type.into(), inner_type],
-                    false
-                );
-                
-                let mut option_val = option_struct_type.get_undef();
-                option_val = self.builder.build_insert_value(option_val, tag, 0, "tag").unwrap().into_struct_value();
-                
-                return Ok(option_val.into());
+                return Ok(self.build_none(option_type));
             }
         }
         
@@ -1503,6 +2193,35 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         Err(format!("Variable '{}' not found", name))
     }
 
+    // None -> { i32 0, T undef }
+    fn build_none(&self, option_type: &OptionType) -> BasicValueEnum<'ctx> {
+        let i32_type = self.context.i32_type();
+        let tag = i32_type.const_int(0, false);
+        let inner_type = self.compile_type(&option_type.inner);
+        
+        let option_struct_type = self.context.struct_type(
+            &[i32_type.into(), inner_type],
+            false
+        );
+        
+        let option_val = option_struct_type.get_undef();
+        self.builder.build_insert_value(option_val, tag, 0, "tag").unwrap().into_struct_value().into()
+    }
+
+    // Operands might be i64 (from comparisons) or i1 (from booleans);
+    // convert to i1 if needed
+    fn to_bool(&self, value: BasicValueEnum<'ctx>) -> Result<IntValue<'ctx>, String> {
+        if !value.is_int_value() {
+            return Err(format!("Expected int value for boolean operator, got {:?}", value));
+        }
+        let int = value.into_int_value();
+        if int.get_type().get_bit_width() == 1 {
+            return Ok(int);
+        }
+        // Convert i64 to i1: x != 0
+        Ok(self.builder.build_int_compare(IntPredicate::NE, int, int.get_type().const_zero(), "to_bool").unwrap())
+    }
+
     fn compile_binary_op(
         &mut self,
         binop: &BinaryOp,
@@ -1513,51 +2232,42 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         if binop.op == "&&" || binop.op == "||" {
             let lhs = self.compile_expr(&binop.left, function)?;
             let rhs = self.compile_expr(&binop.right, function)?;
-            
-            // Operands might be i64 (from comparisons) or i1 (from booleans)
-            // Convert to i1 if needed
-            let lhs_bool = if lhs.is_int_value() {
-                let lhs_int = lhs.into_int_value();
-                if lhs_int.get_type().get_bit_width() == 1 {
-                    lhs_int
-                } else {
-                    // Convert i64 to i1: x != 0
-                    self.builder.build_int_compare(
-                        IntPredicate::NE,
-                        lhs_int,
-                        lhs_int.get_type().const_zero(),
-                        "to_bool"
-                    ).unwrap()
-                }
-            } else {
-                return Err(format!("Expected int value for boolean operator, got {:?}", lhs));
-            };
-            
-            let rhs_bool = if rhs.is_int_value() {
-                let rhs_int = rhs.into_int_value();
-                if rhs_int.get_type().get_bit_width() == 1 {
-                    rhs_int
-                } else {
-                    self.builder.build_int_compare(
-                        IntPredicate::NE,
-                        rhs_int,
-                        rhs_int.get_type().const_zero(),
-                        "to_bool"
-                    ).unwrap()
-                }
-            } else {
-                return Err(format!("Expected int value for boolean operator, got {:?}", rhs));
-            };
+            let lhs_bool = self.to_bool(lhs)?;
+            let rhs_bool = self.to_bool(rhs)?;
             
             let result = match binop.op.as_str() {
                 "&&" => self.builder.build_and(lhs_bool, rhs_bool, "and").unwrap(),
-                "||" => self.builder.build_or(lhs_bool, rhs_bool, "or").unwrap(),
-                _ => unreachable!(),
+                _ => self.builder.build_or(lhs_bool, rhs_bool, "or").unwrap(),
             };
             
             return Ok(result.into());
         }
         
+        // `a => b` only evaluates `b` when `a` holds, so `b != 0 => a / b > 0`
+        // never divides by zero
+        if binop.op == "=>" {
+            let lhs = self.compile_expr(&binop.left, function)?;
+            let lhs_bool = self.to_bool(lhs)?;
+            let lhs_end = self.builder.get_insert_block().unwrap();
+            
+            let rhs_bb = self.context.append_basic_block(function, "implies_rhs");
+            let merge_bb = self.context.append_basic_block(function, "implies_merge");
+            self.builder.build_conditional_branch(lhs_bool, rhs_bb, merge_bb).unwrap();
+            
+            self.builder.position_at_end(rhs_bb);
+            let rhs = self.compile_expr(&binop.right, function)?;
+            let rhs_bool = self.to_bool(rhs)?;
+            self.builder.build_unconditional_branch(merge_bb).unwrap();
+            let rhs_end = self.builder.get_insert_block().unwrap();
+            
+            self.builder.position_at_end(merge_bb);
+            let bool_type = self.context.bool_type();
+            let phi = self.builder.build_phi(bool_type, "implies").unwrap();
+            phi.add_incoming(&[(&bool_type.const_int(1, false), lhs_end), (&rhs_bool, rhs_end)]);
+            
+            return Ok(phi.as_basic_value());
+        }
+        
         // For other operators, check if operands are floats or ints
         let lhs = self.compile_expr(&binop.left, function)?;
         let rhs = self.compile_expr(&binop.right, function)?;
@@ -1590,6 +2300,7 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                 "-" => self.builder.build_float_sub(lhs_float, rhs_float, "fsub").unwrap().into(),
                 "*" => self.builder.build_float_mul(lhs_float, rhs_float, "fmul").unwrap().into(),
                 "/" => self.builder.build_float_div(lhs_float, rhs_float, "fdiv").unwrap().into(),
+                "%" => self.builder.build_float_rem(lhs_float, rhs_float, "frem").unwrap().into(),
                 "==" => self
                     .builder
                     .build_float_compare(FloatPredicate::OEQ, lhs_float, rhs_float, "feq")
@@ -1629,6 +2340,12 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
             "-" => self.builder.build_int_sub(lhs, rhs, "sub").unwrap(),
             "*" => self.builder.build_int_mul(lhs, rhs, "mul").unwrap(),
             "/" => self.builder.build_int_signed_div(lhs, rhs, "div").unwrap(),
+            "%" => self.builder.build_int_signed_rem(lhs, rhs, "rem").unwrap(),
+            "&" => self.builder.build_and(lhs, rhs, "bitand").unwrap(),
+            "|" => self.builder.build_or(lhs, rhs, "bitor").unwrap(),
+            "^" => self.builder.build_xor(lhs, rhs, "bitxor").unwrap(),
+            "<<" => self.builder.build_left_shift(lhs, rhs, "shl").unwrap(),
+            ">>" => self.builder.build_right_shift(lhs, rhs, true, "shr").unwrap(),
             "==" => self
                 .builder
                 .build_int_compare(IntPredicate::EQ, lhs, rhs, "eq")
@@ -1659,6 +2376,25 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         Ok(result.into())
     }
 
+    fn compile_unary_op(
+        &mut self,
+        unop: &UnaryOp,
+        function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let operand = self.compile_expr(&unop.operand, function)?;
+        
+        let result: BasicValueEnum = match unop.op.as_str() {
+            "-" if operand.is_float_value() => {
+                self.builder.build_float_neg(operand.into_float_value(), "fneg").unwrap().into()
+            }
+            "-" => self.builder.build_int_neg(operand.into_int_value(), "neg").unwrap().into(),
+            "not" => self.builder.build_not(operand.into_int_value(), "not").unwrap().into(),
+            _ => return Err(format!("Unsupported unary operator: {}", unop.op)),
+        };
+        
+        Ok(result)
+    }
+
     fn compile_if(
         &mut self,
         if_expr: &IfExpr,
@@ -1694,228 +2430,39 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         Ok(phi.as_basic_value())
     }
 
-    fn compile_match(
+    fn is_tail_call(&self, app: &Application) -> bool {
+        self.tail_loop.as_ref().is_some_and(|tail_loop| tail_loop.calls.contains(&app.span))
+    }
+
+    // Jump back to the loop header of the @tailrec function with the call's
+    // arguments. Nothing follows the jump, so the value left for enclosing
+    // expressions is a placeholder in a block no branch reaches.
+    fn compile_tail_call(
         &mut self,
-        match_expr: &MatchExpr,
+        app: &Application,
         function: FunctionValue<'ctx>,
     ) -> Result<BasicValueEnum<'ctx>, String> {
-        let arms = &match_expr.arms;
+        let (header, params) = match &self.tail_loop {
+            Some(tail_loop) => (tail_loop.header, tail_loop.params.clone()),
+            None => return Err("Tail call outside a @tailrec function".to_string()),
+        };
+        let (_, args) = self.application_spine(app);
+        let mut values = vec![];
+        for (arg, (_, ty)) in args.iter().zip(&params) {
+            values.push(self.compile_expr_with_hint(arg, Some(ty), function)?);
+        }
         
-        if arms.is_empty() {
-            return Err("Match expression must have at least one arm".to_string());
-        }
-
-        let scrutinee_value = self.compile_expr(&match_expr.scrutinee, function)?;
-
-        if arms.len() == 1 {
-            return self.compile_expr(&arms[0].1, function);
-        }
-
-        let (first_pattern, first_expr) = &arms[0];
-        let rest_arms = &arms[1..];
-
-        match first_pattern {
-            Pattern::Literal(lit) => {
-                if let LiteralValue::Int(n) = &lit.value {
-                    let match_bb = self.context.append_basic_block(function, "match_case");
-                    let next_bb = self.context.append_basic_block(function, "match_next");
-
-                    let scrutinee_int = scrutinee_value.into_int_value();
-                    let pattern_value = self.context.i64_type().const_int(*n as u64, true);
-                    let cond = self
-                        .builder
-                        .build_int_compare(IntPredicate::EQ, scrutinee_int, pattern_value, "cond")
-                        .unwrap();
-
-                    self.builder
-                        .build_conditional_branch(cond, match_bb, next_bb)
-                        .unwrap();
-
-                    self.builder.position_at_end(match_bb);
-                    let match_value = self.compile_expr(first_expr, function)?;
-                    let merge_bb = self.context.append_basic_block(function, "match_merge");
-                    self.builder.build_unconditional_branch(merge_bb).unwrap();
-                    let match_bb_end = self.builder.get_insert_block().unwrap();
-
-                    self.builder.position_at_end(next_bb);
-                    let rest_match = MatchExpr {
-                        scrutinee: match_expr.scrutinee.clone(),
-                        arms: rest_arms.to_vec(),
-                    };
-                    let next_value = self.compile_match(&rest_match, function)?;
-                    self.builder.build_unconditional_branch(merge_bb).unwrap();
-                    let next_bb_end = self.builder.get_insert_block().unwrap();
-
-                    self.builder.position_at_end(merge_bb);
-                    let phi = self
-                        .builder
-                        .build_phi(match_value.get_type(), "match_result")
-                        .unwrap();
-                    phi.add_incoming(&[(&match_value, match_bb_end), (&next_value, next_bb_end)]);
-
-                    Ok(phi.as_basic_value())
-                } else {
-                    Err(format!("Unsupported pattern literal: {:?}", lit))
-                }
-            }
-            Pattern::Variable(_) => {
-                self.compile_expr(first_expr, function)
-            }
-            Pattern::Constructor(ctor_pattern) => {
-                // Handle Option/Result constructor patterns
-                match ctor_pattern.name.as_str() {
-                    "Some" | "Ok" => {
-                        // Extract tag from { i32 tag, T value }
-                        let scrutinee_struct = scrutinee_value.into_struct_value();
-                        let tag = self.builder.build_extract_value(scrutinee_struct, 0, "tag")
-                            .unwrap().into_int_value();
-                        
-                        // Check if tag == 1 (Some/Ok)
-                        let tag_one = self.context.i32_type().const_int(1, false);
-                        let is_some = self.builder.build_int_compare(
-                            IntPredicate::EQ, tag, tag_one, "is_some"
-                        ).unwrap();
-                        
-                        let match_bb = self.context.append_basic_block(function, "match_some");
-                        let next_bb = self.context.append_basic_block(function, "match_next");
-                        
-                        self.builder.build_conditional_branch(is_some, match_bb, next_bb).unwrap();
-                        
-                        // Some/Ok branch
-                        self.builder.position_at_end(match_bb);
-                        
-                        // Extract value and bind to pattern variable
-                        if let Some(Pattern::Variable(var_pattern)) = ctor_pattern.args.first() {
-                            let value = self.builder.build_extract_value(scrutinee_struct, 1, "value")
-                                .unwrap();
-                            
-                            let old_var = self.local_vars.insert(var_pattern.name.clone(), value);
-                            let match_value = self.compile_expr(first_expr, function)?;
-                            
-                            // Restore old variable binding
-                            if let Some(old) = old_var {
-                                self.local_vars.insert(var_pattern.name.clone(), old);
-                            } else {
-                                self.local_vars.remove(&var_pattern.name);
-                            }
-                            
-                            let merge_bb = self.context.append_basic_block(function, "match_merge");
-                            self.builder.build_unconditional_branch(merge_bb).unwrap();
-                            let match_bb_end = self.builder.get_insert_block().unwrap();
-                            
-                            // None/Err branch
-                            self.builder.position_at_end(next_bb);
-                            let rest_match = MatchExpr {
-                                scrutinee: match_expr.scrutinee.clone(),
-                                arms: rest_arms.to_vec(),
-                            };
-                            let next_value = self.compile_match(&rest_match, function)?;
-                            self.builder.build_unconditional_branch(merge_bb).unwrap();
-                            let next_bb_end = self.builder.get_insert_block().unwrap();
-                            
-                            self.builder.position_at_end(merge_bb);
-                            let phi = self.builder.build_phi(match_value.get_type(), "match_result").unwrap();
-                            phi.add_incoming(&[(&match_value, match_bb_end), (&next_value, next_bb_end)]);
-                            
-                            Ok(phi.as_basic_value())
-                        } else {
-                            Err("Some/Ok pattern must have exactly one variable argument".to_string())
-                        }
-                    }
-                    "None" | "Err" => {
-                        // Extract tag from { i32 tag, T value }
-                        let scrutinee_struct = scrutinee_value.into_struct_value();
-                        let tag = self.builder.build_extract_value(scrutinee_struct, 0, "tag")
-                            .unwrap().into_int_value();
-                        
-                        let expected_tag = if ctor_pattern.name == "None" { 0 } else { 0 };
-                        let tag_value = self.context.i32_type().const_int(expected_tag, false);
-                        let is_match = self.builder.build_int_compare(
-                            IntPredicate::EQ, tag, tag_value, "is_none"
-                        ).unwrap();
-                        
-                        let match_bb = self.context.append_basic_block(function, "match_none");
-                        let next_bb = self.context.append_basic_block(function, "match_next");
-                        
-                        self.builder.build_conditional_branch(is_match, match_bb, next_bb).unwrap();
-                        
-                        self.builder.position_at_end(match_bb);
-                        let match_value = self.compile_expr(first_expr, function)?;
-                        let merge_bb = self.context.append_basic_block(function, "match_merge");
-                        self.builder.build_unconditional_branch(merge_bb).unwrap();
-                        let match_bb_end = self.builder.get_insert_block().unwrap();
-                        
-                        self.builder.position_at_end(next_bb);
-                        let rest_match = MatchExpr {
-                            scrutinee: match_expr.scrutinee.clone(),
-                            arms: rest_arms.to_vec(),
-                        };
-                        let next_value = self.compile_match(&rest_match, function)?;
-                        self.builder.build_unconditional_branch(merge_bb).unwrap();
-                        let next_bb_end = self.builder.get_insert_block().unwrap();
-                        
-                        self.builder.position_at_end(merge_bb);
-                        let phi = self.builder.build_phi(match_value.get_type(), "match_result").unwrap();
-                        phi.add_incoming(&[(&match_value, match_bb_end), (&next_value, next_bb_end)]);
-                        
-                        Ok(phi.as_basic_value())
-                    }
-                    _ => {
-                        // Check if it's a variant constructor (like North, South, etc.)
-                        let mut found_variant = None;
-                        for (variant_name, constructors) in &self.variant_defs {
-                            for (idx, (ctor_name, ctor_args)) in constructors.iter().enumerate() {
-                                if ctor_name == &ctor_pattern.name && ctor_args.is_empty() {
-                                    found_variant = Some((variant_name.clone(), idx));
-                                    break;
-                                }
-                            }
-                            if found_variant.is_some() {
-                                break;
-                            }
-                        }
-                        
-                        if let Some((_variant_name, tag_idx)) = found_variant {
-                            // Variant constructor: scrutinee is i32 tag value
-                            let scrutinee_int = scrutinee_value.into_int_value();
-                            let expected_tag = self.context.i32_type().const_int(tag_idx as u64, false);
-                            let is_match = self.builder.build_int_compare(
-                                IntPredicate::EQ, scrutinee_int, expected_tag, "is_variant_match"
-                            ).unwrap();
-                            
-                            let match_bb = self.context.append_basic_block(function, "match_variant");
-                            let next_bb = self.context.append_basic_block(function, "match_next");
-                            
-                            self.builder.build_conditional_branch(is_match, match_bb, next_bb).unwrap();
-                            
-                            self.builder.position_at_end(match_bb);
-                            let match_value = self.compile_expr(first_expr, function)?;
-                            let merge_bb = self.context.append_basic_block(function, "match_merge");
-                            self.builder.build_unconditional_branch(merge_bb).unwrap();
-                            let match_bb_end = self.builder.get_insert_block().unwrap();
-                            
-                            self.builder.position_at_end(next_bb);
-                            let rest_match = MatchExpr {
-                                scrutinee: match_expr.scrutinee.clone(),
-                                arms: rest_arms.to_vec(),
-                            };
-                            let next_value = self.compile_match(&rest_match, function)?;
-                            self.builder.build_unconditional_branch(merge_bb).unwrap();
-                            let next_bb_end = self.builder.get_insert_block().unwrap();
-                            
-                            self.builder.position_at_end(merge_bb);
-                            let phi = self.builder.build_phi(match_value.get_type(), "match_result").unwrap();
-                            phi.add_incoming(&[(&match_value, match_bb_end), (&next_value, next_bb_end)]);
-                            
-                            Ok(phi.as_basic_value())
-                        } else {
-                            Err(format!("Unsupported constructor pattern: {}", ctor_pattern.name))
-                        }
-                    }
-                }
-            }
-            _ => Err(format!("Unsupported pattern: {:?}", first_pattern)),
+        let block = self.builder.get_insert_block().unwrap();
+        for ((phi, _), value) in params.iter().zip(&values) {
+            phi.add_incoming(&[(value, block)]);
         }
+        self.builder.build_unconditional_branch(header).unwrap();
+        
+        let after_bb = self.context.append_basic_block(function, "after_tail_call");
+        self.builder.position_at_end(after_bb);
+        let return_type = function.get_type().get_return_type()
+            .ok_or_else(|| "Tail call in a function without a return value".to_string())?;
+        Ok(return_type.const_zero())
     }
 
     // Helper to flatten curried function applications
@@ -1973,8 +2520,10 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                             .map(|(_, field_ty)| self.compile_type(field_ty))
                             .collect();
                         self.context.struct_type(&field_types, false).into()
+                    } else if self.variant_has_payload(type_name) {
+                        self.variant_struct_type(type_name).into()
                     } else if self.variant_defs.contains_key(type_name) {
-                        // Variant types are represented as i32 tag (for simple enums)
+                        // Variant types without payloads are represented as an i32 tag
                         self.context.i32_type().into()
                     } else {
                         panic!("Unsupported basic type: {}", name)
@@ -2003,13 +2552,13 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                 let ok_type = self.compile_type(&result_type.ok_type);
                 let err_type = self.compile_type(&result_type.err_type);
                 
-                // Use the larger type for the union
-                let ok_size = ok_type.size_of().unwrap();
-                let err_size = err_type.size_of().unwrap();
-                let value_type = if ok_size.get_zero_extended_constant().unwrap() >= err_size.get_zero_extended_constant().unwrap() {
-                    ok_type
-                } else {
-                    err_type
+                // Use the larger type for the union. `size_of` only folds to
+                // a number for scalars, so sizes come from the target's
+                // layout; a target that cannot be created fails later anyway
+                let target_data = self.target_machine().map(|machine| machine.get_target_data());
+                let value_type = match target_data {
+                    Ok(data) if data.get_abi_size(&err_type) > data.get_abi_size(&ok_type) => err_type,
+                    _ => ok_type,
                 };
                 
                 self.context.struct_type(&[i32_type.into(), value_type], false).into()
@@ -2019,6 +2568,14 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                 let pointee_type = self.compile_type(&pointer_type.pointee_type);
                 pointee_type.ptr_type(inkwell::AddressSpace::default()).into()
             }
+            Type::Function(_) => self.closure_struct_type().into(),
+            Type::Tuple(tuple_type) => {
+                // (A, B) = { A, B }, an anonymous struct
+                let element_types: Vec<BasicTypeEnum> = tuple_type.element_types.iter()
+                    .map(|element_type| self.compile_type(element_type))
+                    .collect();
+                self.context.struct_type(&element_types, false).into()
+            }
             _ => panic!("Unsupported type: {:?}", ty),
         }
     }
@@ -2034,7 +2591,8 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         let (old_value, old_type) = if let_expr.var_name == "_" {
             (None, None)
         } else {
-            let value_type = self.infer_expr_type(&let_expr.value)?;
+            let value_type = self.infer_value_type(&let_expr.value)?;
+            self.declare_debug_variable(&let_expr.var_name, value, Some(&value_type), let_expr.span, None, function);
             let old_value = self.local_vars.insert(let_expr.var_name.clone(), value);
             let old_type = self.var_types.insert(let_expr.var_name.clone(), value_type);
             (old_value, old_type)
@@ -2059,103 +2617,1207 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         Ok(body_result)
     }
     
-    fn infer_expr_type(&self, expr: &Expr) -> Result<Type, String> {
+    // ========================================================================
+    // Closures
+    // ========================================================================
+    //
+    // A function value is a closure struct { code: i8*, env: i8* }. Closures
+    // are curried: `code` takes the environment and exactly one argument, so
+    // `\x y -> e` compiles as `\x -> \y -> e`. The environment is a malloc'd
+    // struct of the captured values, or null when nothing is captured.
+
+    fn closure_struct_type(&self) -> StructType<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::default());
+        self.context.struct_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false)
+    }
+
+    // LLVM type of the code pointer of a closure with type `ft`
+    fn closure_code_type(&self, ft: &FunctionType) -> inkwell::types::FunctionType<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::default());
+        let param_type = self.compile_type(&ft.param_type);
+        self.compile_type(&ft.return_type).fn_type(&[i8_ptr_type.into(), param_type.into()], false)
+    }
+
+    // Whether `name` is a let binding, pattern variable or parameter in scope
+    // (as opposed to a top-level function)
+    fn is_local_value(&self, name: &str, function: FunctionValue<'ctx>) -> bool {
+        self.local_vars.contains_key(name)
+            || function
+                .get_param_iter()
+                .any(|param| matches!(param.get_name().to_str(), Ok(param_name) if param_name == name))
+    }
+
+    // Callee and arguments of a curried application `f(a)(b)`
+    fn application_spine<'e>(&self, app: &'e Application) -> (&'e Expr, Vec<Expr>) {
+        let mut args = vec![(*app.arg).clone()];
+        let mut callee = &*app.func;
+        while let Expr::Application(inner) = callee {
+            args.push((*inner.arg).clone());
+            callee = &*inner.func;
+        }
+        args.reverse();
+        (callee, args)
+    }
+
+    // Like `infer_expr_type`, but also types lambdas and lambdas applied
+    // directly, as in `(\(x: Int) -> x + 1)(2)`
+    fn infer_value_type(&mut self, expr: &Expr) -> Result<Type, String> {
         match expr {
-            Expr::Literal(lit) => match &lit.value {
-                LiteralValue::Int(_) => Ok(Type::Basic(AstBasicType { name: "Int".to_string() })),
-                LiteralValue::Bool(_) => Ok(Type::Basic(AstBasicType { name: "Bool".to_string() })),
-                LiteralValue::Float(_) => Ok(Type::Basic(AstBasicType { name: "Float64".to_string() })),
-                LiteralValue::String(_) => Ok(Type::Basic(AstBasicType { name: "String".to_string() })),
-                LiteralValue::Unit => Ok(Type::Basic(AstBasicType { name: "Unit".to_string() })),
-            },
-            Expr::Variable(var) => {
-                // First check if it's a regular variable
-                if let Some(ty) = self.var_types.get(&var.name) {
-                    return Ok(ty.clone());
+            Expr::Lambda(lambda) => Ok(Type::Function(self.lambda_type(lambda, None)?)),
+            Expr::Application(app) => match self.application_spine(app) {
+                (Expr::Lambda(lambda), args) => {
+                    let lambda_type = Type::Function(self.lambda_type(lambda, None)?);
+                    peel_function_type(lambda_type, args.len())
                 }
-                
-                // Check if it's a variant constructor
-                for (variant_name, constructors) in &self.variant_defs {
-                    for (ctor_name, ctor_args) in constructors {
-                        if ctor_name == &var.name && ctor_args.is_empty() {
-                            // Nullary constructor - return the variant type
-                            return Ok(Type::Basic(AstBasicType { name: variant_name.clone() }));
-                        }
-                    }
+                _ => self.infer_expr_type(expr),
+            },
+            _ => self.infer_expr_type(expr),
+        }
+    }
+
+    fn compile_expr_with_hint(
+        &mut self,
+        expr: &Expr,
+        hint: Option<&Type>,
+        function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        match expr {
+            Expr::Lambda(lambda) => {
+                let result = self.compile_lambda(lambda, hint, function);
+                if result.is_err() && self.error_span.is_none() {
+                    self.error_span = Some(lambda.span);
                 }
-                
-                Err(format!("Cannot find type for variable '{}'", var.name))
+                result
             }
-            Expr::BinaryOp(binop) => self.infer_expr_type(&binop.left),
-            Expr::FieldAccess(field_access) => {
-                let record_type = self.infer_expr_type(&field_access.record)?;
-                let type_name = if let Type::Basic(AstBasicType { name }) = record_type {
-                    name
-                } else {
-                    return Err("Expected basic type for record".to_string());
-                };
-                
-                let record_def = self.type_defs.get(&type_name)
-                    .ok_or_else(|| format!("Record type '{}' not found", type_name))?;
-                
-                record_def.fields.iter()
-                    .find(|(name, _)| name == &field_access.field)
-                    .map(|(_, ty)| ty.clone())
-                    .ok_or_else(|| format!("Field '{}' not found", field_access.field))
+            // `f(None)` takes the Option type of f's parameter
+            Expr::Variable(var) if var.name == "None" && !self.local_vars.contains_key("None") => match hint {
+                Some(Type::Option(option_type)) => Ok(self.build_none(option_type)),
+                _ => self.compile_expr(expr, function),
             },
-            Expr::Application(app) => {
-                // Check for builtin functions and extern functions
-                if let Expr::Variable(var) = &*app.func {
-                    match var.name.as_str() {
-                        "String_length" => return Ok(Type::Basic(AstBasicType { name: "Nat".to_string() })),
-                        "String_contains" => return Ok(Type::Basic(AstBasicType { name: "Bool".to_string() })),
-                        "print" | "println" => return Ok(Type::Basic(AstBasicType { name: "Unit".to_string() })),
-                        _ => {
-                            // Check if it's an extern function we know about
-                            if let Some(return_type) = self.extern_func_types.get(&var.name) {
-                                return Ok(return_type.clone());
-                            }
-                        }
+            _ => self.compile_expr(expr, function),
+        }
+    }
+
+    // Curried type of a lambda. Unannotated parameters take their type from
+    // `expected`, the function type the context requires.
+    fn lambda_type(&mut self, lambda: &Lambda, expected: Option<&Type>) -> Result<FunctionType, String> {
+        let mut expected = expected.cloned();
+        let mut params = vec![];
+        
+        for (name, annotation) in &lambda.params {
+            let hint = match expected.take() {
+                Some(Type::Function(ft)) => Some(ft),
+                _ => None,
+            };
+            let param_type = annotation
+                .clone()
+                .or_else(|| hint.as_ref().map(|ft| (*ft.param_type).clone()))
+                .ok_or_else(|| format!("Cannot infer type of lambda parameter '{}'", name))?;
+            expected = hint.map(|ft| *ft.return_type);
+            params.push((name.clone(), param_type));
+        }
+        
+        let return_type = match expected {
+            Some(return_type) => return_type,
+            None => {
+                let saved_types = self.var_types.clone();
+                self.var_types.extend(params.iter().cloned());
+                let body_type = self.infer_value_type(&lambda.body);
+                self.var_types = saved_types;
+                body_type?
+            }
+        };
+        
+        Ok(curried_type(&params, &return_type))
+    }
+
+    fn compile_lambda(
+        &mut self,
+        lambda: &Lambda,
+        expected: Option<&Type>,
+        function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let lambda_type = self.lambda_type(lambda, expected)?;
+        let param_name = lambda.params[0].0.clone();
+        let param_type = (*lambda_type.param_type).clone();
+        let return_type = (*lambda_type.return_type).clone();
+        
+        // `\x y -> e` is `\x -> \y -> e`
+        let body = if lambda.params.len() > 1 {
+            Expr::Lambda(Lambda {
+                params: lambda.params[1..].to_vec(),
+                body: lambda.body.clone(),
+                span: lambda.span,
+            })
+        } else {
+            (*lambda.body).clone()
+        };
+        
+        // Capture the free variables of the body that are locals here
+        let mut free = vec![];
+        free_variables(&body, &mut vec![param_name.clone()], &mut free);
+        let mut captures = vec![];
+        for name in free {
+            if self.is_local_value(&name, function) {
+                let value = self.compile_variable(&name, function)?;
+                captures.push((name, value));
+            }
+        }
+        
+        let capture_types: Vec<BasicTypeEnum> = captures.iter().map(|(_, value)| value.get_type()).collect();
+        let env_type = self.context.struct_type(&capture_types, false);
+        let env_ptr = if captures.is_empty() {
+            self.context.i8_type().ptr_type(inkwell::AddressSpace::default()).const_null()
+        } else {
+            self.ensure_malloc_memcpy();
+            let malloc_fn = self.module.get_function("malloc").unwrap();
+            let env_size = env_type.size_of().unwrap();
+            let env_ptr = self.builder
+                .build_call(malloc_fn, &[self.size_arg(malloc_fn, 0, env_size).into()], "env")
+                .unwrap()
+                .try_as_basic_value()
+                .left()
+                .unwrap()
+                .into_pointer_value();
+            for (i, (name, value)) in captures.iter().enumerate() {
+                let slot = self.builder.build_struct_gep(env_type, env_ptr, i as u32, name).unwrap();
+                self.builder.build_store(slot, *value).unwrap();
+            }
+            env_ptr
+        };
+        
+        self.lambda_count += 1;
+        let enclosing = function.get_name().to_str().unwrap_or("lambda").to_string();
+        let code_name = format!("{}.lambda.{}", enclosing, self.lambda_count);
+        let code_fn = self.module.add_function(
+            &code_name,
+            self.closure_code_type(&lambda_type),
+            Some(Linkage::Internal),
+        );
+        
+        // Compile the body in its own function, then resume the enclosing one
+        let saved_block = self.builder.get_insert_block();
+        let saved_vars = std::mem::take(&mut self.local_vars);
+        let saved_types = self.var_types.clone();
+        let saved_return_type = self.current_function_return_type.replace(return_type.clone());
+        
+        let entry_bb = self.context.append_basic_block(code_fn, "entry");
+        self.builder.position_at_end(entry_bb);
+        let file = self.debug_info.as_ref().and_then(|debug| debug.scope).map_or(0, |scope| scope.file);
+        let outer_debug_scope = self.begin_debug_scope(code_fn, &code_name, lambda.span, file);
+        
+        let env_param = code_fn.get_nth_param(0).unwrap().into_pointer_value();
+        for (i, (name, value)) in captures.iter().enumerate() {
+            let slot = self.builder.build_struct_gep(env_type, env_param, i as u32, name).unwrap();
+            let loaded = self.builder.build_load(value.get_type(), slot, name).unwrap();
+            self.local_vars.insert(name.clone(), loaded);
+        }
+        let arg = code_fn.get_nth_param(1).unwrap();
+        arg.set_name(&param_name);
+        self.declare_debug_variable(&param_name, arg, Some(&param_type), lambda.span, Some(1), code_fn);
+        self.local_vars.insert(param_name.clone(), arg);
+        self.var_types.insert(param_name, param_type);
+        
+        let body_result = self.compile_expr_with_hint(&body, Some(&return_type), code_fn);
+        if let Ok(body_value) = &body_result {
+            if matches!(&return_type, Type::Basic(AstBasicType { name }) if name == "Unit") {
+                let unit_val = self.context.i8_type().const_int(0, false);
+                self.builder.build_return(Some(&unit_val)).unwrap();
+            } else {
+                self.builder.build_return(Some(body_value)).unwrap();
+            }
+        }
+        
+        self.end_debug_scope(code_fn, outer_debug_scope);
+        self.local_vars = saved_vars;
+        self.var_types = saved_types;
+        self.current_function_return_type = saved_return_type;
+        if let Some(block) = saved_block {
+            self.builder.position_at_end(block);
+        }
+        body_result?;
+        
+        if !code_fn.verify(true) {
+            return Err(format!("Function verification failed: {}", code_name));
+        }
+        
+        let code_ptr = code_fn.as_global_value().as_pointer_value();
+        let mut closure = self.closure_struct_type().get_undef();
+        closure = self.builder.build_insert_value(closure, code_ptr, 0, "code").unwrap().into_struct_value();
+        closure = self.builder.build_insert_value(closure, env_ptr, 1, "env").unwrap().into_struct_value();
+        Ok(closure.into())
+    }
+
+    // Wrap a top-level function in a closure, as `\a b -> f(a, b)`
+    fn compile_function_value(
+        &mut self,
+        name: &str,
+        function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let params = self.func_params.get(name).cloned().unwrap_or_default();
+        let return_type = self.func_return_types.get(name)
+            .or_else(|| self.extern_func_types.get(name))
+            .cloned()
+            .ok_or_else(|| format!("Function '{}' not found", name))?;
+        
+        let span = Span::default();
+        let mut lambda_params = vec![];
+        let mut body = Expr::Variable(Variable { name: name.to_string(), span });
+        if params.is_empty() {
+            let unit = Type::Basic(AstBasicType { name: "Unit".to_string() });
+            lambda_params.push(("__unit".to_string(), Some(unit)));
+            let unit_arg = Expr::Literal(Literal {
+                value: LiteralValue::Unit,
+                type_name: "Unit".to_string(),
+                span,
+            });
+            body = Expr::Application(Application { func: Box::new(body), arg: Box::new(unit_arg), span });
+        }
+        for (i, (_, param_type)) in params.iter().enumerate() {
+            let arg_name = format!("__arg{}", i);
+            lambda_params.push((arg_name.clone(), Some(param_type.clone())));
+            let arg = Expr::Variable(Variable { name: arg_name, span });
+            body = Expr::Application(Application { func: Box::new(body), arg: Box::new(arg), span });
+        }
+        
+        let typed_params: Vec<(String, Type)> = lambda_params
+            .iter()
+            .map(|(name, ty)| (name.clone(), ty.clone().unwrap()))
+            .collect();
+        let expected = Type::Function(curried_type(&typed_params, &return_type));
+        let lambda = Lambda { params: lambda_params, body: Box::new(body), span };
+        self.compile_lambda(&lambda, Some(&expected), function)
+    }
+
+    // Call `closure` (of type `closure_type`) with each argument in turn
+    fn apply_closure(
+        &mut self,
+        closure: BasicValueEnum<'ctx>,
+        closure_type: Type,
+        args: &[Expr],
+        function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let mut value = closure;
+        let mut value_type = closure_type;
+        
+        for arg in args {
+            let ft = match value_type {
+                Type::Function(ft) if value.is_struct_value() => ft,
+                other => return Err(format!("Cannot call a value of type {:?}", other)),
+            };
+            let arg_value = self.compile_expr_with_hint(arg, Some(&*ft.param_type), function)?;
+            
+            let closure_struct = value.into_struct_value();
+            let code_ptr = self.builder
+                .build_extract_value(closure_struct, 0, "code")
+                .unwrap()
+                .into_pointer_value();
+            let env_ptr = self.builder.build_extract_value(closure_struct, 1, "env").unwrap();
+            
+            let call_site = self.builder
+                .build_indirect_call(
+                    self.closure_code_type(&ft),
+                    code_ptr,
+                    &[env_ptr.into(), arg_value.into()],
+                    "closure_call",
+                )
+                .unwrap();
+            value = call_site
+                .try_as_basic_value()
+                .left()
+                .ok_or_else(|| "Closure returned void".to_string())?;
+            value_type = *ft.return_type;
+        }
+        
+        Ok(value)
+    }
+    
+    // ========================================================================
+    // Generic functions
+    // ========================================================================
+    //
+    // `func id<T>(x: T) -> T` is compiled separately for each set of type
+    // arguments it is called with, as `id<Int>`, `id<Bool>`, ... The type
+    // arguments come from the argument types at the call.
+
+    fn generic_bindings(&self, generic: &FunctionDef, args: &[Expr]) -> Result<HashMap<String, Type>, String> {
+        let mut bindings = HashMap::new();
+        for ((_, param_type), arg) in generic.params.iter().zip(args) {
+            let arg_type = self.infer_expr_type(arg)?;
+            bind_type_params(param_type, &arg_type, &generic.type_params, &mut bindings);
+        }
+        
+        match generic.type_params.iter().find(|param| !bindings.contains_key(*param)) {
+            Some(param) => Err(format!(
+                "Cannot infer type parameter '{}' of '{}' from its arguments",
+                param, generic.name
+            )),
+            None => Ok(bindings),
+        }
+    }
+
+    // Name of the instance of `name` for a call with `args`, compiling it on first use
+    fn instantiate_generic(&mut self, name: &str, args: &[Expr]) -> Result<String, String> {
+        let generic = self.generic_funcs[name].clone();
+        let bindings = self.generic_bindings(&generic, args)?;
+        let type_args: Vec<String> = generic.type_params.iter().map(|param| bindings[param].to_string()).collect();
+        let instance_name = format!("{}<{}>", name, type_args.join(", "));
+        
+        if self.module.get_function(&instance_name).is_none() {
+            let instance = FunctionDef {
+                name: instance_name.clone(),
+                type_params: vec![],
+                params: generic.params.iter()
+                    .map(|(param, ty)| (param.clone(), substitute_type_params(ty, &bindings)))
+                    .collect(),
+                return_type: substitute_type_params(&generic.return_type, &bindings),
+                ..generic
+            };
+            
+            // Compile the instance in the middle of compiling the caller
+            let saved_block = self.builder.get_insert_block();
+            let saved_locals = std::mem::take(&mut self.local_vars);
+            let saved_types = std::mem::take(&mut self.var_types);
+            let saved_return_type = self.current_function_return_type.take();
+            
+            let result = self.compile_function(&instance);
+            
+            if let Some(block) = saved_block {
+                self.builder.position_at_end(block);
+            }
+            self.local_vars = saved_locals;
+            self.var_types = saved_types;
+            self.current_function_return_type = saved_return_type;
+            result?;
+        }
+        
+        Ok(instance_name)
+    }
+
+    // ========================================================================
+    // Variants
+    // ========================================================================
+    //
+    // A variant type whose constructors carry no payload is an i32 tag. Once
+    // any constructor carries one, values are { i32 tag, [N x i64] payload }
+    // with N words enough for the largest payload. A constructor's fields are
+    // read and written through the payload cast to a pointer to the struct of
+    // their types, like a C union.
+
+    // Variant type, tag and payload types of a user-defined constructor
+    fn variant_constructor(&self, name: &str) -> Option<(String, usize, Vec<Type>)> {
+        self.variant_defs.iter().find_map(|(type_name, constructors)| {
+            constructors
+                .iter()
+                .position(|(ctor_name, _)| ctor_name == name)
+                .map(|tag| (type_name.clone(), tag, constructors[tag].1.clone()))
+        })
+    }
+
+    fn variant_has_payload(&self, type_name: &str) -> bool {
+        self.variant_defs
+            .get(type_name)
+            .is_some_and(|constructors| constructors.iter().any(|(_, args)| !args.is_empty()))
+    }
+
+    // Whether a payload of `type_name` contains a `type_name` itself, which
+    // would need boxing
+    fn variant_is_recursive(&self, type_name: &str) -> bool {
+        fn mentions(codegen: &CodeGen, ty: &Type, target: &str, seen: &mut Vec<String>) -> bool {
+            match ty {
+                Type::Basic(AstBasicType { name }) => {
+                    if name == target {
+                        return true;
                     }
-                }
-                
-                // Check for List_get and List_set which have curried application
-                let (func_name, args) = self.flatten_application(app)?;
-                if (func_name == "List_get" || func_name == "List.get" || func_name == "list_get") && (args.len() == 2 || args.len() == 3) {
-                    // List_get: List<T> -> Nat -> T
-                    // list_get: List<T> -> Nat -> T -> T (with default)
-                    // Infer element type from the list argument
-                    let list_type = self.infer_expr_type(&args[0])?;
-                    if let Type::List(list_type) = list_type {
-                        return Ok(*list_type.element_type);
+                    if seen.contains(name) {
+                        return false;
+                    }
+                    seen.push(name.clone());
+                    if let Some(record_type) = codegen.type_defs.get(name) {
+                        record_type.fields.iter().any(|(_, field_ty)| mentions(codegen, field_ty, target, seen))
+                    } else if let Some(constructors) = codegen.variant_defs.get(name) {
+                        constructors.iter().flat_map(|(_, args)| args).any(|arg| mentions(codegen, arg, target, seen))
                     } else {
-                        return Err(format!("list_get expects a list, got {:?}", list_type));
+                        false
                     }
                 }
-                if (func_name == "List_set" || func_name == "List.set") && args.len() == 3 {
-                    // List_set: List<T> -> Nat -> T -> List<T>
-                    // Returns the same list type
-                    return self.infer_expr_type(&args[0]);
-                }
-                if (func_name == "List_push" || func_name == "List.push" || func_name == "list_push") && args.len() == 2 {
-                    // List_push: List<T> -> T -> List<T>
-                    // Returns the same list type
-                    return self.infer_expr_type(&args[0]);
-                }
-                if (func_name == "List_length" || func_name == "List.length") && args.len() == 1 {
-                    // List_length: List<T> -> Nat
-                    return Ok(Type::Basic(AstBasicType { name: "Nat".to_string() }));
-                }
-                if func_name == "HashMap_new" && args.len() == 1 {
-                    // HashMap_new: Nat -> HashMap<Int, Int> (simplified)
-                    // TODO: Support generic HashMap<K, V>
-                    return Ok(Type::Basic(AstBasicType { name: "HashMap".to_string() }));
+                Type::Option(option_type) => mentions(codegen, &option_type.inner, target, seen),
+                Type::Result(result_type) => {
+                    mentions(codegen, &result_type.ok_type, target, seen)
+                        || mentions(codegen, &result_type.err_type, target, seen)
                 }
-                if func_name == "HashMap_put" && args.len() == 3 {
-                    // HashMap_put: HashMap<K, V> -> K -> V -> Unit
-                    return Ok(Type::Basic(AstBasicType { name: "Unit".to_string() }));
+                _ => false,
+            }
+        }
+
+        let mut seen = vec![];
+        self.variant_defs[type_name]
+            .iter()
+            .flat_map(|(_, args)| args)
+            .any(|arg| mentions(self, arg, type_name, &mut seen))
+    }
+
+    // Upper bound on the size of a value of type `ty` in 8-byte words. No
+    // scalar is wider than 8 bytes, so a word per scalar covers padding too.
+    fn type_words(&self, ty: &Type) -> u32 {
+        match ty {
+            Type::Basic(AstBasicType { name }) => match name.as_str() {
+                "String" => 2,
+                type_name => {
+                    if let Some(record_type) = self.type_defs.get(type_name) {
+                        record_type.fields.iter().map(|(_, field_ty)| self.type_words(field_ty)).sum()
+                    } else if self.variant_has_payload(type_name) {
+                        1 + self.variant_payload_words(type_name)
+                    } else {
+                        1
+                    }
                 }
-                if func_name == "HashMap_get" && args.len() == 2 {
+            },
+            Type::List(_) | Type::Function(_) => 2,
+            Type::Option(option_type) => 1 + self.type_words(&option_type.inner),
+            Type::Result(result_type) => {
+                1 + self.type_words(&result_type.ok_type).max(self.type_words(&result_type.err_type))
+            }
+            Type::Tuple(tuple_type) => tuple_type.element_types.iter().map(|element| self.type_words(element)).sum(),
+            _ => 1,
+        }
+    }
+
+    fn variant_payload_words(&self, type_name: &str) -> u32 {
+        self.variant_defs[type_name]
+            .iter()
+            .map(|(_, args)| args.iter().map(|arg| self.type_words(arg)).sum::<u32>())
+            .max()
+            .unwrap_or(0)
+            .max(1)
+    }
+
+    fn variant_struct_type(&self, type_name: &str) -> StructType<'ctx> {
+        let payload_type = self.context.i64_type().array_type(self.variant_payload_words(type_name));
+        self.context.struct_type(&[self.context.i32_type().into(), payload_type.into()], false)
+    }
+
+    fn payload_struct_type(&self, fields: &[Type]) -> StructType<'ctx> {
+        let field_types: Vec<BasicTypeEnum> = fields.iter().map(|ty| self.compile_type(ty)).collect();
+        self.context.struct_type(&field_types, false)
+    }
+
+    // The payload of the variant stored at `slot`, as a pointer to `payload_type`
+    fn payload_pointer(
+        &self,
+        variant_type: StructType<'ctx>,
+        slot: PointerValue<'ctx>,
+        payload_type: StructType<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let payload_ptr = self.builder.build_struct_gep(variant_type, slot, 1, "payload").unwrap();
+        self.builder
+            .build_pointer_cast(payload_ptr, payload_type.ptr_type(inkwell::AddressSpace::default()), "fields")
+            .unwrap()
+    }
+
+    // Value of constructor number `tag` of `type_name`, whose payload types
+    // are `fields`, applied to `values`
+    fn build_variant(
+        &self,
+        type_name: &str,
+        tag: usize,
+        fields: &[Type],
+        values: &[BasicValueEnum<'ctx>],
+        function: FunctionValue<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        let tag_value = self.context.i32_type().const_int(tag as u64, false);
+        if !self.variant_has_payload(type_name) {
+            return tag_value.into();
+        }
+
+        let variant_type = self.variant_struct_type(type_name);
+        let slot = self.build_entry_alloca(function, variant_type.into(), "variant");
+        let tag_ptr = self.builder.build_struct_gep(variant_type, slot, 0, "tag").unwrap();
+        self.builder.build_store(tag_ptr, tag_value).unwrap();
+
+        let payload_type = self.payload_struct_type(fields);
+        let payload_ptr = self.payload_pointer(variant_type, slot, payload_type);
+        for (i, value) in values.iter().enumerate() {
+            let field_ptr = self.builder.build_struct_gep(payload_type, payload_ptr, i as u32, "field").unwrap();
+            self.builder.build_store(field_ptr, *value).unwrap();
+        }
+
+        self.builder.build_load(variant_type, slot, "variant").unwrap()
+    }
+
+    fn variant_tag(&self, type_name: &str, value: BasicValueEnum<'ctx>) -> IntValue<'ctx> {
+        if self.variant_has_payload(type_name) {
+            self.builder
+                .build_extract_value(value.into_struct_value(), 0, "tag")
+                .unwrap()
+                .into_int_value()
+        } else {
+            value.into_int_value()
+        }
+    }
+
+    // Payload of a variant value built by a constructor with payload types `fields`
+    fn variant_fields(
+        &self,
+        type_name: &str,
+        value: BasicValueEnum<'ctx>,
+        fields: &[Type],
+        function: FunctionValue<'ctx>,
+    ) -> Vec<BasicValueEnum<'ctx>> {
+        let variant_type = self.variant_struct_type(type_name);
+        let slot = self.build_entry_alloca(function, variant_type.into(), "variant");
+        self.builder.build_store(slot, value).unwrap();
+
+        let payload_type = self.payload_struct_type(fields);
+        let payload_ptr = self.payload_pointer(variant_type, slot, payload_type);
+        fields
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                let field_ptr = self.builder.build_struct_gep(payload_type, payload_ptr, i as u32, "field").unwrap();
+                self.builder.build_load(self.compile_type(ty), field_ptr, "field").unwrap()
+            })
+            .collect()
+    }
+
+    // Bind local `name` until the returned binding is restored
+    fn bind_local(&mut self, name: String, value: BasicValueEnum<'ctx>, ty: Option<Type>) -> ShadowedBinding<'ctx> {
+        let old_value = self.local_vars.insert(name.clone(), value);
+        let old_type = match ty {
+            Some(ty) => self.var_types.insert(name.clone(), ty),
+            None => self.var_types.remove(&name),
+        };
+        (name, old_value, old_type)
+    }
+
+    fn restore_bindings(&mut self, shadowed: Vec<ShadowedBinding<'ctx>>) {
+        for (name, old_value, old_type) in shadowed.into_iter().rev() {
+            match old_value {
+                Some(value) => self.local_vars.insert(name.clone(), value),
+                None => self.local_vars.remove(&name),
+            };
+            match old_type {
+                Some(ty) => self.var_types.insert(name, ty),
+                None => self.var_types.remove(&name),
+            };
+        }
+    }
+
+    // ========================================================================
+    // Pattern matching
+    // ========================================================================
+    //
+    // A match compiles to a decision tree over a clause matrix: a row per arm
+    // (or per alternative of an or-pattern) and a column per value still to
+    // be tested. The scrutinee is evaluated once. Each test branches on one
+    // column - a tag, an Int or Bool, a string - and keeps the rows that can
+    // still match, until a row of wildcards selects its arm. A guarded arm's
+    // leaf tests the guard and falls through to the rows below it when the
+    // guard fails. Arm bodies are compiled once each; the values their
+    // patterns bind reach them through stack slots, since several leaves may
+    // select the same arm.
+
+    fn compile_match(
+        &mut self,
+        match_expr: &MatchExpr,
+        function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let arms = &match_expr.arms;
+        
+        if arms.is_empty() {
+            return Err("Match expression must have at least one arm".to_string());
+        }
+
+        let scrutinee = Occurrence {
+            value: self.compile_expr(&match_expr.scrutinee, function)?,
+            ty: self.infer_expr_type(&match_expr.scrutinee).ok(),
+        };
+        let rows = arms.iter()
+            .enumerate()
+            .map(|(arm, match_arm)| ClauseRow { patterns: vec![match_arm.pattern.clone()], bindings: vec![], arm })
+            .collect();
+        let mut targets = ArmTargets { arms, targets: arms.iter().map(|_| None).collect() };
+        self.compile_decision(rows, vec![scrutinee], &mut targets, function)?;
+        
+        // Arms no leaf selects are unreachable and left out
+        let merge_bb = self.context.append_basic_block(function, "match_merge");
+        let mut incoming = vec![];
+        for (arm, target) in targets.targets.into_iter().enumerate() {
+            let Some(target) = target else { continue };
+            self.builder.position_at_end(target.block);
+            
+            let mut shadowed = vec![];
+            for (name, slot, llvm_type, ty) in target.slots {
+                let value = self.builder.build_load(llvm_type, slot, &name).unwrap();
+                self.declare_debug_variable(&name, value, ty.as_ref(), arms[arm].pattern.span(), None, function);
+                shadowed.push(self.bind_local(name, value, ty));
+            }
+            let value = self.compile_expr(&arms[arm].body, function);
+            self.restore_bindings(shadowed);
+            let value = value?;
+            
+            self.builder.build_unconditional_branch(merge_bb).unwrap();
+            incoming.push((value, self.builder.get_insert_block().unwrap()));
+        }
+        
+        self.builder.position_at_end(merge_bb);
+        let result_type = incoming.first()
+            .map(|(value, _)| value.get_type())
+            .ok_or_else(|| "Match expression has no reachable arm".to_string())?;
+        let phi = self.builder.build_phi(result_type, "match_result").unwrap();
+        for (value, block) in &incoming {
+            phi.add_incoming(&[(value, *block)]);
+        }
+        Ok(phi.as_basic_value())
+    }
+
+    // Emit the tests that pick an arm for the values `occurrences`, starting
+    // in the current block
+    fn compile_decision(
+        &mut self,
+        rows: Vec<ClauseRow<'ctx>>,
+        occurrences: Vec<Occurrence<'ctx>>,
+        targets: &mut ArmTargets<'_, 'ctx>,
+        function: FunctionValue<'ctx>,
+    ) -> Result<(), String> {
+        let mut rows: Vec<ClauseRow<'ctx>> = rows.into_iter().flat_map(expand_or_patterns).collect();
+        for row in &mut rows {
+            for (pattern, occurrence) in row.patterns.iter_mut().zip(&occurrences) {
+                match pattern {
+                    Pattern::Variable(var) => {
+                        row.bindings.push((var.name.clone(), occurrence.clone()));
+                        *pattern = wildcard();
+                    }
+                    Pattern::Literal(LiteralPattern { value: LiteralValue::Unit, .. }) => *pattern = wildcard(),
+                    _ => {}
+                }
+            }
+        }
+        
+        let Some(first) = rows.first() else {
+            // No arm matches, which coverage checking rules out
+            self.builder.build_unreachable().unwrap();
+            return Ok(());
+        };
+        let Some(column) = first.patterns.iter().position(|pattern| !matches!(pattern, Pattern::Wildcard(_))) else {
+            let row = rows.remove(0);
+            let arms = targets.arms;
+            let Some(guard) = &arms[row.arm].guard else {
+                self.jump_to_arm(row, targets, function);
+                return Ok(());
+            };
+            
+            // The guard sees the row's bindings; the rows below it decide
+            // when it fails
+            let mut shadowed = vec![];
+            for (name, occurrence) in &row.bindings {
+                shadowed.push(self.bind_local(name.clone(), occurrence.value, occurrence.ty.clone()));
+            }
+            let condition = self.compile_expr(guard, function);
+            self.restore_bindings(shadowed);
+            let condition = condition?.into_int_value();
+            
+            let pass_bb = self.context.append_basic_block(function, "guard_pass");
+            let fail_bb = self.context.append_basic_block(function, "guard_fail");
+            self.builder.build_conditional_branch(condition, pass_bb, fail_bb).unwrap();
+            self.builder.position_at_end(pass_bb);
+            self.jump_to_arm(row, targets, function);
+            self.builder.position_at_end(fail_bb);
+            return self.compile_decision(rows, occurrences, targets, function);
+        };
+        
+        let occurrence = occurrences[column].clone();
+        match first.patterns[column].clone() {
+            Pattern::Tuple(tuple) => {
+                // Always matches: test the elements instead
+                let arity = tuple.elements.len();
+                let element_types: Vec<Option<Type>> = match &occurrence.ty {
+                    Some(Type::Tuple(tuple_type)) => tuple_type.element_types.iter().cloned().map(Some).collect(),
+                    _ => vec![None; arity],
+                };
+                let elements = self.struct_fields(&occurrence, element_types);
+                let rows = specialize_rows(&rows, column, arity, |pattern| match pattern {
+                    Pattern::Tuple(tuple) if tuple.elements.len() == arity => Some(tuple.elements.clone()),
+                    _ => None,
+                });
+                self.compile_decision(rows, splice_occurrences(&occurrences, column, elements), targets, function)
+            }
+            Pattern::Record(record) => {
+                // Always matches: test the fields instead
+                let fields = self.record_pattern_fields(&occurrence, &record)?;
+                let field_types = fields.iter().map(|(_, ty)| Some(ty.clone())).collect();
+                let values = self.struct_fields(&occurrence, field_types);
+                let rows = specialize_rows(&rows, column, fields.len(), |pattern| match pattern {
+                    Pattern::Record(record) => Some(fields.iter()
+                        .map(|(name, _)| record.fields.iter()
+                            .find(|(field, _)| field == name)
+                            .map_or_else(wildcard, |(_, field_pattern)| field_pattern.clone()))
+                        .collect()),
+                    _ => None,
+                });
+                self.compile_decision(rows, splice_occurrences(&occurrences, column, values), targets, function)
+            }
+            Pattern::Constructor(ctor_pattern) => {
+                self.compile_constructor_switch(rows, occurrences, column, &ctor_pattern.name, targets, function)
+            }
+            Pattern::Literal(_) => self.compile_literal_switch(rows, occurrences, column, targets, function),
+            pattern => Err(format!("Unsupported pattern: {}", pattern_to_string(&pattern))),
+        }
+    }
+
+    // Branch on the tag of the constructor in `column`, one case per
+    // constructor the rows mention; unmentioned ones go to the rows with a
+    // wildcard there
+    fn compile_constructor_switch(
+        &mut self,
+        rows: Vec<ClauseRow<'ctx>>,
+        occurrences: Vec<Occurrence<'ctx>>,
+        column: usize,
+        first_name: &str,
+        targets: &mut ArmTargets<'_, 'ctx>,
+        function: FunctionValue<'ctx>,
+    ) -> Result<(), String> {
+        let occurrence = occurrences[column].clone();
+        let (tag, constructor_count) = match first_name {
+            "Some" | "None" | "Ok" | "Err" => {
+                let tag = self.builder
+                    .build_extract_value(occurrence.value.into_struct_value(), 0, "tag")
+                    .unwrap()
+                    .into_int_value();
+                (tag, 2)
+            }
+            name => {
+                let (type_name, _, _) = self.variant_constructor(name)
+                    .ok_or_else(|| format!("Unsupported constructor pattern: {}", name))?;
+                (self.variant_tag(&type_name, occurrence.value), self.variant_defs[&type_name].len())
+            }
+        };
+        
+        let mut names: Vec<String> = vec![];
+        for row in &rows {
+            if let Pattern::Constructor(ctor) = &row.patterns[column] {
+                if !names.contains(&ctor.name) {
+                    names.push(ctor.name.clone());
+                }
+            }
+        }
+        
+        let default_bb = self.context.append_basic_block(function, "match_default");
+        let mut cases = vec![];
+        for name in &names {
+            let (tag_index, payload_types) = self.constructor_shape(name, &occurrence)?;
+            let case_bb = self.context.append_basic_block(function, &format!("match_{}", name));
+            cases.push((self.context.i32_type().const_int(tag_index, false), case_bb, name, payload_types));
+        }
+        let switch_cases: Vec<_> = cases.iter().map(|(tag_value, block, _, _)| (*tag_value, *block)).collect();
+        self.builder.build_switch(tag, default_bb, &switch_cases).unwrap();
+        
+        for (_, case_bb, name, payload_types) in cases {
+            self.builder.position_at_end(case_bb);
+            let arity = payload_types.len();
+            for row in &rows {
+                if let Pattern::Constructor(ctor) = &row.patterns[column] {
+                    if &ctor.name == name && ctor.args.len() != arity {
+                        return Err(format!("Pattern '{}' expects {} arguments, got {}", name, arity, ctor.args.len()));
+                    }
+                }
+            }
+            let payload = self.constructor_payload(name, &occurrence, payload_types, function);
+            let case_rows = specialize_rows(&rows, column, arity, |pattern| match pattern {
+                Pattern::Constructor(ctor) if &ctor.name == name => Some(ctor.args.clone()),
+                _ => None,
+            });
+            self.compile_decision(case_rows, splice_occurrences(&occurrences, column, payload), targets, function)?;
+        }
+        
+        self.builder.position_at_end(default_bb);
+        if names.len() == constructor_count {
+            self.builder.build_unreachable().unwrap();
+            Ok(())
+        } else {
+            let default_rows = specialize_rows(&rows, column, 0, |_| None);
+            self.compile_decision(default_rows, splice_occurrences(&occurrences, column, vec![]), targets, function)
+        }
+    }
+
+    // Branch on the Int, Bool or String literal in `column`
+    fn compile_literal_switch(
+        &mut self,
+        rows: Vec<ClauseRow<'ctx>>,
+        occurrences: Vec<Occurrence<'ctx>>,
+        column: usize,
+        targets: &mut ArmTargets<'_, 'ctx>,
+        function: FunctionValue<'ctx>,
+    ) -> Result<(), String> {
+        let occurrence = occurrences[column].clone();
+        let mut literals: Vec<LiteralValue> = vec![];
+        for row in &rows {
+            if let Pattern::Literal(lit) = &row.patterns[column] {
+                if !literals.contains(&lit.value) {
+                    literals.push(lit.value.clone());
+                }
+            }
+        }
+        
+        let default_bb = self.context.append_basic_block(function, "match_default");
+        let case_blocks: Vec<_> = literals.iter()
+            .map(|_| self.context.append_basic_block(function, "match_literal"))
+            .collect();
+        
+        if let LiteralValue::String(_) = &literals[0] {
+            // Strings are compared one literal after another
+            for (i, literal) in literals.iter().enumerate() {
+                let LiteralValue::String(text) = literal else {
+                    return Err(format!("Unsupported pattern literal: {:?}", literal));
+                };
+                let is_equal = self.build_string_equals(occurrence.value, text);
+                let next_bb = if i + 1 < literals.len() {
+                    self.context.append_basic_block(function, "match_next")
+                } else {
+                    default_bb
+                };
+                self.builder.build_conditional_branch(is_equal, case_blocks[i], next_bb).unwrap();
+                self.builder.position_at_end(next_bb);
+            }
+        } else {
+            let mut cases = vec![];
+            for (literal, block) in literals.iter().zip(&case_blocks) {
+                let case_value = match literal {
+                    LiteralValue::Int(n) => self.context.i64_type().const_int(*n as u64, true),
+                    LiteralValue::Bool(b) => self.context.bool_type().const_int(*b as u64, false),
+                    _ => return Err(format!("Unsupported pattern literal: {:?}", literal)),
+                };
+                cases.push((case_value, *block));
+            }
+            self.builder.build_switch(occurrence.value.into_int_value(), default_bb, &cases).unwrap();
+        }
+        
+        for (literal, block) in literals.iter().zip(case_blocks) {
+            self.builder.position_at_end(block);
+            let case_rows = specialize_rows(&rows, column, 0, |pattern| match pattern {
+                Pattern::Literal(lit) if &lit.value == literal => Some(vec![]),
+                _ => None,
+            });
+            self.compile_decision(case_rows, splice_occurrences(&occurrences, column, vec![]), targets, function)?;
+        }
+        
+        self.builder.position_at_end(default_bb);
+        let default_rows = specialize_rows(&rows, column, 0, |_| None);
+        self.compile_decision(default_rows, splice_occurrences(&occurrences, column, vec![]), targets, function)
+    }
+
+    // Store the values `row` binds in its arm's slots and branch to the arm
+    fn jump_to_arm(&mut self, row: ClauseRow<'ctx>, targets: &mut ArmTargets<'_, 'ctx>, function: FunctionValue<'ctx>) {
+        let target = targets.targets[row.arm].get_or_insert_with(|| ArmTarget {
+            block: self.context.append_basic_block(function, "match_arm"),
+            slots: vec![],
+        });
+        for (name, occurrence) in row.bindings {
+            let slot = match target.slots.iter().find(|(slot_name, ..)| *slot_name == name) {
+                Some((_, slot, _, _)) => *slot,
+                None => {
+                    let llvm_type = occurrence.value.get_type();
+                    let slot = self.build_entry_alloca(function, llvm_type, &name);
+                    target.slots.push((name, slot, llvm_type, occurrence.ty));
+                    slot
+                }
+            };
+            self.builder.build_store(slot, occurrence.value).unwrap();
+        }
+        self.builder.build_unconditional_branch(target.block).unwrap();
+    }
+
+    // Stack slot allocated once in the entry block, however often the
+    // current block runs
+    fn build_entry_alloca(&self, function: FunctionValue<'ctx>, ty: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let entry = function.get_first_basic_block().unwrap();
+        let builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name).unwrap()
+    }
+
+    // Tag and payload types of constructor `name` of the matched value
+    fn constructor_shape(&self, name: &str, occurrence: &Occurrence<'ctx>) -> Result<(u64, Vec<Option<Type>>), String> {
+        let ty = occurrence.ty.as_ref();
+        Ok(match name {
+            "None" => (0, vec![]),
+            "Some" => (1, vec![match ty {
+                Some(Type::Option(option_type)) => Some((*option_type.inner).clone()),
+                _ => None,
+            }]),
+            "Ok" => (1, vec![match ty {
+                Some(Type::Result(result_type)) => Some((*result_type.ok_type).clone()),
+                _ => None,
+            }]),
+            "Err" => (0, vec![match ty {
+                Some(Type::Result(result_type)) => Some((*result_type.err_type).clone()),
+                _ => None,
+            }]),
+            _ => {
+                let (_, tag, fields) = self.variant_constructor(name)
+                    .ok_or_else(|| format!("Unsupported constructor pattern: {}", name))?;
+                (tag as u64, fields.into_iter().map(Some).collect())
+            }
+        })
+    }
+
+    // Payload of the matched value, known to be built by constructor `name`
+    fn constructor_payload(
+        &self,
+        name: &str,
+        occurrence: &Occurrence<'ctx>,
+        payload_types: Vec<Option<Type>>,
+        function: FunctionValue<'ctx>,
+    ) -> Vec<Occurrence<'ctx>> {
+        match name {
+            "None" => vec![],
+            "Some" | "Ok" | "Err" => {
+                let ty = payload_types.into_iter().next().flatten();
+                let stored = self.builder
+                    .build_extract_value(occurrence.value.into_struct_value(), 1, "value")
+                    .unwrap();
+                // Err shares the slot sized for Ok, so a smaller error is
+                // read back through memory
+                let value = match &ty {
+                    Some(ty) if self.compile_type(ty) != stored.get_type() => {
+                        let payload_type = self.payload_struct_type(std::slice::from_ref(ty));
+                        let slot = self.build_entry_alloca(function, stored.get_type(), "payload");
+                        self.builder.build_store(slot, stored).unwrap();
+                        let payload_ptr = self.builder
+                            .build_pointer_cast(slot, payload_type.ptr_type(inkwell::AddressSpace::default()), "payload")
+                            .unwrap();
+                        let field_ptr = self.builder.build_struct_gep(payload_type, payload_ptr, 0, "field").unwrap();
+                        self.builder.build_load(self.compile_type(ty), field_ptr, "value").unwrap()
+                    }
+                    _ => stored,
+                };
+                vec![Occurrence { value, ty }]
+            }
+            _ => {
+                let Some((type_name, _, fields)) = self.variant_constructor(name) else { return vec![] };
+                if fields.is_empty() {
+                    return vec![];
+                }
+                self.variant_fields(&type_name, occurrence.value, &fields, function)
+                    .into_iter()
+                    .zip(payload_types)
+                    .map(|(value, ty)| Occurrence { value, ty })
+                    .collect()
+            }
+        }
+    }
+
+    // Every field of a tuple or record value, in order
+    fn struct_fields(&self, occurrence: &Occurrence<'ctx>, types: Vec<Option<Type>>) -> Vec<Occurrence<'ctx>> {
+        let struct_value = occurrence.value.into_struct_value();
+        types.into_iter()
+            .enumerate()
+            .map(|(i, ty)| Occurrence {
+                value: self.builder.build_extract_value(struct_value, i as u32, "field").unwrap(),
+                ty,
+            })
+            .collect()
+    }
+
+    // Declared fields of the record a record pattern matches
+    fn record_pattern_fields(&self, occurrence: &Occurrence<'ctx>, record: &RecordPattern) -> Result<Vec<(String, Type)>, String> {
+        let type_name = match (&occurrence.ty, &record.type_name) {
+            (Some(Type::Record(record_type)), _) => return Ok(record_type.fields.clone()),
+            (Some(Type::Basic(AstBasicType { name })), _) => name.clone(),
+            (_, Some(type_name)) => type_name.clone(),
+            _ => return Err(format!(
+                "Cannot tell the record type matched by {}; name it, as in `Point {{ x }}`",
+                pattern_to_string(&Pattern::Record(record.clone()))
+            )),
+        };
+        self.type_defs.get(&type_name)
+            .map(|record_type| record_type.fields.clone())
+            .ok_or_else(|| format!("Record type '{}' not found", type_name))
+    }
+
+    // Whether the String `value` equals `literal`. Only the shorter length is
+    // compared bytewise, so neither buffer is read past its end.
+    fn build_string_equals(&mut self, value: BasicValueEnum<'ctx>, literal: &str) -> IntValue<'ctx> {
+        self.ensure_memcmp();
+        let i64_type = self.context.i64_type();
+        let string_struct = value.into_struct_value();
+        let data = self.builder.build_extract_value(string_struct, 0, "str_data").unwrap();
+        let length = self.builder.build_extract_value(string_struct, 1, "str_len").unwrap().into_int_value();
+        
+        let literal_data = self.builder.build_global_string_ptr(literal, "pattern_str").unwrap();
+        let literal_length = i64_type.const_int(literal.len() as u64, false);
+        let is_shorter = self.builder.build_int_compare(IntPredicate::ULT, length, literal_length, "is_shorter").unwrap();
+        let compared_length = self.builder.build_select(is_shorter, length, literal_length, "cmp_len").unwrap();
+        
+        let memcmp_fn = self.module.get_function("memcmp").unwrap();
+        let order = self.builder
+            .build_call(
+                memcmp_fn,
+                &[data.into(), literal_data.as_pointer_value().into(), self.size_arg(memcmp_fn, 2, compared_length.into_int_value()).into()],
+                "memcmp_result",
+            )
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let same_bytes = self.builder
+            .build_int_compare(IntPredicate::EQ, order, self.context.i32_type().const_zero(), "same_bytes")
+            .unwrap();
+        let same_length = self.builder
+            .build_int_compare(IntPredicate::EQ, length, literal_length, "same_length")
+            .unwrap();
+        self.builder.build_and(same_bytes, same_length, "str_eq").unwrap()
+    }
+
+    fn ensure_memcmp(&mut self) {
+        if self.module.get_function("memcmp").is_none() {
+            let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::default());
+            let memcmp_type = self.context.i32_type().fn_type(
+                &[i8_ptr_type.into(), i8_ptr_type.into(), self.size_type().into()],
+                false
+            );
+            self.module.add_function("memcmp", memcmp_type, None);
+        }
+    }
+
+    fn infer_expr_type(&self, expr: &Expr) -> Result<Type, String> {
+        match expr {
+            Expr::Literal(lit) => match &lit.value {
+                LiteralValue::Int(_) => Ok(Type::Basic(AstBasicType { name: "Int".to_string() })),
+                LiteralValue::Bool(_) => Ok(Type::Basic(AstBasicType { name: "Bool".to_string() })),
+                LiteralValue::Float(_) => Ok(Type::Basic(AstBasicType { name: "Float64".to_string() })),
+                LiteralValue::String(_) => Ok(Type::Basic(AstBasicType { name: "String".to_string() })),
+                LiteralValue::Unit => Ok(Type::Basic(AstBasicType { name: "Unit".to_string() })),
+            },
+            Expr::Variable(var) => {
+                // First check if it's a regular variable
+                if let Some(ty) = self.var_types.get(&var.name) {
+                    return Ok(ty.clone());
+                }
+                
+                // Check if it's a variant constructor
+                for (variant_name, constructors) in &self.variant_defs {
+                    for (ctor_name, ctor_args) in constructors {
+                        if ctor_name == &var.name && ctor_args.is_empty() {
+                            // Nullary constructor - return the variant type
+                            return Ok(Type::Basic(AstBasicType { name: variant_name.clone() }));
+                        }
+                    }
+                }
+                
+                // A top-level function used as a value
+                if let Some(params) = self.func_params.get(&var.name) {
+                    let return_type = self.func_return_types.get(&var.name)
+                        .or_else(|| self.extern_func_types.get(&var.name));
+                    if let Some(return_type) = return_type {
+                        return Ok(Type::Function(curried_type(params, return_type)));
+                    }
+                }
+                
+                Err(format!("Cannot find type for variable '{}'", var.name))
+            }
+            Expr::BinaryOp(binop) => match binop.op.as_str() {
+                "==" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||" | "=>" => {
+                    Ok(Type::Basic(AstBasicType { name: "Bool".to_string() }))
+                }
+                _ => self.infer_expr_type(&binop.left),
+            },
+            Expr::UnaryOp(unop) => match unop.op.as_str() {
+                "not" => Ok(Type::Basic(AstBasicType { name: "Bool".to_string() })),
+                _ => self.infer_expr_type(&unop.operand),
+            },
+            Expr::FieldAccess(field_access) => {
+                let record_type = self.infer_expr_type(&field_access.record)?;
+                if let Type::Tuple(tuple_type) = &record_type {
+                    return tuple_index(&field_access.field, tuple_type)
+                        .map(|index| tuple_type.element_types[index].clone());
+                }
+                let type_name = if let Type::Basic(AstBasicType { name }) = record_type {
+                    name
+                } else {
+                    return Err("Expected basic type for record".to_string());
+                };
+                
+                let record_def = self.type_defs.get(&type_name)
+                    .ok_or_else(|| format!("Record type '{}' not found", type_name))?;
+                
+                record_def.fields.iter()
+                    .find(|(name, _)| name == &field_access.field)
+                    .map(|(_, ty)| ty.clone())
+                    .ok_or_else(|| format!("Field '{}' not found", field_access.field))
+            },
+            Expr::Application(app) => {
+                // Calls through function values
+                let (callee, args) = self.application_spine(app);
+                let is_closure_call = match callee {
+                    Expr::Variable(var) => self.var_types.contains_key(&var.name),
+                    _ => true,
+                };
+                if is_closure_call {
+                    return peel_function_type(self.infer_expr_type(callee)?, args.len());
+                }
+                
+                // Check for builtin functions and extern functions
+                if let Expr::Variable(var) = &*app.func {
+                    match var.name.as_str() {
+                        "String_length" => return Ok(Type::Basic(AstBasicType { name: "Nat".to_string() })),
+                        "String_contains" => return Ok(Type::Basic(AstBasicType { name: "Bool".to_string() })),
+                        "print" | "println" => return Ok(Type::Basic(AstBasicType { name: "Unit".to_string() })),
+                        _ => {
+                            // Check if it's an extern function we know about
+                            if let Some(return_type) = self.extern_func_types.get(&var.name) {
+                                return Ok(return_type.clone());
+                            }
+                        }
+                    }
+                }
+                
+                // Check for List_get and List_set which have curried application
+                let (func_name, args) = self.flatten_application(app)?;
+                if let Some((type_name, _, fields)) = self.variant_constructor(&func_name) {
+                    if args.len() == fields.len() {
+                        return Ok(Type::Basic(AstBasicType { name: type_name }));
+                    }
+                }
+                if (func_name == "List_get" || func_name == "List.get" || func_name == "list_get") && (args.len() == 2 || args.len() == 3) {
+                    // List_get: List<T> -> Nat -> T
+                    // list_get: List<T> -> Nat -> T -> T (with default)
+                    // Infer element type from the list argument
+                    let list_type = self.infer_expr_type(&args[0])?;
+                    if let Type::List(list_type) = list_type {
+                        return Ok(*list_type.element_type);
+                    } else {
+                        return Err(format!("list_get expects a list, got {:?}", list_type));
+                    }
+                }
+                if (func_name == "List_set" || func_name == "List.set") && args.len() == 3 {
+                    // List_set: List<T> -> Nat -> T -> List<T>
+                    // Returns the same list type
+                    return self.infer_expr_type(&args[0]);
+                }
+                if (func_name == "List_push" || func_name == "List.push" || func_name == "list_push") && args.len() == 2 {
+                    // List_push: List<T> -> T -> List<T>
+                    // Returns the same list type
+                    return self.infer_expr_type(&args[0]);
+                }
+                if (func_name == "List_length" || func_name == "List.length") && args.len() == 1 {
+                    // List_length: List<T> -> Nat
+                    return Ok(Type::Basic(AstBasicType { name: "Nat".to_string() }));
+                }
+                if func_name == "HashMap_new" && args.len() == 1 {
+                    // HashMap_new: Nat -> HashMap<Int, Int> (simplified)
+                    // TODO: Support generic HashMap<K, V>
+                    return Ok(Type::Basic(AstBasicType { name: "HashMap".to_string() }));
+                }
+                if func_name == "HashMap_put" && args.len() == 3 {
+                    // HashMap_put: HashMap<K, V> -> K -> V -> Unit
+                    return Ok(Type::Basic(AstBasicType { name: "Unit".to_string() }));
+                }
+                if func_name == "HashMap_get" && args.len() == 2 {
                     // HashMap_get: HashMap<K, V> -> K -> V
                     return Ok(Type::Basic(AstBasicType { name: "Int".to_string() }));
                 }
@@ -2185,9 +3847,16 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                     return Ok(return_type.clone());
                 }
                 
+                if let Some(generic) = self.generic_funcs.get(&func_name) {
+                    let bindings = self.generic_bindings(generic, &args)?;
+                    return Ok(substitute_type_params(&generic.return_type, &bindings));
+                }
+                
                 // Check if it's a regular function call (user-defined)
                 if let Some(return_type) = self.func_return_types.get(&func_name) {
-                    return Ok(return_type.clone());
+                    // Extra arguments apply the returned function value
+                    let param_count = self.func_params.get(&func_name).map_or(0, Vec::len).max(1);
+                    return peel_function_type(return_type.clone(), args.len().saturating_sub(param_count));
                 }
                 
                 // Fallback: Check if it's a regular function call
@@ -2244,17 +3913,27 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                 // If no exact match, return error
                 Err(format!("Cannot find type definition for record with fields: {:?}", field_names))
             },
+            Expr::Tuple(tuple) => {
+                let element_types = tuple.elements.iter()
+                    .map(|element| self.infer_expr_type(element))
+                    .collect::<Result<_, _>>()?;
+                Ok(Type::Tuple(TupleType { element_types }))
+            },
             Expr::If(if_expr) => {
                 // Infer type from then branch (both branches must have same type)
                 self.infer_expr_type(&if_expr.then_branch)
             },
             Expr::Match(match_expr) => {
-                // Infer type from first arm
-                if let Some((_, first_expr)) = match_expr.arms.first() {
-                    self.infer_expr_type(first_expr)
-                } else {
-                    Err("Cannot infer type from empty match expression".to_string())
+                // Infer type from the first arm that does not depend on
+                // pattern variables
+                let mut result = Err("Cannot infer type from empty match expression".to_string());
+                for arm in &match_expr.arms {
+                    result = self.infer_expr_type(&arm.body);
+                    if result.is_ok() {
+                        break;
+                    }
                 }
+                result
             },
             Expr::Constructor(constructor) => {
                 // List literal: [1, 2, 3] => List<Int>
@@ -2268,6 +3947,8 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                             element_type: Box::new(Type::Basic(AstBasicType { name: "Int".to_string() }))
                         }))
                     }
+                } else if let Some((type_name, _, _)) = self.variant_constructor(&constructor.name) {
+                    Ok(Type::Basic(AstBasicType { name: type_name }))
                 } else {
                     Err(format!("Cannot infer type for constructor: {}", constructor.name))
                 }
@@ -2286,6 +3967,14 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         // Use infer_expr_type to get the type of the record expression
         let record_type = self.infer_expr_type(&field_access.record)?;
         
+        if let Type::Tuple(tuple_type) = &record_type {
+            let index = tuple_index(&field_access.field, tuple_type)?;
+            let element = self.builder
+                .build_extract_value(record_value.into_struct_value(), index as u32, "element")
+                .unwrap();
+            return Ok(element);
+        }
+        
         let type_name = if let Type::Basic(AstBasicType { name }) = record_type {
             name
         } else {
@@ -2336,12 +4025,36 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         for (i, field_value) in field_values.iter().enumerate() {
             struct_val = self
                 .builder
-                .build_insert_value(struct_val, *field_value, i as u32, "field")
+                .build_insert_value(struct_val, *field_value, i as u32, "field")
+                .unwrap()
+                .into_struct_value();
+        }
+        
+        Ok(struct_val.into())
+    }
+    
+    fn compile_tuple(
+        &mut self,
+        tuple: &TupleExpr,
+        function: FunctionValue<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let element_values: Vec<BasicValueEnum> = tuple
+            .elements
+            .iter()
+            .map(|element| self.compile_expr(element, function))
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        let element_types: Vec<BasicTypeEnum> = element_values.iter().map(|v| v.get_type()).collect();
+        let mut tuple_value = self.context.struct_type(&element_types, false).get_undef();
+        for (i, element_value) in element_values.iter().enumerate() {
+            tuple_value = self
+                .builder
+                .build_insert_value(tuple_value, *element_value, i as u32, "element")
                 .unwrap()
                 .into_struct_value();
         }
         
-        Ok(struct_val.into())
+        Ok(tuple_value.into())
     }
     
     fn compile_constructor(
@@ -2401,15 +4114,890 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
                 let list_val = list_type.const_named_struct(&[array_ptr.into(), length_val.into()]);
                 Ok(list_val.into())
             }
+        } else if let Some((type_name, tag, fields)) = self.variant_constructor(&constructor.name) {
+            if constructor.args.len() != fields.len() {
+                return Err(format!(
+                    "Constructor '{}' expects {} arguments, got {}",
+                    constructor.name, fields.len(), constructor.args.len()
+                ));
+            }
+            let values = constructor.args.iter()
+                .zip(&fields)
+                .map(|(arg, ty)| self.compile_expr_with_hint(arg, Some(ty), function))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(self.build_variant(&type_name, tag, &fields, &values, function))
         } else {
             Err(format!("Unknown constructor: {}", constructor.name))
         }
     }
 
+    // ========================================================================
+    // WASI runtime
+    // ========================================================================
+    //
+    // A wasm32-wasi module links without a C library: the C functions compiled
+    // code calls (`write` for print, `malloc` and the memory functions, and
+    // the stdio file functions) are defined in the module itself on top of
+    // imports from `wasi_snapshot_preview1`. Memory comes from a bump
+    // allocator growing linear memory, so `free` does nothing. A `FILE *` is
+    // the WASI file descriptor, and `fopen` opens paths relative to the first
+    // preopened directory, as given by `wasmtime --dir .`. Declarations are
+    // followed whatever widths `@extern` gave their integer types.
+
+    // Define the runtime functions the module refers to
+    fn define_wasi_runtime(&mut self) {
+        self.restore_debug_location(None);
+        for name in WASI_RUNTIME_FUNCTIONS {
+            if self.module.get_function(name).is_some() {
+                self.wasi_runtime_function(name);
+            }
+        }
+    }
+
+    /// Declaration of the WASI function `name`, imported from
+    /// `wasi_snapshot_preview1` under a symbol of its own, `__wasi_<name>`,
+    /// so it cannot clash with a C function of the same name
+    pub fn wasi_import(&self, name: &str) -> FunctionValue<'ctx> {
+        let symbol = format!("__wasi_{}", name);
+        if let Some(function) = self.module.get_function(&symbol) {
+            return function;
+        }
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let fn_type = match name {
+            // (fd, iovs, iovs_len, *bytes) -> errno
+            "fd_write" | "fd_read" => {
+                i32_type.fn_type(&[i32_type.into(), ptr_type.into(), i32_type.into(), ptr_type.into()], false)
+            }
+            "fd_close" => i32_type.fn_type(&[i32_type.into()], false),
+            // (dirfd, dirflags, path, path_len, oflags, rights, inherited rights, fdflags, *fd) -> errno
+            "path_open" => i32_type.fn_type(
+                &[
+                    i32_type.into(), i32_type.into(), ptr_type.into(), i32_type.into(), i32_type.into(),
+                    i64_type.into(), i64_type.into(), i32_type.into(), ptr_type.into(),
+                ],
+                false,
+            ),
+            "proc_exit" => self.context.void_type().fn_type(&[i32_type.into()], false),
+            _ => panic!("Unsupported WASI import: {}", name),
+        };
+        let function = self.module.add_function(&symbol, fn_type, None);
+        for (key, value) in [("wasm-import-module", "wasi_snapshot_preview1"), ("wasm-import-name", name)] {
+            function.add_attribute(AttributeLoc::Function, self.context.create_string_attribute(key, value));
+        }
+        if name == "proc_exit" {
+            let noreturn = Attribute::get_named_enum_kind_id("noreturn");
+            function.add_attribute(AttributeLoc::Function, self.context.create_enum_attribute(noreturn, 0));
+        }
+        function
+    }
+
+    // The runtime function `name`, declared with its C signature unless the
+    // module already declares it, and defined unless it already is
+    fn wasi_runtime_function(&mut self, name: &str) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(name).filter(|function| function.count_basic_blocks() > 0) {
+            return function;
+        }
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let size_type = self.size_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let function = self.module.get_function(name).unwrap_or_else(|| {
+            let fn_type = match name {
+                "malloc" => ptr_type.fn_type(&[size_type.into()], false),
+                "free" => self.context.void_type().fn_type(&[ptr_type.into()], false),
+                "memcpy" => ptr_type.fn_type(&[ptr_type.into(), ptr_type.into(), size_type.into()], false),
+                "memset" => ptr_type.fn_type(&[ptr_type.into(), i32_type.into(), size_type.into()], false),
+                "memcmp" => i32_type.fn_type(&[ptr_type.into(), ptr_type.into(), size_type.into()], false),
+                "strlen" => size_type.fn_type(&[ptr_type.into()], false),
+                "strstr" => ptr_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+                "write" => size_type.fn_type(&[i32_type.into(), ptr_type.into(), size_type.into()], false),
+                "abort" => self.context.void_type().fn_type(&[], false),
+                "puts" => i32_type.fn_type(&[ptr_type.into()], false),
+                "putchar" => i32_type.fn_type(&[i32_type.into()], false),
+                "fopen" => ptr_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+                "fclose" => i32_type.fn_type(&[ptr_type.into()], false),
+                "fputs" => i32_type.fn_type(&[ptr_type.into(), ptr_type.into()], false),
+                "fgets" => ptr_type.fn_type(&[ptr_type.into(), i32_type.into(), ptr_type.into()], false),
+                _ => panic!("Unsupported WASI runtime function: {}", name),
+            };
+            self.module.add_function(name, fn_type, None)
+        });
+        // Keep LLVM from turning the loops below back into calls to these functions
+        function.add_attribute(AttributeLoc::Function, self.context.create_string_attribute("no-builtins", ""));
+
+        // Functions used in the body are defined first, as that moves the builder
+        let callees: Vec<FunctionValue<'ctx>> = match name {
+            "write" => vec![self.wasi_import("fd_write")],
+            "puts" | "fputs" => vec![self.wasi_runtime_function("strlen"), self.wasi_runtime_function("write")],
+            "putchar" => vec![self.wasi_runtime_function("write")],
+            "strstr" => vec![self.wasi_runtime_function("strlen"), self.wasi_runtime_function("memcmp")],
+            "fopen" => vec![self.wasi_runtime_function("strlen"), self.wasi_import("path_open")],
+            "fclose" => vec![self.wasi_import("fd_close")],
+            "fgets" => vec![self.wasi_import("fd_read")],
+            _ => vec![],
+        };
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        let one = i32_type.const_int(1, false);
+        let zero = i32_type.const_zero();
+        let minus_one = i32_type.const_all_ones();
+        match name {
+            "malloc" => {
+                let size = self.wasi_int_param(function, 0);
+                let start = self.build_wasi_alloc(function, size);
+                self.build_wasi_return(function, start.into());
+            }
+            "free" => self.build_wasi_return(function, zero.into()),
+            "memcpy" => {
+                let (dest, source) = (self.wasi_ptr_param(function, 0), self.wasi_ptr_param(function, 1));
+                let size = self.wasi_int_param(function, 2);
+                self.build_wasi_byte_loop(function, dest, size, |codegen, index| codegen.build_wasi_byte(source, index));
+                self.build_wasi_return(function, dest.into());
+            }
+            "memset" => {
+                let dest = self.wasi_ptr_param(function, 0);
+                let byte = self.builder.build_int_truncate(self.wasi_int_param(function, 1), i8_type, "byte").unwrap();
+                let size = self.wasi_int_param(function, 2);
+                self.build_wasi_byte_loop(function, dest, size, |_, _| byte);
+                self.build_wasi_return(function, dest.into());
+            }
+            "memcmp" => {
+                let (left, right) = (self.wasi_ptr_param(function, 0), self.wasi_ptr_param(function, 1));
+                let size = self.wasi_int_param(function, 2);
+                let index_ptr = self.build_entry_alloca(function, i32_type.into(), "index");
+                self.builder.build_store(index_ptr, zero).unwrap();
+                let loop_bb = self.context.append_basic_block(function, "loop");
+                let body_bb = self.context.append_basic_block(function, "body");
+                let differ_bb = self.context.append_basic_block(function, "differ");
+                let next_bb = self.context.append_basic_block(function, "next");
+                let equal_bb = self.context.append_basic_block(function, "equal");
+                self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+                self.builder.position_at_end(loop_bb);
+                let index = self.builder.build_load(i32_type, index_ptr, "i").unwrap().into_int_value();
+                let more = self.builder.build_int_compare(IntPredicate::ULT, index, size, "more").unwrap();
+                self.builder.build_conditional_branch(more, body_bb, equal_bb).unwrap();
+
+                self.builder.position_at_end(body_bb);
+                let a = self.build_wasi_byte(left, index);
+                let b = self.build_wasi_byte(right, index);
+                let same = self.builder.build_int_compare(IntPredicate::EQ, a, b, "same").unwrap();
+                self.builder.build_conditional_branch(same, next_bb, differ_bb).unwrap();
+
+                self.builder.position_at_end(differ_bb);
+                let a = self.builder.build_int_z_extend(a, i32_type, "a").unwrap();
+                let b = self.builder.build_int_z_extend(b, i32_type, "b").unwrap();
+                let order = self.builder.build_int_sub(a, b, "order").unwrap();
+                self.build_wasi_return(function, order.into());
+
+                self.builder.position_at_end(next_bb);
+                let index = self.builder.build_int_add(index, one, "i_next").unwrap();
+                self.builder.build_store(index_ptr, index).unwrap();
+                self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+                self.builder.position_at_end(equal_bb);
+                self.build_wasi_return(function, zero.into());
+            }
+            "strlen" => {
+                let string = self.wasi_ptr_param(function, 0);
+                let index_ptr = self.build_entry_alloca(function, i32_type.into(), "index");
+                self.builder.build_store(index_ptr, zero).unwrap();
+                let loop_bb = self.context.append_basic_block(function, "loop");
+                let next_bb = self.context.append_basic_block(function, "next");
+                let end_bb = self.context.append_basic_block(function, "end");
+                self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+                self.builder.position_at_end(loop_bb);
+                let index = self.builder.build_load(i32_type, index_ptr, "i").unwrap().into_int_value();
+                let byte = self.build_wasi_byte(string, index);
+                let is_nul = self.builder.build_int_compare(IntPredicate::EQ, byte, i8_type.const_zero(), "is_nul").unwrap();
+                self.builder.build_conditional_branch(is_nul, end_bb, next_bb).unwrap();
+
+                self.builder.position_at_end(next_bb);
+                let index = self.builder.build_int_add(index, one, "i_next").unwrap();
+                self.builder.build_store(index_ptr, index).unwrap();
+                self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+                self.builder.position_at_end(end_bb);
+                let length = self.builder.build_load(i32_type, index_ptr, "length").unwrap();
+                self.build_wasi_return(function, length);
+            }
+            "strstr" => {
+                // Compare the needle at each offset it fits at
+                let (haystack, needle) = (self.wasi_ptr_param(function, 0), self.wasi_ptr_param(function, 1));
+                let haystack_len = self.build_wasi_call(callees[0], &[haystack.into()]).into_int_value();
+                let needle_len = self.build_wasi_call(callees[0], &[needle.into()]).into_int_value();
+                let index_ptr = self.build_entry_alloca(function, i32_type.into(), "index");
+                self.builder.build_store(index_ptr, zero).unwrap();
+                let loop_bb = self.context.append_basic_block(function, "loop");
+                let body_bb = self.context.append_basic_block(function, "body");
+                let found_bb = self.context.append_basic_block(function, "found");
+                let next_bb = self.context.append_basic_block(function, "next");
+                let missing_bb = self.context.append_basic_block(function, "missing");
+                let fits = self.builder.build_int_compare(IntPredicate::ULE, needle_len, haystack_len, "fits").unwrap();
+                self.builder.build_conditional_branch(fits, loop_bb, missing_bb).unwrap();
+
+                self.builder.position_at_end(loop_bb);
+                let index = self.builder.build_load(i32_type, index_ptr, "i").unwrap().into_int_value();
+                let last = self.builder.build_int_sub(haystack_len, needle_len, "last").unwrap();
+                let more = self.builder.build_int_compare(IntPredicate::ULE, index, last, "more").unwrap();
+                self.builder.build_conditional_branch(more, body_bb, missing_bb).unwrap();
+
+                self.builder.position_at_end(body_bb);
+                let candidate = unsafe { self.builder.build_gep(i8_type, haystack, &[index], "candidate").unwrap() };
+                let order = self.build_wasi_call(callees[1], &[candidate.into(), needle.into(), needle_len.into()]);
+                let matches = self.builder
+                    .build_int_compare(IntPredicate::EQ, order.into_int_value(), zero, "matches")
+                    .unwrap();
+                self.builder.build_conditional_branch(matches, found_bb, next_bb).unwrap();
+
+                self.builder.position_at_end(found_bb);
+                self.build_wasi_return(function, candidate.into());
+
+                self.builder.position_at_end(next_bb);
+                let index = self.builder.build_int_add(index, one, "i_next").unwrap();
+                self.builder.build_store(index_ptr, index).unwrap();
+                self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+                self.builder.position_at_end(missing_bb);
+                self.build_wasi_return(function, ptr_type.const_null().into());
+            }
+            "write" => {
+                let fd = self.wasi_int_param(function, 0);
+                let (data, size) = (self.wasi_ptr_param(function, 1), self.wasi_int_param(function, 2));
+                let written = self.build_wasi_io(function, callees[0], fd, data, size);
+                self.build_wasi_return(function, written.into());
+            }
+            "abort" => {
+                let trap = self.module.get_function("llvm.trap").unwrap_or_else(|| {
+                    self.module.add_function("llvm.trap", self.context.void_type().fn_type(&[], false), None)
+                });
+                self.builder.build_call(trap, &[], "").unwrap();
+                self.builder.build_unreachable().unwrap();
+            }
+            "puts" | "fputs" => {
+                // puts adds a newline; fputs writes to the stream's descriptor
+                let string = self.wasi_ptr_param(function, 0);
+                let fd = if name == "puts" { one } else { self.wasi_int_param(function, 1) };
+                let length = self.build_wasi_call(callees[0], &[string.into()]);
+                let mut written = self.build_wasi_call(callees[1], &[fd.into(), string.into(), length]).into_int_value();
+                if name == "puts" {
+                    let newline = self.builder.build_global_string_ptr("\n", "newline").unwrap();
+                    written = self.build_wasi_call(callees[1], &[fd.into(), newline.as_pointer_value().into(), one.into()])
+                        .into_int_value();
+                }
+                let failed = self.builder.build_int_compare(IntPredicate::SLT, written, zero, "failed").unwrap();
+                let result = self.builder.build_select(failed, minus_one, zero, "result").unwrap();
+                self.build_wasi_return(function, result);
+            }
+            "putchar" => {
+                let char_code = self.wasi_int_param(function, 0);
+                let slot = self.build_entry_alloca(function, i8_type.into(), "char");
+                let byte = self.builder.build_int_truncate(char_code, i8_type, "byte").unwrap();
+                self.builder.build_store(slot, byte).unwrap();
+                let written = self.build_wasi_call(callees[0], &[one.into(), slot.into(), one.into()]).into_int_value();
+                let failed = self.builder.build_int_compare(IntPredicate::SLT, written, zero, "failed").unwrap();
+                let result = self.builder.build_select(failed, minus_one, char_code, "result").unwrap();
+                self.build_wasi_return(function, result);
+            }
+            "fopen" => {
+                // "r" reads, "w" truncates or creates, "a" appends or creates; "+" also does the other
+                let (path, mode) = (self.wasi_ptr_param(function, 0), self.wasi_ptr_param(function, 1));
+                let path_len = self.build_wasi_call(callees[0], &[path.into()]);
+                let first = self.build_wasi_byte(mode, zero);
+                let second = self.build_wasi_byte(mode, one);
+                let is_char = |byte: IntValue<'ctx>, c: u8, label: &str| {
+                    self.builder
+                        .build_int_compare(IntPredicate::EQ, byte, i8_type.const_int(c as u64, false), label)
+                        .unwrap()
+                };
+                let reading = is_char(first, b'r', "reading");
+                let appending = is_char(first, b'a', "appending");
+                let update = is_char(second, b'+', "update");
+                let can_read = self.builder.build_or(reading, update, "can_read").unwrap();
+                let reading_only = self.builder.build_not(reading, "writing").unwrap();
+                let can_write = self.builder.build_or(reading_only, update, "can_write").unwrap();
+
+                let i64_type = self.context.i64_type();
+                let read_right = self.builder
+                    .build_select(can_read, i64_type.const_int(WASI_RIGHT_FD_READ, false), i64_type.const_zero(), "read_right")
+                    .unwrap()
+                    .into_int_value();
+                let write_right = self.builder
+                    .build_select(can_write, i64_type.const_int(WASI_RIGHT_FD_WRITE, false), i64_type.const_zero(), "write_right")
+                    .unwrap()
+                    .into_int_value();
+                let rights = self.builder.build_or(read_right, write_right, "rights").unwrap();
+                let truncate = self.builder
+                    .build_select(appending, zero, i32_type.const_int(WASI_OFLAGS_TRUNC, false), "truncate")
+                    .unwrap()
+                    .into_int_value();
+                let create = self.builder
+                    .build_or(truncate, i32_type.const_int(WASI_OFLAGS_CREAT, false), "create")
+                    .unwrap();
+                let oflags = self.builder.build_select(reading, zero, create, "oflags").unwrap();
+                let fdflags = self.builder
+                    .build_select(appending, i32_type.const_int(WASI_FDFLAGS_APPEND, false), zero, "fdflags")
+                    .unwrap();
+
+                let fd_ptr = self.build_entry_alloca(function, i32_type.into(), "fd");
+                let errno = self.builder
+                    .build_call(
+                        callees[1],
+                        &[
+                            i32_type.const_int(WASI_PREOPENED_DIR_FD, false).into(),
+                            i32_type.const_int(WASI_LOOKUPFLAGS_SYMLINK_FOLLOW, false).into(),
+                            path.into(),
+                            path_len.into(),
+                            oflags.into(),
+                            rights.into(),
+                            rights.into(),
+                            fdflags.into(),
+                            fd_ptr.into(),
+                        ],
+                        "errno",
+                    )
+                    .unwrap()
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                let fd = self.builder.build_load(i32_type, fd_ptr, "fd").unwrap().into_int_value();
+                let stream = self.builder.build_int_to_ptr(fd, ptr_type, "stream").unwrap();
+                let failed = self.builder.build_int_compare(IntPredicate::NE, errno, zero, "failed").unwrap();
+                let result = self.builder.build_select(failed, ptr_type.const_null(), stream, "result").unwrap();
+                self.build_wasi_return(function, result);
+            }
+            "fclose" => {
+                let fd = self.wasi_int_param(function, 0);
+                let errno = self.build_wasi_call(callees[0], &[fd.into()]).into_int_value();
+                let failed = self.builder.build_int_compare(IntPredicate::NE, errno, zero, "failed").unwrap();
+                let result = self.builder.build_select(failed, minus_one, zero, "result").unwrap();
+                self.build_wasi_return(function, result);
+            }
+            "fgets" => {
+                // Read a byte at a time up to a newline, the end of the file
+                // or a full buffer, and terminate the line with NUL
+                let buffer = self.wasi_ptr_param(function, 0);
+                let capacity = self.wasi_int_param(function, 1);
+                let fd = self.wasi_int_param(function, 2);
+                let limit = self.builder.build_int_sub(capacity, one, "limit").unwrap();
+                let count_ptr = self.build_entry_alloca(function, i32_type.into(), "count");
+                self.builder.build_store(count_ptr, zero).unwrap();
+                let loop_bb = self.context.append_basic_block(function, "loop");
+                let read_bb = self.context.append_basic_block(function, "read");
+                let check_bb = self.context.append_basic_block(function, "check");
+                let done_bb = self.context.append_basic_block(function, "done");
+                self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+                self.builder.position_at_end(loop_bb);
+                let count = self.builder.build_load(i32_type, count_ptr, "count").unwrap().into_int_value();
+                let room = self.builder.build_int_compare(IntPredicate::SLT, count, limit, "room").unwrap();
+                self.builder.build_conditional_branch(room, read_bb, done_bb).unwrap();
+
+                self.builder.position_at_end(read_bb);
+                let slot = unsafe { self.builder.build_gep(i8_type, buffer, &[count], "slot").unwrap() };
+                let read = self.build_wasi_io(function, callees[0], fd, slot, one);
+                let got_byte = self.builder.build_int_compare(IntPredicate::EQ, read, one, "got_byte").unwrap();
+                self.builder.build_conditional_branch(got_byte, check_bb, done_bb).unwrap();
+
+                self.builder.position_at_end(check_bb);
+                let byte = self.build_wasi_byte(buffer, count);
+                let count = self.builder.build_int_add(count, one, "count_next").unwrap();
+                self.builder.build_store(count_ptr, count).unwrap();
+                let newline = self.builder
+                    .build_int_compare(IntPredicate::EQ, byte, i8_type.const_int(b'\n' as u64, false), "newline")
+                    .unwrap();
+                self.builder.build_conditional_branch(newline, done_bb, loop_bb).unwrap();
+
+                self.builder.position_at_end(done_bb);
+                let count = self.builder.build_load(i32_type, count_ptr, "count").unwrap().into_int_value();
+                let end = unsafe { self.builder.build_gep(i8_type, buffer, &[count], "end").unwrap() };
+                self.builder.build_store(end, i8_type.const_zero()).unwrap();
+                let empty = self.builder.build_int_compare(IntPredicate::EQ, count, zero, "empty").unwrap();
+                let result = self.builder.build_select(empty, ptr_type.const_null(), buffer, "result").unwrap();
+                self.build_wasi_return(function, result);
+            }
+            _ => unreachable!("declared above"),
+        }
+        function
+    }
+
+    // Bump-allocate `size` bytes, 8-aligned, growing linear memory as needed;
+    // null once memory cannot grow
+    fn build_wasi_alloc(&self, function: FunctionValue<'ctx>, size: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let heap_top = self.module.get_global("__pole_heap_top").unwrap_or_else(|| {
+            let global = self.module.add_global(i32_type, None, "__pole_heap_top");
+            global.set_linkage(Linkage::Internal);
+            global.set_initializer(&i32_type.const_zero());
+            global
+        });
+        // The heap starts after the stack and static data, where wasm-ld puts `__heap_base`
+        let heap_base = self.module.get_global("__heap_base")
+            .unwrap_or_else(|| self.module.add_global(self.context.i8_type(), None, "__heap_base"));
+        let memory_size = self.module.get_function("llvm.wasm.memory.size.i32").unwrap_or_else(|| {
+            self.module.add_function("llvm.wasm.memory.size.i32", i32_type.fn_type(&[i32_type.into()], false), None)
+        });
+        let memory_grow = self.module.get_function("llvm.wasm.memory.grow.i32").unwrap_or_else(|| {
+            let grow_type = i32_type.fn_type(&[i32_type.into(), i32_type.into()], false);
+            self.module.add_function("llvm.wasm.memory.grow.i32", grow_type, None)
+        });
+
+        let top = self.builder.build_load(i32_type, heap_top.as_pointer_value(), "top").unwrap().into_int_value();
+        let base = self.builder.build_ptr_to_int(heap_base.as_pointer_value(), i32_type, "heap_base").unwrap();
+        let unset = self.builder.build_int_compare(IntPredicate::EQ, top, i32_type.const_zero(), "unset").unwrap();
+        let top = self.builder.build_select(unset, base, top, "top").unwrap().into_int_value();
+        let top = self.builder.build_int_add(top, i32_type.const_int(7, false), "top").unwrap();
+        let start = self.builder.build_and(top, i32_type.const_int(!7u32 as u64, false), "start").unwrap();
+        let end = self.builder.build_int_add(start, size, "end").unwrap();
+
+        let page_size = i32_type.const_int(WASM_PAGE_SIZE, false);
+        let memory_index = i32_type.const_zero();
+        let pages = self.builder
+            .build_call(memory_size, &[memory_index.into()], "pages")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let memory_end = self.builder.build_int_mul(pages, page_size, "memory_end").unwrap();
+        let grow_bb = self.context.append_basic_block(function, "grow");
+        let failed_bb = self.context.append_basic_block(function, "out_of_memory");
+        let done_bb = self.context.append_basic_block(function, "allocated");
+        let fits = self.builder.build_int_compare(IntPredicate::ULE, end, memory_end, "fits").unwrap();
+        self.builder.build_conditional_branch(fits, done_bb, grow_bb).unwrap();
+
+        self.builder.position_at_end(grow_bb);
+        let missing = self.builder.build_int_sub(end, memory_end, "missing").unwrap();
+        let missing = self.builder.build_int_add(missing, i32_type.const_int(WASM_PAGE_SIZE - 1, false), "missing").unwrap();
+        let new_pages = self.builder.build_int_unsigned_div(missing, page_size, "new_pages").unwrap();
+        let previous = self.builder
+            .build_call(memory_grow, &[memory_index.into(), new_pages.into()], "previous_pages")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let grown = self.builder
+            .build_int_compare(IntPredicate::NE, previous, i32_type.const_all_ones(), "grown")
+            .unwrap();
+        self.builder.build_conditional_branch(grown, done_bb, failed_bb).unwrap();
+
+        self.builder.position_at_end(failed_bb);
+        self.build_wasi_return(function, ptr_type.const_null().into());
+
+        self.builder.position_at_end(done_bb);
+        self.builder.build_store(heap_top.as_pointer_value(), end).unwrap();
+        self.builder.build_int_to_ptr(start, ptr_type, "block").unwrap()
+    }
+
+    // Read or write `size` bytes at `data` on `fd` with `io` (fd_read or
+    // fd_write); the number of bytes transferred, or -1 on error
+    fn build_wasi_io(
+        &self,
+        function: FunctionValue<'ctx>,
+        io: FunctionValue<'ctx>,
+        fd: IntValue<'ctx>,
+        data: PointerValue<'ctx>,
+        size: IntValue<'ctx>,
+    ) -> IntValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        // struct iovec { buf, buf_len }
+        let iovec_type = self.context.struct_type(&[ptr_type.into(), i32_type.into()], false);
+        let iovec = self.build_entry_alloca(function, iovec_type.into(), "iovec");
+        let transferred_ptr = self.build_entry_alloca(function, i32_type.into(), "transferred");
+        let buf_ptr = self.builder.build_struct_gep(iovec_type, iovec, 0, "iovec_buf").unwrap();
+        self.builder.build_store(buf_ptr, data).unwrap();
+        let len_ptr = self.builder.build_struct_gep(iovec_type, iovec, 1, "iovec_len").unwrap();
+        self.builder.build_store(len_ptr, size).unwrap();
+
+        let one = i32_type.const_int(1, false);
+        let errno = self.builder
+            .build_call(io, &[fd.into(), iovec.into(), one.into(), transferred_ptr.into()], "errno")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let transferred = self.builder.build_load(i32_type, transferred_ptr, "transferred").unwrap().into_int_value();
+        let failed = self.builder.build_int_compare(IntPredicate::NE, errno, i32_type.const_zero(), "failed").unwrap();
+        self.builder
+            .build_select(failed, i32_type.const_all_ones(), transferred, "result")
+            .unwrap()
+            .into_int_value()
+    }
+
+    // Call a runtime function, converting the arguments to the widths it was
+    // declared with; its result as an i32 or a pointer
+    fn build_wasi_call(&self, callee: FunctionValue<'ctx>, args: &[BasicValueEnum<'ctx>]) -> BasicValueEnum<'ctx> {
+        let args: Vec<BasicMetadataValueEnum<'ctx>> = args.iter()
+            .zip(callee.get_type().get_param_types())
+            .map(|(&arg, param_type)| match (arg, param_type) {
+                (BasicValueEnum::IntValue(value), BasicTypeEnum::IntType(int_type)) => self.builder
+                    .build_int_cast_sign_flag(value, int_type, true, "arg")
+                    .unwrap()
+                    .into(),
+                (arg, _) => arg.into(),
+            })
+            .collect();
+        let result = self.builder
+            .build_call(callee, &args, "result")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        match result {
+            BasicValueEnum::IntValue(value) => self.builder
+                .build_int_cast_sign_flag(value, self.context.i32_type(), true, "result")
+                .unwrap()
+                .into(),
+            other => other,
+        }
+    }
+
+    // Parameter `index` of a runtime function as an i32; streams and other
+    // pointers as their address
+    fn wasi_int_param(&self, function: FunctionValue<'ctx>, index: u32) -> IntValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        match function.get_nth_param(index).unwrap() {
+            BasicValueEnum::PointerValue(pointer) => self.builder.build_ptr_to_int(pointer, i32_type, "param").unwrap(),
+            param => self.builder
+                .build_int_cast_sign_flag(param.into_int_value(), i32_type, true, "param")
+                .unwrap(),
+        }
+    }
+
+    fn wasi_ptr_param(&self, function: FunctionValue<'ctx>, index: u32) -> PointerValue<'ctx> {
+        match function.get_nth_param(index).unwrap() {
+            BasicValueEnum::IntValue(address) => {
+                let ptr_type = self.context.ptr_type(AddressSpace::default());
+                self.builder.build_int_to_ptr(address, ptr_type, "param").unwrap()
+            }
+            param => param.into_pointer_value(),
+        }
+    }
+
+    // Store `byte(index)` to `dest[index]` for every index below `size`, a
+    // byte at a time: without bulk memory, wasm32 lowers the llvm.memcpy and
+    // llvm.memset intrinsics to calls to memcpy and memset themselves
+    fn build_wasi_byte_loop(
+        &self,
+        function: FunctionValue<'ctx>,
+        dest: PointerValue<'ctx>,
+        size: IntValue<'ctx>,
+        byte: impl Fn(&Self, IntValue<'ctx>) -> IntValue<'ctx>,
+    ) {
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let index_ptr = self.build_entry_alloca(function, i32_type.into(), "index");
+        self.builder.build_store(index_ptr, i32_type.const_zero()).unwrap();
+        let loop_bb = self.context.append_basic_block(function, "loop");
+        let body_bb = self.context.append_basic_block(function, "body");
+        let done_bb = self.context.append_basic_block(function, "done");
+        self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+        self.builder.position_at_end(loop_bb);
+        let index = self.builder.build_load(i32_type, index_ptr, "i").unwrap().into_int_value();
+        let more = self.builder.build_int_compare(IntPredicate::ULT, index, size, "more").unwrap();
+        self.builder.build_conditional_branch(more, body_bb, done_bb).unwrap();
+
+        self.builder.position_at_end(body_bb);
+        let value = byte(self, index);
+        let slot = unsafe { self.builder.build_gep(i8_type, dest, &[index], "slot").unwrap() };
+        self.builder.build_store(slot, value).unwrap();
+        let index = self.builder.build_int_add(index, i32_type.const_int(1, false), "i_next").unwrap();
+        self.builder.build_store(index_ptr, index).unwrap();
+        self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+        self.builder.position_at_end(done_bb);
+    }
+
+    // The byte at `data[index]`
+    fn build_wasi_byte(&self, data: PointerValue<'ctx>, index: IntValue<'ctx>) -> IntValue<'ctx> {
+        let i8_type = self.context.i8_type();
+        let byte_ptr = unsafe { self.builder.build_gep(i8_type, data, &[index], "byte_ptr").unwrap() };
+        self.builder.build_load(i8_type, byte_ptr, "byte").unwrap().into_int_value()
+    }
+
+    // Return `value` as the type the runtime function was declared to return
+    fn build_wasi_return(&self, function: FunctionValue<'ctx>, value: BasicValueEnum<'ctx>) {
+        let result: Option<BasicValueEnum<'ctx>> = match (function.get_type().get_return_type(), value) {
+            (None, _) => None,
+            (Some(BasicTypeEnum::IntType(int_type)), BasicValueEnum::IntValue(value)) => Some(
+                self.builder.build_int_cast_sign_flag(value, int_type, true, "ret").unwrap().into(),
+            ),
+            (Some(BasicTypeEnum::IntType(int_type)), BasicValueEnum::PointerValue(pointer)) => {
+                Some(self.builder.build_ptr_to_int(pointer, int_type, "ret").unwrap().into())
+            }
+            (Some(BasicTypeEnum::PointerType(ptr_type)), BasicValueEnum::IntValue(address)) => {
+                Some(self.builder.build_int_to_ptr(address, ptr_type, "ret").unwrap().into())
+            }
+            (Some(BasicTypeEnum::PointerType(_)), value) => Some(value),
+            (Some(other), _) => Some(other.const_zero()),
+        };
+        match result {
+            Some(value) => self.builder.build_return(Some(&value)).unwrap(),
+            None => self.builder.build_return(None).unwrap(),
+        };
+    }
+
+    // ========================================================================
+    // Debug info
+    // ========================================================================
+    //
+    // Every compiled function, lambdas included, gets a DWARF subprogram, and
+    // each instruction the line and column of the innermost expression it was
+    // compiled from. A local variable is a stack slot holding its value,
+    // described with `llvm.dbg.declare`; from -O1 on, mem2reg rewrites those
+    // into `llvm.dbg.value`. Int, Nat, Float64, Bool, Unit, String, record and
+    // tuple values are described; variables of other types are left out.
+
+    // Register the imported files the program's functions come from
+    fn add_debug_files(&mut self, program: &Program) {
+        let Some(debug) = &mut self.debug_info else { return };
+        for (function, path) in function_files(program) {
+            let index = match debug.files.iter().position(|file| file.path == path) {
+                Some(index) => index,
+                None => {
+                    // An unreadable file still names the function's source,
+                    // with every line reported as 1
+                    let source = std::fs::read_to_string(&path).unwrap_or_default();
+                    let (file_name, directory) = debug_file_name(Path::new(&path));
+                    let file = debug.builder.create_file(&file_name, &directory);
+                    debug.files.push(DebugFile::new(file, path, source));
+                    debug.files.len() - 1
+                }
+            };
+            debug.function_files.insert(function, index);
+        }
+    }
+
+    // File a function was defined in; instances of generic functions, named
+    // `f<Int>`, are in the file of `f`
+    fn debug_file_of(&self, name: &str) -> usize {
+        let name = name.split('<').next().unwrap_or(name);
+        self.debug_info.as_ref()
+            .and_then(|debug| debug.function_files.get(name).copied())
+            .unwrap_or(0)
+    }
+
+    // Give `function` a subprogram at `span` in `file` and make it the scope
+    // of the locations set from here on
+    fn begin_debug_scope(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        name: &str,
+        span: Span,
+        file: usize,
+    ) -> OuterDebugScope<'ctx> {
+        let Some(debug) = &mut self.debug_info else { return (None, None) };
+        let outer_location = debug.location;
+        let (line, _) = debug.files[file].line_col(span);
+        let di_file = debug.files[file].file;
+        let subroutine_type = debug.builder.create_subroutine_type(di_file, None, &[], DIFlags::PUBLIC);
+        let subprogram = debug.builder.create_function(
+            debug.compile_unit.as_debug_info_scope(),
+            name,
+            None,
+            di_file,
+            line,
+            subroutine_type,
+            function.get_linkage() == Linkage::Internal,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            self.opt_level != OptLevel::O0,
+        );
+        function.set_subprogram(subprogram);
+        let outer_scope = debug.scope.replace(DebugScope { scope: subprogram.as_debug_info_scope(), file });
+        self.set_debug_location(span);
+        (outer_scope, outer_location)
+    }
+
+    fn end_debug_scope(&mut self, function: FunctionValue<'ctx>, (outer_scope, outer_location): OuterDebugScope<'ctx>) {
+        if let Some(debug) = &mut self.debug_info {
+            // The subprogram's variable list stays a temporary node until
+            // finalized, and the verifier rejects the function while it is
+            if let Some(subprogram) = function.get_subprogram() {
+                unsafe {
+                    inkwell::llvm_sys::debuginfo::LLVMDIBuilderFinalizeSubprogram(
+                        debug.builder.as_mut_ptr(),
+                        subprogram.as_mut_ptr(),
+                    );
+                }
+            }
+            debug.scope = outer_scope;
+        }
+        self.restore_debug_location(outer_location);
+    }
+
+    // Locate the instructions built from here on at `span`; returns the
+    // location to restore afterwards
+    fn set_debug_location(&mut self, span: Span) -> Option<DILocation<'ctx>> {
+        let debug = self.debug_info.as_mut()?;
+        let scope = debug.scope?;
+        let (line, column) = debug.files[scope.file].line_col(span);
+        let location = debug.builder.create_debug_location(self.context, line, column, scope.scope, None);
+        self.builder.set_current_debug_location(location);
+        debug.location.replace(location)
+    }
+
+    // Locate the instructions built from here on at `location`, or nowhere
+    fn restore_debug_location(&mut self, location: Option<DILocation<'ctx>>) {
+        let Some(debug) = &mut self.debug_info else { return };
+        debug.location = location;
+        match location {
+            Some(location) => self.builder.set_current_debug_location(location),
+            None => self.builder.unset_current_debug_location(),
+        }
+    }
+
+    // Describe a variable bound at `span` to the debugger. `arg_no` numbers
+    // parameters from 1; `None` makes a local.
+    fn declare_debug_variable(
+        &self,
+        name: &str,
+        value: BasicValueEnum<'ctx>,
+        ty: Option<&Type>,
+        span: Span,
+        arg_no: Option<u32>,
+        function: FunctionValue<'ctx>,
+    ) {
+        let Some(debug) = &self.debug_info else { return };
+        let Some(scope) = debug.scope else { return };
+        if name == "_" {
+            return;
+        }
+        let Some(di_type) = ty.and_then(|ty| self.debug_type(ty)) else { return };
+        
+        let (line, column) = debug.files[scope.file].line_col(span);
+        let file = debug.files[scope.file].file;
+        let variable = match arg_no {
+            Some(arg_no) => debug.builder.create_parameter_variable(
+                scope.scope, name, arg_no, file, line, di_type, true, DIFlags::ZERO,
+            ),
+            None => debug.builder.create_auto_variable(
+                scope.scope, name, file, line, di_type, true, DIFlags::ZERO, 0,
+            ),
+        };
+        let location = debug.builder.create_debug_location(self.context, line, column, scope.scope, None);
+        let slot = self.build_entry_alloca(function, value.get_type(), name);
+        self.builder.build_store(slot, value).unwrap();
+        let block = self.builder.get_insert_block().unwrap();
+        debug.builder.insert_declare_at_end(slot, Some(variable), None, location, block);
+    }
+
+    // DWARF type of values of type `ty`, if it is one described
+    fn debug_type(&self, ty: &Type) -> Option<DIType<'ctx>> {
+        let debug = self.debug_info.as_ref()?;
+        let encoding = match ty {
+            Type::Basic(AstBasicType { name }) => match name.as_str() {
+                "Int" => DW_ATE_SIGNED,
+                "Nat" | "Unit" => DW_ATE_UNSIGNED,
+                "Float64" => DW_ATE_FLOAT,
+                "Bool" => DW_ATE_BOOLEAN,
+                "String" => {
+                    let char_type = debug.builder
+                        .create_basic_type("char", 8, DW_ATE_SIGNED_CHAR, DIFlags::PUBLIC)
+                        .ok()?
+                        .as_type();
+                    let pointer_bits = debug.target_data.get_pointer_byte_size(None) as u64 * 8;
+                    let data_type = debug.builder
+                        .create_pointer_type("char*", char_type, pointer_bits, pointer_bits as u32, AddressSpace::default())
+                        .as_type();
+                    let length_type = self.debug_type(&Type::Basic(AstBasicType { name: "Int".to_string() }));
+                    let members = vec![("data".to_string(), Some(data_type)), ("length".to_string(), length_type)];
+                    return self.debug_struct_type(name, ty, members);
+                }
+                type_name => {
+                    let record = self.type_defs.get(type_name)?;
+                    let members = record.fields.iter()
+                        .map(|(field, field_type)| (field.clone(), self.debug_type(field_type)))
+                        .collect();
+                    return self.debug_struct_type(type_name, ty, members);
+                }
+            },
+            Type::Tuple(tuple) => {
+                let members = tuple.element_types.iter()
+                    .enumerate()
+                    .map(|(index, element_type)| (index.to_string(), self.debug_type(element_type)))
+                    .collect();
+                return self.debug_struct_type(&ty.to_string(), ty, members);
+            }
+            _ => return None,
+        };
+        let size = debug.target_data.get_abi_size(&self.compile_type(ty)) * 8;
+        debug.builder
+            .create_basic_type(&ty.to_string(), size, encoding, DIFlags::PUBLIC)
+            .ok()
+            .map(|basic| basic.as_type())
+    }
+
+    // Structure type laid out as `ty` compiles, with the members that have a
+    // DWARF type
+    fn debug_struct_type(
+        &self,
+        name: &str,
+        ty: &Type,
+        members: Vec<(String, Option<DIType<'ctx>>)>,
+    ) -> Option<DIType<'ctx>> {
+        let debug = self.debug_info.as_ref()?;
+        let struct_type = self.compile_type(ty).into_struct_type();
+        let scope = debug.compile_unit.as_debug_info_scope();
+        let file = debug.compile_unit.get_file();
+        let elements: Vec<DIType> = members.into_iter()
+            .enumerate()
+            .filter_map(|(index, (member, member_type))| {
+                let field_type = struct_type.get_field_type_at_index(index as u32)?;
+                let offset = debug.target_data.offset_of_element(&struct_type, index as u32)?;
+                let member_type = debug.builder.create_member_type(
+                    scope,
+                    &member,
+                    file,
+                    0,
+                    debug.target_data.get_abi_size(&field_type) * 8,
+                    debug.target_data.get_abi_alignment(&field_type) * 8,
+                    offset * 8,
+                    DIFlags::PUBLIC,
+                    member_type?,
+                );
+                Some(member_type.as_type())
+            })
+            .collect();
+        let struct_type = debug.builder.create_struct_type(
+            scope,
+            name,
+            file,
+            0,
+            debug.target_data.get_abi_size(&struct_type) * 8,
+            debug.target_data.get_abi_alignment(&struct_type) * 8,
+            DIFlags::PUBLIC,
+            None,
+            &elements,
+            0,
+            None,
+            name,
+        );
+        Some(struct_type.as_type())
+    }
+
     pub fn get_module(&self) -> &Module<'ctx> {
         &self.module
     }
 
+    /// LLVM representation of a Pole type, for reading compiled values back
+    /// out of memory. Record and variant names resolve against the program
+    /// compiled last.
+    pub fn llvm_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        self.compile_type(ty)
+    }
+
     pub fn print_to_string(&self) -> String {
         self.module.print_to_string().to_string()
     }
@@ -2427,28 +5015,245 @@ impl<'ctx, 'arena> CodeGen<'ctx, 'arena> {
         Ok(())
     }
 
-    /// Write object file (.o)
-    pub fn write_object_file(&self, path: &Path) -> Result<(), String> {
-        Target::initialize_native(&InitializationConfig::default())
-            .map_err(|e| format!("Failed to initialize native target: {}", e))?;
-
-        let target_triple = TargetMachine::get_default_triple();
+    /// Target machine for the configured target and optimization level
+    pub fn target_machine(&self) -> Result<TargetMachine, String> {
+        if self.target.triple.is_some() {
+            Target::initialize_all(&InitializationConfig::default());
+        } else {
+            Target::initialize_native(&InitializationConfig::default())
+                .map_err(|e| format!("Failed to initialize native target: {}", e))?;
+        }
+        
+        let target_triple = self.target.target_triple();
+        let triple_name = target_triple.as_str().to_string_lossy().into_owned();
         let target = Target::from_triple(&target_triple)
-            .map_err(|e| format!("Failed to create target: {}", e))?;
+            .map_err(|e| format!("Unknown target '{}': {}", triple_name, e))?;
+
+        // The WASI runtime's memcpy and memset lower to bulk memory
+        // instructions rather than calls to themselves
+        let mut features = self.target.features.clone();
+        if self.target.is_wasi() && !features.contains("bulk-memory") {
+            features = if features.is_empty() { "+bulk-memory".to_string() } else { format!("+bulk-memory,{}", features) };
+        }
+        // wasm-ld links position-dependent code
+        let reloc_mode = if self.target.is_wasi() { RelocMode::Static } else { RelocMode::PIC };
 
-        let target_machine = target
+        target
             .create_target_machine(
                 &target_triple,
-                "generic",
-                "",
-                OptimizationLevel::Default,
-                RelocMode::PIC,
+                &self.target.cpu,
+                &features,
+                self.opt_level.llvm(),
+                reloc_mode,
                 CodeModel::Default,
             )
-            .ok_or_else(|| "Failed to create target machine".to_string())?;
+            .ok_or_else(|| format!("Failed to create target machine for '{}'", triple_name))
+    }
+
+    /// Run the `default<On>` pipeline of LLVM's new pass manager over the
+    /// module. `@inline` functions are inlined even at `O0`.
+    pub fn optimize(&self) -> Result<(), String> {
+        self.module
+            .verify()
+            .map_err(|e| format!("Module verification failed: {}", e))?;
+        let pipeline = format!("default<{}>", self.opt_level.name());
+        self.module
+            .run_passes(&pipeline, &self.target_machine()?, PassBuilderOptions::create())
+            .map_err(|e| format!("Optimization failed: {}", e))
+    }
 
-        target_machine
+    /// Write object file (.o) for the configured target, generating code at
+    /// the configured optimization level. Call `optimize` first to also
+    /// optimize the IR.
+    pub fn write_object_file(&self, path: &Path) -> Result<(), String> {
+        self.target_machine()?
             .write_to_file(&self.module, FileType::Object, path)
             .map_err(|e| format!("Failed to write object file: {}", e))
     }
 }
+
+// File name and absolute directory of `path`, as DWARF records them
+fn debug_file_name(path: &Path) -> (String, String) {
+    let file_name = path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let directory = if parent.is_absolute() {
+        parent.to_path_buf()
+    } else {
+        let current_dir = std::env::current_dir().unwrap_or_default();
+        if parent.as_os_str().is_empty() { current_dir } else { current_dir.join(parent) }
+    };
+    (file_name, directory.display().to_string())
+}
+
+fn wildcard() -> Pattern {
+    Pattern::Wildcard(WildcardPattern { span: Span::default() })
+}
+
+// A row per alternative of the or-patterns heading its columns
+fn expand_or_patterns(row: ClauseRow<'_>) -> Vec<ClauseRow<'_>> {
+    let Some(column) = row.patterns.iter().position(|pattern| matches!(pattern, Pattern::Or(_))) else {
+        return vec![row];
+    };
+    let Pattern::Or(or) = row.patterns[column].clone() else { unreachable!() };
+    or.alternatives
+        .into_iter()
+        .flat_map(|alternative| {
+            let mut expanded = row.clone();
+            expanded.patterns[column] = alternative;
+            expand_or_patterns(expanded)
+        })
+        .collect()
+}
+
+// Rows that can still match once `column` is known to hold a value of some
+// shape, with that column replaced by the `arity` sub-patterns the shape has.
+// `sub_patterns` gives them for a pattern of that shape and `None` for one of
+// another; wildcards match every shape.
+fn specialize_rows<'ctx>(
+    rows: &[ClauseRow<'ctx>],
+    column: usize,
+    arity: usize,
+    sub_patterns: impl Fn(&Pattern) -> Option<Vec<Pattern>>,
+) -> Vec<ClauseRow<'ctx>> {
+    rows.iter()
+        .filter_map(|row| {
+            let replacement = match &row.patterns[column] {
+                Pattern::Wildcard(_) => vec![wildcard(); arity],
+                pattern => sub_patterns(pattern)?,
+            };
+            let mut row = row.clone();
+            row.patterns.splice(column..=column, replacement);
+            Some(row)
+        })
+        .collect()
+}
+
+fn splice_occurrences<'ctx>(
+    occurrences: &[Occurrence<'ctx>],
+    column: usize,
+    replacement: Vec<Occurrence<'ctx>>,
+) -> Vec<Occurrence<'ctx>> {
+    let mut spliced = occurrences.to_vec();
+    spliced.splice(column..=column, replacement);
+    spliced
+}
+
+// Element `field` of a tuple of type `tuple_type`, as in `t.0`
+fn tuple_index(field: &str, tuple_type: &TupleType) -> Result<usize, String> {
+    field.parse::<usize>()
+        .ok()
+        .filter(|index| *index < tuple_type.element_types.len())
+        .ok_or_else(|| format!("Tuple of {} elements has no element '{}'", tuple_type.element_types.len(), field))
+}
+
+// Result type of applying a function of type `ty` to `count` arguments
+fn peel_function_type(ty: Type, count: usize) -> Result<Type, String> {
+    let mut result = ty;
+    for _ in 0..count {
+        result = match result {
+            Type::Function(ft) => *ft.return_type,
+            other => return Err(format!("Cannot call a value of type {:?}", other)),
+        };
+    }
+    Ok(result)
+}
+
+// Bind the type parameters in `param_type` by matching it against the
+// type of the argument passed for it
+fn bind_type_params(param_type: &Type, arg_type: &Type, type_params: &[String], out: &mut HashMap<String, Type>) {
+    match (param_type, arg_type) {
+        (Type::Basic(basic), _) if type_params.contains(&basic.name) => {
+            out.entry(basic.name.clone()).or_insert_with(|| arg_type.clone());
+        }
+        (Type::Option(p), Type::Option(a)) => bind_type_params(&p.inner, &a.inner, type_params, out),
+        (Type::Result(p), Type::Result(a)) => {
+            bind_type_params(&p.ok_type, &a.ok_type, type_params, out);
+            bind_type_params(&p.err_type, &a.err_type, type_params, out);
+        }
+        (Type::List(p), Type::List(a)) => bind_type_params(&p.element_type, &a.element_type, type_params, out),
+        (Type::Pointer(p), Type::Pointer(a)) => bind_type_params(&p.pointee_type, &a.pointee_type, type_params, out),
+        (Type::Tuple(p), Type::Tuple(a)) => {
+            for (p, a) in p.element_types.iter().zip(&a.element_types) {
+                bind_type_params(p, a, type_params, out);
+            }
+        }
+        (Type::Record(p), Type::Record(a)) => {
+            for ((_, p), (_, a)) in p.fields.iter().zip(&a.fields) {
+                bind_type_params(p, a, type_params, out);
+            }
+        }
+        (Type::Function(p), Type::Function(a)) => {
+            bind_type_params(&p.param_type, &a.param_type, type_params, out);
+            bind_type_params(&p.return_type, &a.return_type, type_params, out);
+        }
+        _ => {}
+    }
+}
+
+// Names `expr` uses that are not bound inside it, in order of first use
+fn free_variables(expr: &Expr, bound: &mut Vec<String>, out: &mut Vec<String>) {
+    match expr {
+        Expr::Literal(_) => {}
+        Expr::Variable(var) => {
+            if !bound.contains(&var.name) && !out.contains(&var.name) {
+                out.push(var.name.clone());
+            }
+        }
+        Expr::Lambda(lambda) => {
+            let depth = bound.len();
+            bound.extend(lambda.params.iter().map(|(name, _)| name.clone()));
+            free_variables(&lambda.body, bound, out);
+            bound.truncate(depth);
+        }
+        Expr::Application(app) => {
+            free_variables(&app.func, bound, out);
+            free_variables(&app.arg, bound, out);
+        }
+        Expr::Let(let_expr) => {
+            free_variables(&let_expr.value, bound, out);
+            bound.push(let_expr.var_name.clone());
+            free_variables(&let_expr.body, bound, out);
+            bound.pop();
+        }
+        Expr::If(if_expr) => {
+            free_variables(&if_expr.condition, bound, out);
+            free_variables(&if_expr.then_branch, bound, out);
+            free_variables(&if_expr.else_branch, bound, out);
+        }
+        Expr::Match(match_expr) => {
+            free_variables(&match_expr.scrutinee, bound, out);
+            for arm in &match_expr.arms {
+                let depth = bound.len();
+                arm.pattern.collect_bindings(bound);
+                if let Some(guard) = &arm.guard {
+                    free_variables(guard, bound, out);
+                }
+                free_variables(&arm.body, bound, out);
+                bound.truncate(depth);
+            }
+        }
+        Expr::Constructor(constructor) => {
+            for arg in &constructor.args {
+                free_variables(arg, bound, out);
+            }
+        }
+        Expr::BinaryOp(binop) => {
+            free_variables(&binop.left, bound, out);
+            free_variables(&binop.right, bound, out);
+        }
+        Expr::UnaryOp(unop) => free_variables(&unop.operand, bound, out),
+        Expr::Tuple(tuple) => {
+            for element in &tuple.elements {
+                free_variables(element, bound, out);
+            }
+        }
+        Expr::Record(record) => {
+            for (_, value) in &record.fields {
+                free_variables(value, bound, out);
+            }
+        }
+        Expr::FieldAccess(field_access) => free_variables(&field_access.record, bound, out),
+    }
+}
@@ -0,0 +1,176 @@
+// Differential Testing
+// Runs every `@test_case` of a program, and its `main`, both through the
+// reference interpreter and as JIT-compiled code, and reports where what
+// the two return differs. The interpreter gives the expected values, so the
+// cases need no `expected` of their own to be compared.
+
+use std::fmt;
+
+use crate::ast::*;
+use crate::codegen::CodegenError;
+use crate::interpreter::{has_default_stub, Interpreter};
+use crate::linker::flatten_program;
+use crate::test_runner::{
+    annotation_arg, call_syntax, case_thunk, run_prepared, PreparedCase, TestOutcome, TestResult, TestValue,
+    TypeTable,
+};
+
+/// How the interpreter and the JIT compare on one case
+#[derive(Debug, Clone, PartialEq)]
+pub enum Comparison {
+    /// Both returned this value
+    Agreed(TestValue),
+    Mismatch { interpreter: TestValue, jit: TestValue },
+    /// The case could not be run on one side: a runtime error in the
+    /// interpreter, an extern the JIT cannot resolve, a type the runner
+    /// cannot read back, ...
+    Skipped(String),
+}
+
+/// One `@test_case`, or `main`
+#[derive(Debug, Clone)]
+pub struct CaseComparison {
+    pub function: String,
+    /// `input` as written in the annotation
+    pub input: Option<String>,
+    pub comparison: Comparison,
+}
+
+/// `✓ factorial(5) = 120`, `✗ size(3): interpreter 3, JIT 2`,
+/// `- main(): No stub for extern function 'SDL_Init'`
+impl fmt::Display for CaseComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let call = call_syntax(&self.function, self.input.as_deref());
+        match &self.comparison {
+            Comparison::Agreed(value) => write!(f, "✓ {} = {}", call, value),
+            Comparison::Mismatch { interpreter, jit } => {
+                write!(f, "✗ {}: interpreter {}, JIT {}", call, interpreter, jit)
+            }
+            Comparison::Skipped(reason) => write!(f, "- {}: {}", call, reason),
+        }
+    }
+}
+
+/// Comparisons for every case of a program, `@test_case`s in declaration
+/// order and then `main`
+#[derive(Debug, Clone, Default)]
+pub struct DifferentialReport {
+    pub cases: Vec<CaseComparison>,
+}
+
+impl DifferentialReport {
+    fn count(&self, predicate: impl Fn(&Comparison) -> bool) -> usize {
+        self.cases.iter().filter(|case| predicate(&case.comparison)).count()
+    }
+
+    pub fn agreed(&self) -> usize {
+        self.count(|comparison| matches!(comparison, Comparison::Agreed(_)))
+    }
+
+    pub fn mismatched(&self) -> usize {
+        self.count(|comparison| matches!(comparison, Comparison::Mismatch { .. }))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|comparison| matches!(comparison, Comparison::Skipped(_)))
+    }
+
+    /// No case gave different results; skipped cases do not count
+    pub fn all_agreed(&self) -> bool {
+        self.mismatched() == 0
+    }
+}
+
+impl fmt::Display for DifferentialReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            writeln!(f, "{}", case)?;
+        }
+        write!(f, "{} agreed, {} mismatched, {} skipped", self.agreed(), self.mismatched(), self.skipped())
+    }
+}
+
+/// Run the program's `@test_case` inputs and its `main` through the
+/// interpreter, then compile the program and check the JIT returns the same
+/// values. Only cases the interpreter completes are run natively, and none
+/// are when the program declares an extern the interpreter has no stub for,
+/// since the JIT cannot resolve arbitrary libraries. As with
+/// `run_test_cases`, a case that crashes natively takes the process down.
+pub fn run_differential(program: &Program) -> Result<DifferentialReport, CodegenError> {
+    let program = flatten_program(program).map_err(CodegenError::from)?;
+    let types = TypeTable::new(&program);
+
+    let mut calls: Vec<(&FunctionDef, Option<&str>)> = Vec::new();
+    for function in &program.func_defs {
+        let annotations = function.annotations.iter().filter(|annotation| annotation.name == "test_case");
+        calls.extend(annotations.map(|annotation| (function, annotation_arg(annotation, "input"))));
+    }
+    // `main` as a case of its own, unless it has test cases already
+    let main = program.func_defs.iter().find(|function| function.name == "main");
+    if let Some(main) = main.filter(|main| !main.annotations.iter().any(|annotation| annotation.name == "test_case")) {
+        match main.params.as_slice() {
+            [] => calls.push((main, None)),
+            [(_, Type::Basic(basic))] if basic.name == "Unit" => calls.push((main, Some("()"))),
+            _ => {}
+        }
+    }
+    let thunks: Vec<Result<FunctionDef, String>> = calls.iter()
+        .enumerate()
+        .map(|(index, (function, input))| case_thunk(function, *input, format!("__differential_{}", index)))
+        .collect();
+
+    let mut interpreter = Interpreter::new(&program);
+    let interpreted: Vec<Result<TestValue, String>> = thunks.iter()
+        .map(|thunk| {
+            let thunk = thunk.as_ref().map_err(Clone::clone)?;
+            let value = interpreter.eval(&thunk.body).map_err(|error| error.to_string());
+            interpreter.take_output();
+            types.interpreted(&value?, &thunk.return_type)
+        })
+        .collect();
+
+    let mut results: Vec<TestResult> = calls.iter()
+        .map(|(function, input)| TestResult {
+            function: function.name.clone(),
+            index: 0,
+            input: input.map(str::to_string),
+            outcome: TestOutcome::Passed,
+            duration: Default::default(),
+        })
+        .collect();
+    let unresolved = program.extern_funcs.iter().find(|decl| !has_default_stub(&decl.c_name));
+    match unresolved {
+        Some(decl) => {
+            let reason = format!("the JIT cannot resolve extern function '{}'", decl.c_name);
+            for result in &mut results {
+                result.outcome = TestOutcome::Error(reason.clone());
+            }
+        }
+        None => {
+            let cases = thunks.iter()
+                .zip(&interpreted)
+                .enumerate()
+                .filter_map(|(result, (thunk, value))| match (thunk, value) {
+                    (Ok(thunk), Ok(value)) => Some(PreparedCase { result, thunk: thunk.clone(), expected: value.clone() }),
+                    _ => None,
+                })
+                .collect();
+            run_prepared(&program, &types, cases, &mut results)?;
+        }
+    }
+
+    let cases = results.into_iter()
+        .zip(interpreted)
+        .map(|(result, value)| {
+            let comparison = match (value, result.outcome) {
+                (Err(message), _) | (_, TestOutcome::Error(message)) => Comparison::Skipped(message),
+                (Ok(value), TestOutcome::Passed) => Comparison::Agreed(value),
+                (Ok(_), TestOutcome::Failed { expected, actual }) => {
+                    Comparison::Mismatch { interpreter: expected, jit: actual }
+                }
+            };
+            CaseComparison { function: result.function, input: result.input, comparison }
+        })
+        .collect();
+    Ok(DifferentialReport { cases })
+}
@@ -0,0 +1,595 @@
+// Compiler Driver
+// The pipeline behind `polec`: load a program with its imports, type-check
+// it, compile it with CodeGen, and turn the module into LLVM IR, an object
+// file or an executable, or run it on the JIT.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use bumpalo::Bump;
+use inkwell::builder::{Builder, BuilderError};
+use inkwell::context::Context;
+use inkwell::values::{FunctionValue, IntValue};
+use thiserror::Error;
+
+use crate::ast::*;
+pub use crate::codegen::{OptLevel, TargetSpec};
+
+use crate::codegen::CodeGen;
+use crate::link_manifest::LinkManifest;
+use crate::linker::{load_program, LinkError};
+use crate::printer::format_source;
+use crate::type_checker::check_types;
+
+// ============================================================================
+// Options
+// ============================================================================
+
+/// How to compile, link and run a program
+#[derive(Debug, Clone)]
+pub struct DriverOptions {
+    pub opt_level: OptLevel,
+    /// Function the program starts in, `main` if not given. It takes no
+    /// arguments and returns `Int`, `Nat`, `Bool` or `Unit`, which becomes
+    /// the exit code.
+    pub entry: Option<String>,
+    /// Libraries to link, as for `cc -l`
+    pub link_libs: Vec<String>,
+    /// Directories searched for them, as for `cc -L`
+    pub lib_dirs: Vec<PathBuf>,
+    /// Compile `requires`/`ensures` clauses into runtime checks
+    pub contract_checks: bool,
+    /// Emit DWARF debug info for the program's source files
+    pub debug_info: bool,
+    /// Target triple to compile for, as for `clang --target`; the host if
+    /// not given
+    pub target: Option<String>,
+    /// CPU to generate code for, as for `-mcpu`
+    pub cpu: Option<String>,
+    /// CPU features to enable or disable, as `+neon,-sve`
+    pub features: Option<String>,
+    /// Directory to write the module's IR to, as `<name>.before.ll` and
+    /// `<name>.after.ll` around optimization
+    pub dump_ir: Option<PathBuf>,
+}
+
+impl DriverOptions {
+    pub fn entry_name(&self) -> &str {
+        self.entry.as_deref().unwrap_or("main")
+    }
+
+    /// The target to compile for. Without a triple, the host with its own
+    /// CPU and features; with one, the `generic` CPU unless `cpu` is given.
+    pub fn target_spec(&self) -> TargetSpec {
+        let host = TargetSpec::host();
+        let native = self.target.is_none();
+        TargetSpec {
+            triple: self.target.clone(),
+            cpu: self.cpu.clone().unwrap_or_else(|| if native { host.cpu } else { "generic".to_string() }),
+            features: self.features.clone()
+                .unwrap_or_else(|| if native && self.cpu.is_none() { host.features } else { String::new() }),
+        }
+    }
+}
+
+impl Default for DriverOptions {
+    fn default() -> Self {
+        DriverOptions {
+            opt_level: OptLevel::default(),
+            entry: None,
+            link_libs: vec![],
+            lib_dirs: vec![],
+            contract_checks: false,
+            debug_info: false,
+            target: None,
+            cpu: None,
+            features: None,
+            dump_ir: None,
+        }
+    }
+}
+
+// ============================================================================
+// Errors
+// ============================================================================
+
+/// An error located in a source file: `file:line:col: message`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line_col: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(file: &str, source: Option<&str>, span: Option<Span>, message: impl Into<String>) -> Self {
+        Diagnostic {
+            file: file.to_string(),
+            line_col: span.zip(source).map(|(span, source)| span.line_col(source)),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line_col {
+            Some((line, col)) => write!(f, "{}:{}:{}: error: {}", self.file, line, col, self.message),
+            None => write!(f, "{}: error: {}", self.file, self.message),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DriverError {
+    /// The program does not parse, link, type-check or compile
+    #[error("{}", render_all(.0))]
+    Diagnostics(Vec<Diagnostic>),
+
+    /// Emitting, linking or running the compiled program failed
+    #[error("{0}")]
+    Failed(String),
+}
+
+fn render_all(diagnostics: &[Diagnostic]) -> String {
+    diagnostics.iter().map(Diagnostic::to_string).collect::<Vec<_>>().join("\n")
+}
+
+// ============================================================================
+// Pipeline
+// ============================================================================
+
+/// A loaded and type-checked program, with the source its diagnostics point into
+pub struct CheckedProgram {
+    pub program: Program,
+    pub file: String,
+    pub source: String,
+}
+
+impl CheckedProgram {
+    fn diagnostic(&self, span: Option<Span>, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(&self.file, Some(&self.source), span, message)
+    }
+}
+
+/// Parse the file at `path`, load its imports and type-check the result
+pub fn load_and_check(path: &Path) -> Result<CheckedProgram, DriverError> {
+    let file = path.display().to_string();
+    let source = fs::read_to_string(path)
+        .map_err(|e| DriverError::Diagnostics(vec![Diagnostic::new(&file, None, None, e.to_string())]))?;
+    let program = load_program(path)
+        .map_err(|errors| DriverError::Diagnostics(errors.iter().map(|e| link_diagnostic(e, &file, &source)).collect()))?;
+
+    let checked = CheckedProgram { program, file, source };
+    let result = check_types(checked.program.clone());
+    if !result.success {
+        let diagnostics = result.errors
+            .into_iter()
+            .map(|error| checked.diagnostic(error.span, error.message))
+            .collect();
+        return Err(DriverError::Diagnostics(diagnostics));
+    }
+    Ok(checked)
+}
+
+/// Read the file at `path` and format it as `polec fmt` does: the source as
+/// read and its canonical form. Imports are not loaded.
+pub fn format_file(path: &Path) -> Result<(String, String), DriverError> {
+    let file = path.display().to_string();
+    let source = fs::read_to_string(path)
+        .map_err(|e| DriverError::Diagnostics(vec![Diagnostic::new(&file, None, None, e.to_string())]))?;
+    match format_source(&source) {
+        Ok(formatted) => Ok((source, formatted)),
+        Err(errors) => Err(DriverError::Diagnostics(
+            errors.into_iter().map(|error| Diagnostic::new(&file, Some(&source), Some(error.span), error.message)).collect(),
+        )),
+    }
+}
+
+// Errors in imported files are located against those files' sources
+fn link_diagnostic(error: &LinkError, root_file: &str, root_source: &str) -> Diagnostic {
+    match &error.file {
+        Some(file) if file != root_file => {
+            let source = fs::read_to_string(file).ok();
+            Diagnostic::new(file, source.as_deref(), error.span, error.message.clone())
+        }
+        _ => Diagnostic::new(root_file, Some(root_source), error.span, error.message.clone()),
+    }
+}
+
+/// Compile a checked program into an LLVM module optimized at the options'
+/// level. With an entry function other than `main`, a C `main` calling it
+/// is added, so the module links into an executable as it is; for
+/// wasm32-wasi, a `_start` calling it and exiting with its result.
+pub fn compile<'ctx, 'arena>(
+    checked: &CheckedProgram,
+    options: &DriverOptions,
+    context: &'ctx Context,
+    arena: &'arena Bump,
+) -> Result<CodeGen<'ctx, 'arena>, DriverError> {
+    let module_name = Path::new(&checked.file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "main".to_string());
+    let mut codegen = CodeGen::new(context, &module_name, arena);
+    codegen.set_contract_checks(options.contract_checks);
+    codegen.set_opt_level(options.opt_level);
+    codegen.set_target(options.target_spec()).map_err(DriverError::Failed)?;
+    if options.debug_info {
+        codegen.enable_debug_info(Path::new(&checked.file), &checked.source)
+            .map_err(DriverError::Failed)?;
+    }
+    codegen.compile_program(&checked.program)
+        .map_err(|error| DriverError::Diagnostics(vec![checked.diagnostic(error.span, error.message)]))?;
+
+    if options.target_spec().is_wasi() {
+        let entry = entry_function(checked, options.entry_name())?;
+        add_wasi_start(context, &codegen, entry)?;
+    } else if let Some(name) = &options.entry {
+        let entry = entry_function(checked, name)?;
+        if entry.name != "main" {
+            add_c_main(context, &codegen, entry)?;
+        }
+    }
+
+    dump_ir(&codegen, options, &module_name, "before")?;
+    codegen.optimize().map_err(DriverError::Failed)?;
+    dump_ir(&codegen, options, &module_name, "after")?;
+    Ok(codegen)
+}
+
+// `<dir>/<name>.<stage>.ll` when the options ask for IR dumps
+fn dump_ir(codegen: &CodeGen, options: &DriverOptions, name: &str, stage: &str) -> Result<(), DriverError> {
+    let Some(dir) = &options.dump_ir else { return Ok(()) };
+    fs::create_dir_all(dir)
+        .map_err(|e| DriverError::Failed(format!("Cannot create {}: {}", dir.display(), e)))?;
+    codegen.write_ir_to_file(&dir.join(format!("{}.{}.ll", name, stage)))
+        .map_err(DriverError::Failed)
+}
+
+// The entry function, checked to be callable as a program
+fn entry_function<'p>(checked: &'p CheckedProgram, name: &str) -> Result<&'p FunctionDef, DriverError> {
+    let function = checked.program.func_defs.iter()
+        .find(|function| function.name == name)
+        .ok_or_else(|| DriverError::Diagnostics(vec![checked.diagnostic(None, format!("entry function '{}' not found", name))]))?;
+    if !function.params.is_empty() || !function.type_params.is_empty() {
+        return Err(DriverError::Diagnostics(vec![checked.diagnostic(
+            Some(function.span),
+            format!("entry function '{}' must not take arguments", name),
+        )]));
+    }
+    match &function.return_type {
+        Type::Basic(basic) if matches!(basic.name.as_str(), "Int" | "Nat" | "Bool" | "Unit") => Ok(function),
+        other => Err(DriverError::Diagnostics(vec![checked.diagnostic(
+            Some(function.span),
+            format!("entry function '{}' must return Int, Nat, Bool or Unit, not {}", name, other),
+        )])),
+    }
+}
+
+// `int main(void)` returning the entry function's result as the exit code
+fn add_c_main<'ctx>(context: &'ctx Context, codegen: &CodeGen<'ctx, '_>, entry: &FunctionDef) -> Result<(), DriverError> {
+    let module = codegen.get_module();
+    if module.get_function("main").is_some() {
+        return Err(DriverError::Failed(format!(
+            "cannot start the program in '{}': it already defines 'main'",
+            entry.name,
+        )));
+    }
+    let callee = module.get_function(&entry.name)
+        .ok_or_else(|| DriverError::Failed(format!("entry function '{}' was not compiled", entry.name)))?;
+
+    let main = module.add_function("main", context.i32_type().fn_type(&[], false), None);
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(main, "entry"));
+    let code = build_exit_code(context, &builder, callee, entry)?;
+    builder.build_return(Some(&code)).map_err(builder_failed)?;
+    Ok(())
+}
+
+// `_start`, where a WASI runtime starts the module: calls the entry
+// function and exits with its result through `proc_exit`
+fn add_wasi_start<'ctx>(context: &'ctx Context, codegen: &CodeGen<'ctx, '_>, entry: &FunctionDef) -> Result<(), DriverError> {
+    let module = codegen.get_module();
+    if module.get_function("_start").is_some() {
+        return Err(DriverError::Failed(format!(
+            "cannot start the program in '{}': it already defines '_start'",
+            entry.name,
+        )));
+    }
+    let callee = module.get_function(&entry.name)
+        .ok_or_else(|| DriverError::Failed(format!("entry function '{}' was not compiled", entry.name)))?;
+
+    let start = module.add_function("_start", context.void_type().fn_type(&[], false), None);
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(start, "entry"));
+    let code = build_exit_code(context, &builder, callee, entry)?;
+    builder.build_call(codegen.wasi_import("proc_exit"), &[code.into()], "").map_err(builder_failed)?;
+    builder.build_unreachable().map_err(builder_failed)?;
+    Ok(())
+}
+
+// Call the entry function and turn its result into an i32 exit code
+fn build_exit_code<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    callee: FunctionValue<'ctx>,
+    entry: &FunctionDef,
+) -> Result<IntValue<'ctx>, DriverError> {
+    let i32_type = context.i32_type();
+    let result = builder.build_call(callee, &[], "result").map_err(builder_failed)?.try_as_basic_value().left();
+    let code = match (&entry.return_type, result) {
+        (Type::Basic(basic), Some(value)) if basic.name == "Bool" => {
+            builder.build_int_z_extend(value.into_int_value(), i32_type, "code").map_err(builder_failed)?
+        }
+        (Type::Basic(basic), Some(value)) if basic.name != "Unit" => {
+            builder.build_int_truncate(value.into_int_value(), i32_type, "code").map_err(builder_failed)?
+        }
+        _ => i32_type.const_zero(),
+    };
+    Ok(code)
+}
+
+fn builder_failed(error: BuilderError) -> DriverError {
+    DriverError::Failed(error.to_string())
+}
+
+/// Write the module as textual LLVM IR
+pub fn emit_llvm(codegen: &CodeGen, path: &Path) -> Result<(), DriverError> {
+    codegen.write_ir_to_file(path).map_err(DriverError::Failed)
+}
+
+/// Write the module as an object file for the target it was compiled for
+pub fn emit_object(codegen: &CodeGen, path: &Path) -> Result<(), DriverError> {
+    codegen.write_object_file(path).map_err(DriverError::Failed)
+}
+
+/// Warnings about libraries declared with `@link` whose functions the
+/// program never calls
+pub fn link_warnings(codegen: &CodeGen) -> Vec<String> {
+    codegen.link_manifest()
+        .unused()
+        .map(|library| format!(
+            "library '{}' is linked by @link but none of its functions ({}) are called",
+            library.name,
+            library.functions.join(", "),
+        ))
+        .collect()
+}
+
+/// Link an object file into an executable with the system C compiler
+/// (`$CC`, or `cc`), adding the libraries of the program's link manifest
+/// to those given in the options. For another target, `$CC` must be a
+/// compiler for that target, such as `aarch64-linux-gnu-gcc`.
+pub fn link_executable(
+    object: &Path,
+    output: &Path,
+    manifest: &LinkManifest,
+    options: &DriverOptions,
+) -> Result<(), DriverError> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let mut command = Command::new(&cc);
+    command.arg(object).arg("-o").arg(output);
+    for dir in &options.lib_dirs {
+        command.arg(format!("-L{}", dir.display()));
+    }
+    for lib in &options.link_libs {
+        command.arg(format!("-l{}", lib));
+    }
+    command.args(manifest.linker_args());
+
+    let result = command.output()
+        .map_err(|e| DriverError::Failed(format!("Failed to run linker '{}': {}", cc, e)))?;
+    if !result.status.success() {
+        return Err(DriverError::Failed(format!(
+            "Linking failed:\n{}",
+            String::from_utf8_lossy(&result.stderr).trim_end(),
+        )));
+    }
+    Ok(())
+}
+
+/// Link an object file compiled for wasm32-wasi into a `.wasm` module with
+/// `$WASM_LD` (or `wasm-ld`). The module defines the C functions it uses
+/// itself, so no C library is linked; libraries from the options and the
+/// link manifest must be static wasm32 libraries.
+pub fn link_wasm(
+    object: &Path,
+    output: &Path,
+    manifest: &LinkManifest,
+    options: &DriverOptions,
+) -> Result<(), DriverError> {
+    let linker = std::env::var("WASM_LD").unwrap_or_else(|_| "wasm-ld".to_string());
+    let mut command = Command::new(&linker);
+    command.arg(object).arg("-o").arg(output);
+    for dir in &options.lib_dirs {
+        command.arg(format!("-L{}", dir.display()));
+    }
+    for lib in &options.link_libs {
+        command.arg(format!("-l{}", lib));
+    }
+    command.args(manifest.linker_args());
+
+    let result = command.output()
+        .map_err(|e| DriverError::Failed(format!("Failed to run linker '{}': {}", linker, e)))?;
+    if !result.status.success() {
+        return Err(DriverError::Failed(format!(
+            "Linking failed:\n{}",
+            String::from_utf8_lossy(&result.stderr).trim_end(),
+        )));
+    }
+    Ok(())
+}
+
+/// Link a compiled program into an executable at `output`, or a `.wasm`
+/// module for wasm32-wasi
+pub fn build_executable(
+    checked: &CheckedProgram,
+    codegen: &CodeGen,
+    output: &Path,
+    options: &DriverOptions,
+) -> Result<(), DriverError> {
+    entry_function(checked, options.entry_name())?;
+    let object = output.with_extension("o");
+    emit_object(codegen, &object)?;
+    let linked = if options.target_spec().is_wasi() {
+        link_wasm(&object, output, codegen.link_manifest(), options)
+    } else {
+        link_executable(&object, output, codegen.link_manifest(), options)
+    };
+    let _ = fs::remove_file(&object);
+    linked
+}
+
+/// Link a program compiled for wasm32-wasi and run it under `$WASI_RUNTIME`
+/// (or `wasmtime`) with the current directory preopened, returning its exit
+/// code. The runtime is given wasmtime's arguments: `--dir=. <module>`.
+pub fn run_wasi(checked: &CheckedProgram, codegen: &CodeGen, options: &DriverOptions) -> Result<i32, DriverError> {
+    let name = Path::new(&checked.file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "main".to_string());
+    let module = std::env::temp_dir().join(format!("polec_{}_{}.wasm", name, std::process::id()));
+    build_executable(checked, codegen, &module, options)?;
+
+    let runtime = std::env::var("WASI_RUNTIME").unwrap_or_else(|_| "wasmtime".to_string());
+    let status = Command::new(&runtime).arg("--dir=.").arg(&module).status();
+    let _ = fs::remove_file(&module);
+    let status = status.map_err(|e| DriverError::Failed(format!("Failed to run WASI runtime '{}': {}", runtime, e)))?;
+    status.code()
+        .ok_or_else(|| DriverError::Failed(format!("WASI runtime '{}' was terminated by a signal", runtime)))
+}
+
+/// Run the entry function of a compiled program on the JIT and return its
+/// result as an exit code. The libraries it links are loaded into the
+/// process first.
+pub fn run_jit(checked: &CheckedProgram, codegen: &CodeGen, options: &DriverOptions) -> Result<i32, DriverError> {
+    let entry = entry_function(checked, options.entry_name())?;
+    if let Some(triple) = options.target.as_ref().filter(|_| !options.target_spec().is_host()) {
+        return Err(DriverError::Failed(format!(
+            "cannot run code compiled for '{}' on this machine; use emit-obj or build instead",
+            triple,
+        )));
+    }
+
+    let mut lib_dirs = options.lib_dirs.clone();
+    let mut libs = options.link_libs.clone();
+    for library in &codegen.link_manifest().libraries {
+        lib_dirs.extend(library.search_paths.iter().map(PathBuf::from));
+        if !libs.contains(&library.name) {
+            libs.push(library.name.clone());
+        }
+    }
+    for lib in &libs {
+        load_library(lib, &lib_dirs)?;
+    }
+    let engine = codegen.get_module()
+        .create_jit_execution_engine(options.opt_level.llvm())
+        .map_err(|e| DriverError::Failed(format!("Failed to create execution engine: {}", e)))?;
+
+    let missing = |e| DriverError::Failed(format!("Failed to get entry function '{}': {}", entry.name, e));
+    let Type::Basic(return_type) = &entry.return_type else { unreachable!("checked by entry_function") };
+    let code = unsafe {
+        match return_type.name.as_str() {
+            "Bool" => engine.get_function::<unsafe extern "C" fn() -> bool>(&entry.name).map_err(missing)?.call() as i32,
+            "Unit" => {
+                engine.get_function::<unsafe extern "C" fn() -> i8>(&entry.name).map_err(missing)?.call();
+                0
+            }
+            _ => engine.get_function::<unsafe extern "C" fn() -> i64>(&entry.name).map_err(missing)?.call() as i32,
+        }
+    };
+    Ok(code)
+}
+
+// Load `lib<name>.so` (or the platform's equivalent) from the library
+// directories, or else from the system search path
+fn load_library(name: &str, lib_dirs: &[PathBuf]) -> Result<(), DriverError> {
+    let file = format!("{}{}{}", std::env::consts::DLL_PREFIX, name, std::env::consts::DLL_SUFFIX);
+    let candidates = lib_dirs.iter().map(|dir| dir.join(&file)).filter(|path| path.exists());
+    for path in candidates.chain(std::iter::once(PathBuf::from(&file))) {
+        if inkwell::support::load_library_permanently(&path).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(DriverError::Failed(format!("Cannot load library '{}' ({})", name, file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_opt_level() {
+        assert_eq!("2".parse::<OptLevel>(), Ok(OptLevel::O2));
+        assert_eq!("s".parse::<OptLevel>(), Ok(OptLevel::Os));
+        assert_eq!("fast".parse::<OptLevel>().unwrap_err(), "unknown optimization level 'fast' (expected 0, 1, 2, 3 or s)");
+    }
+
+    #[test]
+    fn test_default_opt_level() {
+        // Library users get the level polec documents as its default
+        let context = Context::create();
+        let arena = Bump::new();
+        assert_eq!(OptLevel::default(), OptLevel::O0);
+        assert_eq!(CodeGen::new(&context, "default", &arena).opt_level(), OptLevel::default());
+        assert_eq!(DriverOptions::default().opt_level, OptLevel::default());
+    }
+
+    #[test]
+    fn test_target_spec() {
+        let options = DriverOptions { target: Some("aarch64-linux-gnu".to_string()), ..DriverOptions::default() };
+        let target = options.target_spec();
+        assert_eq!((target.cpu.as_str(), target.features.as_str()), ("generic", ""));
+        assert_eq!(target.target_triple().as_str().to_str(), Ok("aarch64-unknown-linux-gnu"));
+        assert!(DriverOptions::default().target_spec().is_host());
+        assert!(!target.is_wasi());
+
+        let wasi = DriverOptions { target: Some("wasm32-wasi".to_string()), ..DriverOptions::default() };
+        assert!(wasi.target_spec().is_wasi());
+    }
+
+    #[test]
+    fn test_type_errors_are_located() {
+        let path = std::env::temp_dir().join(format!("polec_driver_test_{}.pole-ir", std::process::id()));
+        fs::write(&path, "func main () -> Int :\n  true\n").unwrap();
+        let result = load_and_check(&path);
+        let _ = fs::remove_file(&path);
+
+        let Err(DriverError::Diagnostics(diagnostics)) = result else { panic!("expected type errors") };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file, path.display().to_string());
+        assert!(diagnostics[0].line_col.is_some(), "{}", diagnostics[0]);
+        assert!(diagnostics[0].to_string().contains(": error: "), "{}", diagnostics[0]);
+    }
+
+    #[test]
+    fn test_builtins_type_check() {
+        // HashMap_* builtins and a @variadic printf with extra arguments
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/44-hashmap-test.pole-ir");
+        if let Err(error) = load_and_check(&path) {
+            panic!("{}", error);
+        }
+    }
+
+    #[test]
+    fn test_format_file() {
+        let path = std::env::temp_dir().join(format!("polec_fmt_test_{}.pole-ir", std::process::id()));
+        fs::write(&path, "func main () -> Int :
+  if true then 1 else 0
+").unwrap();
+        let formatted = format_file(&path);
+        fs::write(&path, "func main ( -> Int :
+  1
+").unwrap();
+        let broken = format_file(&path);
+        let _ = fs::remove_file(&path);
+
+        let (_, formatted) = formatted.unwrap();
+        assert_eq!(formatted, "func main() -> Int:\n  if true then\n    1\n  else\n    0\n");
+        let Err(DriverError::Diagnostics(diagnostics)) = broken else { panic!("expected a syntax error") };
+        assert_eq!(diagnostics[0].line_col, Some((1, 13)));
+    }
+}
@@ -0,0 +1,491 @@
+// Match Exhaustiveness
+// Pattern-matrix usefulness analysis (Maranget, "Warnings for pattern
+// matching"): finds values no arm matches and arms no value reaches
+
+use crate::ast::*;
+
+/// How values of a type are built, as far as patterns can tell them apart
+pub enum Signature {
+    /// Every value is built by exactly one of these constructors, given
+    /// with their argument types
+    Complete(Vec<(Con, Vec<Type>)>),
+    /// Infinitely many values (Int, String, ...): only a wildcard covers them all
+    Open,
+}
+
+/// Pattern constructor
+#[derive(Debug, Clone, PartialEq)]
+pub enum Con {
+    /// `Some`, `None`, `Ok`, `Err`, user variants, and `true`/`false`
+    Variant(String),
+    /// Tuple of the given size; `()` is the empty tuple
+    Tuple(usize),
+    /// Record with these fields, in declaration order
+    Record(Vec<String>),
+    /// Int, Float64 or String literal, as written
+    Literal(String),
+}
+
+// Pattern with variables turned into wildcards and record fields completed
+#[derive(Debug, Clone, PartialEq)]
+enum Pat {
+    Wild,
+    Con(Con, Vec<Pat>),
+}
+
+/// Result of analysing one `match`
+#[derive(Debug, Default, PartialEq)]
+pub struct MatchReport {
+    /// Example values no arm matches, rendered as patterns
+    pub missing: Vec<String>,
+    /// Indices of arms that can never be reached
+    pub unreachable: Vec<usize>,
+}
+
+// Witnesses listed before the rest are elided
+const MAX_MISSING: usize = 5;
+
+/// Analyse the arms of a match on a value of type `scrutinee`, given as
+/// their patterns and whether they have a guard. A guarded arm may decline
+/// any value, so it covers nothing for the arms after it.
+/// `signature` describes the constructors of a type, with type variables
+/// and aliases already resolved by the caller.
+pub fn check_match(
+    arms: &[(&Pattern, bool)],
+    scrutinee: &Type,
+    signature: &dyn Fn(&Type) -> Signature,
+) -> MatchReport {
+    let analysis = Analysis { signature };
+    let types = vec![scrutinee.clone()];
+    let mut report = MatchReport::default();
+
+    // An or-pattern contributes a row per alternative, and is unreachable
+    // only if all of them are
+    let mut rows: Vec<Vec<Pat>> = vec![];
+    for (i, &(pattern, guarded)) in arms.iter().enumerate() {
+        let alternatives: Vec<Vec<Pat>> = analysis.lower(pattern, scrutinee)
+            .into_iter()
+            .map(|pat| vec![pat])
+            .collect();
+        if !alternatives.iter().any(|row| analysis.is_useful(&rows, row, &types)) {
+            report.unreachable.push(i);
+        }
+        if !guarded {
+            rows.extend(alternatives);
+        }
+    }
+
+    // Each witness found is added as a row so the next search finds another.
+    // Example literals are widened first: `2` stands for every other Int.
+    while let Some(witness) = analysis.witness(&rows, &types) {
+        if report.missing.len() == MAX_MISSING {
+            report.missing.push("...".to_string());
+            break;
+        }
+        report.missing.push(render(&witness[0]));
+        rows.push(witness.iter().map(widen_literals).collect());
+    }
+
+    report
+}
+
+struct Analysis<'a> {
+    signature: &'a dyn Fn(&Type) -> Signature,
+}
+
+impl Analysis<'_> {
+    // The or-free patterns `pattern` stands for
+    fn lower(&self, pattern: &Pattern, ty: &Type) -> Vec<Pat> {
+        match pattern {
+            Pattern::Wildcard(_) | Pattern::Variable(_) => vec![Pat::Wild],
+            Pattern::Literal(lit) => vec![match &lit.value {
+                LiteralValue::Bool(b) => Pat::Con(Con::Variant(b.to_string()), vec![]),
+                LiteralValue::Unit => Pat::Con(Con::Tuple(0), vec![]),
+                LiteralValue::Int(n) => Pat::Con(Con::Literal(n.to_string()), vec![]),
+                LiteralValue::Float(f) => Pat::Con(Con::Literal(format!("{:?}", f)), vec![]),
+                LiteralValue::String(s) => Pat::Con(Con::Literal(format!("{:?}", s)), vec![]),
+            }],
+            Pattern::Constructor(ctor) => {
+                let con = Con::Variant(ctor.name.clone());
+                let arg_types = self.arg_types(ty, &con).unwrap_or_default();
+                let args = ctor.args.iter()
+                    .enumerate()
+                    .map(|(i, arg)| self.lower_arg(arg, arg_types.get(i)))
+                    .collect();
+                with_args(con, args)
+            }
+            Pattern::Tuple(tuple) => {
+                let con = Con::Tuple(tuple.elements.len());
+                let element_types = self.arg_types(ty, &con).unwrap_or_default();
+                let elements = tuple.elements.iter()
+                    .enumerate()
+                    .map(|(i, element)| self.lower_arg(element, element_types.get(i)))
+                    .collect();
+                with_args(con, elements)
+            }
+            Pattern::Record(record) => {
+                let fields = match (self.signature)(ty) {
+                    Signature::Complete(constructors) => match constructors.into_iter().next() {
+                        Some((Con::Record(names), types)) => names.into_iter().zip(types).collect(),
+                        _ => vec![],
+                    },
+                    Signature::Open => vec![],
+                };
+                // Fields the pattern leaves out match anything
+                let args = fields.iter()
+                    .map(|(name, field_type): &(String, Type)| {
+                        match record.fields.iter().find(|(field, _)| field == name) {
+                            Some((_, field_pattern)) => self.lower(field_pattern, field_type),
+                            None => vec![Pat::Wild],
+                        }
+                    })
+                    .collect();
+                with_args(Con::Record(fields.into_iter().map(|(name, _)| name).collect()), args)
+            }
+            Pattern::Or(or) => or.alternatives.iter()
+                .flat_map(|alternative| self.lower(alternative, ty))
+                .collect(),
+        }
+    }
+
+    fn lower_arg(&self, pattern: &Pattern, ty: Option<&Type>) -> Vec<Pat> {
+        match ty {
+            Some(ty) => self.lower(pattern, ty),
+            None => vec![Pat::Wild],
+        }
+    }
+
+    fn arg_types(&self, ty: &Type, con: &Con) -> Option<Vec<Type>> {
+        match (self.signature)(ty) {
+            Signature::Complete(constructors) => constructors.into_iter()
+                .find(|(candidate, _)| candidate == con)
+                .map(|(_, arg_types)| arg_types),
+            Signature::Open => None,
+        }
+    }
+
+    // Could a value matched by `row` escape every row of `matrix`?
+    fn is_useful(&self, matrix: &[Vec<Pat>], row: &[Pat], types: &[Type]) -> bool {
+        let Some((first_type, rest_types)) = types.split_first() else {
+            return matrix.is_empty();
+        };
+
+        match &row[0] {
+            Pat::Con(con, args) => {
+                let arg_types = self.arg_types(first_type, con).unwrap_or_else(|| wild_types(args.len()));
+                let mut specialized_row = args.clone();
+                specialized_row.extend_from_slice(&row[1..]);
+                self.is_useful(
+                    &specialize(matrix, con, args.len()),
+                    &specialized_row,
+                    &concat_types(&arg_types, rest_types),
+                )
+            }
+            Pat::Wild => match self.covered_signature(matrix, first_type) {
+                Some(constructors) => constructors.into_iter().any(|(con, arg_types)| {
+                    let mut specialized_row = vec![Pat::Wild; arg_types.len()];
+                    specialized_row.extend_from_slice(&row[1..]);
+                    self.is_useful(
+                        &specialize(matrix, &con, arg_types.len()),
+                        &specialized_row,
+                        &concat_types(&arg_types, rest_types),
+                    )
+                }),
+                None => self.is_useful(&default_matrix(matrix), &row[1..], rest_types),
+            },
+        }
+    }
+
+    // A row of values, one per column, that no row of `matrix` matches
+    fn witness(&self, matrix: &[Vec<Pat>], types: &[Type]) -> Option<Vec<Pat>> {
+        let Some((first_type, rest_types)) = types.split_first() else {
+            return if matrix.is_empty() { Some(vec![]) } else { None };
+        };
+
+        if let Some(constructors) = self.covered_signature(matrix, first_type) {
+            // Every constructor appears, so the gap is inside one of them
+            return constructors.into_iter().find_map(|(con, arg_types)| {
+                let arity = arg_types.len();
+                let mut witness = self.witness(
+                    &specialize(matrix, &con, arity),
+                    &concat_types(&arg_types, rest_types),
+                )?;
+                let rest = witness.split_off(arity);
+                let mut row = vec![Pat::Con(con, witness)];
+                row.extend(rest);
+                Some(row)
+            });
+        }
+
+        let mut rest = self.witness(&default_matrix(matrix), rest_types)?;
+        let heads = head_constructors(matrix);
+        let first = match (self.signature)(first_type) {
+            Signature::Complete(constructors) if !heads.is_empty() => {
+                let (con, arg_types) = constructors.into_iter().find(|(con, _)| !heads.contains(con))?;
+                Pat::Con(con, vec![Pat::Wild; arg_types.len()])
+            }
+            Signature::Open => match unlisted_literal(&heads) {
+                Some(literal) => Pat::Con(Con::Literal(literal), vec![]),
+                None => Pat::Wild,
+            },
+            Signature::Complete(_) => Pat::Wild,
+        };
+        rest.insert(0, first);
+        Some(rest)
+    }
+
+    // The type's constructors, if every one of them heads some row
+    fn covered_signature(&self, matrix: &[Vec<Pat>], ty: &Type) -> Option<Vec<(Con, Vec<Type>)>> {
+        let Signature::Complete(constructors) = (self.signature)(ty) else { return None };
+        let heads = head_constructors(matrix);
+        if constructors.iter().all(|(con, _)| heads.contains(con)) {
+            Some(constructors)
+        } else {
+            None
+        }
+    }
+}
+
+// `con` applied to every combination of the alternatives of its arguments
+fn with_args(con: Con, args: Vec<Vec<Pat>>) -> Vec<Pat> {
+    let mut combinations: Vec<Vec<Pat>> = vec![vec![]];
+    for alternatives in args {
+        combinations = combinations.into_iter()
+            .flat_map(|prefix| alternatives.iter().map(move |alternative| {
+                let mut combination = prefix.clone();
+                combination.push(alternative.clone());
+                combination
+            }))
+            .collect();
+    }
+    combinations.into_iter().map(|args| Pat::Con(con.clone(), args)).collect()
+}
+
+// Rows that match constructor `con`, with its arguments spliced in place of
+// the first column
+fn specialize(matrix: &[Vec<Pat>], con: &Con, arity: usize) -> Vec<Vec<Pat>> {
+    matrix.iter()
+        .filter_map(|row| {
+            let mut specialized = match &row[0] {
+                Pat::Con(head, args) if head == con => args.clone(),
+                Pat::Con(..) => return None,
+                Pat::Wild => vec![Pat::Wild; arity],
+            };
+            specialized.extend_from_slice(&row[1..]);
+            Some(specialized)
+        })
+        .collect()
+}
+
+// Rows whose first column matches anything, without that column
+fn default_matrix(matrix: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    matrix.iter()
+        .filter(|row| row[0] == Pat::Wild)
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+fn head_constructors(matrix: &[Vec<Pat>]) -> Vec<Con> {
+    let mut heads = vec![];
+    for row in matrix {
+        if let Pat::Con(con, _) = &row[0] {
+            if !heads.contains(con) {
+                heads.push(con.clone());
+            }
+        }
+    }
+    heads
+}
+
+// A literal of the same kind as `heads` that none of them is, as an example
+// of a value they miss
+fn unlisted_literal(heads: &[Con]) -> Option<String> {
+    let literals: Vec<&str> = heads.iter()
+        .filter_map(|con| match con {
+            Con::Literal(literal) => Some(literal.as_str()),
+            _ => None,
+        })
+        .collect();
+    let first = literals.first()?;
+
+    let candidates: Box<dyn Iterator<Item = String>> = if first.starts_with('"') {
+        Box::new((0..).map(|n: usize| format!("{:?}", "a".repeat(n))))
+    } else if first.parse::<i64>().is_ok() {
+        Box::new((0..).map(|n: i64| n.to_string()))
+    } else {
+        Box::new((0..).map(|n: i32| format!("{:?}", f64::from(n) + 0.5)))
+    };
+    candidates.take(literals.len() + 1).find(|candidate| !literals.contains(&candidate.as_str()))
+}
+
+// Column types for the arguments of a constructor the signature does not
+// know, which the caller only lets through after reporting a type error
+fn wild_types(count: usize) -> Vec<Type> {
+    vec![Type::Basic(BasicType { name: "_".to_string() }); count]
+}
+
+fn concat_types(first: &[Type], rest: &[Type]) -> Vec<Type> {
+    first.iter().chain(rest).cloned().collect()
+}
+
+fn widen_literals(pat: &Pat) -> Pat {
+    match pat {
+        Pat::Con(Con::Literal(_), _) => Pat::Wild,
+        Pat::Con(con, args) => Pat::Con(con.clone(), args.iter().map(widen_literals).collect()),
+        Pat::Wild => Pat::Wild,
+    }
+}
+
+fn render(pat: &Pat) -> String {
+    match pat {
+        Pat::Wild => "_".to_string(),
+        Pat::Con(Con::Variant(name), args) if args.is_empty() => name.clone(),
+        Pat::Con(Con::Variant(name), args) => format!("{}({})", name, render_list(args)),
+        Pat::Con(Con::Tuple(_), args) => format!("({})", render_list(args)),
+        Pat::Con(Con::Record(names), args) => {
+            let fields: Vec<String> = names.iter()
+                .zip(args)
+                .map(|(name, arg)| format!("{}: {}", name, render(arg)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Pat::Con(Con::Literal(literal), _) => literal.clone(),
+    }
+}
+
+fn render_list(pats: &[Pat]) -> String {
+    pats.iter().map(render).collect::<Vec<_>>().join(", ")
+}
+
+/// Source form of `pattern`, for messages about it
+pub fn pattern_to_string(pattern: &Pattern) -> String {
+    let list = |patterns: &[Pattern]| patterns.iter().map(pattern_to_string).collect::<Vec<_>>().join(", ");
+    match pattern {
+        Pattern::Wildcard(_) => "_".to_string(),
+        Pattern::Variable(var) => var.name.clone(),
+        Pattern::Literal(lit) => match &lit.value {
+            LiteralValue::Int(n) => n.to_string(),
+            LiteralValue::Float(f) => format!("{:?}", f),
+            LiteralValue::Bool(b) => b.to_string(),
+            LiteralValue::String(s) => format!("{:?}", s),
+            LiteralValue::Unit => "()".to_string(),
+        },
+        Pattern::Constructor(ctor) if ctor.args.is_empty() => ctor.name.clone(),
+        Pattern::Constructor(ctor) => format!("{}({})", ctor.name, list(&ctor.args)),
+        Pattern::Tuple(tuple) => format!("({})", list(&tuple.elements)),
+        Pattern::Record(record) => {
+            let fields: Vec<String> = record.fields.iter()
+                .map(|(name, field)| format!("{}: {}", name, pattern_to_string(field)))
+                .collect();
+            match &record.type_name {
+                Some(type_name) => format!("{} {{{}}}", type_name, fields.join(", ")),
+                None => format!("{{{}}}", fields.join(", ")),
+            }
+        }
+        Pattern::Or(or) => or.alternatives.iter().map(pattern_to_string).collect::<Vec<_>>().join(" | "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir_parser::parse_ir;
+    use crate::type_checker::check_types;
+
+    fn errors(ir: &str) -> Vec<String> {
+        let program = parse_ir(ir).unwrap();
+        check_types(program).errors.into_iter().map(|e| e.message).collect()
+    }
+
+    #[test]
+    fn test_missing_variants_are_listed() {
+        let ir = r#"
+type Direction = North | South | East | West
+
+func turn (d: Direction) -> Int :
+  match d with
+  | North -> 0
+  | East -> 1
+"#;
+        assert_eq!(errors(ir), vec!["Match is not exhaustive: missing South, West"]);
+    }
+
+    #[test]
+    fn test_missing_nested_case() {
+        let ir = r#"
+func describe (r: Result<Option<Int>, String>) -> Int :
+  match r with
+  | Ok(Some(n)) -> n
+  | Err(_) -> 0
+"#;
+        assert_eq!(errors(ir), vec!["Match is not exhaustive: missing Ok(None)"]);
+    }
+
+    #[test]
+    fn test_literals_need_a_catch_all() {
+        let ir = r#"
+func small (n: Int) -> Bool :
+  match n with
+  | 0 -> true
+  | 1 -> true
+
+func flag (b: Bool) -> Int :
+  match b with
+  | true -> 1
+  | false -> 0
+"#;
+        assert_eq!(errors(ir), vec!["Match is not exhaustive: missing 2"]);
+    }
+
+    #[test]
+    fn test_unreachable_arms() {
+        let ir = r#"
+func unwrap (o: Option<Int>) -> Int :
+  match o with
+  | Some(x) -> x
+  | _ -> 0
+  | None -> 1
+  | Some(0) -> 2
+"#;
+        assert_eq!(errors(ir), vec![
+            "Unreachable match arm: None is already covered by earlier arms",
+            "Unreachable match arm: Some(0) is already covered by earlier arms",
+        ]);
+    }
+
+    #[test]
+    fn test_or_patterns_and_nested_records() {
+        let ir = r#"
+type Point = { x: Int, y: Int }
+
+func classify (p: Option<(Bool, Point)>) -> Int :
+  match p with
+  | Some((true, Point { x: 0 })) | None -> 0
+  | Some((false, { y })) -> y
+  | Some((true | false, _)) -> 1
+  | Some((false, _)) -> 2
+"#;
+        assert_eq!(errors(ir), vec![
+            "Unreachable match arm: Some((false, _)) is already covered by earlier arms",
+        ]);
+    }
+
+    #[test]
+    fn test_guarded_arms_do_not_cover() {
+        let ir = r#"
+func sign (o: Option<Int>) -> Int :
+  match o with
+  | Some(n) if n > 0 -> 1
+  | None -> 0
+
+func clamp (n: Int) -> Int :
+  match n with
+  | x if x > 100 -> 100
+  | x -> x
+  | 0 -> 0
+"#;
+        assert_eq!(errors(ir), vec![
+            "Match is not exhaustive: missing Some(_)",
+            "Unreachable match arm: 0 is already covered by earlier arms",
+        ]);
+    }
+}
@@ -0,0 +1,1252 @@
+// Reference Interpreter
+// Evaluates a Program by walking its AST, giving every construct the meaning
+// the LLVM backend gives it, so the two can be run side by side (see
+// `differential`). Extern functions are served by Rust stubs.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::*;
+
+// ============================================================================
+// Values and Errors
+// ============================================================================
+
+/// Value computed by the interpreter
+#[derive(Debug, Clone)]
+pub enum Value<'p> {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(Rc<str>),
+    Unit,
+    Tuple(Vec<Value<'p>>),
+    /// Fields in the order the record expression gives them
+    Record(Vec<(&'p str, Value<'p>)>),
+    /// `Some(1)`, `None`, `Ok(())`, `Err(e)` and user variants
+    Constructor(&'p str, Vec<Value<'p>>),
+    /// Lists are never updated in place: `List_set` and `List_push` copy
+    List(Rc<Vec<Value<'p>>>),
+    /// Maps are shared, and `HashMap_put` updates every copy
+    HashMap(Rc<RefCell<BTreeMap<i64, Value<'p>>>>),
+    Function(Rc<FunctionValue<'p>>),
+    /// Opaque handle an extern stub returned
+    Pointer(usize),
+}
+
+/// A function, lambda or builtin with the arguments applied to it so far
+pub struct FunctionValue<'p> {
+    callee: Callee<'p>,
+    applied: Vec<Value<'p>>,
+}
+
+impl fmt::Debug for FunctionValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<function>")
+    }
+}
+
+#[derive(Clone)]
+enum Callee<'p> {
+    Target(Target<'p>),
+    Lambda(&'p Lambda, Env<'p>),
+}
+
+/// Source syntax for the value: `Some({ name: "a", age: 3 })`
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join(values: &[Value]) -> String {
+            values.iter().map(Value::to_string).collect::<Vec<_>>().join(", ")
+        }
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", LiteralValue::String(s.to_string())),
+            Value::Unit => write!(f, "()"),
+            Value::Tuple(elements) => write!(f, "({})", join(elements)),
+            Value::Record(fields) => {
+                let fields: Vec<String> = fields.iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Value::Constructor(name, args) if args.is_empty() => write!(f, "{}", name),
+            Value::Constructor(name, args) => write!(f, "{}({})", name, join(args)),
+            Value::List(elements) => write!(f, "[{}]", join(elements)),
+            Value::HashMap(map) => write!(f, "<HashMap of {}>", map.borrow().len()),
+            Value::Function(_) => write!(f, "<function>"),
+            Value::Pointer(address) => write!(f, "<pointer {:#x}>", address),
+        }
+    }
+}
+
+impl Value<'_> {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float64",
+            Value::Bool(_) => "Bool",
+            Value::String(_) => "String",
+            Value::Unit => "Unit",
+            Value::Tuple(_) => "tuple",
+            Value::Record(_) => "record",
+            Value::Constructor(..) => "variant",
+            Value::List(_) => "List",
+            Value::HashMap(_) => "HashMap",
+            Value::Function(_) => "function",
+            Value::Pointer(_) => "Ptr",
+        }
+    }
+}
+
+/// Why evaluation stopped
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    /// Innermost expression being evaluated
+    pub span: Option<Span>,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>, span: Option<Span>) -> Self {
+        RuntimeError { message: message.into(), span }
+    }
+
+    // Locate the error at `span` unless an inner expression already did
+    fn at(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<String> for RuntimeError {
+    fn from(message: String) -> Self {
+        RuntimeError::new(message, None)
+    }
+}
+
+/// Rust implementation of an extern function: gets the call's arguments and
+/// the program's output so far, and returns the function's result
+pub type ExternStub<'p> = Box<dyn FnMut(&[Value<'p>], &mut String) -> Result<Value<'p>, String> + 'p>;
+
+// ============================================================================
+// Environments
+// ============================================================================
+
+// Local bindings, innermost first, shared with the closures capturing them
+#[derive(Clone, Default)]
+struct Env<'p>(Option<Rc<Binding<'p>>>);
+
+struct Binding<'p> {
+    name: &'p str,
+    value: Value<'p>,
+    outer: Env<'p>,
+}
+
+impl<'p> Env<'p> {
+    fn bind(&self, name: &'p str, value: Value<'p>) -> Env<'p> {
+        Env(Some(Rc::new(Binding { name, value, outer: self.clone() })))
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Value<'p>> {
+        let mut env = self;
+        while let Some(binding) = &env.0 {
+            if binding.name == name {
+                return Some(&binding.value);
+            }
+            env = &binding.outer;
+        }
+        None
+    }
+}
+
+// ============================================================================
+// Interpreter
+// ============================================================================
+
+// Functions the backend compiles inline, and the arguments they take
+const BUILTINS: &[(&str, usize)] = &[
+    ("String_length", 1),
+    ("String.length", 1),
+    ("String_contains", 2),
+    ("print", 1),
+    ("println", 1),
+    ("List_concat", 1),
+    ("List.concat", 1),
+    // Or 3, the last a default for out-of-bounds indices
+    ("List_get", 2),
+    ("List.get", 2),
+    ("list_get", 2),
+    ("List_set", 3),
+    ("List.set", 3),
+    ("list_new", 0),
+    ("List_push", 2),
+    ("List.push", 2),
+    ("list_push", 2),
+    ("List_length", 1),
+    ("List.length", 1),
+    ("HashMap_new", 1),
+    ("HashMap_put", 3),
+    ("HashMap_get", 2),
+    ("HashMap_size", 1),
+    ("int_to_float", 1),
+    ("float_to_int", 1),
+];
+
+// What a name called as a function refers to
+#[derive(Clone, Copy)]
+enum Target<'p> {
+    Builtin(&'static str),
+    Constructor(&'p str, usize),
+    Function(&'p FunctionDef),
+    Extern(&'p ExternFunctionDecl),
+}
+
+// Result of evaluating an expression in tail position: a value, or a call
+// of a Pole function left to the caller so tail calls take no Rust stack
+enum Flow<'p> {
+    Done(Value<'p>),
+    Call(Rc<FunctionValue<'p>>, Vec<Value<'p>>),
+}
+
+/// Tree-walking evaluator for a flattened program (see `flatten_program`)
+pub struct Interpreter<'p> {
+    functions: HashMap<&'p str, &'p FunctionDef>,
+    externs: HashMap<&'p str, &'p ExternFunctionDecl>,
+    // Variant constructors and the number of fields they take
+    constructors: HashMap<&'p str, usize>,
+    // Keyed by C name
+    stubs: HashMap<String, ExternStub<'p>>,
+    output: String,
+    check_contracts: bool,
+    step_limit: u64,
+    steps: u64,
+    depth_limit: usize,
+    depth: usize,
+}
+
+impl<'p> Interpreter<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        let mut interpreter = Interpreter {
+            functions: program.func_defs.iter().map(|function| (function.name.as_str(), function)).collect(),
+            externs: program.extern_funcs.iter().map(|extern_func| (extern_func.name.as_str(), extern_func)).collect(),
+            constructors: program.type_defs.iter()
+                .filter_map(|type_def| match &type_def.definition {
+                    TypeDefKind::Variant(variants) => Some(variants),
+                    _ => None,
+                })
+                .flatten()
+                .map(|(name, fields)| (name.as_str(), fields.len()))
+                .collect(),
+            stubs: HashMap::new(),
+            output: String::new(),
+            check_contracts: false,
+            step_limit: 100_000_000,
+            steps: 0,
+            depth_limit: 1_000,
+            depth: 0,
+        };
+        for (c_name, stub) in default_stubs() {
+            interpreter.stubs.insert(c_name.to_string(), stub);
+        }
+        interpreter
+    }
+
+    /// Evaluate `requires` on entry and `ensures` on return, failing with
+    /// the violated clause
+    pub fn set_contract_checks(&mut self, enabled: bool) {
+        self.check_contracts = enabled;
+    }
+
+    /// Evaluation steps one `eval` or `call` may take before it fails, so
+    /// programs that never stop are reported instead of hanging. Defaults
+    /// to 100,000,000.
+    pub fn set_step_limit(&mut self, steps: u64) {
+        self.step_limit = steps;
+    }
+
+    /// Calls that may be in progress at once, tail calls aside. Each takes
+    /// up to several kilobytes of Rust stack, so raise it only on threads
+    /// with a large stack. Defaults to 1,000.
+    pub fn set_depth_limit(&mut self, depth: usize) {
+        self.depth_limit = depth;
+    }
+
+    /// Serve calls of the extern function with the C name `c_name` with
+    /// `stub`, replacing the built-in stub if there is one
+    pub fn register_extern(
+        &mut self,
+        c_name: impl Into<String>,
+        stub: impl FnMut(&[Value<'p>], &mut String) -> Result<Value<'p>, String> + 'p,
+    ) {
+        self.stubs.insert(c_name.into(), Box::new(stub));
+    }
+
+    /// What `print`, `println` and the output stubs wrote so far
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Value of a closed expression, e.g. `factorial(5)`
+    pub fn eval(&mut self, expr: &'p Expr) -> Result<Value<'p>, RuntimeError> {
+        self.steps = 0;
+        self.depth = 0;
+        self.eval_in(expr, &Env::default())
+    }
+
+    /// Call the function, extern or builtin `name` with `args`
+    pub fn call(&mut self, name: &str, args: Vec<Value<'p>>) -> Result<Value<'p>, RuntimeError> {
+        self.steps = 0;
+        self.depth = 0;
+        let target = self.resolve(name).ok_or_else(|| format!("Unknown function '{}'", name))?;
+        self.apply(Rc::new(FunctionValue { callee: Callee::Target(target), applied: vec![] }), args)
+    }
+
+    // What `name` refers to when no local binding shadows it, in the order
+    // the backend looks names up
+    fn resolve(&self, name: &str) -> Option<Target<'p>> {
+        if let Some((builtin, _)) = BUILTINS.iter().find(|(builtin, _)| *builtin == name) {
+            return Some(Target::Builtin(builtin));
+        }
+        match name {
+            "Some" => return Some(Target::Constructor("Some", 1)),
+            "None" => return Some(Target::Constructor("None", 0)),
+            "Ok" => return Some(Target::Constructor("Ok", 1)),
+            "Err" => return Some(Target::Constructor("Err", 1)),
+            _ => {}
+        }
+        if let Some((name, fields)) = self.constructors.get_key_value(name) {
+            return Some(Target::Constructor(name, *fields));
+        }
+        if let Some(function) = self.functions.get(name) {
+            return Some(Target::Function(function));
+        }
+        self.externs.get(name).map(|extern_func| Target::Extern(extern_func))
+    }
+
+    // Arguments `target` takes when called with `given` of them
+    fn arity(target: Target, given: usize) -> usize {
+        match target {
+            Target::Builtin("List_get" | "List.get" | "list_get") if given >= 3 => 3,
+            Target::Builtin(name) => BUILTINS.iter().find(|(builtin, _)| *builtin == name).map_or(0, |(_, arity)| *arity),
+            Target::Constructor(_, fields) => fields,
+            Target::Function(function) => function.params.len(),
+            Target::Extern(extern_func) if extern_func.annotations.iter().any(|annotation| annotation.name == "variadic") => {
+                given.max(extern_func.params.len())
+            }
+            Target::Extern(extern_func) => extern_func.params.len(),
+        }
+    }
+
+    fn tick(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        if self.steps > self.step_limit {
+            return Err(format!("Step limit of {} exceeded", self.step_limit).into());
+        }
+        Ok(())
+    }
+
+    fn eval_in(&mut self, expr: &'p Expr, env: &Env<'p>) -> Result<Value<'p>, RuntimeError> {
+        match self.eval_tail(expr, env.clone())? {
+            Flow::Done(value) => Ok(value),
+            Flow::Call(function, args) => self.apply(function, args).map_err(|e| e.at(expr.span())),
+        }
+    }
+
+    // Evaluate `expr` up to the call it ends in, if any. Lets, ifs and
+    // matches continue with their body in the same loop.
+    fn eval_tail(&mut self, mut expr: &'p Expr, mut env: Env<'p>) -> Result<Flow<'p>, RuntimeError> {
+        loop {
+            self.tick().map_err(|e| e.at(expr.span()))?;
+            match expr {
+                Expr::Let(let_expr) => {
+                    let value = self.eval_in(&let_expr.value, &env)?;
+                    env = env.bind(&let_expr.var_name, value);
+                    expr = &let_expr.body;
+                }
+                Expr::If(if_expr) => expr = self.select_branch(if_expr, &env)?,
+                Expr::Match(match_expr) => (expr, env) = self.select_arm(match_expr, &env)?,
+                Expr::Application(app) => return self.eval_application(app, &env),
+                _ => return self.eval_simple(expr, &env).map(Flow::Done).map_err(|e| e.at(expr.span())),
+            }
+        }
+    }
+
+    fn select_branch(&mut self, if_expr: &'p IfExpr, env: &Env<'p>) -> Result<&'p Expr, RuntimeError> {
+        let condition = self.eval_in(&if_expr.condition, env)?;
+        match truthy(&condition) {
+            Ok(true) => Ok(&if_expr.then_branch),
+            Ok(false) => Ok(&if_expr.else_branch),
+            Err(e) => Err(RuntimeError::new(e, Some(if_expr.condition.span()))),
+        }
+    }
+
+    // Body of the first arm whose pattern matches and whose guard holds, and
+    // the environment it runs in
+    fn select_arm(&mut self, match_expr: &'p MatchExpr, env: &Env<'p>) -> Result<(&'p Expr, Env<'p>), RuntimeError> {
+        let scrutinee = self.eval_in(&match_expr.scrutinee, env)?;
+        for arm in &match_expr.arms {
+            let mut bindings = vec![];
+            if !match_pattern(&arm.pattern, &scrutinee, &mut bindings) {
+                continue;
+            }
+            let arm_env = bindings.into_iter().fold(env.clone(), |env, (name, value)| env.bind(name, value));
+            if let Some(guard) = &arm.guard {
+                let passed = self.eval_in(guard, &arm_env)?;
+                if !truthy(&passed).map_err(|e| RuntimeError::from(e).at(guard.span()))? {
+                    continue;
+                }
+            }
+            return Ok((&arm.body, arm_env));
+        }
+        Err(RuntimeError::new(format!("No match arm matches {}", scrutinee), Some(match_expr.span)))
+    }
+
+    // Calls of Pole functions and lambdas that use all their arguments are
+    // left to the caller; everything else is applied here
+    fn eval_application(&mut self, app: &'p Application, env: &Env<'p>) -> Result<Flow<'p>, RuntimeError> {
+        let mut arg_exprs = vec![&*app.arg];
+        let mut callee = &*app.func;
+        while let Expr::Application(inner) = callee {
+            arg_exprs.push(&inner.arg);
+            callee = &inner.func;
+        }
+        arg_exprs.reverse();
+
+        let function = match callee {
+            Expr::Variable(var) if env.lookup(&var.name).is_none() => match self.resolve(&var.name) {
+                Some(target) => Rc::new(FunctionValue { callee: Callee::Target(target), applied: vec![] }),
+                None => return Err(RuntimeError::new(format!("Unknown function '{}'", var.name), Some(var.span))),
+            },
+            _ => match self.eval_in(callee, env)? {
+                Value::Function(function) => function,
+                other => {
+                    return Err(RuntimeError::new(format!("Cannot call a value of type {}", other.type_name()), Some(callee.span())))
+                }
+            },
+        };
+        let mut args = Vec::with_capacity(arg_exprs.len());
+        for arg in arg_exprs {
+            args.push(self.eval_in(arg, env)?);
+        }
+
+        let total = function.applied.len() + args.len();
+        let exact = match &function.callee {
+            Callee::Lambda(lambda, _) => total == lambda.params.len(),
+            Callee::Target(Target::Function(def)) => {
+                total == def.params.len() || (def.params.is_empty() && total == 1 && matches!(args[..], [Value::Unit]))
+            }
+            Callee::Target(_) => false,
+        };
+        if exact {
+            Ok(Flow::Call(function, args))
+        } else {
+            self.apply(function, args).map(Flow::Done).map_err(|e| e.at(app.span))
+        }
+    }
+
+    // Apply a function value, running tail calls in a loop
+    fn apply(&mut self, mut function: Rc<FunctionValue<'p>>, mut args: Vec<Value<'p>>) -> Result<Value<'p>, RuntimeError> {
+        if self.depth >= self.depth_limit {
+            return Err(format!("Call depth limit of {} exceeded", self.depth_limit).into());
+        }
+        self.depth += 1;
+        // The result of a Unit function is (), whatever it ends in calling
+        let mut returns_unit = false;
+        let result = loop {
+            if let Err(e) = self.tick() {
+                break Err(e);
+            }
+            let mut all = function.applied.clone();
+            all.append(&mut args);
+            let arity = match &function.callee {
+                Callee::Lambda(lambda, _) => lambda.params.len(),
+                Callee::Target(target) => Self::arity(*target, all.len()),
+            };
+            // `f()` applies a function without parameters to unit
+            if arity == 0 && matches!(all.first(), Some(Value::Unit)) {
+                all.remove(0);
+            }
+            if all.len() < arity {
+                break Ok(Value::Function(Rc::new(FunctionValue { callee: function.callee.clone(), applied: all })));
+            }
+            let rest = all.split_off(arity);
+
+            if let Callee::Target(Target::Function(def)) = &function.callee {
+                returns_unit |= is_unit(&def.return_type);
+            }
+            let flow = self.enter(&function.callee, all);
+            let value = match flow {
+                Ok(Flow::Call(next, next_args)) if rest.is_empty() => {
+                    function = next;
+                    args = next_args;
+                    continue;
+                }
+                Ok(Flow::Call(next, next_args)) => self.apply(next, next_args),
+                Ok(Flow::Done(value)) => Ok(value),
+                Err(e) => Err(e),
+            };
+            match value {
+                Ok(value) if rest.is_empty() => break Ok(if returns_unit { Value::Unit } else { value }),
+                // Arguments beyond the callee's parameters apply the function it returns
+                Ok(Value::Function(next)) => {
+                    function = next;
+                    args = rest;
+                    returns_unit = false;
+                }
+                Ok(other) => break Err(format!("Cannot call a value of type {}", other.type_name()).into()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.depth -= 1;
+        result
+    }
+
+    // Run the callee on all the arguments it takes
+    fn enter(&mut self, callee: &Callee<'p>, args: Vec<Value<'p>>) -> Result<Flow<'p>, RuntimeError> {
+        match callee {
+            Callee::Lambda(lambda, captured) => {
+                let env = lambda.params.iter()
+                    .zip(args)
+                    .fold(captured.clone(), |env, ((name, _), value)| env.bind(name, value));
+                self.eval_tail(&lambda.body, env)
+            }
+            Callee::Target(Target::Function(function)) => self.call_function(function, args),
+            Callee::Target(Target::Builtin(name)) => Ok(Flow::Done(self.call_builtin(name, args)?)),
+            Callee::Target(Target::Constructor(name, _)) => Ok(Flow::Done(Value::Constructor(name, args))),
+            Callee::Target(Target::Extern(extern_func)) => Ok(Flow::Done(self.call_extern(extern_func, &args)?)),
+        }
+    }
+
+    fn call_function(&mut self, function: &'p FunctionDef, args: Vec<Value<'p>>) -> Result<Flow<'p>, RuntimeError> {
+        let env = function.params.iter()
+            .zip(args)
+            .fold(Env::default(), |env, ((name, _), value)| env.bind(name, value));
+        if !self.check_contracts {
+            return self.eval_tail(&function.body, env);
+        }
+
+        let violation = |clause: &str, expr: &Expr| {
+            RuntimeError::new(format!("Contract violation in '{}': {} {}", function.name, clause, expr), Some(expr.span()))
+        };
+        for clause in &function.requires {
+            if !truthy(&self.eval_in(clause, &env)?)? {
+                return Err(violation("requires", clause));
+            }
+        }
+        let result = self.eval_in(&function.body, &env)?;
+        let result_env = env.bind("result", result.clone());
+        for clause in &function.ensures {
+            if !truthy(&self.eval_in(clause, &result_env)?)? {
+                return Err(violation("ensures", clause));
+            }
+        }
+        Ok(Flow::Done(result))
+    }
+
+    fn call_extern(&mut self, extern_func: &ExternFunctionDecl, args: &[Value<'p>]) -> Result<Value<'p>, RuntimeError> {
+        let stub = self.stubs.get_mut(&extern_func.c_name)
+            .ok_or_else(|| format!("No stub for extern function '{}'", extern_func.c_name))?;
+        let value = stub(args, &mut self.output).map_err(|e| format!("{}: {}", extern_func.c_name, e))?;
+        Ok(if is_unit(&extern_func.return_type) { Value::Unit } else { value })
+    }
+
+    fn call_builtin(&mut self, name: &str, args: Vec<Value<'p>>) -> Result<Value<'p>, String> {
+        let mut args = args.into_iter();
+        let mut next = || args.next().ok_or_else(|| format!("{} is missing an argument", name));
+        match name {
+            "String_length" | "String.length" => Ok(Value::Int(string(&next()?)?.len() as i64)),
+            "String_contains" => {
+                let haystack = next()?;
+                let needle = next()?;
+                Ok(Value::Bool(string(&haystack)?.contains(string(&needle)?)))
+            }
+            "print" | "println" => {
+                self.output.push_str(string(&next()?)?);
+                if name == "println" {
+                    self.output.push('\n');
+                }
+                Ok(Value::Unit)
+            }
+            "List_concat" | "List.concat" => {
+                let mut elements = vec![];
+                for inner in list(&next()?)?.iter() {
+                    elements.extend(list(inner)?.iter().cloned());
+                }
+                Ok(Value::List(Rc::new(elements)))
+            }
+            "List_get" | "List.get" | "list_get" => {
+                let elements = list(&next()?)?.clone();
+                let index = int(&next()?)?;
+                let default = next().ok();
+                match (usize::try_from(index).ok().and_then(|index| elements.get(index)), default) {
+                    (Some(element), _) => Ok(element.clone()),
+                    (None, Some(default)) => Ok(default),
+                    (None, None) => Err(format!("List index {} out of bounds for length {}", index, elements.len())),
+                }
+            }
+            "List_set" | "List.set" => {
+                let mut elements = list(&next()?)?.as_ref().clone();
+                let index = int(&next()?)?;
+                let length = elements.len();
+                let slot = usize::try_from(index).ok()
+                    .and_then(|index| elements.get_mut(index))
+                    .ok_or_else(|| format!("List index {} out of bounds for length {}", index, length))?;
+                *slot = next()?;
+                Ok(Value::List(Rc::new(elements)))
+            }
+            "list_new" => Ok(Value::List(Rc::new(vec![]))),
+            "List_push" | "List.push" | "list_push" => {
+                let mut elements = list(&next()?)?.as_ref().clone();
+                elements.push(next()?);
+                Ok(Value::List(Rc::new(elements)))
+            }
+            "List_length" | "List.length" => Ok(Value::Int(list(&next()?)?.len() as i64)),
+            "HashMap_new" => {
+                let capacity = int(&next()?)?;
+                if capacity <= 0 {
+                    return Err(format!("HashMap capacity must be positive, got {}", capacity));
+                }
+                Ok(Value::HashMap(Rc::new(RefCell::new(BTreeMap::new()))))
+            }
+            "HashMap_put" => {
+                let map = next()?;
+                let key = int(&next()?)?;
+                hash_map(&map)?.borrow_mut().insert(key, next()?);
+                Ok(Value::Unit)
+            }
+            // Missing keys read as 0, as in the backend's zeroed buckets
+            "HashMap_get" => {
+                let map = next()?;
+                let key = int(&next()?)?;
+                let value = hash_map(&map)?.borrow().get(&key).cloned();
+                Ok(value.unwrap_or(Value::Int(0)))
+            }
+            "HashMap_size" => Ok(Value::Int(hash_map(&next()?)?.borrow().len() as i64)),
+            "int_to_float" => Ok(Value::Float(int(&next()?)? as f64)),
+            "float_to_int" => match next()? {
+                Value::Float(x) => Ok(Value::Int(x as i64)),
+                other => Err(format!("float_to_int expects Float64, got {}", other.type_name())),
+            },
+            _ => Err(format!("Unknown builtin '{}'", name)),
+        }
+    }
+
+    // Expressions that neither call nor continue in a subexpression, each
+    // in a function of its own to keep the frames of recursive calls small
+    fn eval_simple(&mut self, expr: &'p Expr, env: &Env<'p>) -> Result<Value<'p>, RuntimeError> {
+        match expr {
+            Expr::Literal(lit) => Ok(match &lit.value {
+                LiteralValue::Int(n) => Value::Int(*n),
+                LiteralValue::Float(x) => Value::Float(*x),
+                LiteralValue::Bool(b) => Value::Bool(*b),
+                LiteralValue::String(s) => Value::String(s.as_str().into()),
+                LiteralValue::Unit => Value::Unit,
+            }),
+            Expr::Variable(var) => self.eval_variable(var, env),
+            Expr::Lambda(lambda) => Ok(Value::Function(Rc::new(FunctionValue {
+                callee: Callee::Lambda(lambda, env.clone()),
+                applied: vec![],
+            }))),
+            Expr::Constructor(constructor) => self.eval_constructor(constructor, env),
+            Expr::BinaryOp(binop) => self.eval_binary_op(binop, env),
+            Expr::UnaryOp(unop) => self.eval_unary_op(unop, env),
+            Expr::Tuple(tuple) => tuple.elements.iter()
+                .map(|element| self.eval_in(element, env))
+                .collect::<Result<_, _>>()
+                .map(Value::Tuple),
+            Expr::Record(record) => record.fields.iter()
+                .map(|(name, value)| Ok((name.as_str(), self.eval_in(value, env)?)))
+                .collect::<Result<_, RuntimeError>>()
+                .map(Value::Record),
+            Expr::FieldAccess(access) => self.eval_field_access(access, env),
+            Expr::Let(_) | Expr::If(_) | Expr::Match(_) | Expr::Application(_) => self.eval_in(expr, env),
+        }
+    }
+
+    fn eval_variable(&mut self, var: &'p Variable, env: &Env<'p>) -> Result<Value<'p>, RuntimeError> {
+        if let Some(value) = env.lookup(&var.name) {
+            return Ok(value.clone());
+        }
+        match self.resolve(&var.name) {
+            Some(Target::Constructor(name, 0)) => Ok(Value::Constructor(name, vec![])),
+            Some(target) => Ok(Value::Function(Rc::new(FunctionValue { callee: Callee::Target(target), applied: vec![] }))),
+            None => Err(format!("Unbound variable '{}'", var.name).into()),
+        }
+    }
+
+    fn eval_constructor(&mut self, constructor: &'p Constructor, env: &Env<'p>) -> Result<Value<'p>, RuntimeError> {
+        let args = constructor.args.iter()
+            .map(|arg| self.eval_in(arg, env))
+            .collect::<Result<Vec<_>, _>>()?;
+        if constructor.name == "List" {
+            return Ok(Value::List(Rc::new(args)));
+        }
+        match self.resolve(&constructor.name) {
+            Some(Target::Constructor(name, fields)) if fields == args.len() => Ok(Value::Constructor(name, args)),
+            Some(Target::Constructor(name, fields)) => {
+                Err(format!("Constructor '{}' expects {} arguments, got {}", name, fields, args.len()).into())
+            }
+            _ => Err(format!("Unknown constructor '{}'", constructor.name).into()),
+        }
+    }
+
+    fn eval_binary_op(&mut self, binop: &'p BinaryOp, env: &Env<'p>) -> Result<Value<'p>, RuntimeError> {
+        // Both operands are evaluated, `&&` and `||` included, but `a => b`
+        // only evaluates `b` when `a` holds
+        let left = self.eval_in(&binop.left, env)?;
+        if binop.op == "=>" && !truthy(&left)? {
+            return Ok(Value::Bool(true));
+        }
+        let right = self.eval_in(&binop.right, env)?;
+        Ok(binary_op(&binop.op, &left, &right)?)
+    }
+
+    fn eval_unary_op(&mut self, unop: &'p UnaryOp, env: &Env<'p>) -> Result<Value<'p>, RuntimeError> {
+        match (unop.op.as_str(), self.eval_in(&unop.operand, env)?) {
+            ("-", Value::Int(n)) => Ok(Value::Int(n.wrapping_neg())),
+            ("-", Value::Float(x)) => Ok(Value::Float(-x)),
+            ("not", Value::Bool(b)) => Ok(Value::Bool(!b)),
+            ("not", Value::Int(n)) => Ok(Value::Int(!n)),
+            (op, operand) => Err(format!("Unsupported unary operator {} on {}", op, operand.type_name()).into()),
+        }
+    }
+
+    fn eval_field_access(&mut self, access: &'p FieldAccess, env: &Env<'p>) -> Result<Value<'p>, RuntimeError> {
+        match self.eval_in(&access.record, env)? {
+            Value::Record(fields) => fields.into_iter()
+                .find(|(name, _)| *name == access.field)
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("Record has no field '{}'", access.field).into()),
+            Value::Tuple(elements) => access.field.parse::<usize>().ok()
+                .and_then(|index| elements.into_iter().nth(index))
+                .ok_or_else(|| format!("Tuple has no element '{}'", access.field).into()),
+            other => Err(format!("Cannot access field '{}' of a value of type {}", access.field, other.type_name()).into()),
+        }
+    }
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Basic(basic) if basic.name == "Unit")
+}
+
+// Conditions may also be integers, nonzero meaning true
+fn truthy(value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        Value::Int(n) => Ok(*n != 0),
+        other => Err(format!("Expected Bool, got {}", other.type_name())),
+    }
+}
+
+fn int(value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Int(n) => Ok(*n),
+        other => Err(format!("Expected Int, got {}", other.type_name())),
+    }
+}
+
+fn float(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Float(x) => Ok(*x),
+        Value::Int(n) => Ok(*n as f64),
+        other => Err(format!("Expected Float64, got {}", other.type_name())),
+    }
+}
+
+fn string<'v>(value: &'v Value) -> Result<&'v str, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        other => Err(format!("Expected String, got {}", other.type_name())),
+    }
+}
+
+fn list<'v, 'p>(value: &'v Value<'p>) -> Result<&'v Rc<Vec<Value<'p>>>, String> {
+    match value {
+        Value::List(elements) => Ok(elements),
+        other => Err(format!("Expected List, got {}", other.type_name())),
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn hash_map<'v, 'p>(value: &'v Value<'p>) -> Result<&'v RefCell<BTreeMap<i64, Value<'p>>>, String> {
+    match value {
+        Value::HashMap(map) => Ok(map),
+        other => Err(format!("Expected HashMap, got {}", other.type_name())),
+    }
+}
+
+fn binary_op<'p>(op: &str, left: &Value<'p>, right: &Value<'p>) -> Result<Value<'p>, String> {
+    let unsupported = || format!("Unsupported operator {} on {} and {}", op, left.type_name(), right.type_name());
+    match op {
+        "&&" => return Ok(Value::Bool(truthy(left)? && truthy(right)?)),
+        "||" => return Ok(Value::Bool(truthy(left)? || truthy(right)?)),
+        "=>" => return Ok(Value::Bool(!truthy(left)? || truthy(right)?)),
+        "==" => return values_equal(left, right).map(Value::Bool),
+        "!=" => return values_equal(left, right).map(|equal| Value::Bool(!equal)),
+        _ => {}
+    }
+
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => {
+            let (a, b) = (*a, *b);
+            let shift = || u32::try_from(b).ok().filter(|shift| *shift < 64).ok_or_else(|| format!("Shift by {} bits", b));
+            Ok(match op {
+                "+" => Value::Int(a.wrapping_add(b)),
+                "-" => Value::Int(a.wrapping_sub(b)),
+                "*" => Value::Int(a.wrapping_mul(b)),
+                "/" | "%" if b == 0 => return Err("Division by zero".to_string()),
+                "/" => Value::Int(a.checked_div(b).ok_or("Integer overflow in division")?),
+                "%" => Value::Int(a.checked_rem(b).ok_or("Integer overflow in division")?),
+                "&" => Value::Int(a & b),
+                "|" => Value::Int(a | b),
+                "^" => Value::Int(a ^ b),
+                "<<" => Value::Int(a << shift()?),
+                ">>" => Value::Int(a >> shift()?),
+                "<" => Value::Bool(a < b),
+                "<=" => Value::Bool(a <= b),
+                ">" => Value::Bool(a > b),
+                ">=" => Value::Bool(a >= b),
+                _ => return Err(unsupported()),
+            })
+        }
+        // Mixed operands are computed in floating point
+        (Value::Float(_), Value::Int(_) | Value::Float(_)) | (Value::Int(_), Value::Float(_)) => {
+            let (a, b) = (float(left)?, float(right)?);
+            Ok(match op {
+                "+" => Value::Float(a + b),
+                "-" => Value::Float(a - b),
+                "*" => Value::Float(a * b),
+                "/" => Value::Float(a / b),
+                "%" => Value::Float(a % b),
+                "<" => Value::Bool(a < b),
+                "<=" => Value::Bool(a <= b),
+                ">" => Value::Bool(a > b),
+                ">=" => Value::Bool(a >= b),
+                _ => return Err(unsupported()),
+            })
+        }
+        (Value::Bool(a), Value::Bool(b)) => Ok(match op {
+            "&" => Value::Bool(a & b),
+            "|" => Value::Bool(a | b),
+            "^" => Value::Bool(a ^ b),
+            _ => return Err(unsupported()),
+        }),
+        _ => Err(unsupported()),
+    }
+}
+
+fn values_equal<'p>(left: &Value<'p>, right: &Value<'p>) -> Result<bool, String> {
+    fn all<'p>(a: &[Value<'p>], b: &[Value<'p>]) -> Result<bool, String> {
+        if a.len() != b.len() {
+            return Ok(false);
+        }
+        for (a, b) in a.iter().zip(b) {
+            if !values_equal(a, b)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Ok(a == b),
+        (Value::Float(_), Value::Int(_) | Value::Float(_)) | (Value::Int(_), Value::Float(_)) => {
+            Ok(float(left)? == float(right)?)
+        }
+        (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+        (Value::String(a), Value::String(b)) => Ok(a == b),
+        (Value::Unit, Value::Unit) => Ok(true),
+        (Value::Tuple(a), Value::Tuple(b)) => all(a, b),
+        (Value::List(a), Value::List(b)) => all(a, b),
+        (Value::Record(a), Value::Record(b)) => {
+            if a.len() != b.len() {
+                return Ok(false);
+            }
+            for (name, value) in a {
+                match b.iter().find(|(other, _)| other == name) {
+                    Some((_, other)) if values_equal(value, other)? => {}
+                    _ => return Ok(false),
+                }
+            }
+            Ok(true)
+        }
+        (Value::Constructor(name_a, a), Value::Constructor(name_b, b)) => Ok(name_a == name_b && all(a, b)?),
+        (Value::HashMap(a), Value::HashMap(b)) => Ok(Rc::ptr_eq(a, b)),
+        (Value::Pointer(a), Value::Pointer(b)) => Ok(a == b),
+        _ => Err(format!("Cannot compare {} with {}", left.type_name(), right.type_name())),
+    }
+}
+
+// Bindings `pattern` makes when it matches `value`, appended to `bindings`
+fn match_pattern<'p>(pattern: &'p Pattern, value: &Value<'p>, bindings: &mut Vec<(&'p str, Value<'p>)>) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard(_), _) => true,
+        (Pattern::Variable(var), value) => {
+            bindings.push((&var.name, value.clone()));
+            true
+        }
+        (Pattern::Literal(lit), value) => match (&lit.value, value) {
+            (LiteralValue::Int(a), Value::Int(b)) => a == b,
+            (LiteralValue::Float(a), Value::Float(b)) => a == b,
+            (LiteralValue::Bool(a), Value::Bool(b)) => a == b,
+            (LiteralValue::String(a), Value::String(b)) => a.as_str() == &**b,
+            (LiteralValue::Unit, _) => true,
+            _ => false,
+        },
+        (Pattern::Constructor(constructor), Value::Constructor(name, args)) => {
+            constructor.name == *name
+                && constructor.args.len() == args.len()
+                && constructor.args.iter().zip(args).all(|(pattern, arg)| match_pattern(pattern, arg, bindings))
+        }
+        (Pattern::Tuple(tuple), Value::Tuple(elements)) => {
+            tuple.elements.len() == elements.len()
+                && tuple.elements.iter().zip(elements).all(|(pattern, element)| match_pattern(pattern, element, bindings))
+        }
+        (Pattern::Record(record), Value::Record(fields)) => record.fields.iter().all(|(name, pattern)| {
+            fields.iter()
+                .find(|(field, _)| field == name)
+                .is_some_and(|(_, value)| match_pattern(pattern, value, bindings))
+        }),
+        (Pattern::Or(or), value) => or.alternatives.iter().any(|alternative| {
+            let before = bindings.len();
+            let matched = match_pattern(alternative, value, bindings);
+            if !matched {
+                bindings.truncate(before);
+            }
+            matched
+        }),
+        _ => false,
+    }
+}
+
+// ============================================================================
+// Extern Stubs
+// ============================================================================
+
+// Stubs for the C library functions examples call; they have the effect
+// the C functions have on the program's output
+fn default_stubs<'p>() -> Vec<(&'static str, ExternStub<'p>)> {
+    fn unary<'p>(f: fn(f64) -> f64) -> ExternStub<'p> {
+        Box::new(move |args, _| Ok(Value::Float(f(float(arg(args, 0)?)?))))
+    }
+    fn binary<'p>(f: fn(f64, f64) -> f64) -> ExternStub<'p> {
+        Box::new(move |args, _| Ok(Value::Float(f(float(arg(args, 0)?)?, float(arg(args, 1)?)?))))
+    }
+
+    vec![
+        ("printf", Box::new(|args: &[Value<'p>], output: &mut String| {
+            let text = format_c(string(arg(args, 0)?)?, &args[1..])?;
+            output.push_str(&text);
+            Ok(Value::Int(text.len() as i64))
+        })),
+        ("puts", Box::new(|args: &[Value<'p>], output: &mut String| {
+            let text = string(arg(args, 0)?)?;
+            output.push_str(text);
+            output.push('\n');
+            Ok(Value::Int(text.len() as i64 + 1))
+        })),
+        ("putchar", Box::new(|args: &[Value<'p>], output: &mut String| {
+            let c = int(arg(args, 0)?)?;
+            output.push(char::from(c as u8));
+            Ok(Value::Int(c & 0xff))
+        })),
+        ("strlen", Box::new(|args: &[Value<'p>], _: &mut String| Ok(Value::Int(string(arg(args, 0)?)?.len() as i64)))),
+        ("abs", Box::new(|args: &[Value<'p>], _: &mut String| Ok(Value::Int(int(arg(args, 0)?)?.wrapping_abs())))),
+        ("labs", Box::new(|args: &[Value<'p>], _: &mut String| Ok(Value::Int(int(arg(args, 0)?)?.wrapping_abs())))),
+        ("sqrt", unary(f64::sqrt)),
+        ("sin", unary(f64::sin)),
+        ("cos", unary(f64::cos)),
+        ("tan", unary(f64::tan)),
+        ("exp", unary(f64::exp)),
+        ("log", unary(f64::ln)),
+        ("floor", unary(f64::floor)),
+        ("ceil", unary(f64::ceil)),
+        ("round", unary(f64::round)),
+        ("fabs", unary(f64::abs)),
+        ("pow", binary(f64::powf)),
+        ("atan2", binary(f64::atan2)),
+        ("fmin", binary(f64::min)),
+        ("fmax", binary(f64::max)),
+    ]
+}
+
+/// Whether the interpreter has a built-in stub for the C function `c_name`
+pub fn has_default_stub(c_name: &str) -> bool {
+    default_stubs().iter().any(|(name, _)| *name == c_name)
+}
+
+fn arg<'a, 'p>(args: &'a [Value<'p>], index: usize) -> Result<&'a Value<'p>, String> {
+    args.get(index).ok_or_else(|| format!("missing argument {}", index + 1))
+}
+
+// `printf` formatting of `args`: flags, width and precision with the d, i,
+// u, x, X, c, s, f and % conversions. Integers are cut to the width the
+// length modifier gives them, as C reads them from the argument list.
+fn format_c(format: &str, args: &[Value]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut flags = String::new();
+        while let Some(&flag) = chars.peek().filter(|flag| "-+ 0#".contains(**flag)) {
+            flags.push(flag);
+            chars.next();
+        }
+        let mut width = 0;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            width = width * 10 + digit as usize;
+            chars.next();
+        }
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut digits = 0;
+            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                digits = digits * 10 + digit as usize;
+                chars.next();
+            }
+            precision = Some(digits);
+        }
+        let mut length = String::new();
+        while let Some(&modifier) = chars.peek().filter(|modifier| "hlqjzt".contains(**modifier)) {
+            length.push(modifier);
+            chars.next();
+        }
+        let conversion = chars.next().ok_or("incomplete conversion at the end of the format")?;
+        if conversion == '%' {
+            out.push('%');
+            continue;
+        }
+        let mut next = || args.next().ok_or_else(|| format!("no argument for %{}", conversion));
+
+        let (body, numeric) = match conversion {
+            'd' | 'i' => {
+                let n = int(next()?)?;
+                let n = match length.as_str() {
+                    "" => n as i32 as i64,
+                    "h" => n as i16 as i64,
+                    "hh" => n as i8 as i64,
+                    _ => n,
+                };
+                let mut digits = n.unsigned_abs().to_string();
+                if let Some(precision) = precision {
+                    digits = format!("{:0>1$}", digits, precision);
+                }
+                let sign = if n < 0 {
+                    "-"
+                } else if flags.contains('+') {
+                    "+"
+                } else if flags.contains(' ') {
+                    " "
+                } else {
+                    ""
+                };
+                (format!("{}{}", sign, digits), true)
+            }
+            'u' | 'x' | 'X' => {
+                let n = int(next()?)? as u64;
+                let n = match length.as_str() {
+                    "" => n as u32 as u64,
+                    "h" => n as u16 as u64,
+                    "hh" => n as u8 as u64,
+                    _ => n,
+                };
+                let digits = match conversion {
+                    'u' => n.to_string(),
+                    'x' => format!("{:x}", n),
+                    _ => format!("{:X}", n),
+                };
+                match precision {
+                    Some(precision) => (format!("{:0>1$}", digits, precision), true),
+                    None => (digits, true),
+                }
+            }
+            'c' => (char::from(int(next()?)? as u8).to_string(), false),
+            's' => {
+                let text = string(next()?)?;
+                match precision {
+                    Some(precision) => (text.chars().take(precision).collect(), false),
+                    None => (text.to_string(), false),
+                }
+            }
+            'f' | 'F' => {
+                let x = float(next()?)?;
+                let body = if x.is_nan() {
+                    "nan".to_string()
+                } else if x.is_infinite() {
+                    if x < 0.0 { "-inf" } else { "inf" }.to_string()
+                } else {
+                    format!("{:.*}", precision.unwrap_or(6), x)
+                };
+                let body = match body.starts_with('-') {
+                    false if flags.contains('+') => format!("+{}", body),
+                    false if flags.contains(' ') => format!(" {}", body),
+                    _ => body,
+                };
+                (if conversion == 'F' { body.to_uppercase() } else { body }, true)
+            }
+            other => return Err(format!("unsupported conversion %{}", other)),
+        };
+
+        let padding = width.saturating_sub(body.chars().count());
+        if flags.contains('-') {
+            out.push_str(&body);
+            out.push_str(&" ".repeat(padding));
+        } else if flags.contains('0') && numeric && (precision.is_none() || "fF".contains(conversion)) {
+            let sign_length = if body.starts_with(['-', '+', ' ']) { 1 } else { 0 };
+            out.push_str(&body[..sign_length]);
+            out.push_str(&"0".repeat(padding));
+            out.push_str(&body[sign_length..]);
+        } else {
+            out.push_str(&" ".repeat(padding));
+            out.push_str(&body);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_parser::{parse_expression, parse_ir};
+
+    // Value of `expr` in `source`, as source text
+    fn run(source: &str, expr: &str) -> Result<String, String> {
+        let program = parse_ir(source).unwrap();
+        let expr = parse_expression(expr).unwrap();
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.eval(&expr).map(|value| value.to_string()).map_err(|e| e.message)
+    }
+
+    #[test]
+    fn test_arithmetic_and_recursion() {
+        let source = "func fact(n: Int) -> Int:\n  if n <= 1 then 1 else n * fact(n - 1)\n\n\
+                      func half(x: Float64) -> Float64:\n  x / 2.0\n";
+        assert_eq!(run(source, "fact(10)"), Ok("3628800".to_string()));
+        assert_eq!(run(source, "half(int_to_float(5))"), Ok("2.5".to_string()));
+        assert_eq!(run(source, "7 / 2 + 7 % 2 + float_to_int(2.9)"), Ok("6".to_string()));
+        assert_eq!(run(source, "9223372036854775807 + 1"), Ok("-9223372036854775808".to_string()));
+        assert_eq!(run(source, "1 / (fact(1) - 1)"), Err("Division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_records_variants_and_matches() {
+        let source = "type Shape =\n  | Circle(Float64)\n  | Rect(Int, Int)\n  | Empty\n\
+                      type Point = { x: Int, y: Int }\n\n\
+                      func area(s: Shape) -> Int:\n  match s with\n  | Rect(w, h) if w == h -> 0 - w * h\n  \
+                      | Rect(w, h) -> w * h\n  | Circle(_) | Empty -> 1\n\n\
+                      func flip(p: Point) -> Point:\n  { y: p.x, x: p.y }\n\n\
+                      func safe_div(a: Int, b: Int) -> Result<Int, String>:\n  if b == 0 then Err(\"zero\") else Ok(a / b)\n";
+        assert_eq!(run(source, "(area(Rect(2, 3)), area(Rect(2, 2)), area(Empty))"), Ok("(6, -4, 1)".to_string()));
+        assert_eq!(run(source, "flip({ x: 1, y: 2 }).y"), Ok("1".to_string()));
+        assert_eq!(run(source, "(safe_div(7, 2), safe_div(1, 0))"), Ok("(Ok(3), Err(\"zero\"))".to_string()));
+        assert_eq!(run(source, "match Some((1, 2)) with | Some((a, b)) -> a + b | None -> 0"), Ok("3".to_string()));
+        assert_eq!(run(source, "match 5 with | 1 -> true"), Err("No match arm matches 5".to_string()));
+    }
+
+    #[test]
+    fn test_closures_and_partial_application() {
+        let source = "func add(a: Int, b: Int) -> Int:\n  a + b\n\n\
+                      func twice(f: Int -> Int, x: Int) -> Int:\n  f(f(x))\n\n\
+                      func adder(n: Int) -> Int -> Int:\n  \\x -> x + n\n";
+        assert_eq!(run(source, "twice(add(10), 1)"), Ok("21".to_string()));
+        assert_eq!(run(source, "adder(3)(4)"), Ok("7".to_string()));
+        assert_eq!(run(source, "let k = 5 in twice(\\x -> x * k, 2)"), Ok("50".to_string()));
+        assert_eq!(run(source, "add"), Ok("<function>".to_string()));
+    }
+
+    #[test]
+    fn test_lists_and_hash_maps() {
+        let source = "func fill(xs: List<Int>, n: Int) -> List<Int>:\n  if n == 0 then xs else fill(List_push(xs, n), n - 1)\n\n\
+                      func count(dummy: Unit) -> Int:\n  let m = HashMap_new(16) in\n  let _ = HashMap_put(m, 1, 10) in\n  \
+                      let _ = HashMap_put(m, 2, 20) in\n  HashMap_get(m, 1) + HashMap_get(m, 2) + HashMap_get(m, 3) + HashMap_size(m)\n";
+        assert_eq!(run(source, "fill([], 3)"), Ok("[3, 2, 1]".to_string()));
+        // Updates copy the list
+        assert_eq!(run(source, "let xs = [1, 2] in let ys = List_set(xs, 0, 9) in (xs, ys)"), Ok("([1, 2], [9, 2])".to_string()));
+        assert_eq!(run(source, "(List_get([1, 2], 1), list_get([1, 2], 5, 0), List_length(List_concat([[1], [2, 3]])))"), Ok("(2, 0, 3)".to_string()));
+        assert_eq!(run(source, "List_get([1], 1)"), Err("List index 1 out of bounds for length 1".to_string()));
+        // Puts update the map every binding refers to
+        assert_eq!(run(source, "count(())"), Ok("32".to_string()));
+        assert_eq!(run(source, "(String_length(\"hello\"), String_contains(\"hello\", \"ell\"))"), Ok("(5, true)".to_string()));
+    }
+
+    #[test]
+    fn test_unit_functions_and_tail_calls() {
+        let source = "func count_down(n: Int) -> Unit:\n  if n == 0 then () else count_down(n - 1)\n\n\
+                      func last(n: Int) -> Int:\n  if n == 0 then 42 else last(n - 1)\n\n\
+                      func deep(n: Int) -> Int:\n  if n == 0 then 0 else 1 + deep(n - 1)\n\n\
+                      func spin(n: Int) -> Int:\n  spin(n + 1)\n\n\
+                      func ignore(n: Int) -> Unit:\n  last(n)\n";
+        // Tail calls run in constant stack
+        assert_eq!(run(source, "count_down(100000)"), Ok("()".to_string()));
+        assert_eq!(run(source, "last(100000)"), Ok("42".to_string()));
+        assert_eq!(run(source, "ignore(3)"), Ok("()".to_string()));
+
+        let program = parse_ir(source).unwrap();
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.set_step_limit(1000);
+        assert_eq!(interpreter.call("spin", vec![Value::Int(0)]).unwrap_err().message, "Step limit of 1000 exceeded");
+        interpreter.set_step_limit(1_000_000);
+        interpreter.set_depth_limit(100);
+        assert_eq!(interpreter.call("deep", vec![Value::Int(99)]).unwrap().to_string(), "99");
+        assert_eq!(interpreter.call("deep", vec![Value::Int(100)]).unwrap_err().message, "Call depth limit of 100 exceeded");
+    }
+
+    #[test]
+    fn test_extern_stubs_and_output() {
+        let source = "@extern(\"printf\")\n@variadic\nfunc printf(format: String) -> Int\n\n\
+                      @extern(\"SDL_Init\")\nfunc sdl_init(flags: Int) -> Int\n\n\
+                      func greet(n: Int) -> Int:\n  let _ = println(\"hi\") in\n  printf(\"%s %5.2f|%-3d|%03lld\\n\", \"n\", 1.5, n, 7)\n";
+        let program = parse_ir(source).unwrap();
+        let mut interpreter = Interpreter::new(&program);
+        assert_eq!(interpreter.call("greet", vec![Value::Int(4)]).unwrap().to_string(), "16");
+        assert_eq!(interpreter.take_output(), "hi\nn  1.50|4  |007\n");
+
+        let error = interpreter.call("sdl_init", vec![Value::Int(0)]).unwrap_err();
+        assert_eq!(error.message, "No stub for extern function 'SDL_Init'");
+        interpreter.register_extern("SDL_Init", |args, output| {
+            output.push_str("init\n");
+            Ok(Value::Int(int(&args[0])? + 1))
+        });
+        assert_eq!(interpreter.call("sdl_init", vec![Value::Int(1)]).unwrap().to_string(), "2");
+        assert_eq!(interpreter.output(), "init\n");
+        assert!(has_default_stub("puts") && !has_default_stub("SDL_Init"));
+    }
+
+    #[test]
+    fn test_contract_checks() {
+        let source = "func pos(n: Int) -> Int\n  requires n > 0\n  ensures result > 1\n:\n  n\n";
+        let program = parse_ir(source).unwrap();
+        let mut interpreter = Interpreter::new(&program);
+        assert_eq!(interpreter.call("pos", vec![Value::Int(0)]).unwrap().to_string(), "0");
+        interpreter.set_contract_checks(true);
+        assert_eq!(interpreter.call("pos", vec![Value::Int(0)]).unwrap_err().message, "Contract violation in 'pos': requires n > 0");
+        assert_eq!(interpreter.call("pos", vec![Value::Int(1)]).unwrap_err().message, "Contract violation in 'pos': ensures result > 1");
+        assert_eq!(interpreter.call("pos", vec![Value::Int(2)]).unwrap().to_string(), "2");
+
+        // `=>` does not evaluate the division it guards
+        let source = "func ratio(a: Int, b: Int) -> Int\n  requires (b != 0) => (a / b >= 0)\n:\n  if b == 0 then 0 else a / b\n";
+        let program = parse_ir(source).unwrap();
+        let mut interpreter = Interpreter::new(&program);
+        interpreter.set_contract_checks(true);
+        assert_eq!(interpreter.call("ratio", vec![Value::Int(7), Value::Int(0)]).unwrap().to_string(), "0");
+        assert!(interpreter.call("ratio", vec![Value::Int(-7), Value::Int(2)]).is_err());
+    }
+}
@@ -3,17 +3,182 @@
 
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_until, take_while, take_while1},
-    character::complete::{alpha1, alphanumeric1, char, digit1, multispace0, multispace1, space0, space1},
-    combinator::{map, opt, recognize, value},
+    bytes::complete::{tag, take_while},
+    character::complete::{alpha1, alphanumeric1, char, digit1, multispace0, multispace1, one_of, space0, space1},
+    combinator::{cut, map, not, opt, recognize, value, verify},
+    error::{context, ContextError, ErrorKind, ParseError},
     multi::{many0, many1, separated_list0, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
+use std::cell::Cell;
+use std::fmt;
+
 use crate::ast::*;
 
-type ParseResult<'a, T> = IResult<&'a str, T>;
+type ParseResult<'a, T> = IResult<&'a str, T, ParseFailure>;
+
+// ============================================================================
+// Syntax Errors
+// ============================================================================
+
+/// Syntax error with the source range it was found at
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl SyntaxError {
+    /// Format as `line:col: message` against the parsed source
+    pub fn render(&self, source: &str) -> String {
+        let (line, col) = self.span.line_col(source);
+        format!("{}:{}: {}", line, col, self.message)
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at {}..{})", self.message, self.span.start, self.span.end)
+    }
+}
+
+// nom error type: keeps the furthest offset any alternative reached, plus the
+// innermost `context` label describing what was expected there
+#[derive(Debug, Clone, PartialEq)]
+struct ParseFailure {
+    offset: usize,
+    expected: Option<&'static str>,
+}
+
+impl ParseFailure {
+    fn at(input: &str, expected: &'static str) -> Self {
+        ParseFailure { offset: offset_of(input), expected: Some(expected) }
+    }
+
+    fn into_syntax_error(self, source: &str) -> SyntaxError {
+        let rest = &source[self.offset.min(source.len())..];
+        let start = source.len() - rest.trim_start().len();
+        let token = rest.split_whitespace().next();
+        let found = match token {
+            Some(token) => format!("'{}'", token.chars().take(20).collect::<String>()),
+            None => "end of input".to_string(),
+        };
+        let message = match self.expected {
+            Some(expected) => format!("expected {}, found {}", expected, found),
+            None => format!("unexpected {}", found),
+        };
+        let end = start + token.map(str::len).unwrap_or(0);
+        SyntaxError { message, span: Span::new(start, end) }
+    }
+}
+
+impl<'a> ParseError<&'a str> for ParseFailure {
+    fn from_error_kind(input: &'a str, _kind: ErrorKind) -> Self {
+        ParseFailure { offset: offset_of(input), expected: None }
+    }
+
+    fn append(_input: &'a str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn or(self, other: Self) -> Self {
+        if other.offset > self.offset
+            || (other.offset == self.offset && other.expected.is_some())
+        {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+impl<'a> ContextError<&'a str> for ParseFailure {
+    fn add_context(_input: &'a str, ctx: &'static str, mut other: Self) -> Self {
+        if other.expected.is_none() {
+            other.expected = Some(ctx);
+        }
+        other
+    }
+}
+
+fn failure_of(err: nom::Err<ParseFailure>) -> ParseFailure {
+    match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        nom::Err::Incomplete(_) => ParseFailure { offset: usize::MAX, expected: None },
+    }
+}
+
+// ============================================================================
+// Source Spans
+// ============================================================================
+
+thread_local! {
+    // Start address of the text handed to `parse_ir`, used to turn the `&str`
+    // slices nom hands around back into byte offsets
+    static SOURCE_START: Cell<usize> = Cell::new(0);
+}
+
+// Restores the previous source base when a (possibly nested) parse finishes
+struct SourceGuard {
+    previous: usize,
+}
+
+impl SourceGuard {
+    fn enter(source: &str) -> Self {
+        let previous = SOURCE_START.with(|start| start.replace(source.as_ptr() as usize));
+        SourceGuard { previous }
+    }
+}
+
+impl Drop for SourceGuard {
+    fn drop(&mut self) {
+        SOURCE_START.with(|start| start.set(self.previous));
+    }
+}
+
+fn offset_of(input: &str) -> usize {
+    (input.as_ptr() as usize).saturating_sub(SOURCE_START.with(|start| start.get()))
+}
+
+// Span of the text consumed between `start` and `rest`, without the
+// surrounding whitespace and comments
+fn span_between(start: &str, rest: &str) -> Span {
+    let start = skip_ws_and_comments(start).map(|(s, _)| s).unwrap_or(start);
+    let consumed = &start[..start.len().saturating_sub(rest.len())];
+    let begin = offset_of(start);
+    Span::new(begin, begin + consumed.trim_end().len())
+}
+
+trait Located {
+    fn locate(&mut self, span: Span);
+}
+
+impl Located for Expr {
+    fn locate(&mut self, span: Span) {
+        self.set_span(span);
+    }
+}
+
+impl Located for Pattern {
+    fn locate(&mut self, span: Span) {
+        self.set_span(span);
+    }
+}
+
+// Run `parser` and record the span of everything it consumed on the result
+fn located<'a, F, O>(mut parser: F) -> impl FnMut(&'a str) -> ParseResult<'a, O>
+where
+    F: FnMut(&'a str) -> ParseResult<'a, O>,
+    O: Located,
+{
+    move |input: &'a str| {
+        let (rest, mut node) = parser(input)?;
+        node.locate(span_between(input, rest));
+        Ok((rest, node))
+    }
+}
 
 // ============================================================================
 // Helper Parsers
@@ -23,7 +188,20 @@ fn ws<'a, F, O>(parser: F) -> impl FnMut(&'a str) -> ParseResult<'a, O>
 where
     F: FnMut(&'a str) -> ParseResult<'a, O>,
 {
-    delimited(multispace0, parser, multispace0)
+    delimited(skip_ws_and_comments, parser, multispace0)
+}
+
+// Reserved words that can never name a variable or function
+const KEYWORDS: &[&str] = &[
+    "func", "type", "match", "with", "if", "then", "else", "let", "in", "requires", "ensures",
+    "module", "signature", "import", "end", "not", "and", "or",
+];
+
+// Keywords that start a top-level declaration; the parser resynchronises on these
+const TOP_LEVEL_KEYWORDS: &[&str] = &["func", "type", "module", "signature", "import", "end"];
+
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> ParseResult<'a, &'a str> {
+    terminated(tag(word), not(alt((alphanumeric1, tag("_")))))
 }
 
 fn identifier(input: &str) -> ParseResult<String> {
@@ -66,8 +244,9 @@ fn parse_basic_type(input: &str) -> ParseResult<Type> {
             tag("Bool"),
             tag("String"),
             tag("Unit"),
-            // Fallback: any identifier is a type name (for custom types)
-            recognize(identifier),
+            // Fallback: any identifier is a type name (for custom types),
+            // possibly qualified by a module path (`Core.Position`)
+            recognize(pair(identifier, many0(preceded(char('.'), identifier)))),
         )),
         |s: &str| Type::Basic(BasicType { name: s.to_string() }),
     )(input)
@@ -131,11 +310,18 @@ fn parse_tuple_type(input: &str) -> ParseResult<Type> {
             separated_list0(ws(char(',')), parse_type),
             char(')'),
         ),
-        |types| Type::Tuple(TupleType { element_types: types }),
+        |mut types| {
+            // `(A -> B)` is just a parenthesised type
+            if types.len() == 1 {
+                types.pop().unwrap()
+            } else {
+                Type::Tuple(TupleType { element_types: types })
+            }
+        },
     )(input)
 }
 
-fn parse_type(input: &str) -> ParseResult<Type> {
+fn parse_atomic_type(input: &str) -> ParseResult<Type> {
     alt((
         parse_option_type,
         parse_result_type,
@@ -146,6 +332,23 @@ fn parse_type(input: &str) -> ParseResult<Type> {
     ))(input)
 }
 
+// `A -> B -> R` is right-associative: `A -> (B -> R)`
+fn parse_type(input: &str) -> ParseResult<Type> {
+    let (input, first) = parse_atomic_type(input)?;
+    let (input, rest) = many0(preceded(ws(tag("->")), parse_atomic_type))(input)?;
+    
+    let mut types: Vec<Type> = std::iter::once(first).chain(rest).collect();
+    let mut result = types.pop().expect("at least one type");
+    while let Some(param) = types.pop() {
+        result = Type::Function(FunctionType {
+            param_type: Box::new(param),
+            return_type: Box::new(result),
+            effect: None,
+        });
+    }
+    Ok((input, result))
+}
+
 // ============================================================================
 // Annotation Parsers
 // ============================================================================
@@ -158,8 +361,8 @@ fn parse_annotation(input: &str) -> ParseResult<Annotation> {
                 identifier,
                 opt(delimited(
                     char('('),
-                    take_until(")"),
-                    char(')'),
+                    balanced_args,
+                    context("')' to close annotation arguments", char(')')),
                 )),
             ),
             |(name, args_str)| {
@@ -174,15 +377,65 @@ fn parse_annotation(input: &str) -> ParseResult<Annotation> {
     )(input)
 }
 
+// Text up to the `)` closing an annotation, skipping over nested brackets
+// and string literals so `expected=Some((1, 2))` stays in one piece
+fn balanced_args(input: &str) -> ParseResult<&str> {
+    let end = top_level_positions(input, |c| c == ')').next().unwrap_or(input.len());
+    Ok((&input[end..], &input[..end]))
+}
+
+// Byte positions of characters matching `is_target` that sit outside any
+// brackets or string literal
+fn top_level_positions<'a>(
+    input: &'a str,
+    is_target: impl Fn(char) -> bool + 'a,
+) -> impl Iterator<Item = usize> + 'a {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    input.char_indices().filter_map(move |(pos, c)| {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            return None;
+        }
+        if depth == 0 && is_target(c) {
+            return Some(pos);
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        None
+    })
+}
+
 fn parse_annotation_args(input: &str) -> Vec<(String, String)> {
-    input.split(',')
+    let mut pieces = vec![];
+    let mut start = 0;
+    for comma in top_level_positions(input, |c| c == ',') {
+        pieces.push(&input[start..comma]);
+        start = comma + 1;
+    }
+    pieces.push(&input[start..]);
+    
+    pieces.into_iter()
         .enumerate()
         .filter_map(|(idx, s)| {
             let s = s.trim();
-            let parts: Vec<&str> = s.splitn(2, '=').collect();
-            if parts.len() == 2 {
+            // key=value only when the key is a plain identifier (not `a == b`)
+            let key_value = s.split_once('=').filter(|(key, value)| {
+                !value.starts_with('=') && identifier(key.trim()).map_or(false, |(rest, _)| rest.is_empty())
+            });
+            if let Some((key, value)) = key_value {
                 // key=value format
-                Some((parts[0].trim().to_string(), parts[1].trim().to_string()))
+                Some((key.trim().to_string(), value.trim().to_string()))
             } else if !s.is_empty() {
                 // Positional argument (e.g., @extern("printf"))
                 // Strip quotes if present
@@ -217,7 +470,7 @@ fn parse_multiline_record_type_def(annotations: Vec<Annotation>) -> impl FnMut(&
             remaining = new_input;
             
             // Check for closing brace
-            if let Ok((new_input, _)) = char::<_, nom::error::Error<&str>>('}')(remaining) {
+            if let Ok((new_input, _)) = char::<_, ParseFailure>('}')(remaining) {
                 remaining = new_input;
                 break;
             }
@@ -236,6 +489,7 @@ fn parse_multiline_record_type_def(annotations: Vec<Annotation>) -> impl FnMut(&
             name,
             definition: TypeDefKind::Record(RecordType { fields }),
             annotations: annotations.clone(),
+            span: Span::default(),
         }))
     }
 }
@@ -257,7 +511,7 @@ fn parse_variant_type_def(annotations: Vec<Annotation>) -> impl FnMut(&str) -> P
             remaining = new_input;
             
             // Check if next line starts with |
-            if let Ok((new_input, _)) = char::<_, nom::error::Error<&str>>('|')(remaining) {
+            if let Ok((new_input, _)) = char::<_, ParseFailure>('|')(remaining) {
                 let (new_input, _) = space0(new_input)?;
                 let (new_input, cons_name) = identifier(new_input)?;
                 
@@ -277,13 +531,14 @@ fn parse_variant_type_def(annotations: Vec<Annotation>) -> impl FnMut(&str) -> P
         }
         
         if constructors.is_empty() {
-            return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)));
+            return Err(nom::Err::Error(ParseFailure::from_error_kind(input, ErrorKind::Tag)));
         }
         
         Ok((remaining, TypeDef {
             name,
             definition: TypeDefKind::Variant(constructors),
             annotations: annotations.clone(),
+            span: Span::default(),
         }))
     }
 }
@@ -297,7 +552,7 @@ fn parse_inline_type_def(annotations: Vec<Annotation>) -> impl FnMut(&str) -> Pa
         let (input, _) = ws(char('='))(input)?;
         
         // Check if it's an inline record
-        if let Ok((new_input, _)) = char::<_, nom::error::Error<&str>>('{')(input) {
+        if let Ok((new_input, _)) = char::<_, ParseFailure>('{')(input) {
             // Parse inline record: { x: Int, y: Int }
             let (new_input, fields) = separated_list0(
                 ws(char(',')),
@@ -313,13 +568,14 @@ fn parse_inline_type_def(annotations: Vec<Annotation>) -> impl FnMut(&str) -> Pa
                 name,
                 definition: TypeDefKind::Record(RecordType { fields }),
                 annotations: annotations.clone(),
+                span: Span::default(),
             }))
         } else {
             // Check if it's an inline variant: North | South | East | West
             // Try to parse as variant constructors separated by |
             if let Ok((new_input, first_cons)) = identifier(input) {
                 // Look ahead for |
-                if let Ok((lookahead, _)) = ws(char::<_, nom::error::Error<&str>>('|'))(new_input) {
+                if let Ok((lookahead, _)) = ws(char::<_, ParseFailure>('|'))(new_input) {
                     // It's an inline variant!
                     let mut constructors = vec![(first_cons, vec![])];
                     let mut remaining = lookahead;
@@ -334,7 +590,7 @@ fn parse_inline_type_def(annotations: Vec<Annotation>) -> impl FnMut(&str) -> Pa
                         constructors.push((cons_name, vec![]));
                         
                         // Check for more |
-                        match ws(char::<_, nom::error::Error<&str>>('|'))(new_input) {
+                        match ws(char::<_, ParseFailure>('|'))(new_input) {
                             Ok((new_remaining, _)) => remaining = new_remaining,
                             Err(_) => {
                                 remaining = new_input;
@@ -347,6 +603,7 @@ fn parse_inline_type_def(annotations: Vec<Annotation>) -> impl FnMut(&str) -> Pa
                         name,
                         definition: TypeDefKind::Variant(constructors),
                         annotations: annotations.clone(),
+                        span: Span::default(),
                     }));
                 }
             }
@@ -358,6 +615,7 @@ fn parse_inline_type_def(annotations: Vec<Annotation>) -> impl FnMut(&str) -> Pa
                 name,
                 definition: TypeDefKind::Alias(aliased_type),
                 annotations: annotations.clone(),
+                span: Span::default(),
             }))
         }
     }
@@ -382,6 +640,7 @@ fn parse_unit_literal(input: &str) -> ParseResult<Expr> {
         Expr::Literal(Literal {
             value: LiteralValue::Unit,
             type_name: "Unit".to_string(),
+            span: Span::default(),
         }),
         tuple((char('('), space0, char(')'))),
     )(input)
@@ -393,6 +652,7 @@ fn parse_int_literal(input: &str) -> ParseResult<Expr> {
         |s: &str| Expr::Literal(Literal {
             value: LiteralValue::Int(s.parse().unwrap()),
             type_name: "Int".to_string(),
+            span: Span::default(),
         }),
     )(input)
 }
@@ -403,6 +663,7 @@ fn parse_float_literal(input: &str) -> ParseResult<Expr> {
         |s: &str| Expr::Literal(Literal {
             value: LiteralValue::Float(s.parse().unwrap()),
             type_name: "Float64".to_string(),
+            span: Span::default(),
         }),
     )(input)
 }
@@ -413,6 +674,7 @@ fn parse_bool_literal(input: &str) -> ParseResult<Expr> {
             Expr::Literal(Literal {
                 value: LiteralValue::Bool(true),
                 type_name: "Bool".to_string(),
+                span: Span::default(),
             }),
             tag("true"),
         ),
@@ -420,6 +682,7 @@ fn parse_bool_literal(input: &str) -> ParseResult<Expr> {
             Expr::Literal(Literal {
                 value: LiteralValue::Bool(false),
                 type_name: "Bool".to_string(),
+                span: Span::default(),
             }),
             tag("false"),
         ),
@@ -462,12 +725,34 @@ fn parse_string_literal(input: &str) -> ParseResult<Expr> {
         |s: &str| Expr::Literal(Literal {
             value: LiteralValue::String(process_escape_sequences(s)),
             type_name: "String".to_string(),
+            span: Span::default(),
         }),
     )(input)
 }
 
+// Identifier usable as a variable or function name
+fn value_identifier(input: &str) -> ParseResult<String> {
+    verify(identifier, |name: &str| !KEYWORDS.contains(&name))(input)
+}
+
+// Variable or function name, optionally qualified by a module path:
+// `Math.add`, `Game.Core.min`. Module names are capitalised, which keeps
+// this apart from field access on (lowercase) variables.
+fn value_path(input: &str) -> ParseResult<String> {
+    alt((
+        map(
+            recognize(pair(
+                verify(identifier, |name: &str| name.starts_with(|c: char| c.is_uppercase())),
+                many1(preceded(char('.'), identifier)),
+            )),
+            |s: &str| s.to_string(),
+        ),
+        value_identifier,
+    ))(input)
+}
+
 fn parse_variable(input: &str) -> ParseResult<Expr> {
-    map(identifier, |name| Expr::Variable(Variable { name }))(input)
+    map(value_path, |name| Expr::Variable(Variable { name, span: Span::default() }))(input)
 }
 
 // ============================================================================
@@ -475,35 +760,78 @@ fn parse_variable(input: &str) -> ParseResult<Expr> {
 // ============================================================================
 
 fn parse_literal_pattern(input: &str) -> ParseResult<Pattern> {
+    let literal = |value| Pattern::Literal(LiteralPattern { value, span: Span::default() });
     alt((
         map(
             recognize(pair(opt(char('-')), digit1)),
-            |s: &str| Pattern::Literal(LiteralPattern {
-                value: LiteralValue::Int(s.parse().unwrap()),
-            }),
+            move |s: &str| literal(LiteralValue::Int(s.parse().unwrap())),
         ),
-        map(
-            tag("true"),
-            |_| Pattern::Literal(LiteralPattern {
-                value: LiteralValue::Bool(true),
-            }),
+        map(keyword("true"), move |_| literal(LiteralValue::Bool(true))),
+        map(keyword("false"), move |_| literal(LiteralValue::Bool(false))),
+        map(parse_string_literal, move |expr| match expr {
+            Expr::Literal(lit) => literal(lit.value),
+            _ => unreachable!(),
+        }),
+        map(pair(char('('), ws(char(')'))), move |_| literal(LiteralValue::Unit)),
+    ))(input)
+}
+
+// `(a, b)`; a single parenthesised pattern is just grouped
+fn parse_tuple_pattern(input: &str) -> ParseResult<Pattern> {
+    map(
+        delimited(
+            char('('),
+            separated_list0(ws(char(',')), ws(parse_pattern)),
+            cut(context("')' after tuple pattern", ws(char(')')))),
         ),
-        map(
-            tag("false"),
-            |_| Pattern::Literal(LiteralPattern {
-                value: LiteralValue::Bool(false),
-            }),
+        |mut elements| {
+            if elements.len() == 1 {
+                elements.remove(0)
+            } else {
+                Pattern::Tuple(TuplePattern { elements, span: Span::default() })
+            }
+        },
+    )(input)
+}
+
+// `Point { x, y: 0 }` or `{ x, .. }`; a field without a pattern binds a
+// variable of the same name, and fields left out match anything
+fn parse_record_pattern(input: &str) -> ParseResult<Pattern> {
+    let field = map(
+        pair(ws(identifier), opt(preceded(ws(char(':')), ws(parse_pattern)))),
+        |(name, pattern)| {
+            let pattern = pattern.unwrap_or_else(|| {
+                Pattern::Variable(VariablePattern { name: name.clone(), span: Span::default() })
+            });
+            (name, pattern)
+        },
+    );
+    map(
+        pair(
+            opt(terminated(
+                verify(identifier, |name: &str| name.starts_with(|c: char| c.is_uppercase())),
+                space0,
+            )),
+            delimited(
+                char('{'),
+                terminated(
+                    separated_list0(ws(char(',')), field),
+                    pair(opt(ws(char(','))), opt(ws(tag("..")))),
+                ),
+                cut(context("'}' after record pattern", ws(char('}')))),
+            ),
         ),
-    ))(input)
+        |(type_name, fields)| Pattern::Record(RecordPattern { type_name, fields, span: Span::default() }),
+    )(input)
 }
 
 fn parse_wildcard_pattern(input: &str) -> ParseResult<Pattern> {
-    value(Pattern::Wildcard(WildcardPattern), char('_'))(input)
+    value(Pattern::Wildcard(WildcardPattern { span: Span::default() }), char('_'))(input)
 }
 
 fn parse_variable_pattern(input: &str) -> ParseResult<Pattern> {
     map(identifier, |name| {
-        Pattern::Variable(VariablePattern { name })
+        Pattern::Variable(VariablePattern { name, span: Span::default() })
     })(input)
 }
 
@@ -523,20 +851,45 @@ fn parse_constructor_pattern(input: &str) -> ParseResult<Pattern> {
                 Pattern::Constructor(ConstructorPattern {
                     name,
                     args: args.unwrap_or_default(),
+                    span: Span::default(),
                 })
             } else {
-                Pattern::Variable(VariablePattern { name })
+                Pattern::Variable(VariablePattern { name, span: Span::default() })
             }
         },
     )(input)
 }
 
-fn parse_pattern(input: &str) -> ParseResult<Pattern> {
-    alt((
+fn parse_single_pattern(input: &str) -> ParseResult<Pattern> {
+    located(alt((
         parse_wildcard_pattern,
         parse_literal_pattern,
+        parse_tuple_pattern,
+        parse_record_pattern,
         parse_constructor_pattern,
         parse_variable_pattern,
+    )))(input)
+}
+
+// `p1 | p2 | ...`
+fn parse_pattern(input: &str) -> ParseResult<Pattern> {
+    located(map(
+        pair(
+            parse_single_pattern,
+            many0(preceded(
+                ws(terminated(char('|'), not(char('|')))),
+                cut(context("pattern after '|'", parse_single_pattern)),
+            )),
+        ),
+        |(first, rest)| {
+            if rest.is_empty() {
+                first
+            } else {
+                let mut alternatives = vec![first];
+                alternatives.extend(rest);
+                Pattern::Or(OrPattern { alternatives, span: Span::default() })
+            }
+        },
     ))(input)
 }
 
@@ -546,52 +899,75 @@ fn parse_pattern(input: &str) -> ParseResult<Pattern> {
 
 fn parse_binary_op(input: &str) -> ParseResult<Expr> {
     // Left-associative binary operator parser with precedence
-    parse_logical_or(input)
+    parse_implication(input)
 }
 
 // Precedence levels (lowest to highest):
-// 1. || (logical or)
-// 2. && (logical and)  
-// 3. ==, !=, <, >, <=, >= (comparison)
-// 4. +, - (additive)
-// 5. *, /, % (multiplicative)
-
-fn parse_logical_or(input: &str) -> ParseResult<Expr> {
-    let (input, mut left) = parse_logical_and(input)?;
+// 0. => (implication, right-associative; used in contracts)
+// 1. ||, or (logical or)
+// 2. &&, and (logical and)
+// 3. ==, !=, <, >, <=, >= (comparison, non-associative)
+// 4. | (bitwise or)
+// 5. ^ (bitwise xor)
+// 6. & (bitwise and)
+// 7. <<, >> (shift)
+// 8. +, - (additive)
+// 9. *, /, % (multiplicative)
+// 10. -, not (unary prefix)
+
+fn parse_implication(input: &str) -> ParseResult<Expr> {
+    let (input, left) = parse_logical_or(input)?;
     
-    let mut current_input = input;
-    while let Ok((input2, _)) = ws(tag("||"))(current_input) {
-        let (input3, right) = parse_logical_and(input2)?;
-        left = Expr::BinaryOp(BinaryOp {
-            op: "||".to_string(),
+    if let Ok((input2, _)) = ws(tag("=>"))(input) {
+        let (input3, right) = cut(context("expression after '=>'", parse_implication))(input2)?;
+        let span = left.span().to(right.span());
+        return Ok((input3, Expr::BinaryOp(BinaryOp {
+            op: "=>".to_string(),
             left: Box::new(left),
             right: Box::new(right),
-        });
-        current_input = input3;
+            span,
+        })));
     }
     
-    Ok((current_input, left))
+    Ok((input, left))
 }
 
-fn parse_logical_and(input: &str) -> ParseResult<Expr> {
-    let (input, mut left) = parse_comparison(input)?;
+// `operand (operator operand)*`, grouped to the left
+fn parse_left_assoc<'a, F>(
+    input: &'a str,
+    operand: fn(&'a str) -> ParseResult<'a, Expr>,
+    mut operator: F,
+) -> ParseResult<'a, Expr>
+where
+    F: FnMut(&'a str) -> ParseResult<'a, &'a str>,
+{
+    let (mut input, mut left) = operand(input)?;
     
-    let mut current_input = input;
-    while let Ok((input2, _)) = ws(tag("&&"))(current_input) {
-        let (input3, right) = parse_comparison(input2)?;
+    while let Ok((rest, op)) = operator(input) {
+        let (rest, right) = operand(rest)?;
+        let span = left.span().to(right.span());
         left = Expr::BinaryOp(BinaryOp {
-            op: "&&".to_string(),
+            op: op.to_string(),
             left: Box::new(left),
             right: Box::new(right),
+            span,
         });
-        current_input = input3;
+        input = rest;
     }
     
-    Ok((current_input, left))
+    Ok((input, left))
+}
+
+fn parse_logical_or(input: &str) -> ParseResult<Expr> {
+    parse_left_assoc(input, parse_logical_and, ws(alt((tag("||"), value("||", keyword("or"))))))
+}
+
+fn parse_logical_and(input: &str) -> ParseResult<Expr> {
+    parse_left_assoc(input, parse_comparison, ws(alt((tag("&&"), value("&&", keyword("and"))))))
 }
 
 fn parse_comparison(input: &str) -> ParseResult<Expr> {
-    let (input, left) = parse_additive(input)?;
+    let (input, left) = parse_bitwise_or(input)?;
     
     let (input, op_and_right) = opt(tuple((
         ws(alt((
@@ -599,56 +975,80 @@ fn parse_comparison(input: &str) -> ParseResult<Expr> {
             tag("<="), tag(">="),
             tag("<"), tag(">"),
         ))),
-        parse_additive,
+        parse_bitwise_or,
     )))(input)?;
     
     if let Some((op, right)) = op_and_right {
+        let span = left.span().to(right.span());
         Ok((input, Expr::BinaryOp(BinaryOp {
             op: op.to_string(),
             left: Box::new(left),
             right: Box::new(right),
+            span,
         })))
     } else {
         Ok((input, left))
     }
 }
 
-fn parse_additive(input: &str) -> ParseResult<Expr> {
-    let (input, mut left) = parse_multiplicative(input)?;
-    
-    let mut current_input = input;
-    while let Ok((input2, op)) = ws(alt((tag("+"), tag("-"))))(current_input) {
-        let (input3, right) = parse_multiplicative(input2)?;
-        left = Expr::BinaryOp(BinaryOp {
-            op: op.to_string(),
-            left: Box::new(left),
-            right: Box::new(right),
-        });
-        current_input = input3;
+// A `|` starting a line, or followed on its line by `pattern ->` or
+// `pattern if`, begins the next arm of an enclosing match rather than a
+// bitwise or
+fn bitwise_or_operator(input: &str) -> ParseResult<&str> {
+    let (rest, op) = preceded(space0, terminated(tag("|"), not(char('|'))))(input)?;
+    let line = rest.split('\n').next().unwrap_or_default();
+    if pair(ws(parse_pattern), alt((tag("->"), keyword("if"))))(line).is_ok() {
+        return Err(nom::Err::Error(ParseFailure::from_error_kind(input, ErrorKind::Tag)));
     }
-    
-    Ok((current_input, left))
+    let (rest, _) = multispace0(rest)?;
+    Ok((rest, op))
+}
+
+fn parse_bitwise_or(input: &str) -> ParseResult<Expr> {
+    parse_left_assoc(input, parse_bitwise_xor, bitwise_or_operator)
+}
+
+fn parse_bitwise_xor(input: &str) -> ParseResult<Expr> {
+    parse_left_assoc(input, parse_bitwise_and, ws(tag("^")))
+}
+
+fn parse_bitwise_and(input: &str) -> ParseResult<Expr> {
+    parse_left_assoc(input, parse_shift, ws(terminated(tag("&"), not(char('&')))))
+}
+
+fn parse_shift(input: &str) -> ParseResult<Expr> {
+    parse_left_assoc(input, parse_additive, ws(alt((tag("<<"), tag(">>")))))
+}
+
+fn parse_additive(input: &str) -> ParseResult<Expr> {
+    // `-` but not the `->` ending a match guard
+    parse_left_assoc(input, parse_multiplicative, ws(alt((tag("+"), terminated(tag("-"), not(char('>')))))))
 }
 
 fn parse_multiplicative(input: &str) -> ParseResult<Expr> {
-    let (input, mut left) = parse_postfix_expr(input)?;
-    
-    let mut current_input = input;
-    while let Ok((input2, op)) = ws(alt((tag("*"), tag("/"), tag("%"))))(current_input) {
-        let (input3, right) = parse_postfix_expr(input2)?;
-        left = Expr::BinaryOp(BinaryOp {
-            op: op.to_string(),
-            left: Box::new(left),
-            right: Box::new(right),
-        });
-        current_input = input3;
-    }
+    parse_left_assoc(input, parse_unary, ws(alt((tag("*"), tag("/"), tag("%")))))
+}
+
+// `not b`, `-x`; a negative number literal stays a literal
+fn parse_unary(input: &str) -> ParseResult<Expr> {
+    let start = input;
+    let (input, op) = match alt((
+        keyword("not"),
+        terminated(tag("-"), not(one_of(">0123456789"))),
+    ))(input) {
+        Ok(result) => result,
+        Err(_) => return parse_postfix_expr(input),
+    };
+    let (input, operand) = cut(context("operand of unary operator", ws(parse_unary)))(input)?;
     
-    Ok((current_input, left))
+    Ok((input, Expr::UnaryOp(UnaryOp {
+        op: op.to_string(),
+        operand: Box::new(operand),
+        span: span_between(start, input),
+    })))
 }
 
-fn parse_application(input: &str) -> ParseResult<Expr> {
-    let (input, func_name) = identifier(input)?;
+fn parse_call_args(input: &str) -> ParseResult<Vec<Expr>> {
     let (input, _) = ws(char('('))(input)?;
     
     // Parse comma-separated arguments
@@ -658,6 +1058,12 @@ fn parse_application(input: &str) -> ParseResult<Expr> {
     )(input)?;
     
     let (input, _) = ws(char(')'))(input)?;
+    Ok((input, args))
+}
+
+// Apply `func` to `args` in curried form, spanning `call_span`
+fn apply_args(func: Expr, args: Vec<Expr>, call_span: Span) -> Expr {
+    let func_span = func.span();
     
     // If no arguments, return a simple function call application
     // This handles both zero-argument functions like create_list()
@@ -665,105 +1071,183 @@ fn parse_application(input: &str) -> ParseResult<Expr> {
     if args.is_empty() {
         // Zero-argument function call: f() becomes Application(f, Unit)
         // We represent this as applying the function to a Unit literal
-        return Ok((input, Expr::Application(Application {
-            func: Box::new(Expr::Variable(Variable { name: func_name })),
+        return Expr::Application(Application {
+            func: Box::new(func),
             arg: Box::new(Expr::Literal(Literal {
                 value: LiteralValue::Unit,
                 type_name: "Unit".to_string(),
+                span: Span::new(func_span.end, call_span.end),
             })),
-        })));
+            span: call_span,
+        });
     }
     
     // Build nested Application for curried form
     // f(x, y) becomes Application(Application(f, x), y)
-    let mut expr = Expr::Variable(Variable { name: func_name });
+    let mut expr = func;
     for arg in args {
+        let span = func_span.to(arg.span());
         expr = Expr::Application(Application {
             func: Box::new(expr),
             arg: Box::new(arg),
+            span,
         });
     }
+    expr.set_span(call_span);
+    expr
+}
+
+fn parse_application(input: &str) -> ParseResult<Expr> {
+    let start = input;
+    let (input, func_name) = value_path(input)?;
+    let func_span = span_between(start, input);
+    let (input, args) = parse_call_args(input)?;
+    let func = Expr::Variable(Variable { name: func_name, span: func_span });
     
-    Ok((input, expr))
+    Ok((input, apply_args(func, args, span_between(start, input))))
 }
 
 fn parse_match_expr(input: &str) -> ParseResult<Expr> {
-    let (input, _) = ws(tag("match"))(input)?;
-    let (input, scrutinee) = ws(parse_simple_expr)(input)?;
-    let (input, _) = ws(tag("with"))(input)?;
+    let start = input;
+    let (input, _) = ws(keyword("match"))(input)?;
+    let (input, scrutinee) = cut(context("match scrutinee", ws(parse_simple_expr)))(input)?;
+    let (input, _) = cut(context("'with' after match scrutinee", ws(keyword("with"))))(input)?;
     
-    // Parse match arms: | pattern -> expr
-    let (input, arms) = many1(preceded(
-        multispace0,
+    // Parse match arms: | pattern [if guard] -> expr
+    let (input, arms) = context("match arm '| pattern -> expr'", many1(preceded(
+        skip_ws_and_comments,
         map(
             tuple((
                 preceded(ws(char('|')), ws(parse_pattern)),
-                preceded(ws(tag("->")), ws(parse_expr)),
+                opt(preceded(ws(keyword("if")), cut(context("match guard", ws(parse_binary_op))))),
+                cut(preceded(
+                    context("'->' after pattern", ws(tag("->"))),
+                    context("match arm body", ws(parse_expr)),
+                )),
             )),
-            |(pattern, expr)| (pattern, expr),
+            |(pattern, guard, body)| MatchArm { pattern, guard, body },
         ),
-    ))(input)?;
+    )))(input)?;
     
     Ok((input, Expr::Match(MatchExpr {
         scrutinee: Box::new(scrutinee),
         arms,
+        span: span_between(start, input),
     })))
 }
 
 fn parse_if_expr(input: &str) -> ParseResult<Expr> {
-    let (input, _) = ws(tag("if"))(input)?;
-    let (input, condition) = ws(parse_simple_expr)(input)?;
-    let (input, _) = ws(tag("then"))(input)?;
-    let (input, then_branch) = ws(parse_expr)(input)?;
-    let (input, _) = ws(tag("else"))(input)?;
-    let (input, else_branch) = ws(parse_expr)(input)?;
+    let start = input;
+    let (input, _) = ws(keyword("if"))(input)?;
+    let (input, condition) = cut(context("if condition", ws(parse_simple_expr)))(input)?;
+    let (input, _) = cut(context("'then' after if condition", ws(keyword("then"))))(input)?;
+    let (input, then_branch) = cut(context("then branch", ws(parse_expr)))(input)?;
+    let (input, _) = cut(context("'else' branch", ws(keyword("else"))))(input)?;
+    let (input, else_branch) = cut(context("else branch", ws(parse_expr)))(input)?;
     
     Ok((input, Expr::If(IfExpr {
         condition: Box::new(condition),
         then_branch: Box::new(then_branch),
         else_branch: Box::new(else_branch),
+        span: span_between(start, input),
     })))
 }
 
 fn parse_let_expr(input: &str) -> ParseResult<Expr> {
-    let (input, _) = ws(tag("let"))(input)?;
-    let (input, var_name) = ws(identifier)(input)?;
-    let (input, _) = ws(char('='))(input)?;
+    let start = input;
+    let (input, _) = ws(keyword("let"))(input)?;
+    let (input, var_name) = cut(context("variable name after 'let'", ws(value_identifier)))(input)?;
+    let (input, _) = cut(context("'=' in let binding", ws(char('='))))(input)?;
     // Use parse_non_control_expr instead of parse_simple_expr to support record/list literals
-    let (input, value) = ws(parse_non_control_expr)(input)?;
-    let (input, _) = ws(tag("in"))(input)?;
-    let (input, body) = ws(parse_expr)(input)?;
+    let (input, value) = cut(context("let value", ws(parse_non_control_expr)))(input)?;
+    let (input, _) = cut(context("'in' after let value", ws(keyword("in"))))(input)?;
+    let (input, body) = cut(context("let body", ws(parse_expr)))(input)?;
     
     Ok((input, Expr::Let(LetExpr {
         var_name,
         value: Box::new(value),
         body: Box::new(body),
+        span: span_between(start, input),
     })))
 }
 
+fn parse_lambda_param(input: &str) -> ParseResult<(String, Option<Type>)> {
+    alt((
+        map(
+            delimited(ws(char('(')), parse_function_param, ws(char(')'))),
+            |(name, ty)| (name, Some(ty)),
+        ),
+        map(ws(value_identifier), |name| (name, None)),
+    ))(input)
+}
+
+fn parse_lambda(input: &str) -> ParseResult<Expr> {
+    let start = input;
+    let (input, _) = ws(char('\\'))(input)?;
+    let (input, params) = cut(context("lambda parameter", many1(parse_lambda_param)))(input)?;
+    let (input, _) = cut(context("'->' after lambda parameters", ws(tag("->"))))(input)?;
+    let (input, body) = cut(context("lambda body", ws(parse_expr)))(input)?;
+    
+    Ok((input, Expr::Lambda(Lambda {
+        params,
+        body: Box::new(body),
+        span: span_between(start, input),
+    })))
+}
+
+// `(e)` is just `e`; `(e1, e2, ...)` is a tuple
+fn parse_parenthesized_expr(input: &str) -> ParseResult<Expr> {
+    map(
+        delimited(
+            char('('),
+            separated_list1(ws(char(',')), ws(parse_expr)),
+            context("closing ')'", char(')')),
+        ),
+        |mut elements| {
+            if elements.len() == 1 {
+                elements.pop().unwrap()
+            } else {
+                Expr::Tuple(TupleExpr { elements, span: Span::default() })
+            }
+        },
+    )(input)
+}
+
 // Primary expressions (literals, variables, parenthesized)
 fn parse_primary_expr(input: &str) -> ParseResult<Expr> {
-    alt((
+    located(alt((
         parse_list_literal,
         parse_record_expr,
         parse_literal,
         parse_application,
         parse_variable,
-        delimited(char('('), parse_expr, char(')')),
-    ))(input)
+        parse_parenthesized_expr,
+    )))(input)
 }
 
-// Postfix expressions (field access)
+// Postfix expressions (field access, tuple projection like `t.0`, calls on
+// computed functions like `f(1)(2)`)
 fn parse_postfix_expr(input: &str) -> ParseResult<Expr> {
-    let (input, mut expr) = parse_primary_expr(input)?;
-    
-    let (input, fields) = many0(preceded(char('.'), identifier))(input)?;
+    let (mut input, mut expr) = parse_primary_expr(input)?;
     
-    for field in fields {
-        expr = Expr::FieldAccess(FieldAccess {
-            record: Box::new(expr),
-            field,
-        });
+    loop {
+        let field = alt((identifier, map(digit1, str::to_string)));
+        if let Ok((rest, field)) = preceded(char::<_, ParseFailure>('.'), field)(input) {
+            let span = Span::new(expr.span().start, offset_of(rest));
+            expr = Expr::FieldAccess(FieldAccess {
+                record: Box::new(expr),
+                field,
+                span,
+            });
+            input = rest;
+        } else if input.starts_with('(') {
+            let (rest, args) = parse_call_args(input)?;
+            let span = Span::new(expr.span().start, offset_of(rest));
+            expr = apply_args(expr, args, span);
+            input = rest;
+        } else {
+            break;
+        }
     }
     
     Ok((input, expr))
@@ -772,6 +1256,7 @@ fn parse_postfix_expr(input: &str) -> ParseResult<Expr> {
 // Non-control expressions (includes if/match but not let, to avoid infinite recursion)
 fn parse_non_control_expr(input: &str) -> ParseResult<Expr> {
     alt((
+        parse_lambda,
         parse_match_expr,
         parse_if_expr,
         parse_binary_op,
@@ -786,6 +1271,7 @@ fn parse_simple_expr(input: &str) -> ParseResult<Expr> {
 // Full expression parser
 fn parse_expr(input: &str) -> ParseResult<Expr> {
     alt((
+        parse_lambda,
         parse_match_expr,
         parse_if_expr,
         parse_let_expr,
@@ -807,49 +1293,57 @@ fn parse_function_param(input: &str) -> ParseResult<(String, Type)> {
 
 fn parse_requires(input: &str) -> ParseResult<Expr> {
     preceded(
-        ws(tag("requires")),
-        ws(parse_simple_expr),
+        ws(keyword("requires")),
+        cut(context("precondition expression", ws(parse_simple_expr))),
     )(input)
 }
 
 fn parse_ensures(input: &str) -> ParseResult<Expr> {
     preceded(
-        ws(tag("ensures")),
-        ws(parse_simple_expr),
+        ws(keyword("ensures")),
+        cut(context("postcondition expression", ws(parse_simple_expr))),
+    )(input)
+}
+
+fn parse_param_list(input: &str) -> ParseResult<Vec<(String, Type)>> {
+    delimited(
+        context("'(' before parameters", char('(')),
+        separated_list0(ws(char(',')), parse_function_param),
+        context("')' after parameters", char(')')),
     )(input)
 }
 
 fn parse_function_def(input: &str) -> ParseResult<FunctionDef> {
+    let start = input;
     let (input, annotations) = many0(terminated(parse_annotation, multispace0))(input)?;
-    let (input, _) = ws(tag("func"))(input)?;
-    let (input, name) = ws(identifier)(input)?;
-    let (input, params) = delimited(
-        char('('),
-        separated_list0(ws(char(',')), parse_function_param),
-        char(')'),
-    )(input)?;
-    let (input, _) = ws(tag("->"))(input)?;
-    let (input, return_type) = ws(parse_type)(input)?;
+    let (input, _) = ws(keyword("func"))(input)?;
+    let (input, name) = context("function name", ws(value_identifier))(input)?;
+    let (input, type_params) = parse_type_params(input)?;
+    let (input, params) = parse_param_list(input)?;
+    let (input, _) = context("'->' before return type", ws(tag("->")))(input)?;
+    let (input, return_type) = context("return type", ws(parse_type))(input)?;
     
     // Parse requires/ensures clauses
-    let (input, requires) = many0(terminated(parse_requires, multispace0))(input)?;
-    let (input, ensures) = many0(terminated(parse_ensures, multispace0))(input)?;
+    let (input, requires) = many0(terminated(parse_requires, skip_ws_and_comments))(input)?;
+    let (input, ensures) = many0(terminated(parse_ensures, skip_ws_and_comments))(input)?;
     
     // Expect ':' before body
-    let (input, _) = ws(char(':'))(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = context("':' before function body", ws(char(':')))(input)?;
+    let (input, _) = skip_ws_and_comments(input)?;
     
     // Parse body
-    let (input, body) = parse_expr(input)?;
+    let (input, body) = context("function body expression", parse_expr)(input)?;
     
     Ok((input, FunctionDef {
         name,
+        type_params,
         params,
         return_type,
         requires,
         ensures,
         body,
         annotations,
+        span: span_between(start, input),
     }))
 }
 
@@ -868,15 +1362,12 @@ fn parse_extern_function_decl(annotations: Vec<Annotation>) -> impl FnMut(&str)
             "unknown_noextern".to_string()
         };
         
-        let (input, _) = ws(tag("func"))(input)?;
-        let (input, name) = ws(identifier)(input)?;
-        let (input, params) = delimited(
-            char('('),
-            separated_list0(ws(char(',')), parse_function_param),
-            char(')'),
-        )(input)?;
-        let (input, _) = ws(tag("->"))(input)?;
-        let (input, return_type) = ws(parse_type)(input)?;
+        let start = input;
+        let (input, _) = ws(keyword("func"))(input)?;
+        let (input, name) = context("function name", ws(value_identifier))(input)?;
+        let (input, params) = parse_param_list(input)?;
+        let (input, _) = context("'->' before return type", ws(tag("->")))(input)?;
+        let (input, return_type) = context("return type", ws(parse_type))(input)?;
         
         Ok((input, ExternFunctionDecl {
             name,
@@ -884,6 +1375,7 @@ fn parse_extern_function_decl(annotations: Vec<Annotation>) -> impl FnMut(&str)
             params,
             return_type,
             annotations: annotations.clone(),
+            span: span_between(start, input),
         }))
     }
 }
@@ -893,86 +1385,263 @@ fn parse_extern_function_decl(annotations: Vec<Annotation>) -> impl FnMut(&str)
 // ============================================================================
 
 pub fn parse_ir(input: &str) -> Result<Program, String> {
-    let (mut remaining, _) = skip_ws_and_comments(input)
-        .map_err(|e| format!("Parse error: {:?}", e))?;
-    
-    let mut type_defs = vec![];
-    let mut func_defs = vec![];
-    let mut extern_funcs = vec![];
+    let (program, errors) = parse_ir_recovering(input);
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        let messages: Vec<String> = errors.iter().map(|e| e.render(input)).collect();
+        Err(format!("Parse error: {}", messages.join("\n")))
+    }
+}
+
+/// Parse a program, recovering from syntax errors at the next top-level
+/// declaration. Returns every declaration that parsed along with all errors.
+pub fn parse_ir_recovering(input: &str) -> (Program, Vec<SyntaxError>) {
+    let _source = SourceGuard::enter(input);
+    let mut errors = vec![];
+    let (_, program) = parse_items(input, input, false, &mut errors);
+    (program, errors)
+}
+
+/// Parse a single expression, such as the value of an `@test_case` argument
+pub fn parse_expression(input: &str) -> Result<Expr, String> {
+    let _source = SourceGuard::enter(input);
+    let result = ws(parse_expr)(input).and_then(|(rest, expr)| {
+        let (rest, _) = skip_ws_and_comments(rest)?;
+        if rest.is_empty() {
+            Ok(expr)
+        } else {
+            Err(nom::Err::Error(ParseFailure::at(rest, "end of expression")))
+        }
+    });
+    result.map_err(|err| format!("Parse error: {}", failure_of(err).into_syntax_error(input).render(input)))
+}
+
+// Parse declarations until the end of input, or (inside a module or
+// signature body) until the closing `end`, which is consumed
+fn parse_items<'a>(
+    input: &'a str,
+    source: &str,
+    in_module: bool,
+    errors: &mut Vec<SyntaxError>,
+) -> (&'a str, Program) {
+    let mut program = Program {
+        type_defs: vec![],
+        func_defs: vec![],
+        extern_funcs: vec![],
+        modules: vec![],
+        signatures: vec![],
+        imports: vec![],
+    };
+    let mut remaining = input;
     
-    // Parse type definitions and function definitions in order
+    // Parse declarations in order
     loop {
-        let (new_input, _) = skip_ws_and_comments(remaining)
-            .map_err(|e| format!("Parse error: {:?}", e))?;
+        let (decl_start, _) = skip_ws_and_comments(remaining)
+            .unwrap_or((remaining, ()));
         
-        if new_input.is_empty() {
-            break;
+        if decl_start.is_empty() {
+            if in_module {
+                errors.push(ParseFailure::at(decl_start, "'end' to close module").into_syntax_error(source));
+            }
+            return (decl_start, program);
         }
         
-        // Parse annotations if present
-        let (new_input, annotations) = if new_input.starts_with("@") {
-            many0(terminated(
-                parse_annotation,
-                skip_ws_and_comments,
-            ))(new_input).map_err(|e| format!("Parse error: {:?}", e))?
-        } else {
-            (new_input, vec![])
-        };
-        
-        let (new_input, _) = skip_ws_and_comments(new_input)
-            .map_err(|e| format!("Parse error: {:?}", e))?;
-        
-        // Try to parse type definition
-        if new_input.starts_with("type ") {
-            // Try each type definition parser in order
-            let parse_result = parse_multiline_record_type_def(vec![])(new_input)
-                .or_else(|_| parse_variant_type_def(vec![])(new_input))
-                .or_else(|_| parse_inline_type_def(vec![])(new_input));
-            
-            if let Ok((new_input, mut type_def)) = parse_result {
-                type_def.annotations = annotations;
-                type_defs.push(type_def);
-                remaining = new_input;
-                continue;
+        if in_module {
+            if let Ok((rest, _)) = keyword("end")(decl_start) {
+                return (rest, program);
             }
         }
         
-        // Check if this is an extern function (has @extern annotation)
-        let has_extern = annotations.iter().any(|ann| ann.name == "extern");
+        // Parse annotations if present
+        let (new_input, annotations) = many0(terminated(
+            parse_annotation,
+            skip_ws_and_comments,
+        ))(decl_start).unwrap_or((decl_start, vec![]));
         
-        // Try to parse function definition
-        if new_input.starts_with("func ") {
-            if has_extern {
-                // Parse as extern function declaration (no body)
-                if let Ok((new_input, extern_func)) = parse_extern_function_decl(annotations)(new_input) {
-                    extern_funcs.push(extern_func);
-                    remaining = new_input;
-                    continue;
-                }
+        let result = if starts_with_keyword(new_input, "type") {
+            // Try each type definition parser in order
+            parse_multiline_record_type_def(vec![])(new_input)
+                .or_else(|e1| parse_variant_type_def(vec![])(new_input)
+                    .map_err(|e2| nom::Err::Error(failure_of(e1).or(failure_of(e2)))))
+                .or_else(|e| parse_inline_type_def(vec![])(new_input)
+                    .map_err(|e3| nom::Err::Error(failure_of(e).or(failure_of(e3)))))
+                .map(|(rest, mut type_def)| {
+                    type_def.annotations = annotations;
+                    type_def.span = span_between(new_input, rest);
+                    program.type_defs.push(type_def);
+                    rest
+                })
+        } else if starts_with_keyword(new_input, "func") {
+            if annotations.iter().any(|ann| ann.name == "extern") {
+                // Extern function declaration (no body)
+                parse_extern_function_decl(annotations)(new_input)
+                    .map(|(rest, extern_func)| {
+                        program.extern_funcs.push(extern_func);
+                        rest
+                    })
             } else {
-                // Parse as regular function definition (with body)
-                if let Ok((new_input, mut func_def)) = parse_function_def(new_input) {
-                    func_def.annotations = [annotations, func_def.annotations].concat();
-                    func_defs.push(func_def);
-                    remaining = new_input;
-                    continue;
-                }
+                // Regular function definition (with body)
+                parse_function_def(new_input)
+                    .map(|(rest, mut func_def)| {
+                        func_def.annotations = [annotations, func_def.annotations].concat();
+                        program.func_defs.push(func_def);
+                        rest
+                    })
             }
-        }
-        
-        // Skip unrecognized lines
-        if let Some(newline_pos) = new_input.find('\n') {
-            remaining = &new_input[newline_pos + 1..];
+        } else if starts_with_keyword(new_input, "module") {
+            parse_module_header(new_input).map(|(body_start, (name, signature))| {
+                let (rest, body) = parse_items(body_start, source, true, errors);
+                program.modules.push(ModuleDef {
+                    name,
+                    signature,
+                    body,
+                    file: None,
+                    span: span_between(new_input, rest),
+                });
+                rest
+            })
+        } else if starts_with_keyword(new_input, "signature") {
+            parse_signature_def(new_input).map(|(rest, signature)| {
+                program.signatures.push(signature);
+                rest
+            })
+        } else if starts_with_keyword(new_input, "import") {
+            parse_import(new_input).map(|(rest, import)| {
+                program.imports.push(import);
+                rest
+            })
+        } else if new_input.is_empty() && !program.func_defs.is_empty() {
+            // Trailing annotations (e.g. `@test_case(expected=0)` after `main`)
+            // belong to the last function in the file
+            if let Some(last) = program.func_defs.last_mut() {
+                last.annotations.extend(annotations);
+            }
+            Ok(new_input)
         } else {
-            break;
+            let expected = if !annotations.is_empty() {
+                "declaration after annotations"
+            } else if in_module {
+                "declaration or 'end'"
+            } else {
+                "top-level declaration ('func', 'type', 'module', 'signature' or 'import')"
+            };
+            Err(nom::Err::Error(ParseFailure::at(new_input, expected)))
+        };
+        
+        match result {
+            Ok(rest) => remaining = rest,
+            Err(err) => {
+                errors.push(failure_of(err).into_syntax_error(source));
+                // Resync after the annotations so they are not re-read as a new item
+                remaining = resync(if new_input.is_empty() { decl_start } else { new_input });
+            }
         }
     }
+}
+
+// ============================================================================
+// Module Parsers
+// ============================================================================
+
+fn module_name(input: &str) -> ParseResult<String> {
+    verify(identifier, |name: &str| name.starts_with(|c: char| c.is_uppercase()))(input)
+}
+
+fn module_path(input: &str) -> ParseResult<Vec<String>> {
+    map(
+        pair(module_name, many0(preceded(char('.'), module_name))),
+        |(first, rest)| std::iter::once(first).chain(rest).collect(),
+    )(input)
+}
+
+// `module Name [: Signature] :` -- the body is parsed by `parse_items`
+fn parse_module_header(input: &str) -> ParseResult<(String, Option<String>)> {
+    let (input, _) = ws(keyword("module"))(input)?;
+    let (input, name) = cut(context("capitalised module name", ws(module_name)))(input)?;
+    let (input, _) = cut(context("':' after module name", ws(char(':'))))(input)?;
+    // `module M : Sig :` names the signature the module must implement
+    let (input, signature) = opt(terminated(ws(module_name), ws(char(':'))))(input)?;
+    Ok((input, (name, signature)))
+}
+
+fn parse_type_params(input: &str) -> ParseResult<Vec<String>> {
+    map(
+        opt(delimited(
+            ws(char('<')),
+            separated_list0(ws(char(',')), identifier),
+            context("'>' after type parameters", ws(char('>'))),
+        )),
+        Option::unwrap_or_default,
+    )(input)
+}
+
+fn parse_signature_item(input: &str) -> ParseResult<SignatureItem> {
+    let start = input;
+    if let Ok((input, _)) = ws(keyword("func"))(input) {
+        let (input, name) = cut(context("function name", ws(value_identifier)))(input)?;
+        let (input, _) = cut(context("':' before function type", ws(char(':'))))(input)?;
+        let (input, func_type) = cut(context("function type", ws(parse_type)))(input)?;
+        return Ok((input, SignatureItem::Func { name, func_type, span: span_between(start, input) }));
+    }
+    let (input, _) = ws(keyword("type"))(input)?;
+    let (input, name) = cut(context("type name", ws(identifier)))(input)?;
+    let (input, type_params) = parse_type_params(input)?;
+    Ok((input, SignatureItem::Type { name, type_params, span: span_between(start, input) }))
+}
+
+fn parse_signature_def(input: &str) -> ParseResult<SignatureDef> {
+    let start = input;
+    let (input, _) = ws(keyword("signature"))(input)?;
+    let (input, name) = cut(context("capitalised signature name", ws(module_name)))(input)?;
+    let (input, type_params) = parse_type_params(input)?;
+    let (input, _) = cut(context("':' after signature name", ws(char(':'))))(input)?;
+    let (input, items) = many0(preceded(skip_ws_and_comments, parse_signature_item))(input)?;
+    let (input, _) = cut(context("'func', 'type' or 'end' in signature", ws(keyword("end"))))(input)?;
     
-    Ok(Program {
-        type_defs,
-        func_defs,
-        extern_funcs,
-    })
+    Ok((input, SignatureDef {
+        name,
+        type_params,
+        items,
+        span: span_between(start, input),
+    }))
+}
+
+// `import Game.Core [as core]`
+fn parse_import(input: &str) -> ParseResult<Import> {
+    let start = input;
+    let (input, _) = ws(keyword("import"))(input)?;
+    let (input, path) = cut(context("module path", ws(module_path)))(input)?;
+    let (input, alias) = opt(preceded(
+        ws(keyword("as")),
+        cut(context("capitalised alias after 'as'", ws(module_name))),
+    ))(input)?;
+    
+    Ok((input, Import {
+        path,
+        alias,
+        span: span_between(start, input),
+    }))
+}
+
+fn starts_with_keyword(input: &str, word: &'static str) -> bool {
+    keyword(word)(input).is_ok()
+}
+
+// Skip past the declaration starting at `input` to the next line that opens
+// a top-level item (an annotation or a declaration keyword)
+fn resync(input: &str) -> &str {
+    let mut rest = input;
+    while let Some(newline) = rest.find('\n') {
+        rest = &rest[newline + 1..];
+        let line = rest.trim_start();
+        if line.starts_with('@')
+            || TOP_LEVEL_KEYWORDS.iter().any(|word| starts_with_keyword(line, word))
+        {
+            return rest;
+        }
+    }
+    ""
 }
 
 #[cfg(test)]
@@ -1063,6 +1732,307 @@ func factorial (n: Nat) -> Nat
         assert_eq!(program.func_defs[0].requires.len(), 1);
         assert_eq!(program.func_defs[0].ensures.len(), 1);
     }
+
+    #[test]
+    fn test_spans_cover_source() {
+        let input = "func add (x: Int, y: Int) -> Int :\n  x + y * 2\n\nfunc one () -> Int :\n  add(1, foo.bar)\n";
+        let program = parse_ir(input).unwrap();
+
+        let add = &program.func_defs[0];
+        assert_eq!(add.span.snippet(input), "func add (x: Int, y: Int) -> Int :\n  x + y * 2");
+        assert_eq!(add.body.span().snippet(input), "x + y * 2");
+        if let Expr::BinaryOp(binop) = &add.body {
+            assert_eq!(binop.right.span().snippet(input), "y * 2");
+        } else {
+            panic!("expected binary op");
+        }
+
+        let one = &program.func_defs[1];
+        assert_eq!(one.body.span().snippet(input), "add(1, foo.bar)");
+        assert_eq!(one.body.span().line_col(input), (5, 3));
+        if let Expr::Application(app) = &one.body {
+            assert_eq!(app.arg.span().snippet(input), "foo.bar");
+        } else {
+            panic!("expected application");
+        }
+    }
+
+    #[test]
+    fn test_recovers_at_next_declaration() {
+        let input = r#"
+func ok_one () -> Int :
+  1
+
+func broken (x: Int) -> Int
+  x + 1
+
+func also_broken () -> Int :
+  let y = 2
+  y
+
+type Color = Red | Green
+
+func ok_two () -> Int :
+  2
+"#;
+        let (program, errors) = parse_ir_recovering(input);
+        
+        let names: Vec<&str> = program.func_defs.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["ok_one", "ok_two"]);
+        assert_eq!(program.type_defs.len(), 1);
+        
+        assert_eq!(errors.len(), 2, "errors: {:?}", errors);
+        assert_eq!(errors[0].message, "expected ':' before function body, found 'x'");
+        assert_eq!(errors[0].span.snippet(input), "x");
+        assert_eq!(errors[0].span.line_col(input), (6, 3));
+        assert_eq!(errors[1].message, "expected 'in' after let value, found 'y'");
+        assert_eq!(errors[1].span.line_col(input), (10, 3));
+        
+        let message = parse_ir(input).unwrap_err();
+        assert!(message.contains("6:3: expected ':' before function body"), "{}", message);
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let expr = parse_expression(" Some({name: \"a\", age: -3}) ").unwrap();
+        assert_eq!(expr.to_string(), "Some({ name: \"a\", age: -3 })");
+        
+        let message = parse_expression("(1, 2) 3").unwrap_err();
+        assert!(message.contains("expected end of expression, found '3'"), "{}", message);
+    }
+
+    #[test]
+    fn test_reports_stray_top_level_text() {
+        let (program, errors) = parse_ir_recovering("oops\n\nfunc f () -> Int :\n  1\n");
+        assert_eq!(program.func_defs.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "expected top-level declaration ('func', 'type', 'module', 'signature' or 'import'), found 'oops'");
+    }
+
+    #[test]
+    fn test_trailing_annotations_attach_to_last_function() {
+        let input = "func main () -> Int :\n  0\n\n@test_case(expected=0)\n";
+        let program = parse_ir(input).unwrap();
+        assert_eq!(program.func_defs[0].annotations.len(), 1);
+        assert_eq!(program.func_defs[0].annotations[0].name, "test_case");
+    }
+
+    #[test]
+    fn test_annotation_args_with_nesting() {
+        let (_, ann) = parse_annotation(r#"@test_case(input={name="a, b", age=3}, expected=Ok(()))"#).unwrap();
+        assert_eq!(ann.args, vec![
+            ("input".to_string(), r#"{name="a, b", age=3}"#.to_string()),
+            ("expected".to_string(), "Ok(())".to_string()),
+        ]);
+        
+        let (_, ann) = parse_annotation("@test(f(Some(1), 0) == 1)").unwrap();
+        assert_eq!(ann.args, vec![("0".to_string(), "f(Some(1), 0) == 1".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_implication_in_contract() {
+        let input = "func f (n: Int) -> Int\n  ensures n == 1 => result == 1\n:\n  n\n";
+        let program = parse_ir(input).unwrap();
+        match &program.func_defs[0].ensures[0] {
+            Expr::BinaryOp(binop) => assert_eq!(binop.op, "=>"),
+            other => panic!("expected implication, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_modules_signatures_and_imports() {
+        let input = r#"
+import Game.Core as C
+
+signature ORDERED<T> :
+  type T
+  func compare : T -> T -> Int
+end
+
+module IntOrder : ORDERED :
+  type T = Int
+
+  func compare (a: Int, b: Int) -> Int :
+    a - b
+end
+
+func main () -> Int :
+  IntOrder.compare(C.min(1, 2), 3)
+"#;
+        let program = parse_ir(input).unwrap();
+
+        assert_eq!(program.imports[0].module_name(), "Game.Core");
+        assert_eq!(program.imports[0].alias.as_deref(), Some("C"));
+
+        let signature = &program.signatures[0];
+        assert_eq!(signature.type_params, vec!["T"]);
+        match &signature.items[1] {
+            SignatureItem::Func { name, func_type: Type::Function(f), .. } => {
+                assert_eq!(name, "compare");
+                assert!(matches!(&*f.return_type, Type::Function(_)));
+            }
+            other => panic!("expected function item, got {:?}", other),
+        }
+
+        let module = &program.modules[0];
+        assert_eq!(module.signature.as_deref(), Some("ORDERED"));
+        assert_eq!(module.body.func_defs[0].name, "compare");
+        assert_eq!(module.body.type_defs[0].name, "T");
+
+        let Expr::Application(call) = &program.func_defs[0].body else { panic!("expected call") };
+        let Expr::Application(inner) = &*call.func else { panic!("expected curried call") };
+        assert!(matches!(&*inner.func, Expr::Variable(v) if v.name == "IntOrder.compare"));
+    }
+
+    #[test]
+    fn test_reports_unclosed_module() {
+        let (program, errors) = parse_ir_recovering("module M :
+  func f () -> Int :
+    1
+");
+        assert_eq!(program.modules.len(), 1);
+        assert!(errors[0].message.contains("'end' to close module"), "{}", errors[0].message);
+    }
+
+    #[test]
+    fn test_parse_lambda() {
+        let input = r#"
+func apply (f: (Int -> Int) -> Int, g: Int -> Int) -> Int :
+  f(g)
+
+func main () -> Int :
+  apply(\g -> g(1), \(x: Int) y -> x + y)(make_adder(10)(7))
+"#;
+        let program = parse_ir(input).unwrap();
+
+        let (_, f_type) = &program.func_defs[0].params[0];
+        let Type::Function(f) = f_type else { panic!("expected function type, got {:?}", f_type) };
+        assert!(matches!(&*f.param_type, Type::Function(_)));
+        assert!(matches!(&*f.return_type, Type::Basic(b) if b.name == "Int"));
+
+        // apply(a, b)(c) is ((apply a) b) c
+        let Expr::Application(outer) = &program.func_defs[1].body else { panic!("expected call") };
+        assert!(matches!(&*outer.arg, Expr::Application(_)));
+        let Expr::Application(second) = &*outer.func else { panic!("expected curried call") };
+        let Expr::Lambda(lambda) = &*second.arg else { panic!("expected lambda, got {:?}", second.arg) };
+        let params: Vec<_> = lambda.params.iter().map(|(name, ty)| (name.as_str(), ty.is_some())).collect();
+        assert_eq!(params, vec![("x", true), ("y", false)]);
+        assert!(matches!(&*lambda.body, Expr::BinaryOp(_)));
+    }
+
+    #[test]
+    fn test_parse_generic_function() {
+        let program = parse_ir("func first<A, B> (a: A, b: B) -> A :\n  a\n").unwrap();
+        let func = &program.func_defs[0];
+        assert_eq!(func.type_params, vec!["A", "B"]);
+        assert_eq!(func.params[1].1, Type::Basic(BasicType { name: "B".to_string() }));
+    }
+
+    #[test]
+    fn test_reports_lambda_without_arrow() {
+        let (_, errors) = parse_ir_recovering("func f () -> Int :\n  (\\x x + 1)(2)\n");
+        assert!(errors[0].message.contains("'->' after lambda parameters"), "{}", errors[0].message);
+    }
+
+    #[test]
+    fn test_parse_nested_patterns() {
+        let (_, pattern) = parse_pattern(r#"Some((x, Point { y, z: "a" | "b", .. }))"#).unwrap();
+        let Pattern::Constructor(some) = pattern else { panic!("{:?}", pattern) };
+        let Pattern::Tuple(tuple) = &some.args[0] else { panic!("{:?}", some.args[0]) };
+        assert!(matches!(&tuple.elements[0], Pattern::Variable(var) if var.name == "x"));
+        let Pattern::Record(record) = &tuple.elements[1] else { panic!("{:?}", tuple.elements[1]) };
+        assert_eq!(record.type_name.as_deref(), Some("Point"));
+        assert!(matches!(&record.fields[0], (name, Pattern::Variable(var)) if name == "y" && var.name == "y"));
+        let Pattern::Or(or) = &record.fields[1].1 else { panic!("{:?}", record.fields[1]) };
+        assert_eq!(or.alternatives.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_or_pattern_arm() {
+        let program = parse_ir("func f (b: Bool) -> Int :\n  match b with\n  | true | false -> 1\n").unwrap();
+        let Expr::Match(match_expr) = &program.func_defs[0].body else { panic!() };
+        assert_eq!(match_expr.arms.len(), 1);
+        assert!(matches!(&match_expr.arms[0].pattern, Pattern::Or(or) if or.alternatives.len() == 2));
+    }
+
+    #[test]
+    fn test_parse_match_guard() {
+        let program = parse_ir("func f (n: Int) -> Int :\n  match n with\n  | x if x > 0 -> x - 1\n  | _ -> 0\n").unwrap();
+        let Expr::Match(match_expr) = &program.func_defs[0].body else { panic!() };
+        assert!(matches!(&match_expr.arms[0].guard, Some(Expr::BinaryOp(op)) if op.op == ">"));
+        assert!(matches!(&match_expr.arms[0].body, Expr::BinaryOp(op) if op.op == "-"));
+        assert!(match_expr.arms[1].guard.is_none());
+    }
+
+    #[test]
+    fn test_parse_tuple_and_projection() {
+        let program = parse_ir("func f (n: Int) -> Int :\n  let p = (n, (n * 2)) in\n  p.0 + p.1\n").unwrap();
+        let Expr::Let(let_expr) = &program.func_defs[0].body else { panic!() };
+        assert!(matches!(&*let_expr.value, Expr::Tuple(tuple) if tuple.elements.len() == 2));
+        let Expr::BinaryOp(sum) = &*let_expr.body else { panic!() };
+        assert!(matches!(&*sum.left, Expr::FieldAccess(access) if access.field == "0"));
+        assert!(matches!(&*sum.right, Expr::FieldAccess(access) if access.field == "1"));
+    }
+
+    // Operators of an expression, innermost first, with unary ones as `neg`
+    // and `not`
+    fn operator_tree(expr: &Expr) -> String {
+        match expr {
+            Expr::BinaryOp(op) => format!("({} {} {})", operator_tree(&op.left), op.op, operator_tree(&op.right)),
+            Expr::UnaryOp(op) if op.op == "-" => format!("neg {}", operator_tree(&op.operand)),
+            Expr::UnaryOp(op) => format!("{} {}", op.op, operator_tree(&op.operand)),
+            Expr::Variable(var) => var.name.clone(),
+            Expr::Literal(Literal { value: LiteralValue::Int(n), .. }) => n.to_string(),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let cases = [
+            ("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            ("a << 2 + b & 1", "((a << (2 + b)) & 1)"),
+            ("a % 3 * -b", "((a % 3) * neg b)"),
+            ("not a && b || c and d or e", "(((not a && b) || (c && d)) || e)"),
+            ("x & 1 == 0", "((x & 1) == 0)"),
+            ("-1 - -x", "(-1 - neg x)"),
+        ];
+        for (source, expected) in cases {
+            let (rest, expr) = parse_expr(source).unwrap();
+            assert_eq!(rest, "", "{}", source);
+            assert_eq!(operator_tree(&expr), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_bitwise_or_in_match_arm_body() {
+        let program = parse_ir("func f (n: Int) -> Int :\n  match n with\n  | 0 -> n | 1\n  | _ -> n\n").unwrap();
+        let Expr::Match(match_expr) = &program.func_defs[0].body else { panic!() };
+        assert_eq!(match_expr.arms.len(), 2);
+        assert!(matches!(&match_expr.arms[0].body, Expr::BinaryOp(op) if op.op == "|"));
+    }
+
+    #[test]
+    fn test_display_expr_reparses() {
+        let sources = [
+            "n >= 0 && (n < 10 || big)",
+            "(n == 0) => (result == 1)",
+            "a - (b - c) * -x.0",
+            "not (a | b) == f(x, g())",
+            "let p = (1, \"a\\n\") in match p with | (0, s) if s != \"\" -> [s] | _ -> []",
+            "\\x (y: Int) -> { x: x, y: -y }.x",
+        ];
+        for source in sources {
+            let (rest, expr) = parse_expr(source).unwrap();
+            assert_eq!(rest, "", "{}", source);
+            let printed = expr.to_string();
+            let (rest, reparsed) = parse_expr(&printed).unwrap();
+            assert_eq!(rest, "", "{}", printed);
+            assert_eq!(expr.without_spans(), reparsed.without_spans(), "{}", printed);
+        }
+        let (_, expr) = parse_expr("(n == 0) => (result == 1)").unwrap();
+        assert_eq!(expr.to_string(), "n == 0 => result == 1");
+    }
 }
 
     #[test]
@@ -1111,7 +2081,7 @@ func factorial (n: Nat) -> Nat
 
 fn parse_record_expr(input: &str) -> ParseResult<Expr> {
     let (input, _) = char('{')(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = skip_ws_and_comments(input)?;
     
     let (input, fields) = separated_list0(
         ws(char(',')),
@@ -1122,28 +2092,29 @@ fn parse_record_expr(input: &str) -> ParseResult<Expr> {
         ),
     )(input)?;
     
-    let (input, _) = multispace0(input)?;
-    let (input, _) = char('}')(input)?;
+    let (input, _) = skip_ws_and_comments(input)?;
+    let (input, _) = context("'}' to close record", char('}'))(input)?;
     
-    Ok((input, Expr::Record(RecordExpr { fields })))
+    Ok((input, Expr::Record(RecordExpr { fields, span: Span::default() })))
 }
 
 fn parse_list_literal(input: &str) -> ParseResult<Expr> {
     let (input, _) = char('[')(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = skip_ws_and_comments(input)?;
     
     let (input, elements) = separated_list0(
         ws(char(',')),
         ws(parse_expr),
     )(input)?;
     
-    let (input, _) = multispace0(input)?;
-    let (input, _) = char(']')(input)?;
+    let (input, _) = skip_ws_and_comments(input)?;
+    let (input, _) = context("']' to close list", char(']'))(input)?;
     
     // Represent list literal as Constructor
     Ok((input, Expr::Constructor(Constructor {
         name: "List".to_string(),
         args: elements,
+        span: Span::default(),
     })))
 }
 
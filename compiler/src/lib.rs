@@ -8,10 +8,31 @@ pub mod python_bindings;
 pub mod codegen;
 pub mod arena;
 pub mod memory;
+pub mod linker;
+pub mod exhaustiveness;
+pub mod tailrec;
+pub mod verifier;
+pub mod test_runner;
+pub mod driver;
+pub mod link_manifest;
+pub mod repl;
+pub mod lsp;
+pub mod printer;
+pub mod interpreter;
+pub mod differential;
 
 pub use ast::*;
-pub use ir_parser::parse_ir;
+pub use ir_parser::{parse_ir, parse_ir_recovering, parse_expression, SyntaxError};
 pub use type_checker::{check_types, TypeCheckResult, TypeError};
-pub use codegen::CodeGen;
+pub use codegen::{CodeGen, CodegenError, OptLevel, TargetSpec};
+pub use linker::{link_program, load_program, flatten_program, FileSource, LinkError, ModuleSource};
 pub use arena::CompilerArenas;
 pub use memory::{CompileError, MemoryStats};
+pub use verifier::{verify_contracts, ClauseKind, ClauseReport, Counterexample, Verdict, VerificationReport};
+pub use test_runner::{run_test_cases, TestOutcome, TestReport, TestResult, TestValue};
+pub use link_manifest::{LinkLibrary, LinkManifest};
+pub use repl::{run_session, Evaluation, Repl};
+pub use lsp::{run_server, Document};
+pub use printer::{format_source, print_program};
+pub use interpreter::{Interpreter, RuntimeError};
+pub use differential::{run_differential, CaseComparison, Comparison, DifferentialReport};
@@ -0,0 +1,174 @@
+// Link Manifest
+// Native libraries and headers a program depends on, collected from the
+// `@link` and `@header` annotations on its extern declarations, so a driver
+// can pass the right `-l`/`-L` flags without being told.
+
+use std::fmt;
+
+use crate::ast::*;
+
+/// A library named by `@link("SDL2")` or `@link(name="SDL2", path="/opt/lib")`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkLibrary {
+    /// Name as passed to `-l`
+    pub name: String,
+    /// Directories to search for it, as passed to `-L`
+    pub search_paths: Vec<String>,
+    /// C names of the extern functions declared with it
+    pub functions: Vec<String>,
+    /// Whether compiled code refers to any of those functions
+    pub used: bool,
+}
+
+/// Link requirements of a compiled program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkManifest {
+    /// In order of first declaration, one entry per library
+    pub libraries: Vec<LinkLibrary>,
+    /// Headers named by `@header`, in order of first declaration
+    pub headers: Vec<String>,
+}
+
+impl LinkManifest {
+    /// Record the `@link` and `@header` annotations of an extern declaration
+    pub fn add_extern(&mut self, decl: &ExternFunctionDecl) -> Result<(), String> {
+        for annotation in &decl.annotations {
+            match annotation.name.as_str() {
+                "link" => {
+                    let name = annotation_value(annotation, "name", true)
+                        .ok_or_else(|| format!("@link on '{}' needs a library name", decl.name))?;
+                    let library = match self.libraries.iter().position(|library| library.name == name) {
+                        Some(index) => &mut self.libraries[index],
+                        None => {
+                            self.libraries.push(LinkLibrary {
+                                name,
+                                search_paths: vec![],
+                                functions: vec![],
+                                used: false,
+                            });
+                            self.libraries.last_mut().unwrap()
+                        }
+                    };
+                    if let Some(path) = annotation_value(annotation, "path", false) {
+                        push_unique(&mut library.search_paths, path);
+                    }
+                    push_unique(&mut library.functions, decl.c_name.clone());
+                }
+                "header" => {
+                    let header = annotation_value(annotation, "path", true)
+                        .ok_or_else(|| format!("@header on '{}' needs a header path", decl.name))?;
+                    push_unique(&mut self.headers, header);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the libraries providing `c_name` as used
+    pub fn mark_used(&mut self, c_name: &str) {
+        for library in &mut self.libraries {
+            if library.functions.iter().any(|function| function == c_name) {
+                library.used = true;
+            }
+        }
+    }
+
+    /// Libraries none of whose functions the program refers to
+    pub fn unused(&self) -> impl Iterator<Item = &LinkLibrary> {
+        self.libraries.iter().filter(|library| !library.used)
+    }
+
+    /// `-L` flags for every search path, then `-l` for every library
+    pub fn linker_args(&self) -> Vec<String> {
+        let mut search_paths = vec![];
+        for library in &self.libraries {
+            for path in &library.search_paths {
+                push_unique(&mut search_paths, format!("-L{}", path));
+            }
+        }
+        let libraries = self.libraries.iter().map(|library| format!("-l{}", library.name));
+        search_paths.into_iter().chain(libraries).collect()
+    }
+}
+
+/// `-lSDL2 (SDL_Init, SDL_Quit)`, one library per line
+impl fmt::Display for LinkManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for library in &self.libraries {
+            write!(f, "-l{}", library.name)?;
+            for path in &library.search_paths {
+                write!(f, " -L{}", path)?;
+            }
+            write!(f, " ({})", library.functions.join(", "))?;
+            if !library.used {
+                write!(f, " unused")?;
+            }
+            writeln!(f)?;
+        }
+        for header in &self.headers {
+            writeln!(f, "#include <{}>", header)?;
+        }
+        Ok(())
+    }
+}
+
+// `key=...`, or the first positional argument if `positional`, without quotes
+fn annotation_value(annotation: &Annotation, key: &str, positional: bool) -> Option<String> {
+    annotation.args.iter()
+        .find(|(name, _)| name == key)
+        .or_else(|| annotation.args.iter().find(|(name, _)| positional && name == "0"))
+        .map(|(_, value)| value.trim_matches('"').to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn push_unique(values: &mut Vec<String>, value: String) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_parser::parse_ir;
+
+    #[test]
+    fn test_collects_libraries_and_headers() {
+        let ir = r#"
+@extern("SDL_Init")
+@link("SDL2")
+@header("SDL2/SDL.h")
+func sdl_init(flags: Int) -> Int
+
+@extern("SDL_Quit")
+@link("SDL2", path="/opt/sdl/lib")
+@header("SDL2/SDL.h")
+func sdl_quit(dummy: Unit) -> Unit
+
+@extern("sqrt")
+@link("m")
+func c_sqrt(x: Float64) -> Float64
+"#;
+        let program = parse_ir(ir).unwrap();
+        let mut manifest = LinkManifest::default();
+        for decl in &program.extern_funcs {
+            manifest.add_extern(decl).unwrap();
+        }
+        manifest.mark_used("SDL_Quit");
+
+        assert_eq!(manifest.libraries.len(), 2);
+        assert_eq!(manifest.libraries[0].functions, vec!["SDL_Init", "SDL_Quit"]);
+        assert_eq!(manifest.headers, vec!["SDL2/SDL.h"]);
+        assert_eq!(manifest.linker_args(), vec!["-L/opt/sdl/lib", "-lSDL2", "-lm"]);
+        let unused: Vec<&str> = manifest.unused().map(|library| library.name.as_str()).collect();
+        assert_eq!(unused, vec!["m"]);
+    }
+
+    #[test]
+    fn test_link_needs_a_name() {
+        let program = parse_ir("@extern(\"f\")\n@link\nfunc f(x: Int) -> Int\n").unwrap();
+        let error = LinkManifest::default().add_extern(&program.extern_funcs[0]).unwrap_err();
+        assert_eq!(error, "@link on 'f' needs a library name");
+    }
+}
@@ -0,0 +1,629 @@
+// Module Linker
+// Resolves `import` declarations across files and flattens modules into a
+// single program whose items carry namespaced names (`Core.min`)
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::*;
+use crate::ir_parser::parse_ir_recovering;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkError {
+    pub message: String,
+    /// File the span points into; `None` for the root program
+    pub file: Option<String>,
+    pub span: Option<Span>,
+}
+
+impl LinkError {
+    pub fn new(message: impl Into<String>, span: Option<Span>) -> Self {
+        LinkError {
+            message: message.into(),
+            file: None,
+            span,
+        }
+    }
+
+    fn in_file(mut self, file: Option<&String>) -> Self {
+        self.file = file.cloned();
+        self
+    }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file)?;
+        }
+        match self.span {
+            Some(span) => write!(f, "{} (at {}..{})", self.message, span.start, span.end),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+// ============================================================================
+// Module Sources
+// ============================================================================
+
+/// Where imported modules come from
+pub trait ModuleSource {
+    /// Source text of the module at `path`, with a file name for diagnostics
+    fn load(&self, path: &[String]) -> Result<(String, String), String>;
+}
+
+/// Loads `import Game.Core` from `Game/Core.pole-ir` or `game/core.pole-ir`
+/// under each search root, in order
+pub struct FileSource {
+    roots: Vec<PathBuf>,
+}
+
+impl FileSource {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        FileSource { roots }
+    }
+}
+
+impl ModuleSource for FileSource {
+    fn load(&self, path: &[String]) -> Result<(String, String), String> {
+        let exact: PathBuf = path.iter().collect();
+        let lowercase: PathBuf = path.iter().map(|segment| segment.to_lowercase()).collect();
+
+        for root in &self.roots {
+            for candidate in [&exact, &lowercase] {
+                let file = root.join(candidate).with_extension("pole-ir");
+                if file.is_file() {
+                    let source = fs::read_to_string(&file)
+                        .map_err(|e| format!("Cannot read {}: {}", file.display(), e))?;
+                    return Ok((file.display().to_string(), source));
+                }
+            }
+        }
+
+        Err(format!(
+            "Module '{}' not found (looked for {}.pole-ir)",
+            path.join("."),
+            lowercase.display()
+        ))
+    }
+}
+
+/// In-memory modules keyed by dotted path (`"Game.Core"`)
+impl ModuleSource for HashMap<String, String> {
+    fn load(&self, path: &[String]) -> Result<(String, String), String> {
+        let name = path.join(".");
+        self.get(&name)
+            .map(|source| (name.clone(), source.clone()))
+            .ok_or_else(|| format!("Module '{}' not found", name))
+    }
+}
+
+// ============================================================================
+// Loading
+// ============================================================================
+
+/// Parse `path` and every module it imports, searching relative to the
+/// file's directory
+pub fn load_program(path: &Path) -> Result<Program, Vec<LinkError>> {
+    let file = path.display().to_string();
+    let source = fs::read_to_string(path)
+        .map_err(|e| vec![LinkError::new(format!("Cannot read {}: {}", file, e), None)])?;
+
+    let (program, errors) = parse_ir_recovering(&source);
+    if !errors.is_empty() {
+        return Err(errors
+            .into_iter()
+            .map(|e| LinkError::new(e.message, Some(e.span)).in_file(Some(&file)))
+            .collect());
+    }
+
+    let root = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    link_program(program, &FileSource::new(vec![root]))
+}
+
+/// Load every module reachable through `import` and add it to
+/// `program.modules`. Each module is loaded once, however many files import it.
+pub fn link_program(mut program: Program, source: &dyn ModuleSource) -> Result<Program, Vec<LinkError>> {
+    let mut loaded: HashSet<String> = program.modules.iter().map(|m| m.name.clone()).collect();
+    let mut pending: Vec<(Import, Option<String>)> = vec![];
+    collect_imports(&program, None, &mut pending);
+    let mut errors = vec![];
+
+    while let Some((import, importer)) = pending.pop() {
+        let name = import.module_name();
+        if !loaded.insert(name.clone()) {
+            continue;
+        }
+
+        match source.load(&import.path) {
+            Ok((file, text)) => {
+                let (body, parse_errors) = parse_ir_recovering(&text);
+                errors.extend(parse_errors.into_iter().map(|e| {
+                    LinkError::new(e.message, Some(e.span)).in_file(Some(&file))
+                }));
+                collect_imports(&body, Some(&file), &mut pending);
+                program.modules.push(ModuleDef {
+                    name,
+                    signature: None,
+                    body,
+                    file: Some(file),
+                    span: import.span,
+                });
+            }
+            Err(message) => {
+                errors.push(LinkError::new(message, Some(import.span)).in_file(importer.as_ref()));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+fn collect_imports(program: &Program, file: Option<&String>, out: &mut Vec<(Import, Option<String>)>) {
+    for import in program.imports.iter().rev() {
+        out.push((import.clone(), file.cloned()));
+    }
+    for module in &program.modules {
+        collect_imports(&module.body, module.file.as_ref().or(file), out);
+    }
+}
+
+// ============================================================================
+// Flattening
+// ============================================================================
+
+// Names defined directly in one module
+#[derive(Default)]
+struct ModuleItems {
+    values: HashSet<String>,
+    types: HashSet<String>,
+}
+
+impl ModuleItems {
+    fn of(body: &Program) -> Self {
+        ModuleItems {
+            values: body.func_defs.iter().map(|f| f.name.clone())
+                .chain(body.extern_funcs.iter().map(|f| f.name.clone()))
+                .collect(),
+            types: body.type_defs.iter().map(|t| t.name.clone()).collect(),
+        }
+    }
+}
+
+// Lexical scope of one module body
+struct Scope<'a> {
+    // "" for the root program, "Core." inside module Core
+    prefix: String,
+    items: ModuleItems,
+    // Module names and import aliases visible here -> canonical module name
+    modules: HashMap<String, String>,
+    file: Option<String>,
+    parent: Option<&'a Scope<'a>>,
+}
+
+impl<'a> Scope<'a> {
+    fn new(prefix: String, body: &Program, file: Option<String>, parent: Option<&'a Scope<'a>>) -> Self {
+        let mut modules = HashMap::new();
+        for module in &body.modules {
+            modules.insert(module.name.clone(), format!("{}{}", prefix, module.name));
+        }
+        for import in &body.imports {
+            let canonical = import.module_name();
+            if let Some(alias) = &import.alias {
+                modules.insert(alias.clone(), canonical.clone());
+            }
+            modules.insert(canonical.clone(), canonical);
+        }
+        Scope {
+            prefix,
+            items: ModuleItems::of(body),
+            modules,
+            file,
+            parent,
+        }
+    }
+
+    fn lookup_module(&self, name: &str) -> Option<&String> {
+        self.modules.get(name).or_else(|| self.parent.and_then(|p| p.lookup_module(name)))
+    }
+}
+
+struct Flattener {
+    // Canonical module name -> items it defines
+    tables: HashMap<String, ModuleItems>,
+    errors: Vec<LinkError>,
+}
+
+/// Move every module item to the top level under its qualified name and
+/// rewrite references to match. Inside a module, its own items (and those of
+/// enclosing inline modules) are visible unqualified; other modules are
+/// reached as `Module.item` or through an `import ... as alias`.
+///
+/// Variant constructors are not namespaced.
+pub fn flatten_program(program: &Program) -> Result<Program, Vec<LinkError>> {
+    if program.modules.is_empty() && program.imports.is_empty() {
+        return Ok(program.clone());
+    }
+
+    let mut flattener = Flattener {
+        tables: HashMap::new(),
+        errors: vec![],
+    };
+    flattener.collect_tables("", &program.modules);
+
+    let mut out = Program {
+        type_defs: vec![],
+        func_defs: vec![],
+        extern_funcs: vec![],
+        modules: vec![],
+        signatures: vec![],
+        imports: vec![],
+    };
+    let root = Scope::new(String::new(), program, None, None);
+    flattener.flatten_body(program, &root, &mut out);
+
+    if flattener.errors.is_empty() {
+        Ok(out)
+    } else {
+        Err(flattener.errors)
+    }
+}
+
+/// Source file of each function defined in an imported file, keyed by the
+/// qualified name `flatten_program` gives it. Other functions come from the
+/// root file.
+pub fn function_files(program: &Program) -> HashMap<String, String> {
+    fn collect(body: &Program, prefix: &str, file: Option<&String>, out: &mut HashMap<String, String>) {
+        if let Some(file) = file {
+            for func_def in &body.func_defs {
+                out.insert(format!("{}{}", prefix, func_def.name), file.clone());
+            }
+        }
+        for module in &body.modules {
+            let file = module.file.as_ref().or(file);
+            collect(&module.body, &format!("{}{}.", prefix, module.name), file, out);
+        }
+    }
+
+    let mut out = HashMap::new();
+    collect(program, "", None, &mut out);
+    out
+}
+
+impl Flattener {
+    fn collect_tables(&mut self, prefix: &str, modules: &[ModuleDef]) {
+        for module in modules {
+            let canonical = format!("{}{}", prefix, module.name);
+            self.tables.insert(canonical.clone(), ModuleItems::of(&module.body));
+            self.collect_tables(&format!("{}.", canonical), &module.body.modules);
+        }
+    }
+
+    fn error(&mut self, scope: &Scope, message: String, span: Span) {
+        self.errors.push(LinkError::new(message, Some(span)).in_file(scope.file.as_ref()));
+    }
+
+    fn flatten_body(&mut self, body: &Program, scope: &Scope, out: &mut Program) {
+        for import in &body.imports {
+            if !self.tables.contains_key(&import.module_name()) {
+                self.error(scope, format!(
+                    "Import '{}' was not resolved (load the program with linker::link_program)",
+                    import.module_name()
+                ), import.span);
+            }
+        }
+
+        for type_def in &body.type_defs {
+            let mut type_def = type_def.clone();
+            type_def.name = format!("{}{}", scope.prefix, type_def.name);
+            match &mut type_def.definition {
+                TypeDefKind::Alias(ty) => self.rename_type(ty, scope, type_def.span),
+                TypeDefKind::Variant(variants) => {
+                    for (_, args) in variants.iter_mut() {
+                        for ty in args.iter_mut() {
+                            self.rename_type(ty, scope, type_def.span);
+                        }
+                    }
+                }
+                TypeDefKind::Record(record) => {
+                    for (_, ty) in record.fields.iter_mut() {
+                        self.rename_type(ty, scope, type_def.span);
+                    }
+                }
+            }
+            out.type_defs.push(type_def);
+        }
+
+        for extern_func in &body.extern_funcs {
+            let mut extern_func = extern_func.clone();
+            extern_func.name = format!("{}{}", scope.prefix, extern_func.name);
+            for (_, ty) in extern_func.params.iter_mut() {
+                self.rename_type(ty, scope, extern_func.span);
+            }
+            self.rename_type(&mut extern_func.return_type, scope, extern_func.span);
+            out.extern_funcs.push(extern_func);
+        }
+
+        for func_def in &body.func_defs {
+            let mut func_def = func_def.clone();
+            func_def.name = format!("{}{}", scope.prefix, func_def.name);
+            for (_, ty) in func_def.params.iter_mut() {
+                self.rename_type(ty, scope, func_def.span);
+            }
+            self.rename_type(&mut func_def.return_type, scope, func_def.span);
+
+            let mut locals: Vec<String> = func_def.params.iter().map(|(name, _)| name.clone()).collect();
+            for clause in func_def.requires.iter_mut().chain(func_def.ensures.iter_mut()) {
+                locals.push("result".to_string());
+                self.rename_expr(clause, scope, &mut locals);
+                locals.pop();
+            }
+            self.rename_expr(&mut func_def.body, scope, &mut locals);
+            out.func_defs.push(func_def);
+        }
+
+        out.signatures.extend(body.signatures.iter().cloned());
+
+        for module in &body.modules {
+            let canonical = format!("{}{}", scope.prefix, module.name);
+            // Imported files only see what they import; inline modules also
+            // see the enclosing scope
+            let parent = if module.file.is_some() { None } else { Some(scope) };
+            let file = module.file.clone().or_else(|| scope.file.clone());
+            let child = Scope::new(format!("{}.", canonical), &module.body, file, parent);
+            self.flatten_body(&module.body, &child, out);
+        }
+    }
+
+    fn rename_type(&mut self, ty: &mut Type, scope: &Scope, span: Span) {
+        match ty {
+            Type::Basic(basic) => {
+                if let Some(name) = self.resolve(&basic.name, scope, span, false) {
+                    basic.name = name;
+                }
+            }
+            Type::Option(option) => self.rename_type(&mut option.inner, scope, span),
+            Type::Result(result) => {
+                self.rename_type(&mut result.ok_type, scope, span);
+                self.rename_type(&mut result.err_type, scope, span);
+            }
+            Type::List(list) => self.rename_type(&mut list.element_type, scope, span),
+            Type::Pointer(pointer) => self.rename_type(&mut pointer.pointee_type, scope, span),
+            Type::Tuple(tuple) => {
+                for element in tuple.element_types.iter_mut() {
+                    self.rename_type(element, scope, span);
+                }
+            }
+            Type::Record(record) => {
+                for (_, field) in record.fields.iter_mut() {
+                    self.rename_type(field, scope, span);
+                }
+            }
+            Type::Function(function) => {
+                self.rename_type(&mut function.param_type, scope, span);
+                self.rename_type(&mut function.return_type, scope, span);
+            }
+            Type::Var(_) => {}
+        }
+    }
+
+    // Qualified name for a value or type reference, or `None` to leave it as
+    // written (locals, builtins, constructors)
+    fn resolve(&mut self, name: &str, scope: &Scope, span: Span, is_value: bool) -> Option<String> {
+        if let Some((module, item)) = name.rsplit_once('.') {
+            let canonical = scope.lookup_module(module)?.clone();
+            let items = self.tables.get(&canonical)?;
+            let defined = if is_value { &items.values } else { &items.types };
+            if !defined.contains(item) {
+                let kind = if is_value { "function" } else { "type" };
+                self.error(scope, format!("Module '{}' has no {} '{}'", canonical, kind, item), span);
+                return None;
+            }
+            return Some(format!("{}.{}", canonical, item));
+        }
+
+        let mut current = Some(scope);
+        while let Some(s) = current {
+            let defined = if is_value { &s.items.values } else { &s.items.types };
+            if defined.contains(name) {
+                return Some(format!("{}{}", s.prefix, name));
+            }
+            current = s.parent;
+        }
+        None
+    }
+
+    fn rename_expr(&mut self, expr: &mut Expr, scope: &Scope, locals: &mut Vec<String>) {
+        match expr {
+            Expr::Literal(_) => {}
+            Expr::Variable(var) => {
+                if !locals.contains(&var.name) {
+                    if let Some(name) = self.resolve(&var.name, scope, var.span, true) {
+                        var.name = name;
+                    }
+                }
+            }
+            Expr::Lambda(lambda) => {
+                for (_, ty) in lambda.params.iter_mut() {
+                    if let Some(ty) = ty {
+                        self.rename_type(ty, scope, lambda.span);
+                    }
+                }
+                let depth = locals.len();
+                locals.extend(lambda.params.iter().map(|(name, _)| name.clone()));
+                self.rename_expr(&mut lambda.body, scope, locals);
+                locals.truncate(depth);
+            }
+            Expr::Application(app) => {
+                self.rename_expr(&mut app.func, scope, locals);
+                self.rename_expr(&mut app.arg, scope, locals);
+            }
+            Expr::Let(let_expr) => {
+                self.rename_expr(&mut let_expr.value, scope, locals);
+                locals.push(let_expr.var_name.clone());
+                self.rename_expr(&mut let_expr.body, scope, locals);
+                locals.pop();
+            }
+            Expr::If(if_expr) => {
+                self.rename_expr(&mut if_expr.condition, scope, locals);
+                self.rename_expr(&mut if_expr.then_branch, scope, locals);
+                self.rename_expr(&mut if_expr.else_branch, scope, locals);
+            }
+            Expr::Match(match_expr) => {
+                self.rename_expr(&mut match_expr.scrutinee, scope, locals);
+                for arm in match_expr.arms.iter_mut() {
+                    let depth = locals.len();
+                    arm.pattern.collect_bindings(locals);
+                    if let Some(guard) = &mut arm.guard {
+                        self.rename_expr(guard, scope, locals);
+                    }
+                    self.rename_expr(&mut arm.body, scope, locals);
+                    locals.truncate(depth);
+                }
+            }
+            Expr::Constructor(constructor) => {
+                for arg in constructor.args.iter_mut() {
+                    self.rename_expr(arg, scope, locals);
+                }
+            }
+            Expr::BinaryOp(binop) => {
+                self.rename_expr(&mut binop.left, scope, locals);
+                self.rename_expr(&mut binop.right, scope, locals);
+            }
+            Expr::UnaryOp(unop) => self.rename_expr(&mut unop.operand, scope, locals),
+            Expr::Tuple(tuple) => {
+                for element in tuple.elements.iter_mut() {
+                    self.rename_expr(element, scope, locals);
+                }
+            }
+            Expr::Record(record) => {
+                for (_, value) in record.fields.iter_mut() {
+                    self.rename_expr(value, scope, locals);
+                }
+            }
+            Expr::FieldAccess(field_access) => {
+                self.rename_expr(&mut field_access.record, scope, locals)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_parser::parse_ir;
+
+    fn sources(modules: &[(&str, &str)]) -> HashMap<String, String> {
+        modules.iter().map(|(name, src)| (name.to_string(), src.to_string())).collect()
+    }
+
+    fn func_names(program: &Program) -> Vec<&str> {
+        program.func_defs.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_inline_module_is_namespaced() {
+        let program = parse_ir(r#"
+module Math:
+  func square (x: Int) -> Int :
+    x * x
+
+  func quad (x: Int) -> Int :
+    square(square(x))
+end
+
+func main () -> Int :
+  Math.quad(2)
+"#).unwrap();
+
+        let flat = flatten_program(&program).unwrap();
+        assert_eq!(func_names(&flat), vec!["main", "Math.square", "Math.quad"]);
+
+        let quad = &flat.func_defs[2];
+        let Expr::Application(outer) = &quad.body else { panic!("expected call") };
+        let Expr::Variable(callee) = &*outer.func else { panic!("expected variable") };
+        assert_eq!(callee.name, "Math.square");
+    }
+
+    #[test]
+    fn test_imports_resolve_across_files() {
+        let modules = sources(&[
+            ("Game.Core", r#"
+type Position = { x: Int, y: Int }
+
+func origin () -> Position :
+  { x: 0, y: 0 }
+
+func min (a: Int, b: Int) -> Int :
+  if a < b then a else b
+"#),
+            ("Game.Combat", r#"
+import Game.Core as C
+
+func clamp_hp (hp: Int) -> Int :
+  C.min(hp, 100)
+"#),
+        ]);
+
+        let program = parse_ir(r#"
+import Game.Core
+import Game.Combat
+
+func main () -> Int :
+  let min = 3 in
+  Game.Combat.clamp_hp(min)
+"#).unwrap();
+
+        let linked = link_program(program, &modules).unwrap();
+        let flat = flatten_program(&linked).unwrap();
+
+        let mut names = func_names(&flat);
+        names.sort();
+        assert_eq!(names, vec!["Game.Combat.clamp_hp", "Game.Core.min", "Game.Core.origin", "main"]);
+        assert_eq!(flat.type_defs[0].name, "Game.Core.Position");
+
+        let files = function_files(&linked);
+        assert_eq!(files.get("Game.Combat.clamp_hp").map(String::as_str), Some("Game.Combat"));
+        assert_eq!(files.get("main"), None);
+
+        let origin = flat.func_defs.iter().find(|f| f.name == "Game.Core.origin").unwrap();
+        assert_eq!(origin.return_type, Type::Basic(BasicType { name: "Game.Core.Position".to_string() }));
+
+        // The local `min` shadows nothing outside its module, and stays local
+        let main = flat.func_defs.iter().find(|f| f.name == "main").unwrap();
+        let Expr::Let(let_expr) = &main.body else { panic!("expected let") };
+        let Expr::Application(call) = &*let_expr.body else { panic!("expected call") };
+        assert_eq!(call.func.without_spans(), Expr::Variable(Variable {
+            name: "Game.Combat.clamp_hp".to_string(),
+            span: Span::default(),
+        }));
+        assert_eq!(call.arg.without_spans(), Expr::Variable(Variable {
+            name: "min".to_string(),
+            span: Span::default(),
+        }));
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let program = parse_ir("import Missing\n\nfunc main () -> Int :\n  0\n").unwrap();
+        let errors = link_program(program, &sources(&[])).unwrap_err();
+        assert_eq!(errors[0].message, "Module 'Missing' not found");
+
+        let program = parse_ir("module M:\n  func f () -> Int :\n    1\nend\n\nfunc main () -> Int :\n  M.g()\n").unwrap();
+        let errors = flatten_program(&program).unwrap_err();
+        assert_eq!(errors[0].message, "Module 'M' has no function 'g'");
+
+        let program = parse_ir("import Core\n\nfunc main () -> Int :\n  0\n").unwrap();
+        let errors = flatten_program(&program).unwrap_err();
+        assert!(errors[0].message.starts_with("Import 'Core' was not resolved"));
+    }
+}
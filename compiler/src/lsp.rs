@@ -0,0 +1,876 @@
+// Language Server
+// `pole-lsp`: the Language Server Protocol for `.pole-ir` files over stdin
+// and stdout. Each open document is parsed with `ir_parser`, linked against
+// the modules it imports and type-checked on every change; diagnostics,
+// hovers, definitions, symbols and completions come from that analysis.
+// Messages are JSON-RPC with `Content-Length` headers, read and written with
+// `serde_json`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::ast::*;
+use crate::ir_parser::parse_ir_recovering;
+use crate::linker::{function_files, link_program, FileSource};
+use crate::type_checker::{check_types_recording, curried_type, VariableTypes};
+
+// ============================================================================
+// Protocol Types
+// ============================================================================
+
+/// Zero-based line and column, the column counted in UTF-16 code units as
+/// LSP clients count them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub range: Range,
+    /// 1 for errors, the only severity reported
+    pub severity: u32,
+    pub source: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Hover {
+    pub contents: Markup,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Markup {
+    /// Always `markdown`
+    pub kind: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub detail: String,
+    /// LSP `SymbolKind`
+    pub kind: u32,
+    /// The whole declaration
+    pub range: Range,
+    /// The declared name
+    pub selection_range: Range,
+    pub children: Vec<DocumentSymbol>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompletionItem {
+    pub label: String,
+    /// LSP `CompletionItemKind`
+    pub kind: u32,
+    pub detail: String,
+}
+
+// `SymbolKind` values
+const SYMBOL_MODULE: u32 = 2;
+const SYMBOL_ENUM: u32 = 10;
+const SYMBOL_INTERFACE: u32 = 11;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_ENUM_MEMBER: u32 = 22;
+const SYMBOL_STRUCT: u32 = 23;
+const SYMBOL_TYPE_PARAMETER: u32 = 26;
+
+// `CompletionItemKind` values
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_MODULE: u32 = 9;
+const COMPLETION_KEYWORD: u32 = 14;
+const COMPLETION_ENUM_MEMBER: u32 = 20;
+const COMPLETION_STRUCT: u32 = 22;
+
+/// Functions the compiler provides: name, type and what it does
+const BUILTINS: &[(&str, &str, &str)] = &[
+    ("List_get", "List<a> -> Int -> a", "Element at an index"),
+    ("List_set", "List<a> -> Int -> a -> List<a>", "The list with the element at an index replaced"),
+    ("List_push", "List<a> -> a -> List<a>", "The list with an element appended"),
+    ("List_length", "List<a> -> Nat", "Number of elements"),
+    ("List_concat", "List<List<a>> -> List<a>", "Elements of every list, in order"),
+    ("list_new", "Unit -> List<a>", "Empty list"),
+    ("list_get", "List<a> -> Int -> a -> a", "Element at an index, or the default given last"),
+    ("list_set", "List<a> -> Int -> a -> List<a>", "The list with the element at an index replaced"),
+    ("list_push", "List<a> -> a -> List<a>", "The list with an element appended"),
+    ("HashMap_new", "Nat -> HashMap", "Empty map with room for the given number of entries"),
+    ("HashMap_put", "HashMap -> Int -> Int -> Unit", "Set the value of a key"),
+    ("HashMap_get", "HashMap -> Int -> Int", "Value of a key, 0 if it has none"),
+    ("HashMap_size", "HashMap -> Nat", "Number of keys"),
+    ("String_length", "String -> Nat", "Length in bytes"),
+    ("String_contains", "String -> String -> Bool", "Whether the second string occurs in the first"),
+    ("print", "String -> Unit", "Write to stdout"),
+    ("println", "String -> Unit", "Write to stdout, then a newline"),
+    ("int_to_float", "Int -> Float64", "The same number as a float"),
+    ("float_to_int", "Float64 -> Int", "The float rounded toward zero"),
+    ("Some", "a -> Option<a>", "An option holding a value"),
+    ("None", "Option<a>", "An option holding nothing"),
+    ("Ok", "a -> Result<a, e>", "A successful result"),
+    ("Err", "e -> Result<a, e>", "A failed result"),
+];
+
+const KEYWORDS: &[&str] = &[
+    "func", "type", "let", "in", "if", "then", "else", "match", "with", "requires", "ensures",
+    "module", "signature", "import", "as", "end", "true", "false",
+];
+
+// ============================================================================
+// Document Analysis
+// ============================================================================
+
+/// An open `.pole-ir` file and what analyzing it found
+pub struct Document {
+    source: String,
+    // As parsed; declarations that failed to parse are missing
+    program: Program,
+    diagnostics: Vec<Diagnostic>,
+    // Types of the variables in this file's functions
+    types: VariableTypes,
+    definitions: Vec<Definition>,
+    // Import aliases -> module names
+    aliases: HashMap<String, String>,
+}
+
+// A declaration a name in the document can refer to
+struct Definition {
+    // Qualified as `flatten_program` names it, as `Shapes.area`
+    name: String,
+    // `area : Int -> Int -> Int`, `type Shape = Circle(Float64) | Square(Float64)`
+    detail: String,
+    completion_kind: u32,
+    location: Location,
+}
+
+impl Document {
+    /// Parse, link and type-check `source`, the text of the document at
+    /// `uri`. Imports are searched for next to the document.
+    pub fn analyze(uri: &str, source: String) -> Document {
+        let path = uri_to_path(uri);
+        let root = path.as_deref().and_then(Path::parent).map(Path::to_path_buf).unwrap_or_default();
+        let (program, syntax_errors) = parse_ir_recovering(&source);
+
+        // Errors in imported files are reported at the top of the document
+        let mut errors: Vec<(Option<Span>, String)> = syntax_errors.into_iter()
+            .map(|error| (Some(error.span), error.message))
+            .collect();
+        let elsewhere = |file: Option<String>, span: Option<Span>, message: String| match file {
+            Some(file) if Some(Path::new(&file)) != path.as_deref() => (None, format!("{}: {}", file, message)),
+            _ => (span, message),
+        };
+
+        // A program with syntax errors is missing declarations, so checking
+        // it would report uses of them as undefined
+        let mut linked = program.clone();
+        let mut types = VariableTypes::new();
+        if errors.is_empty() {
+            match link_program(program.clone(), &FileSource::new(vec![root])) {
+                Ok(program) => {
+                    let (result, mut all_types) = check_types_recording(program.clone());
+                    errors.extend(result.errors.into_iter().map(|error| elsewhere(error.location, error.span, error.message)));
+                    let imported = function_files(&program);
+                    all_types.retain(|function, _| !imported.contains_key(function));
+                    types = all_types;
+                    linked = program;
+                }
+                Err(link_errors) => {
+                    errors.extend(link_errors.into_iter().map(|error| elsewhere(error.file, error.span, error.message)));
+                }
+            }
+        }
+
+        let diagnostics = errors.into_iter()
+            .map(|(span, message)| Diagnostic {
+                range: range_of(&source, span.unwrap_or(Span::new(0, 0))),
+                severity: 1,
+                source: "pole",
+                message,
+            })
+            .collect();
+        let mut definitions = vec![];
+        collect_definitions(&linked, "", None, uri, &source, &mut definitions);
+        let aliases = linked.imports.iter()
+            .filter_map(|import| Some((import.alias.clone()?, import.module_name())))
+            .collect();
+        Document {
+            source,
+            program,
+            diagnostics,
+            types,
+            definitions,
+            aliases,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Type of the variable at `position`, or the declaration of the name
+    /// there
+    pub fn hover(&self, position: Position) -> Option<Hover> {
+        let offset = offset_at(&self.source, position);
+        let variable = self.types.values()
+            .flatten()
+            .filter(|(span, _)| span.start <= offset && offset < span.end)
+            .min_by_key(|(span, _)| span.end - span.start);
+        if let Some((span, ty)) = variable {
+            let text = format!("{} : {}", span.snippet(&self.source), ty);
+            return Some(hover(text, None, range_of(&self.source, *span)));
+        }
+
+        let span = word_at(&self.source, offset)?;
+        let word = span.snippet(&self.source);
+        let range = range_of(&self.source, span);
+        if let Some(definition) = self.lookup(word) {
+            return Some(hover(definition.detail.clone(), None, range));
+        }
+        let (name, ty, doc) = BUILTINS.iter().find(|(name, _, _)| *name == word)?;
+        Some(hover(format!("{} : {}", name, ty), Some(doc), range))
+    }
+
+    /// Where the function, type, variant constructor or module named at
+    /// `position` is declared
+    pub fn definition(&self, position: Position) -> Option<Location> {
+        let span = word_at(&self.source, offset_at(&self.source, position))?;
+        self.lookup(span.snippet(&self.source)).map(|definition| definition.location.clone())
+    }
+
+    /// Outline of the declarations in the document
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        document_symbols(&self.program, &self.source)
+    }
+
+    /// Builtins, keywords and everything the document and its imports declare
+    pub fn completions(&self) -> Vec<CompletionItem> {
+        let builtins = BUILTINS.iter().map(|(name, ty, _)| CompletionItem {
+            label: name.to_string(),
+            kind: COMPLETION_FUNCTION,
+            detail: ty.to_string(),
+        });
+        let declared = self.definitions.iter().map(|definition| CompletionItem {
+            label: definition.name.clone(),
+            kind: definition.completion_kind,
+            detail: definition.detail.clone(),
+        });
+        let keywords = KEYWORDS.iter().map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: COMPLETION_KEYWORD,
+            detail: String::new(),
+        });
+        builtins.chain(declared).chain(keywords).collect()
+    }
+
+    // `name` as written: qualified, through an import alias, or an item of
+    // the module the reference is in
+    fn lookup(&self, name: &str) -> Option<&Definition> {
+        let find = |qualified: &str| self.definitions.iter().find(|definition| definition.name == qualified);
+        if let Some(definition) = find(name) {
+            return Some(definition);
+        }
+        let (module, item) = match name.rsplit_once('.') {
+            Some((module, item)) => (Some(module), item),
+            None => (None, name),
+        };
+        if let Some(canonical) = module.and_then(|module| self.aliases.get(module)) {
+            if let Some(definition) = find(&format!("{}.{}", canonical, item)) {
+                return Some(definition);
+            }
+        }
+        let suffix = format!(".{}", item);
+        self.definitions.iter().find(|definition| definition.name.ends_with(&suffix))
+    }
+}
+
+fn hover(code: String, doc: Option<&str>, range: Range) -> Hover {
+    let mut value = format!("```pole\n{}\n```", code);
+    if let Some(doc) = doc {
+        value.push_str("\n\n");
+        value.push_str(doc);
+    }
+    Hover { contents: Markup { kind: "markdown", value }, range }
+}
+
+// Definitions in `body` and its modules. `file` is where `body` was read
+// from, `None` for the document itself.
+fn collect_definitions(body: &Program, prefix: &str, file: Option<&String>, uri: &str, source: &str, out: &mut Vec<Definition>) {
+    let location = |span: Span| Location {
+        uri: file.map_or_else(|| uri.to_string(), |file| path_to_uri(Path::new(file))),
+        range: range_of(source, span),
+    };
+
+    for type_def in &body.type_defs {
+        let name = format!("{}{}", prefix, type_def.name);
+        out.push(Definition {
+            detail: format!("type {} = {}", name, type_definition(&type_def.definition)),
+            completion_kind: COMPLETION_STRUCT,
+            location: location(declared_name(source, type_def.span, "type", &type_def.name)),
+            name,
+        });
+        if let TypeDefKind::Variant(variants) = &type_def.definition {
+            let variant_type = Type::Basic(BasicType { name: type_def.name.clone() });
+            for (constructor, param_types) in variants {
+                let params: Vec<(String, Type)> = param_types.iter().map(|ty| (String::new(), ty.clone())).collect();
+                let ty = if params.is_empty() {
+                    variant_type.clone()
+                } else {
+                    Type::Function(curried_type(&params, &variant_type))
+                };
+                out.push(Definition {
+                    name: format!("{}{}", prefix, constructor),
+                    detail: format!("{} : {}", constructor, ty),
+                    completion_kind: COMPLETION_ENUM_MEMBER,
+                    location: location(declared_name(source, type_def.span, "", constructor)),
+                });
+            }
+        }
+    }
+
+    let functions = body.func_defs.iter()
+        .map(|function| (&function.name, &function.params, &function.return_type, function.span))
+        .chain(body.extern_funcs.iter().map(|decl| (&decl.name, &decl.params, &decl.return_type, decl.span)));
+    for (short_name, params, return_type, span) in functions {
+        let name = format!("{}{}", prefix, short_name);
+        out.push(Definition {
+            detail: format!("{} : {}", name, Type::Function(curried_type(params, return_type))),
+            completion_kind: COMPLETION_FUNCTION,
+            location: location(declared_name(source, span, "func", short_name)),
+            name,
+        });
+    }
+
+    for module in &body.modules {
+        let name = format!("{}{}", prefix, module.name);
+        match &module.file {
+            // Imported modules are declared by their whole file
+            Some(module_file) => {
+                let module_source = fs::read_to_string(module_file).unwrap_or_default();
+                out.push(Definition {
+                    name: name.clone(),
+                    detail: format!("module {}", name),
+                    completion_kind: COMPLETION_MODULE,
+                    location: Location {
+                        uri: path_to_uri(Path::new(module_file)),
+                        range: range_of(&module_source, Span::new(0, 0)),
+                    },
+                });
+                collect_definitions(&module.body, &format!("{}.", name), Some(module_file), uri, &module_source, out);
+            }
+            None => {
+                out.push(Definition {
+                    name: name.clone(),
+                    detail: format!("module {}", name),
+                    completion_kind: COMPLETION_MODULE,
+                    location: location(declared_name(source, module.span, "module", &module.name)),
+                });
+                collect_definitions(&module.body, &format!("{}.", name), file, uri, source, out);
+            }
+        }
+    }
+}
+
+fn document_symbols(body: &Program, source: &str) -> Vec<DocumentSymbol> {
+    let symbol = |name: &str, detail: String, kind: u32, span: Span, keyword: &str, children| DocumentSymbol {
+        name: name.to_string(),
+        detail,
+        kind,
+        range: range_of(source, span),
+        selection_range: range_of(source, declared_name(source, span, keyword, name)),
+        children,
+    };
+
+    let mut symbols: Vec<(usize, DocumentSymbol)> = vec![];
+    for type_def in &body.type_defs {
+        let (kind, children) = match &type_def.definition {
+            TypeDefKind::Variant(variants) => {
+                let members = variants.iter()
+                    .map(|(constructor, _)| symbol(constructor, String::new(), SYMBOL_ENUM_MEMBER, type_def.span, "", vec![]))
+                    .collect();
+                (SYMBOL_ENUM, members)
+            }
+            TypeDefKind::Record(_) => (SYMBOL_STRUCT, vec![]),
+            TypeDefKind::Alias(_) => (SYMBOL_TYPE_PARAMETER, vec![]),
+        };
+        let detail = type_definition(&type_def.definition);
+        symbols.push((type_def.span.start, symbol(&type_def.name, detail, kind, type_def.span, "type", children)));
+    }
+    for function in &body.func_defs {
+        let detail = Type::Function(curried_type(&function.params, &function.return_type)).to_string();
+        symbols.push((function.span.start, symbol(&function.name, detail, SYMBOL_FUNCTION, function.span, "func", vec![])));
+    }
+    for decl in &body.extern_funcs {
+        let detail = format!("@extern(\"{}\") {}", decl.c_name, Type::Function(curried_type(&decl.params, &decl.return_type)));
+        symbols.push((decl.span.start, symbol(&decl.name, detail, SYMBOL_FUNCTION, decl.span, "func", vec![])));
+    }
+    for signature in &body.signatures {
+        symbols.push((signature.span.start, symbol(&signature.name, String::new(), SYMBOL_INTERFACE, signature.span, "signature", vec![])));
+    }
+    for module in &body.modules {
+        let detail = module.signature.clone().unwrap_or_default();
+        let children = document_symbols(&module.body, source);
+        symbols.push((module.span.start, symbol(&module.name, detail, SYMBOL_MODULE, module.span, "module", children)));
+    }
+
+    symbols.sort_by_key(|(start, _)| *start);
+    symbols.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+// `Circle(Float64) | Square(Float64)`, `{ x: Int, y: Int }` or the aliased type
+fn type_definition(definition: &TypeDefKind) -> String {
+    match definition {
+        TypeDefKind::Alias(ty) => ty.to_string(),
+        TypeDefKind::Record(record) => Type::Record(record.clone()).to_string(),
+        TypeDefKind::Variant(variants) => variants.iter()
+            .map(|(constructor, params)| match params.as_slice() {
+                [] => constructor.clone(),
+                _ => {
+                    let params: Vec<String> = params.iter().map(Type::to_string).collect();
+                    format!("{}({})", constructor, params.join(", "))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" | "),
+    }
+}
+
+// ============================================================================
+// Positions
+// ============================================================================
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Span of `name` where the declaration at `span` declares it: the first
+// occurrence as a whole word, after `keyword` unless that is empty. The
+// declaration's start if there is none.
+fn declared_name(source: &str, span: Span, keyword: &str, name: &str) -> Span {
+    let start = span.start.min(source.len());
+    let end = span.end.clamp(start, source.len());
+    let Some(text) = source.get(start..end) else {
+        return Span::new(start, start);
+    };
+    text.match_indices(name)
+        .map(|(index, _)| start + index)
+        .find(|&at| {
+            let before = &source[..at];
+            let whole_word = !before.ends_with(is_identifier_char) && !source[at + name.len()..].starts_with(is_identifier_char);
+            whole_word && (keyword.is_empty() || before.trim_end().ends_with(keyword))
+        })
+        .map_or(Span::new(start, start), |at| Span::new(at, at + name.len()))
+}
+
+// The name at `offset`, qualified names like `Shapes.area` included
+fn word_at(source: &str, offset: usize) -> Option<Span> {
+    let is_word_char = |c: char| is_identifier_char(c) || c == '.';
+    let offset = floor_char_boundary(source, offset);
+    let start = source[..offset].char_indices()
+        .rev()
+        .find(|(_, c)| !is_word_char(*c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let end = source[offset..].find(|c: char| !is_word_char(c)).map_or(source.len(), |i| offset + i);
+    let word = source[start..end].trim_matches('.');
+    if word.is_empty() {
+        return None;
+    }
+    let start = start + source[start..end].find(word)?;
+    Some(Span::new(start, start + word.len()))
+}
+
+fn floor_char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Position of a byte offset in `source`
+pub fn position_at(source: &str, offset: usize) -> Position {
+    let offset = floor_char_boundary(source, offset);
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+/// Byte offset of a position in `source`, clamped to its line
+pub fn offset_at(source: &str, position: Position) -> usize {
+    let line_start = source.split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum::<usize>();
+    let line = source[line_start..].split('\n').next().unwrap_or("");
+    let mut units = 0;
+    for (index, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + index;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+fn range_of(source: &str, span: Span) -> Range {
+    Range {
+        start: position_at(source, span.start),
+        end: position_at(source, span.end.max(span.start)),
+    }
+}
+
+/// `file:///home/a/b.pole-ir` -> `/home/a/b.pole-ir`
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail.get(..2)
+            .filter(|_| byte == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+// ============================================================================
+// Server
+// ============================================================================
+
+/// Serve the Language Server Protocol on `input` and `output` until the
+/// client sends `exit` or closes the input
+pub fn run_server(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+    let mut documents: HashMap<String, Document> = HashMap::new();
+    while let Some(message) = read_message(input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let position = Position {
+            line: params["position"]["line"].as_u64().unwrap_or(0) as u32,
+            character: params["position"]["character"].as_u64().unwrap_or(0) as u32,
+        };
+
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    // Full text on every change
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                },
+                "serverInfo": { "name": "pole-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => Some(Value::Null),
+            "exit" => return Ok(()),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = params["textDocument"]["text"].as_str()
+                    .or_else(|| params["contentChanges"].as_array()?.last()?["text"].as_str());
+                if let Some(text) = text {
+                    let document = Document::analyze(uri, text.to_string());
+                    publish_diagnostics(output, uri, document.diagnostics())?;
+                    documents.insert(uri.to_string(), document);
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                documents.remove(uri);
+                publish_diagnostics(output, uri, &[])?;
+                None
+            }
+            "textDocument/hover" => Some(json!(documents.get(uri).and_then(|document| document.hover(position)))),
+            "textDocument/definition" => {
+                Some(json!(documents.get(uri).and_then(|document| document.definition(position))))
+            }
+            "textDocument/documentSymbol" => Some(json!(documents.get(uri).map(Document::symbols))),
+            "textDocument/completion" => Some(json!(documents.get(uri).map(Document::completions))),
+            _ => None,
+        };
+
+        // Notifications have no id and get no response
+        if let Some(id) = message.get("id") {
+            let response = match result {
+                Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": format!("Unsupported method '{}'", method) },
+                }),
+            };
+            write_message(output, &response)?;
+        }
+    }
+    Ok(())
+}
+
+fn publish_diagnostics(output: &mut dyn Write, uri: &str, diagnostics: &[Diagnostic]) -> io::Result<()> {
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    });
+    write_message(output, &notification)
+}
+
+// The next message, or `None` once the input ends
+fn read_message(input: &mut dyn BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Message without Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut dyn Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SHAPES: &str = "\
+// 도형 넓이
+type Shape =
+  | Circle(Float64)
+  | Square(Float64)
+
+func area (shape: Shape) -> Float64 :
+  match shape with
+  | Circle(r) -> 3.14 * r * r
+  | Square(side) -> side * side
+
+func total (shapes: List<Shape>) -> Float64 :
+  area(List_get(shapes, 0)) + area(Square(2.0))
+";
+
+    // Position of the first occurrence of `text`, plus `skip` characters
+    fn position_of(source: &str, text: &str, skip: usize) -> Position {
+        position_at(source, source.find(text).unwrap() + skip)
+    }
+
+    fn document(source: &str) -> Document {
+        Document::analyze("file:///tmp/shapes.pole-ir", source.to_string())
+    }
+
+    #[test]
+    fn test_positions_count_utf16() {
+        let source = "// 넓이 😀\nfunc f";
+        assert_eq!(position_at(source, source.find("😀").unwrap()), Position { line: 0, character: 6 });
+        let func = position_at(source, source.find("func").unwrap());
+        assert_eq!(func, Position { line: 1, character: 0 });
+        assert_eq!(offset_at(source, func), source.find("func").unwrap());
+        assert_eq!(offset_at(source, Position { line: 0, character: 99 }), source.find('\n').unwrap());
+        assert_eq!(offset_at(source, Position { line: 0, character: 9 }), source.find('\n').unwrap());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        assert!(document(SHAPES).diagnostics().is_empty());
+
+        let doc = document("func f (x: Int) -> Int :\n  x + true\n");
+        let diagnostic = &doc.diagnostics()[0];
+        assert!(diagnostic.message.contains("Binary operator '+'"), "{}", diagnostic.message);
+        assert_eq!(diagnostic.range.start, Position { line: 1, character: 2 });
+
+        let doc = document("func f (x: Int) -> Int :\n  x +\n\nfunc g () -> Int :\n  1\n");
+        assert_eq!(doc.diagnostics().len(), 1);
+        assert_eq!(doc.diagnostics()[0].range.start.line, 3);
+    }
+
+    #[test]
+    fn test_builtin_diagnostics() {
+        // Typed as their completions describe them
+        let source = "\
+func count (words: List<List<String>>, word: String) -> Int :
+  let counts = HashMap_new(16) in
+  let all = List_concat(words) in
+  let _ = HashMap_put(counts, String_length(word), List_length(all)) in
+  if String_contains(word, \"a\") then HashMap_get(counts, 1) else HashMap_size(counts)
+";
+        let doc = document(source);
+        assert!(doc.diagnostics().is_empty(), "{:?}", doc.diagnostics());
+
+        let doc = document("func f (word: String) -> Unit :\n  HashMap_put(HashMap_new(16), 1, word)\n");
+        assert_eq!(doc.diagnostics().len(), 1);
+        assert_eq!(doc.diagnostics()[0].range.start.line, 1);
+    }
+
+    #[test]
+    fn test_hover() {
+        let doc = document(SHAPES);
+        let value = |hover: Option<Hover>| hover.map(|hover| hover.contents.value);
+        assert_eq!(value(doc.hover(position_of(SHAPES, "side * side", 0))), Some("```pole\nside : Float64\n```".to_string()));
+        assert_eq!(
+            value(doc.hover(position_of(SHAPES, "area(List_get", 1))),
+            Some("```pole\narea : Shape -> Float64\n```".to_string())
+        );
+        assert_eq!(
+            value(doc.hover(position_of(SHAPES, "List_get(", 2))),
+            Some("```pole\nList_get : List<Shape> -> Int -> Shape\n```".to_string())
+        );
+        assert_eq!(
+            value(doc.hover(position_of(SHAPES, "Shape =", 0))),
+            Some("```pole\ntype Shape = Circle(Float64) | Square(Float64)\n```".to_string())
+        );
+        assert_eq!(value(doc.hover(position_of(SHAPES, "넓이", 0))), None);
+
+        let hover = doc.hover(position_of(SHAPES, "r * r", 0)).unwrap();
+        assert_eq!(hover.range.start, position_of(SHAPES, "r * r", 0));
+        assert_eq!(hover.range.end, position_of(SHAPES, "r * r", 1));
+    }
+
+    #[test]
+    fn test_definition() {
+        let doc = document(SHAPES);
+        let definition = |text: &str, skip: usize| doc.definition(position_of(SHAPES, text, skip)).map(|location| location.range.start);
+        assert_eq!(definition("area(Square", 0), Some(position_of(SHAPES, "area (shape", 0)));
+        assert_eq!(definition("Square(2.0)", 3), Some(position_of(SHAPES, "Square(Float64)", 0)));
+        assert_eq!(definition("List<Shape>", 6), Some(position_of(SHAPES, "Shape =", 0)));
+        assert_eq!(definition("List_get", 0), None);
+        assert_eq!(doc.definition(Position { line: 5, character: 0 }).map(|location| location.uri), None);
+
+        let source = "module Geometry :\n  func double (x: Int) -> Int :\n    x * 2\n\n  func quadruple (x: Int) -> Int :\n    double(double(x))\nend\n\nfunc main () -> Int :\n  Geometry.quadruple(1)\n";
+        let doc = document(source);
+        assert!(doc.diagnostics().is_empty(), "{:?}", doc.diagnostics());
+        let definition = |text: &str| doc.definition(position_of(source, text, 0)).map(|location| location.range.start);
+        assert_eq!(definition("Geometry.quadruple(1)"), Some(position_of(source, "quadruple (x", 0)));
+        assert_eq!(definition("double(double"), Some(position_of(source, "double (x", 0)));
+        assert_eq!(doc.definition(position_of(source, "x * 2", 0)), None);
+    }
+
+    #[test]
+    fn test_symbols_and_completions() {
+        let doc = document(SHAPES);
+        let symbols = doc.symbols();
+        let names: Vec<&str> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, ["Shape", "area", "total"]);
+        assert_eq!(symbols[0].kind, SYMBOL_ENUM);
+        assert_eq!(symbols[0].children.len(), 2);
+        assert_eq!(symbols[0].children[1].selection_range.start, position_of(SHAPES, "Square(Float64)", 0));
+        assert_eq!(symbols[1].detail, "Shape -> Float64");
+        assert_eq!(symbols[1].selection_range.start, position_of(SHAPES, "area (shape", 0));
+
+        let completions = doc.completions();
+        let item = |label: &str| completions.iter().find(|item| item.label == label);
+        assert_eq!(item("HashMap_put").map(|item| item.detail.as_str()), Some("HashMap -> Int -> Int -> Unit"));
+        assert_eq!(item("area").map(|item| item.kind), Some(COMPLETION_FUNCTION));
+        assert_eq!(item("Circle").map(|item| item.detail.as_str()), Some("Circle : Float64 -> Shape"));
+        assert!(item("match").is_some());
+    }
+
+    #[test]
+    fn test_server_session() {
+        let messages = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": { "textDocument": { "uri": "file:///tmp/a%20b.pole-ir", "text": "func f () -> Int :\n  true\n" } },
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": "file:///tmp/a%20b.pole-ir" },
+                    "contentChanges": [{ "text": "func f () -> Nat :\n  List_length([1])\n" }],
+                },
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "textDocument/hover",
+                "params": { "textDocument": { "uri": "file:///tmp/a%20b.pole-ir" }, "position": { "line": 1, "character": 3 } },
+            }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "workspace/symbol", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+            json!({ "jsonrpc": "2.0", "id": 5, "method": "shutdown" }),
+        ];
+        let mut input = vec![];
+        for message in &messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = vec![];
+        run_server(&mut Cursor::new(input), &mut output).unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut replies = vec![];
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        assert_eq!(replies.len(), 6, "{:?}", replies);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], json!(true));
+        assert_eq!(replies[1]["method"], json!("textDocument/publishDiagnostics"));
+        let diagnostics = &replies[1]["params"]["diagnostics"];
+        assert!(diagnostics[0]["message"].as_str().unwrap().contains("does not match declared return type"));
+        assert_eq!(replies[2]["params"]["diagnostics"], json!([]));
+        assert_eq!(replies[3]["result"]["contents"]["value"], json!("```pole\nList_length : List<Int> -> Nat\n```"));
+        assert_eq!(replies[4]["error"]["code"], json!(-32601));
+        assert_eq!(replies[5]["result"], Value::Null);
+    }
+}
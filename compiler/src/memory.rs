@@ -1,5 +1,10 @@
 use thiserror::Error;
 
+use crate::ast::Span;
+use crate::codegen::CodegenError;
+use crate::ir_parser::SyntaxError;
+use crate::type_checker::TypeError;
+
 #[derive(Error, Debug)]
 pub enum CompileError {
     #[error("Out of memory during {phase}: used {used} bytes, limit {limit} bytes")]
@@ -9,14 +14,53 @@ pub enum CompileError {
         limit: usize,
     },
 
-    #[error("Parse error: {0}")]
-    ParseError(String),
+    #[error("Parse error: {message}")]
+    ParseError { message: String, span: Option<Span> },
+
+    #[error("Type check error: {message}")]
+    TypeError { message: String, span: Option<Span> },
 
-    #[error("Type check error: {0}")]
-    TypeError(String),
+    #[error("Codegen error: {message}")]
+    CodegenError { message: String, span: Option<Span> },
+}
 
-    #[error("Codegen error: {0}")]
-    CodegenError(String),
+impl CompileError {
+    /// Source range the error refers to, if known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CompileError::OutOfMemory { .. } => None,
+            CompileError::ParseError { span, .. }
+            | CompileError::TypeError { span, .. }
+            | CompileError::CodegenError { span, .. } => *span,
+        }
+    }
+}
+
+impl From<SyntaxError> for CompileError {
+    fn from(error: SyntaxError) -> Self {
+        CompileError::ParseError {
+            message: error.message,
+            span: Some(error.span),
+        }
+    }
+}
+
+impl From<TypeError> for CompileError {
+    fn from(error: TypeError) -> Self {
+        CompileError::TypeError {
+            message: error.message,
+            span: error.span,
+        }
+    }
+}
+
+impl From<CodegenError> for CompileError {
+    fn from(error: CodegenError) -> Self {
+        CompileError::CodegenError {
+            message: error.message,
+            span: error.span,
+        }
+    }
 }
 
 pub struct MemoryStats {
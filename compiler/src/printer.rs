@@ -0,0 +1,927 @@
+// Pretty Printer for Pole IR
+// Turns a Program back into canonical .pole-ir source: declarations in source
+// order a blank line apart, records and variants one field or constructor per
+// line, and let chains, if/else and match arms laid out the way the examples
+// are written. Parsing the output gives back the same program.
+
+use std::ops::Range;
+
+use crate::ast::*;
+use crate::ir_parser::{parse_ir_recovering, SyntaxError};
+
+const INDENT: usize = 2;
+
+// ============================================================================
+// Entry Points
+// ============================================================================
+
+/// Canonical source for `program`
+pub fn print_program(program: &Program) -> String {
+    let mut lines = vec![];
+    push_items(program, None, 0..0, 0, &mut lines);
+    finish(lines)
+}
+
+/// Reformat `source` canonically. `//` comments between declarations stay
+/// where they are; a declaration with comments inside it is kept as written,
+/// since the program has nowhere to hold them.
+pub fn format_source(source: &str) -> Result<String, Vec<SyntaxError>> {
+    let (program, errors) = parse_ir_recovering(source);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut lines = vec![];
+    push_items(&program, Some(source), 0..source.len(), 0, &mut lines);
+    Ok(finish(lines))
+}
+
+fn finish(lines: Vec<String>) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+// ============================================================================
+// Declarations
+// ============================================================================
+
+#[derive(Clone, Copy)]
+enum Declaration<'a> {
+    Import(&'a Import),
+    Signature(&'a SignatureDef),
+    Type(&'a TypeDef),
+    Extern(&'a ExternFunctionDecl),
+    Module(&'a ModuleDef),
+    Function(&'a FunctionDef),
+}
+
+impl Declaration<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Declaration::Import(import) => import.span,
+            Declaration::Signature(signature) => signature.span,
+            Declaration::Type(type_def) => type_def.span,
+            Declaration::Extern(extern_func) => extern_func.span,
+            Declaration::Module(module) => module.span,
+            Declaration::Function(func) => func.span,
+        }
+    }
+
+    fn annotations(&self) -> &[Annotation] {
+        match self {
+            Declaration::Type(type_def) => &type_def.annotations,
+            Declaration::Extern(extern_func) => &extern_func.annotations,
+            Declaration::Function(func) => &func.annotations,
+            Declaration::Import(_) | Declaration::Signature(_) | Declaration::Module(_) => &[],
+        }
+    }
+}
+
+// Every declaration of `program` in source order. Programs built without
+// spans keep imports first, then signatures, types, externs, modules and
+// functions.
+fn declarations(program: &Program) -> Vec<Declaration<'_>> {
+    let mut declarations: Vec<Declaration> = program.imports.iter().map(Declaration::Import)
+        .chain(program.signatures.iter().map(Declaration::Signature))
+        .chain(program.type_defs.iter().map(Declaration::Type))
+        .chain(program.extern_funcs.iter().map(Declaration::Extern))
+        .chain(program.modules.iter().map(Declaration::Module))
+        .chain(program.func_defs.iter().map(Declaration::Function))
+        .collect();
+    declarations.sort_by_key(|declaration| declaration.span().start);
+    declarations
+}
+
+// Lines for the declarations of `program` at `indent`. Given the source the
+// program was parsed from, the comments in `source[range]` are kept too.
+fn push_items(program: &Program, source: Option<&str>, range: Range<usize>, indent: usize, lines: &mut Vec<String>) {
+    let mut cursor = range.start;
+    let mut previous: Option<Declaration> = None;
+    for declaration in declarations(program) {
+        let span = declaration.span();
+        let comments = source.map(|source| gap_comments(source, cursor..span.start)).unwrap_or_default();
+        push_comments(&comments, indent, previous.is_some(), true, lines);
+        // Consecutive imports stay together
+        if previous.is_some() && !matches!((previous, declaration), (Some(Declaration::Import(_)), Declaration::Import(_)))
+            && comments.iter().all(|comment| comment.trailing)
+        {
+            lines.push(String::new());
+        }
+        push_declaration(declaration, source, indent, lines);
+        cursor = source.map_or(span.end, |source| content_end(source, span));
+        previous = Some(declaration);
+    }
+    if let Some(source) = source {
+        let comments = gap_comments(source, cursor..range.end);
+        push_comments(&comments, indent, previous.is_some(), false, lines);
+    }
+}
+
+fn push_declaration(declaration: Declaration, source: Option<&str>, indent: usize, lines: &mut Vec<String>) {
+    let prefix = pad(indent, 0);
+    for annotation in declaration.annotations() {
+        lines.push(format!("{}{}", prefix, print_annotation(annotation)));
+    }
+
+    let span = declaration.span();
+    if let Declaration::Module(module) = declaration {
+        let signature = module.signature.as_ref().map(|signature| format!(" : {}", signature)).unwrap_or_default();
+        lines.push(format!("{}module {}{} :", prefix, module.name, signature));
+        push_items(&module.body, source, span.start..span.end, indent + INDENT, lines);
+        lines.push(format!("{}end", prefix));
+        return;
+    }
+
+    // Comments inside a declaration have nowhere to go in the program
+    if let Some(source) = source {
+        let text = source[span.start..content_end(source, span)].trim_end();
+        if !comments(text).is_empty() {
+            for (index, line) in text.lines().enumerate() {
+                match index {
+                    0 => lines.push(format!("{}{}", prefix, line.trim_start())),
+                    _ => lines.push(line.trim_end().to_string()),
+                }
+            }
+            return;
+        }
+    }
+
+    let text = match declaration {
+        Declaration::Import(import) => print_import(import),
+        Declaration::Signature(signature) => print_signature(signature),
+        Declaration::Type(type_def) => print_type_def(type_def),
+        Declaration::Extern(extern_func) => print_extern(extern_func),
+        Declaration::Function(func) => print_function(func),
+        Declaration::Module(_) => unreachable!("modules are printed above"),
+    };
+    lines.extend(text.lines().map(|line| format!("{}{}", prefix, line).trim_end().to_string()));
+}
+
+fn print_annotation(annotation: &Annotation) -> String {
+    if annotation.args.is_empty() {
+        return format!("@{}", annotation.name);
+    }
+    // Positional arguments are keyed by their index and lose their quotes
+    let args: Vec<String> = annotation.args.iter()
+        .map(|(key, value)| match key.parse::<usize>() {
+            Ok(_) => format!("\"{}\"", value),
+            Err(_) => format!("{}={}", key, value),
+        })
+        .collect();
+    format!("@{}({})", annotation.name, args.join(", "))
+}
+
+fn print_import(import: &Import) -> String {
+    match &import.alias {
+        Some(alias) => format!("import {} as {}", import.module_name(), alias),
+        None => format!("import {}", import.module_name()),
+    }
+}
+
+fn print_signature(signature: &SignatureDef) -> String {
+    let mut text = format!("signature {}{} :", signature.name, type_params(&signature.type_params));
+    for item in &signature.items {
+        let item = match item {
+            SignatureItem::Func { name, func_type, .. } => format!("func {} : {}", name, func_type),
+            SignatureItem::Type { name, type_params: params, .. } => format!("type {}{}", name, type_params(params)),
+        };
+        text.push_str(&format!("\n{}{}", pad(0, INDENT), item));
+    }
+    text.push_str("\nend");
+    text
+}
+
+fn print_type_def(type_def: &TypeDef) -> String {
+    let inner = pad(0, INDENT);
+    match &type_def.definition {
+        TypeDefKind::Alias(Type::Record(record)) | TypeDefKind::Record(record) if record.fields.is_empty() => {
+            format!("type {} = {{}}", type_def.name)
+        }
+        TypeDefKind::Alias(Type::Record(record)) | TypeDefKind::Record(record) => {
+            let fields: Vec<String> = record.fields.iter()
+                .map(|(name, ty)| format!("{}{}: {}", inner, name, ty))
+                .collect();
+            format!("type {} = {{\n{}\n}}", type_def.name, fields.join(",\n"))
+        }
+        TypeDefKind::Alias(ty) => format!("type {} = {}", type_def.name, ty),
+        TypeDefKind::Variant(constructors) => {
+            let mut text = format!("type {} =", type_def.name);
+            for (name, args) in constructors {
+                text.push_str(&format!("\n{}| {}", inner, name));
+                if !args.is_empty() {
+                    text.push_str(&format!("({})", join(args)));
+                }
+            }
+            text
+        }
+    }
+}
+
+fn print_extern(extern_func: &ExternFunctionDecl) -> String {
+    format!("func {}({}) -> {}", extern_func.name, params(&extern_func.params), extern_func.return_type)
+}
+
+// `func name<T>(params) -> Ret:` and the body, with contract clauses on
+// their own lines before a lone `:`
+fn print_function(func: &FunctionDef) -> String {
+    let mut text = format!(
+        "func {}{}({}) -> {}",
+        func.name,
+        type_params(&func.type_params),
+        params(&func.params),
+        func.return_type
+    );
+    if func.requires.is_empty() && func.ensures.is_empty() {
+        text.push(':');
+    } else {
+        let clauses = func.requires.iter().map(|clause| ("requires", clause))
+            .chain(func.ensures.iter().map(|clause| ("ensures", clause)));
+        for (keyword, clause) in clauses {
+            text.push_str(&format!("\n{}{} {}", pad(0, INDENT), keyword, simple(clause)));
+        }
+        text.push_str("\n:");
+    }
+    text.push_str(&format!("\n{}{}", pad(0, INDENT), block(&func.body, INDENT)));
+    text
+}
+
+fn type_params(params: &[String]) -> String {
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", params.join(", "))
+    }
+}
+
+fn params(params: &[(String, Type)]) -> String {
+    let params: Vec<String> = params.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+    params.join(", ")
+}
+
+fn join<T: std::fmt::Display>(items: &[T]) -> String {
+    items.iter().map(T::to_string).collect::<Vec<_>>().join(", ")
+}
+
+// ============================================================================
+// Expressions
+// ============================================================================
+
+// `expr` laid out over lines, continuing at column `indent` of the
+// declaration; the caller places the first line
+fn block(expr: &Expr, indent: usize) -> String {
+    match expr {
+        Expr::Let(let_expr) => format!(
+            "let {} = {} in\n{}{}",
+            let_expr.var_name,
+            let_value(&let_expr.value, indent),
+            pad(indent, 0),
+            block(&let_expr.body, indent)
+        ),
+        Expr::If(if_expr) => block_if(if_expr, indent),
+        Expr::Match(match_expr) => block_match(match_expr, indent),
+        _ => expr.to_string(),
+    }
+}
+
+// Short ifs stay on the let's line; matches and longer ifs continue below it
+fn let_value(value: &Expr, indent: usize) -> String {
+    match value {
+        Expr::Let(_) => format!("({})", value),
+        Expr::If(if_expr) if !is_flat(if_expr) => block_if(if_expr, indent),
+        Expr::Match(match_expr) => block_match(match_expr, indent + INDENT),
+        _ => value.to_string(),
+    }
+}
+
+// `if c then\n  a\nelse\n  b`, with `else if` chained on the `else` line
+fn block_if(if_expr: &IfExpr, indent: usize) -> String {
+    let inner = pad(indent, INDENT);
+    let mut text = format!(
+        "if {} then\n{}{}\n{}else",
+        simple(&if_expr.condition),
+        inner,
+        block(&if_expr.then_branch, indent + INDENT),
+        pad(indent, 0)
+    );
+    match &*if_expr.else_branch {
+        Expr::If(nested) => text.push_str(&format!(" {}", block_if(nested, indent))),
+        other => text.push_str(&format!("\n{}{}", inner, block(other, indent + INDENT))),
+    }
+    text
+}
+
+// `match s with` and one `| pattern -> body` line per arm at `indent`
+fn block_match(match_expr: &MatchExpr, indent: usize) -> String {
+    let mut text = format!("match {} with", simple(&match_expr.scrutinee));
+    for (index, arm) in match_expr.arms.iter().enumerate() {
+        text.push_str(&format!("\n{}| {}", pad(indent, 0), arm.pattern));
+        if let Some(guard) = &arm.guard {
+            text.push_str(&format!(" if {}", simple(guard)));
+        }
+        text.push_str(" ->");
+        // A match ending an arm would take the following arms as its own
+        let last = index + 1 == match_expr.arms.len();
+        let wrap = |body: String| if !last && ends_with_match(&arm.body) { format!("({})", body) } else { body };
+        match &arm.body {
+            Expr::If(if_expr) if is_flat(if_expr) => text.push_str(&format!(" {}", wrap(arm.body.to_string()))),
+            Expr::Let(_) | Expr::If(_) | Expr::Match(_) => {
+                let body = wrap(block(&arm.body, indent + INDENT));
+                text.push_str(&format!("\n{}{}", pad(indent, INDENT), body));
+            }
+            body => text.push_str(&format!(" {}", wrap(body.to_string()))),
+        }
+    }
+    text
+}
+
+// An if, or `else if` chain, whose branches fit on one line
+fn is_flat(if_expr: &IfExpr) -> bool {
+    let control = |expr: &Expr| matches!(expr, Expr::Let(_) | Expr::If(_) | Expr::Match(_));
+    !control(&if_expr.then_branch)
+        && match &*if_expr.else_branch {
+            Expr::If(nested) => is_flat(nested),
+            other => !control(other),
+        }
+}
+
+fn ends_with_match(expr: &Expr) -> bool {
+    match expr {
+        Expr::Match(_) => true,
+        Expr::Let(let_expr) => ends_with_match(&let_expr.body),
+        Expr::If(if_expr) => ends_with_match(&if_expr.else_branch),
+        Expr::Lambda(lambda) => ends_with_match(&lambda.body),
+        _ => false,
+    }
+}
+
+// An expression where only operators may appear unparenthesised: a match
+// scrutinee, if condition, guard or contract clause
+fn simple(expr: &Expr) -> String {
+    match expr {
+        Expr::Lambda(_) | Expr::Let(_) | Expr::If(_) | Expr::Match(_) => format!("({})", expr),
+        _ => expr.to_string(),
+    }
+}
+
+fn pad(indent: usize, extra: usize) -> String {
+    " ".repeat(indent + extra)
+}
+
+// ============================================================================
+// Comments
+// ============================================================================
+
+struct Comment<'a> {
+    text: &'a str,
+    // On the same line as the end of the previous declaration
+    trailing: bool,
+    // Followed by an empty line
+    blank_after: bool,
+}
+
+// Byte offsets and text of the `//` comments in `text`, outside string
+// literals (which, as in the parser, run to the next `"`)
+fn comments(text: &str) -> Vec<(usize, &str)> {
+    let mut found = vec![];
+    let mut in_string = false;
+    let mut chars = text.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '/' if !in_string && text[pos..].starts_with("//") => {
+                let end = text[pos..].find('\n').map_or(text.len(), |newline| pos + newline);
+                found.push((pos, text[pos..end].trim_end()));
+                while chars.peek().is_some_and(|&(next, _)| next < end) {
+                    chars.next();
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+fn gap_comments(source: &str, range: Range<usize>) -> Vec<Comment<'_>> {
+    let gap = &source[range];
+    comments(gap)
+        .into_iter()
+        .map(|(pos, text)| {
+            let after = &gap[pos + text.len()..];
+            let next_content = after.find(|c: char| !c.is_whitespace()).unwrap_or(after.len());
+            Comment {
+                text,
+                trailing: !gap[..pos].contains('\n'),
+                blank_after: after[..next_content].matches('\n').count() > 1,
+            }
+        })
+        .collect()
+}
+
+// Trailing comments go at the end of the last line; the rest on lines of
+// their own, a blank line after the previous declaration
+fn push_comments(
+    comments: &[Comment],
+    indent: usize,
+    after_declaration: bool,
+    before_declaration: bool,
+    lines: &mut Vec<String>,
+) {
+    for (index, comment) in comments.iter().enumerate() {
+        if comment.trailing && !lines.is_empty() {
+            let last = lines.last_mut().expect("checked above");
+            last.push(' ');
+            last.push_str(comment.text);
+            continue;
+        }
+        let first_own_line = index == 0 || comments[index - 1].trailing;
+        if first_own_line && after_declaration {
+            lines.push(String::new());
+        }
+        lines.push(format!("{}{}", pad(indent, 0), comment.text));
+        if comment.blank_after && (before_declaration || index + 1 < comments.len()) {
+            lines.push(String::new());
+        }
+    }
+}
+
+// End of the declaration at `span` in `source`, leaving out comments and
+// whitespace its parser ran on into
+fn content_end(source: &str, span: Span) -> usize {
+    let mut text = &source[span.start..span.end];
+    loop {
+        text = text.trim_end();
+        let line_start = text.rfind('\n').map_or(0, |newline| newline + 1);
+        if !text[line_start..].trim_start().starts_with("//") {
+            return span.start + text.len();
+        }
+        text = &text[..line_start];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_parser::parse_ir;
+    use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
+
+    const CANONICAL: &str = "\
+import Game.Core as Core
+
+type Point = {
+  x: Int,
+  y: Int
+}
+
+type Shape =
+  | Circle(Float64)
+  | Rect(Point, Point)
+  | Empty
+
+@extern(\"sqrt\")
+func c_sqrt(x: Float64) -> Float64
+
+@test_case(input=3, expected=6)
+func triple(n: Int) -> Int:
+  n * 3
+
+func area(shape: Shape) -> Float64
+  requires true
+  ensures result >= 0.0
+:
+  match shape with
+  | Circle(r) -> 3.14 * r * r
+  | Rect(a, b) ->
+    let w = int_to_float(b.x - a.x) in
+    let h = int_to_float(b.y - a.y) in
+    w * h
+  | Empty -> 0.0
+
+func classify(n: Int) -> String:
+  let sign = if n < 0 then \"-\" else if n == 0 then \"0\" else \"+\" in
+  if n > 100 then
+    \"big\"
+  else if n > 10 then
+    \"medium\"
+  else
+    sign
+
+module Stack : STACK :
+  type Stack = {
+    items: List<Int>
+  }
+
+  func push(s: Stack, x: Int) -> Stack:
+    { items: List_push(s.items, x) }
+end
+";
+
+    #[test]
+    fn test_print_program_is_canonical() {
+        let program = parse_ir(CANONICAL).unwrap();
+        assert_eq!(print_program(&program), CANONICAL);
+    }
+
+    #[test]
+    fn test_print_program_normalizes_layout() {
+        let source = "\
+type Dir = North | South
+type P = { x: Int, y: Int }
+func f (n: Int) -> Int
+:
+  let a = match n with | 0 -> 1 | _ -> 2 in if a == 1 then 0 else a
+@test_case(expected=0)
+";
+        let expected = "\
+type Dir =
+  | North
+  | South
+
+type P = {
+  x: Int,
+  y: Int
+}
+
+@test_case(expected=0)
+func f(n: Int) -> Int:
+  let a = match n with
+    | 0 -> 1
+    | _ -> 2 in
+  if a == 1 then
+    0
+  else
+    a
+";
+        assert_eq!(print_program(&parse_ir(source).unwrap()), expected);
+    }
+
+    #[test]
+    fn test_print_parenthesizes_nested_control() {
+        // A match in a middle arm, a let as a let value, and control
+        // expressions where only operators may appear
+        let source = "\
+func f(x: Option<Int>, y: Bool) -> Int:
+  match (if y then x else None) with
+  | Some(n) if (match n with | 0 -> false | _ -> true) ->
+    (match n with
+    | 1 -> 10
+    | _ -> 20)
+  | _ ->
+    let z = (let w = 1 in w + 1) in
+    -(1) + z
+";
+        let program = parse_ir(source).unwrap();
+        let printed = print_program(&program);
+        assert_eq!(printed, source);
+        assert_eq!(parse_ir(&printed), Ok(program));
+    }
+
+    #[test]
+    fn test_format_source_keeps_comments() {
+        let source = "\
+// Header comment
+
+// About f
+func f() -> Int :
+  1 // trailing
+
+
+func g(x: Int) -> Int:
+  // inside the body
+  x   +   1
+// At the end
+";
+        let expected = "\
+// Header comment
+
+// About f
+func f() -> Int:
+  1 // trailing
+
+func g(x: Int) -> Int:
+  // inside the body
+  x   +   1
+
+// At the end
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+        assert_eq!(parse_ir(&formatted).map(|p| p.without_spans()), parse_ir(source).map(|p| p.without_spans()));
+    }
+
+    #[test]
+    fn test_format_source_reports_syntax_errors() {
+        let errors = format_source("func f( -> Int:\n  1\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+
+    // ========================================================================
+    // Round trip: parse_ir(print_program(p)) == p
+    // ========================================================================
+
+    const RESERVED: &[&str] = &[
+        "func", "type", "match", "with", "if", "then", "else", "let", "in", "requires", "ensures",
+        "module", "signature", "import", "end", "not", "and", "or", "as",
+    ];
+
+    fn name() -> impl Strategy<Value = String> {
+        // `true`/`false` prefixes would read as literals
+        "[a-z][a-z0-9_]{0,5}".prop_filter("reserved word", |name| {
+            !RESERVED.contains(&name.as_str()) && !name.starts_with("true") && !name.starts_with("false")
+        })
+    }
+
+    fn capitalized() -> impl Strategy<Value = String> {
+        // Avoids prefixes of the built-in type names
+        "[ACDEGHJKMQTVWXYZ][a-z0-9]{0,5}"
+    }
+
+    fn basic(name: &str) -> Type {
+        Type::Basic(BasicType { name: name.to_string() })
+    }
+
+    fn ty() -> impl Strategy<Value = Type> {
+        let leaf = prop_oneof![
+            prop::sample::select(vec!["Int", "Nat", "Float64", "Bool", "String", "Unit"]).prop_map(basic),
+            capitalized().prop_map(|name| basic(&name)),
+        ];
+        leaf.prop_recursive(3, 12, 3, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|inner| Type::Option(OptionType { inner: Box::new(inner) })),
+                (inner.clone(), inner.clone()).prop_map(|(ok, err)| Type::Result(ResultType {
+                    ok_type: Box::new(ok),
+                    err_type: Box::new(err),
+                })),
+                inner.clone().prop_map(|element| Type::List(ListType { element_type: Box::new(element) })),
+                inner.clone().prop_map(|pointee| Type::Pointer(PointerType { pointee_type: Box::new(pointee) })),
+                prop::collection::vec(inner.clone(), 2..4)
+                    .prop_map(|element_types| Type::Tuple(TupleType { element_types })),
+                (inner.clone(), inner).prop_map(|(param, ret)| Type::Function(FunctionType {
+                    param_type: Box::new(param),
+                    return_type: Box::new(ret),
+                    effect: None,
+                })),
+            ]
+        })
+    }
+
+    fn literal(value: LiteralValue) -> Literal {
+        let type_name = match value {
+            LiteralValue::Int(_) => "Int",
+            LiteralValue::Float(_) => "Float64",
+            LiteralValue::Bool(_) => "Bool",
+            LiteralValue::String(_) => "String",
+            LiteralValue::Unit => "Unit",
+        };
+        Literal { value, type_name: type_name.to_string(), span: Span::default() }
+    }
+
+    fn literal_value() -> impl Strategy<Value = LiteralValue> {
+        prop_oneof![
+            (-1000i64..1000).prop_map(LiteralValue::Int),
+            (-400i32..400).prop_map(|n| LiteralValue::Float(n as f64 / 8.0)),
+            any::<bool>().prop_map(LiteralValue::Bool),
+            // The parser reads no escaped quotes
+            "[a-z ,.!/\\\\|]{0,8}".prop_map(LiteralValue::String),
+            Just(LiteralValue::Unit),
+        ]
+    }
+
+    fn variable(name: String) -> Expr {
+        Expr::Variable(Variable { name, span: Span::default() })
+    }
+
+    fn apply(callee: Expr, args: Vec<Expr>) -> Expr {
+        let args = if args.is_empty() { vec![Expr::Literal(literal(LiteralValue::Unit))] } else { args };
+        args.into_iter().fold(callee, |func, arg| {
+            Expr::Application(Application { func: Box::new(func), arg: Box::new(arg), span: Span::default() })
+        })
+    }
+
+    fn pattern() -> impl Strategy<Value = Pattern> {
+        let leaf = prop_oneof![
+            Just(Pattern::Wildcard(WildcardPattern { span: Span::default() })),
+            name().prop_map(|name| Pattern::Variable(VariablePattern { name, span: Span::default() })),
+            literal_value()
+                .prop_filter("no float patterns", |value| !matches!(value, LiteralValue::Float(_)))
+                .prop_map(|value| Pattern::Literal(LiteralPattern { value, span: Span::default() })),
+            capitalized().prop_map(|name| Pattern::Constructor(ConstructorPattern {
+                name,
+                args: vec![],
+                span: Span::default(),
+            })),
+        ];
+        leaf.prop_recursive(2, 8, 3, |inner| {
+            let single = inner.clone().prop_filter("alternatives are not or-patterns", |p| !matches!(p, Pattern::Or(_)));
+            prop_oneof![
+                (capitalized(), prop::collection::vec(inner.clone(), 1..3)).prop_map(|(name, args)| {
+                    Pattern::Constructor(ConstructorPattern { name, args, span: Span::default() })
+                }),
+                prop::collection::vec(inner.clone(), 2..4)
+                    .prop_map(|elements| Pattern::Tuple(TuplePattern { elements, span: Span::default() })),
+                (prop::option::of(capitalized()), prop::collection::vec((name(), inner), 0..3)).prop_map(
+                    |(type_name, fields)| Pattern::Record(RecordPattern { type_name, fields, span: Span::default() })
+                ),
+                prop::collection::vec(single, 2..4)
+                    .prop_map(|alternatives| Pattern::Or(OrPattern { alternatives, span: Span::default() })),
+            ]
+        })
+    }
+
+    fn expr() -> impl Strategy<Value = Expr> {
+        let leaf = prop_oneof![
+            literal_value().prop_map(|value| Expr::Literal(literal(value))),
+            name().prop_map(variable),
+        ];
+        leaf.prop_recursive(4, 40, 3, |inner| {
+            let operators = vec![
+                "=>", "||", "&&", "==", "!=", "<", ">", "<=", ">=", "^", "&", "<<", ">>", "+", "-", "*", "/", "%",
+            ];
+            prop_oneof![
+                (prop::collection::vec((name(), prop::option::of(ty())), 1..3), inner.clone()).prop_map(
+                    |(params, body)| Expr::Lambda(Lambda { params, body: Box::new(body), span: Span::default() })
+                ),
+                (name(), prop::collection::vec(inner.clone(), 0..3))
+                    .prop_map(|(callee, args)| apply(variable(callee), args)),
+                (name(), inner.clone(), inner.clone()).prop_map(|(var_name, value, body)| Expr::Let(LetExpr {
+                    var_name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                    span: Span::default(),
+                })),
+                (inner.clone(), inner.clone(), inner.clone()).prop_map(|(condition, then_branch, else_branch)| {
+                    Expr::If(IfExpr {
+                        condition: Box::new(condition),
+                        then_branch: Box::new(then_branch),
+                        else_branch: Box::new(else_branch),
+                        span: Span::default(),
+                    })
+                }),
+                (inner.clone(), prop::collection::vec((pattern(), prop::option::of(inner.clone()), inner.clone()), 1..4))
+                    .prop_map(|(scrutinee, arms)| Expr::Match(MatchExpr {
+                        scrutinee: Box::new(scrutinee),
+                        arms: arms.into_iter().map(|(pattern, guard, body)| MatchArm { pattern, guard, body }).collect(),
+                        span: Span::default(),
+                    })),
+                prop::collection::vec(inner.clone(), 0..3).prop_map(|args| Expr::Constructor(Constructor {
+                    name: "List".to_string(),
+                    args,
+                    span: Span::default(),
+                })),
+                (prop::sample::select(operators), inner.clone(), inner.clone()).prop_map(|(op, left, right)| {
+                    Expr::BinaryOp(BinaryOp {
+                        op: op.to_string(),
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        span: Span::default(),
+                    })
+                }),
+                (prop::sample::select(vec!["-", "not"]), inner.clone()).prop_map(|(op, operand)| {
+                    Expr::UnaryOp(UnaryOp { op: op.to_string(), operand: Box::new(operand), span: Span::default() })
+                }),
+                prop::collection::vec(inner.clone(), 2..4)
+                    .prop_map(|elements| Expr::Tuple(TupleExpr { elements, span: Span::default() })),
+                prop::collection::vec((name(), inner.clone()), 0..3)
+                    .prop_map(|fields| Expr::Record(RecordExpr { fields, span: Span::default() })),
+                // `1.0` would read as a float
+                (inner.prop_filter("no literal records", |e| !matches!(e, Expr::Literal(_))), name())
+                    .prop_map(|(record, field)| Expr::FieldAccess(FieldAccess {
+                        record: Box::new(record),
+                        field,
+                        span: Span::default(),
+                    })),
+            ]
+        })
+    }
+
+    fn annotation() -> impl Strategy<Value = Annotation> {
+        let arg = prop_oneof![
+            "[a-z0-9._/-]{1,8}".prop_map(|value| (None, value)),
+            (name(), (0i64..100).prop_map(|n| n.to_string())).prop_map(|(key, value)| (Some(key), value)),
+        ];
+        (name().prop_filter("not extern", |name| name != "extern"), prop::collection::vec(arg, 0..3)).prop_map(
+            |(name, args)| Annotation {
+                name,
+                args: args.into_iter()
+                    .enumerate()
+                    .map(|(index, (key, value))| (key.unwrap_or_else(|| index.to_string()), value))
+                    .collect(),
+            },
+        )
+    }
+
+    fn params() -> impl Strategy<Value = Vec<(String, Type)>> {
+        prop::collection::vec((name(), ty()), 0..3)
+    }
+
+    fn function() -> impl Strategy<Value = FunctionDef> {
+        (
+            name(),
+            prop::collection::vec(capitalized(), 0..2),
+            params(),
+            ty(),
+            prop::collection::vec(expr(), 0..2),
+            prop::collection::vec(expr(), 0..2),
+            expr(),
+            prop::collection::vec(annotation(), 0..2),
+        )
+            .prop_map(|(name, type_params, params, return_type, requires, ensures, body, annotations)| FunctionDef {
+                name,
+                type_params,
+                params,
+                return_type,
+                requires,
+                ensures,
+                body,
+                annotations,
+                span: Span::default(),
+            })
+    }
+
+    fn extern_func() -> impl Strategy<Value = ExternFunctionDecl> {
+        (name(), "[a-z_]{1,8}", params(), ty(), prop::collection::vec(annotation(), 0..2)).prop_map(
+            |(name, c_name, params, return_type, others)| {
+                let extern_annotation = Annotation { name: "extern".to_string(), args: vec![("0".to_string(), c_name.clone())] };
+                ExternFunctionDecl {
+                    name,
+                    c_name,
+                    params,
+                    return_type,
+                    annotations: std::iter::once(extern_annotation).chain(others).collect(),
+                    span: Span::default(),
+                }
+            },
+        )
+    }
+
+    fn type_def() -> impl Strategy<Value = TypeDef> {
+        let definition = prop_oneof![
+            ty().prop_map(TypeDefKind::Alias),
+            prop::collection::vec((capitalized(), prop::collection::vec(ty(), 0..3)), 1..4).prop_map(TypeDefKind::Variant),
+            prop::collection::vec((name(), ty()), 0..4).prop_map(|fields| TypeDefKind::Record(RecordType { fields })),
+        ];
+        (capitalized(), definition, prop::collection::vec(annotation(), 0..2)).prop_map(
+            |(name, definition, annotations)| TypeDef { name, definition, annotations, span: Span::default() },
+        )
+    }
+
+    fn signature() -> impl Strategy<Value = SignatureDef> {
+        let item = prop_oneof![
+            (name(), ty()).prop_map(|(name, func_type)| SignatureItem::Func { name, func_type, span: Span::default() }),
+            (capitalized(), prop::collection::vec(capitalized(), 0..2))
+                .prop_map(|(name, type_params)| SignatureItem::Type { name, type_params, span: Span::default() }),
+        ];
+        (capitalized(), prop::collection::vec(capitalized(), 0..2), prop::collection::vec(item, 0..3)).prop_map(
+            |(name, type_params, items)| SignatureDef { name, type_params, items, span: Span::default() },
+        )
+    }
+
+    fn import() -> impl Strategy<Value = Import> {
+        (prop::collection::vec(capitalized(), 1..3), prop::option::of(capitalized()))
+            .prop_map(|(path, alias)| Import { path, alias, span: Span::default() })
+    }
+
+    fn declarations_only() -> impl Strategy<Value = Program> {
+        (
+            prop::collection::vec(type_def(), 0..3),
+            prop::collection::vec(function(), 0..3),
+            prop::collection::vec(extern_func(), 0..2),
+        )
+            .prop_map(|(type_defs, func_defs, extern_funcs)| Program {
+                type_defs,
+                func_defs,
+                extern_funcs,
+                modules: vec![],
+                signatures: vec![],
+                imports: vec![],
+            })
+    }
+
+    fn program() -> impl Strategy<Value = Program> {
+        let module = (capitalized(), prop::option::of(capitalized()), declarations_only()).prop_map(
+            |(name, signature, body)| ModuleDef { name, signature, body, file: None, span: Span::default() },
+        );
+        (
+            declarations_only(),
+            prop::collection::vec(module, 0..2),
+            prop::collection::vec(signature(), 0..2),
+            prop::collection::vec(import(), 0..3),
+        )
+            .prop_map(|(program, modules, signatures, imports)| Program { modules, signatures, imports, ..program })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(200))]
+
+        #[test]
+        fn prop_parse_print_round_trip(program in program()) {
+            let printed = print_program(&program);
+            let reparsed = parse_ir(&printed).map(|p| p.without_spans());
+            prop_assert_eq!(reparsed, Ok(program.without_spans()), "printed:\n{}", printed);
+        }
+    }
+}
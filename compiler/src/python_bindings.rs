@@ -198,6 +198,11 @@ fn expression_to_py(py: Python, expr: &Expr) -> PyResult<PyObject> {
             dict.set_item("left", expression_to_py(py, &binop.left)?)?;
             dict.set_item("right", expression_to_py(py, &binop.right)?)?;
         }
+        Expr::UnaryOp(unop) => {
+            dict.set_item("type", "UnaryOp")?;
+            dict.set_item("op", &unop.op)?;
+            dict.set_item("operand", expression_to_py(py, &unop.operand)?)?;
+        }
         Expr::Application(app) => {
             dict.set_item("type", "Application")?;
             dict.set_item("function", expression_to_py(py, &app.func)?)?;
@@ -220,10 +225,13 @@ fn expression_to_py(py: Python, expr: &Expr) -> PyResult<PyObject> {
             dict.set_item("scrutinee", expression_to_py(py, &match_expr.scrutinee)?)?;
             
             let cases_list = PyList::empty(py);
-            for (pattern, body) in &match_expr.arms {
+            for arm in &match_expr.arms {
                 let case_dict = PyDict::new(py);
-                case_dict.set_item("pattern", pattern_to_py(py, pattern)?)?;
-                case_dict.set_item("body", expression_to_py(py, body)?)?;
+                case_dict.set_item("pattern", pattern_to_py(py, &arm.pattern)?)?;
+                if let Some(guard) = &arm.guard {
+                    case_dict.set_item("guard", expression_to_py(py, guard)?)?;
+                }
+                case_dict.set_item("body", expression_to_py(py, &arm.body)?)?;
                 cases_list.append(case_dict)?;
             }
             dict.set_item("cases", cases_list)?;
@@ -263,10 +271,33 @@ fn pattern_to_py(py: Python, pattern: &Pattern) -> PyResult<PyObject> {
             }
             dict.set_item("args", args_list)?;
         }
-        _ => {
-            return Err(PyErr::new::<pyo3::exceptions::PyNotImplementedError, _>(
-                format!("Pattern type not yet implemented: {:?}", pattern)
-            ));
+        Pattern::Tuple(tuple_pat) => {
+            dict.set_item("type", "Tuple")?;
+            
+            let elements_list = PyList::empty(py);
+            for element in &tuple_pat.elements {
+                elements_list.append(pattern_to_py(py, element)?)?;
+            }
+            dict.set_item("elements", elements_list)?;
+        }
+        Pattern::Record(record_pat) => {
+            dict.set_item("type", "Record")?;
+            dict.set_item("type_name", &record_pat.type_name)?;
+            
+            let fields_dict = PyDict::new(py);
+            for (name, field) in &record_pat.fields {
+                fields_dict.set_item(name, pattern_to_py(py, field)?)?;
+            }
+            dict.set_item("fields", fields_dict)?;
+        }
+        Pattern::Or(or_pat) => {
+            dict.set_item("type", "Or")?;
+            
+            let alternatives_list = PyList::empty(py);
+            for alternative in &or_pat.alternatives {
+                alternatives_list.append(pattern_to_py(py, alternative)?)?;
+            }
+            dict.set_item("alternatives", alternatives_list)?;
         }
     }
     
@@ -290,6 +321,9 @@ fn check_types_py(py: Python, input: &str) -> PyResult<PyObject> {
         if let Some(loc) = &error.location {
             error_dict.set_item("location", loc)?;
         }
+        if let Some(span) = error.span {
+            error_dict.set_item("span", (span.start, span.end))?;
+        }
         errors.append(error_dict)?;
     }
     dict.set_item("errors", errors)?;
@@ -297,9 +331,53 @@ fn check_types_py(py: Python, input: &str) -> PyResult<PyObject> {
     Ok(dict.into())
 }
 
+#[pyfunction]
+fn verify_contracts_py(py: Python, input: &str) -> PyResult<PyObject> {
+    let program = ir_parser::parse_ir(input)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Parse error: {}", e)))?;
+    
+    let report = crate::verifier::verify_contracts(&program);
+    
+    let clauses = PyList::empty(py);
+    for clause in &report.clauses {
+        let clause_dict = PyDict::new(py);
+        clause_dict.set_item("function", &clause.function)?;
+        clause_dict.set_item("kind", clause.kind.to_string())?;
+        clause_dict.set_item("index", clause.index)?;
+        clause_dict.set_item("clause", &clause.clause)?;
+        clause_dict.set_item("span", (clause.span.start, clause.span.end))?;
+        match &clause.verdict {
+            crate::verifier::Verdict::Proven => {
+                clause_dict.set_item("status", "proven")?;
+            }
+            crate::verifier::Verdict::Refuted(counterexample) => {
+                clause_dict.set_item("status", "refuted")?;
+                let values = PyDict::new(py);
+                for (name, value) in &counterexample.values {
+                    values.set_item(name, value)?;
+                }
+                clause_dict.set_item("counterexample", values)?;
+                clause_dict.set_item("caller", &counterexample.caller)?;
+            }
+            crate::verifier::Verdict::Unknown(reason) => {
+                clause_dict.set_item("status", "unknown")?;
+                clause_dict.set_item("reason", reason)?;
+            }
+        }
+        clauses.append(clause_dict)?;
+    }
+    
+    let dict = PyDict::new(py);
+    dict.set_item("all_proven", report.all_proven())?;
+    dict.set_item("clauses", clauses)?;
+    
+    Ok(dict.into())
+}
+
 #[pymodule]
 fn pole_compiler(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(parse_ir, m)?)?;
     m.add_function(wrap_pyfunction!(check_types_py, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_contracts_py, m)?)?;
     Ok(())
 }
@@ -0,0 +1,370 @@
+// REPL
+// Sessions of `polec repl`: each submission, a group of declarations or an
+// expression, is type-checked against what earlier submissions defined and
+// JIT-compiled into a module of its own. The modules share one execution
+// engine, so each links against the ones before it.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use bumpalo::Bump;
+use inkwell::context::Context;
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::Linkage;
+use inkwell::OptimizationLevel;
+
+use crate::ast::*;
+use crate::codegen::CodeGen;
+use crate::ir_parser::{parse_expression, parse_ir};
+use crate::linker::{flatten_program, link_program, FileSource, LinkError};
+use crate::test_runner::{add_result_writer, writer_name, Reader, TestValue, TypeTable};
+use crate::type_checker::{check_types, curried_type, map_type, TypeChecker, TypeError};
+
+const HELP: &str = "\
+Enter an expression to evaluate it, or declarations (func, type, @extern,
+module) to define them; declarations end at a blank line.
+  :type <expr>   show the type of an expression without running it
+  :help          show this help
+  :quit          leave the REPL";
+
+/// What a submission did
+#[derive(Debug, Clone, PartialEq)]
+pub enum Evaluation {
+    /// The submission's declarations, as `type Person` and `fact : Int -> Int`
+    Defined(Vec<String>),
+    /// An expression's value and its type
+    Value(TestValue, Type),
+    /// An expression of function type, which is not run
+    Function(Type),
+}
+
+/// `{ name: "a", age: 3 } : Person`, `<function> : Int -> Int`, or a line
+/// per declaration
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Evaluation::Defined(declarations) => write!(f, "{}", declarations.join("\n")),
+            Evaluation::Value(value, ty) => write!(f, "{} : {}", value, ty),
+            Evaluation::Function(ty) => write!(f, "<function> : {}", ty),
+        }
+    }
+}
+
+/// A REPL session
+pub struct Repl<'ctx, 'arena> {
+    context: &'ctx Context,
+    arena: &'arena Bump,
+    // Declarations of every submission so far, with the modules they import
+    program: Program,
+    engine: Option<ExecutionEngine<'ctx>>,
+    // Owners of the modules the engine runs, one per submission
+    codegens: Vec<CodeGen<'ctx, 'arena>>,
+}
+
+impl<'ctx, 'arena> Repl<'ctx, 'arena> {
+    pub fn new(context: &'ctx Context, arena: &'arena Bump) -> Self {
+        Repl {
+            context,
+            arena,
+            program: Program {
+                type_defs: vec![],
+                func_defs: vec![],
+                extern_funcs: vec![],
+                modules: vec![],
+                signatures: vec![],
+                imports: vec![],
+            },
+            engine: None,
+            codegens: vec![],
+        }
+    }
+
+    /// Type-check, compile and run one submission: declarations (`func`,
+    /// `type`, `@extern`, `module`, `import`) or an expression. A failed
+    /// submission leaves the session as it was.
+    pub fn submit(&mut self, source: &str) -> Result<Evaluation, String> {
+        let first_line = source.lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with("//"))
+            .unwrap_or("");
+        if starts_declaration(first_line) {
+            let program = parse_ir(source)?;
+            self.load(program, source)
+        } else {
+            self.evaluate(source)
+        }
+    }
+
+    /// Define the declarations of `program`, parsed from `source`. Imports
+    /// are searched for relative to the current directory.
+    pub fn load(&mut self, program: Program, source: &str) -> Result<Evaluation, String> {
+        let before = flatten_program(&self.program).map_err(|errors| render_link_errors(errors, source))?;
+        let mut merged = self.program.clone();
+        merged.type_defs.extend(program.type_defs);
+        merged.func_defs.extend(program.func_defs);
+        merged.extern_funcs.extend(program.extern_funcs);
+        merged.modules.extend(program.modules);
+        merged.signatures.extend(program.signatures);
+        merged.imports.extend(program.imports);
+        let merged = link_program(merged, &FileSource::new(vec![PathBuf::from(".")]))
+            .map_err(|errors| render_link_errors(errors, source))?;
+        let after = flatten_program(&merged).map_err(|errors| render_link_errors(errors, source))?;
+
+        let mut names = HashSet::new();
+        let type_names = after.type_defs.iter().map(|type_def| &type_def.name);
+        let function_names = after.func_defs.iter().map(|function| &function.name)
+            .chain(after.extern_funcs.iter().map(|decl| &decl.name));
+        for name in type_names.chain(function_names) {
+            if !names.insert(name) {
+                return Err(format!("'{}' is already defined", name));
+            }
+        }
+        let result = check_types(merged.clone());
+        if !result.success {
+            return Err(render_type_errors(result.errors, source));
+        }
+
+        let known = |name: &String| {
+            before.type_defs.iter().any(|type_def| &type_def.name == name)
+                || before.func_defs.iter().any(|function| &function.name == name)
+                || before.extern_funcs.iter().any(|decl| &decl.name == name)
+        };
+        let new_functions: Vec<&FunctionDef> = after.func_defs.iter().filter(|function| !known(&function.name)).collect();
+        let exported: Vec<String> = new_functions.iter()
+            .filter(|function| function.type_params.is_empty())
+            .map(|function| function.name.clone())
+            .collect();
+        self.compile(&merged, &exported, None, source)?;
+        self.program = merged;
+
+        let mut declarations: Vec<String> = after.type_defs.iter()
+            .filter(|type_def| !known(&type_def.name))
+            .map(|type_def| format!("type {}", type_def.name))
+            .collect();
+        declarations.extend(after.extern_funcs.iter().filter(|decl| !known(&decl.name)).map(|decl| {
+            format!("{} : {}", decl.name, Type::Function(curried_type(&decl.params, &decl.return_type)))
+        }));
+        declarations.extend(new_functions.iter().map(|function| {
+            format!("{} : {}", function.name, Type::Function(curried_type(&function.params, &function.return_type)))
+        }));
+        Ok(Evaluation::Defined(declarations))
+    }
+
+    /// Type of the expression `source`, without running it
+    pub fn type_of(&self, source: &str) -> Result<Type, String> {
+        let expr = parse_expression(source)?;
+        self.infer(&expr, source)
+    }
+
+    // Compile the expression into a thunk returning its value, run it and
+    // read the value back
+    fn evaluate(&mut self, source: &str) -> Result<Evaluation, String> {
+        let expr = parse_expression(source)?;
+        let ty = self.infer(&expr, source)?;
+        if let Type::Function(_) = ty {
+            return Ok(Evaluation::Function(ty));
+        }
+
+        let thunk = FunctionDef {
+            name: format!("__repl_{}", self.codegens.len()),
+            type_params: vec![],
+            params: vec![],
+            return_type: ty.clone(),
+            requires: vec![],
+            ensures: vec![],
+            span: expr.span(),
+            body: expr,
+            annotations: vec![],
+        };
+        let writer = writer_name(&thunk.name);
+        let mut program = self.program.clone();
+        program.func_defs.push(thunk.clone());
+        self.compile(&program, std::slice::from_ref(&writer), Some(&thunk.name), source)?;
+
+        let flat = flatten_program(&program).map_err(|errors| render_link_errors(errors, source))?;
+        let types = TypeTable::new(&flat);
+        let engine = self.engine.as_ref().expect("compile creates the engine");
+        let codegen = self.codegens.last().expect("compile keeps the code generator");
+        let reader = Reader {
+            context: self.context,
+            codegen,
+            target_data: engine.get_target_data(),
+            types: &types,
+        };
+        let size = reader.target_data.get_abi_size(&codegen.llvm_type(&ty)) as usize;
+        let mut buffer = vec![0u64; size.div_ceil(8).max(1)];
+        let value = unsafe {
+            let writer = engine.get_function::<unsafe extern "C" fn(*mut u8)>(&writer)
+                .map_err(|e| format!("Failed to get the compiled expression: {}", e))?;
+            writer.call(buffer.as_mut_ptr().cast());
+            reader.read(buffer.as_ptr().cast(), &ty)?
+        };
+        Ok(Evaluation::Value(value, ty))
+    }
+
+    // Type of `expr` in the session. Type variables left unresolved, as in
+    // `[]`, default to Unit, and a record literal takes the record type
+    // declaring its fields.
+    fn infer(&self, expr: &Expr, source: &str) -> Result<Type, String> {
+        let program = flatten_program(&self.program).map_err(|errors| render_link_errors(errors, source))?;
+        let ty = TypeChecker::new(program.clone())
+            .infer_expression(expr)
+            .map_err(|errors| render_type_errors(errors, source))?;
+
+        let mut unnamed = None;
+        let ty = map_type(&ty, &mut |node| match node {
+            Type::Var(_) => Some(Type::Basic(BasicType { name: "Unit".to_string() })),
+            Type::Record(record) => {
+                let declaring = program.type_defs.iter().find(|type_def| match &type_def.definition {
+                    TypeDefKind::Record(declared) => {
+                        declared.fields.len() == record.fields.len()
+                            && record.fields.iter().all(|(name, _)| declared.fields.iter().any(|(field, _)| field == name))
+                    }
+                    _ => false,
+                });
+                if declaring.is_none() {
+                    unnamed.get_or_insert_with(|| node.to_string());
+                }
+                declaring.map(|type_def| Type::Basic(BasicType { name: type_def.name.clone() }))
+            }
+            _ => None,
+        });
+        match unnamed {
+            Some(record) => Err(format!("No record type has the fields of {}", record)),
+            None => Ok(ty),
+        }
+    }
+
+    // Compile `program` into a new module and add it to the engine. Functions
+    // of earlier submissions are declared, not compiled again, and everything
+    // the module defines apart from the `exported` functions is made
+    // internal, so lambdas, generic instances and constants of different
+    // modules never clash.
+    fn compile(&mut self, program: &Program, exported: &[String], thunk: Option<&str>, source: &str) -> Result<(), String> {
+        let earlier = flatten_program(&self.program).map_err(|errors| render_link_errors(errors, source))?;
+        let mut codegen = CodeGen::new(self.context, &format!("repl_{}", self.codegens.len()), self.arena);
+        codegen.set_external_functions(earlier.func_defs.into_iter().map(|function| function.name));
+        codegen.compile_program(program).map_err(|error| render(error.message, error.span, source))?;
+        if let Some(thunk) = thunk {
+            add_result_writer(self.context, &codegen, thunk)?;
+        }
+
+        let module = codegen.get_module();
+        let mut function = module.get_first_function();
+        while let Some(current) = function {
+            let name = current.get_name().to_string_lossy();
+            if current.count_basic_blocks() > 0 && !exported.iter().any(|exported| *exported == name) {
+                current.set_linkage(Linkage::Internal);
+            }
+            function = current.get_next_function();
+        }
+        let mut global = module.get_first_global();
+        while let Some(current) = global {
+            if current.get_initializer().is_some() {
+                current.set_linkage(Linkage::Internal);
+            }
+            global = current.get_next_global();
+        }
+        module.verify().map_err(|e| format!("Module verification failed: {}", e))?;
+
+        if let Some(engine) = &self.engine {
+            engine.add_module(module).map_err(|()| "Failed to add the module to the JIT".to_string())?;
+        } else {
+            let engine = module.create_jit_execution_engine(OptimizationLevel::None)
+                .map_err(|e| format!("Failed to create execution engine: {}", e))?;
+            self.engine = Some(engine);
+        }
+        self.codegens.push(codegen);
+        Ok(())
+    }
+}
+
+/// Read submissions from `input` until it ends or `:quit`, writing what
+/// they evaluate to, or their errors, to `output`. A line starting a
+/// declaration begins a submission that continues up to the next blank
+/// line; any other line is an expression. With `prompt`, a prompt is
+/// written before each line.
+pub fn run_session(repl: &mut Repl, input: &mut dyn BufRead, output: &mut dyn Write, prompt: bool) -> io::Result<()> {
+    let mut pending = String::new();
+    loop {
+        if prompt {
+            write!(output, "{}", if pending.is_empty() { "pole> " } else { "  ... " })?;
+            output.flush()?;
+        }
+        let mut line = String::new();
+        let at_end = input.read_line(&mut line)? == 0;
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        if !pending.is_empty() {
+            if !at_end && !line.trim().is_empty() {
+                pending.push_str(line);
+                pending.push('\n');
+                continue;
+            }
+            let submission = std::mem::take(&mut pending);
+            report(output, repl.submit(&submission))?;
+        }
+        if at_end {
+            return Ok(());
+        }
+
+        let command = line.trim();
+        match command.split_once(' ').map_or((command, ""), |(name, rest)| (name, rest.trim())) {
+            ("", _) => {}
+            (":quit" | ":q", _) => return Ok(()),
+            (":help" | ":h", _) => writeln!(output, "{}", HELP)?,
+            (":type" | ":t", expr) => match repl.type_of(expr) {
+                Ok(ty) => writeln!(output, "{}", ty)?,
+                Err(message) => writeln!(output, "{}", message)?,
+            },
+            (name, _) if name.starts_with(':') => {
+                writeln!(output, "unknown command '{}'; :help lists the commands", name)?
+            }
+            _ if starts_declaration(command) => {
+                pending.push_str(line);
+                pending.push('\n');
+            }
+            _ => report(output, repl.submit(line))?,
+        }
+    }
+}
+
+fn report(output: &mut dyn Write, result: Result<Evaluation, String>) -> io::Result<()> {
+    match result {
+        Ok(Evaluation::Defined(declarations)) if declarations.is_empty() => Ok(()),
+        Ok(evaluation) => writeln!(output, "{}", evaluation),
+        Err(message) => writeln!(output, "{}", message),
+    }
+}
+
+// Whether a line begins a declaration rather than an expression
+fn starts_declaration(line: &str) -> bool {
+    let keyword = line.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or("");
+    line.starts_with('@') || matches!(keyword, "func" | "type" | "module" | "signature" | "import")
+}
+
+// `repl:line:col: message`, located in the submission's source
+fn render(message: String, span: Option<Span>, source: &str) -> String {
+    let error = match span {
+        Some(span) => TypeError::at(message, span),
+        None => TypeError::new(message),
+    };
+    error.render("repl", source)
+}
+
+fn render_type_errors(errors: Vec<TypeError>, source: &str) -> String {
+    errors.iter().map(|error| error.render("repl", source)).collect::<Vec<_>>().join("\n")
+}
+
+// Errors in imported files name the file instead
+fn render_link_errors(errors: Vec<LinkError>, source: &str) -> String {
+    errors.into_iter()
+        .map(|error| match &error.file {
+            Some(file) => format!("{}: {}", file, error.message),
+            None => render(error.message, error.span, source),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,160 @@
+// Tail Calls
+// Finds the recursive calls of a function and whether each is in tail
+// position, so `@tailrec` functions can be checked and compiled to loops
+
+use crate::ast::*;
+
+/// Self-recursive calls of a function, identified by the span of the
+/// outermost application of each call
+#[derive(Debug, Clone, Default)]
+pub struct RecursiveCalls {
+    /// Calls whose result is the function's result, like `go(n - 1, acc * n)`
+    /// in `if n == 0 then acc else go(n - 1, acc * n)`
+    pub tail: Vec<Span>,
+    /// Calls whose result is used further, like `n * fact(n - 1)`, partial
+    /// applications and calls inside lambdas
+    pub non_tail: Vec<Span>,
+}
+
+/// Whether the function is annotated `@tailrec`
+pub fn is_tailrec(function: &FunctionDef) -> bool {
+    function.annotations.iter().any(|annotation| annotation.name == "tailrec")
+}
+
+/// The recursive calls in the body of `function`
+pub fn recursive_calls(function: &FunctionDef) -> RecursiveCalls {
+    // Instances of generic functions are named `f<Int>`; their bodies still call `f`
+    let name = function.name.split('<').next().unwrap_or(&function.name);
+    let mut finder = CallFinder {
+        name,
+        // `f()` applies a function without parameters to `()`
+        arity: function.params.len().max(1),
+        bound: function.params.iter().map(|(name, _)| name.clone()).collect(),
+        calls: RecursiveCalls::default(),
+    };
+    finder.visit(&function.body, true);
+    finder.calls
+}
+
+struct CallFinder<'a> {
+    name: &'a str,
+    arity: usize,
+    // Local names in scope; one equal to the function's name hides it
+    bound: Vec<String>,
+    calls: RecursiveCalls,
+}
+
+impl CallFinder<'_> {
+    fn visit(&mut self, expr: &Expr, tail: bool) {
+        match expr {
+            Expr::Literal(_) | Expr::Variable(_) => {}
+            Expr::Lambda(lambda) => {
+                let depth = self.bound.len();
+                self.bound.extend(lambda.params.iter().map(|(name, _)| name.clone()));
+                self.visit(&lambda.body, false);
+                self.bound.truncate(depth);
+            }
+            Expr::Application(app) => {
+                let mut callee = &*app.func;
+                let mut args = vec![&*app.arg];
+                while let Expr::Application(inner) = callee {
+                    args.push(&inner.arg);
+                    callee = &inner.func;
+                }
+                match callee {
+                    Expr::Variable(var) if var.name == self.name && !self.bound.contains(&var.name) => {
+                        if tail && args.len() == self.arity {
+                            self.calls.tail.push(app.span);
+                        } else {
+                            self.calls.non_tail.push(app.span);
+                        }
+                        for arg in args {
+                            self.visit(arg, false);
+                        }
+                    }
+                    _ => {
+                        self.visit(&app.func, false);
+                        self.visit(&app.arg, false);
+                    }
+                }
+            }
+            Expr::Let(let_expr) => {
+                self.visit(&let_expr.value, false);
+                self.bound.push(let_expr.var_name.clone());
+                self.visit(&let_expr.body, tail);
+                self.bound.pop();
+            }
+            Expr::If(if_expr) => {
+                self.visit(&if_expr.condition, false);
+                self.visit(&if_expr.then_branch, tail);
+                self.visit(&if_expr.else_branch, tail);
+            }
+            Expr::Match(match_expr) => {
+                self.visit(&match_expr.scrutinee, false);
+                for arm in &match_expr.arms {
+                    let depth = self.bound.len();
+                    arm.pattern.collect_bindings(&mut self.bound);
+                    if let Some(guard) = &arm.guard {
+                        self.visit(guard, false);
+                    }
+                    self.visit(&arm.body, tail);
+                    self.bound.truncate(depth);
+                }
+            }
+            Expr::Constructor(constructor) => {
+                for arg in &constructor.args {
+                    self.visit(arg, false);
+                }
+            }
+            Expr::BinaryOp(binop) => {
+                self.visit(&binop.left, false);
+                self.visit(&binop.right, false);
+            }
+            Expr::UnaryOp(unop) => self.visit(&unop.operand, false),
+            Expr::Tuple(tuple) => {
+                for element in &tuple.elements {
+                    self.visit(element, false);
+                }
+            }
+            Expr::Record(record) => {
+                for (_, value) in &record.fields {
+                    self.visit(value, false);
+                }
+            }
+            Expr::FieldAccess(field_access) => self.visit(&field_access.record, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_parser::parse_ir;
+
+    fn calls(ir: &str) -> (usize, usize) {
+        let program = parse_ir(ir).unwrap();
+        let calls = recursive_calls(&program.func_defs[0]);
+        (calls.tail.len(), calls.non_tail.len())
+    }
+
+    #[test]
+    fn test_tail_calls_through_if_match_and_let() {
+        let ir = r#"
+@tailrec
+func go(n: Int, acc: Int) -> Int:
+  match n with
+  | 0 -> acc
+  | _ -> let next = n - 1 in if next > 10 then go(next - 1, acc + 2) else go(next, acc + 1)
+"#;
+        assert_eq!(calls(ir), (2, 0));
+    }
+
+    #[test]
+    fn test_non_tail_calls() {
+        assert_eq!(calls("func fact(n: Int) -> Int:\n  if n == 0 then 1 else n * fact(n - 1)\n"), (0, 1));
+        // The argument of a tail call is not in tail position
+        assert_eq!(calls("func f(n: Int) -> Int:\n  if n == 0 then 0 else f(f(n - 1))\n"), (1, 1));
+        // A local `f` hides the function
+        assert_eq!(calls("func f(n: Int) -> Int:\n  let f = n + 1 in f\n"), (0, 0));
+    }
+}
@@ -0,0 +1,755 @@
+// Native @test_case Runner
+// Compiles the program together with one thunk per `@test_case` (a
+// zero-argument function calling the annotated function on the case's
+// input), JIT-executes the thunks and compares what they return, read back
+// out of memory, with the case's `expected` value.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use bumpalo::Bump;
+use inkwell::context::Context;
+use inkwell::targets::TargetData;
+use inkwell::types::{BasicType, BasicTypeEnum};
+use inkwell::AddressSpace;
+use inkwell::OptimizationLevel;
+
+use crate::ast::*;
+use crate::codegen::{CodeGen, CodegenError};
+use crate::interpreter::Value;
+use crate::ir_parser::parse_expression;
+use crate::linker::flatten_program;
+
+// ============================================================================
+// Values and Results
+// ============================================================================
+
+/// Value a test case expects or produced
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Unit,
+    Tuple(Vec<TestValue>),
+    /// Fields in declaration order
+    Record(Vec<(String, TestValue)>),
+    /// `Some(1)`, `None`, `Ok(())`, `Err(e)` and user variants
+    Constructor(String, Vec<TestValue>),
+    List(Vec<TestValue>),
+}
+
+impl TestValue {
+    // Equality, with floats compared up to a relative tolerance
+    fn matches(&self, other: &TestValue) -> bool {
+        fn all(a: &[TestValue], b: &[TestValue]) -> bool {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.matches(b))
+        }
+        match (self, other) {
+            (TestValue::Float(a), TestValue::Float(b)) => a == b || (a - b).abs() <= 1e-9 * a.abs().max(b.abs()),
+            (TestValue::Tuple(a), TestValue::Tuple(b)) | (TestValue::List(a), TestValue::List(b)) => all(a, b),
+            (TestValue::Record(a), TestValue::Record(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b).all(|((name_a, a), (name_b, b))| name_a == name_b && a.matches(b))
+            }
+            (TestValue::Constructor(name_a, a), TestValue::Constructor(name_b, b)) => name_a == name_b && all(a, b),
+            _ => self == other,
+        }
+    }
+}
+
+/// Source syntax for the value: `Some({ name: "a", age: 3 })`
+impl fmt::Display for TestValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join(values: &[TestValue]) -> String {
+            values.iter().map(TestValue::to_string).collect::<Vec<_>>().join(", ")
+        }
+        match self {
+            TestValue::Int(n) => write!(f, "{}", n),
+            TestValue::Float(x) => write!(f, "{:?}", x),
+            TestValue::Bool(b) => write!(f, "{}", b),
+            TestValue::String(s) => write!(f, "{}", LiteralValue::String(s.clone())),
+            TestValue::Unit => write!(f, "()"),
+            TestValue::Tuple(elements) => write!(f, "({})", join(elements)),
+            TestValue::Record(fields) => {
+                let fields: Vec<String> = fields.iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            TestValue::Constructor(name, args) if args.is_empty() => write!(f, "{}", name),
+            TestValue::Constructor(name, args) => write!(f, "{}({})", name, join(args)),
+            TestValue::List(elements) => write!(f, "[{}]", join(elements)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+    /// The function returned something other than the expected value
+    Failed { expected: TestValue, actual: TestValue },
+    /// The case could not be run: unparsable input, wrong argument count,
+    /// a type the runner cannot read back, ...
+    Error(String),
+}
+
+/// Outcome of one `@test_case`
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub function: String,
+    /// Position among the function's test cases
+    pub index: usize,
+    /// `input` as written in the annotation
+    pub input: Option<String>,
+    pub outcome: TestOutcome,
+    /// Time spent executing the compiled function
+    pub duration: Duration,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.outcome == TestOutcome::Passed
+    }
+}
+
+/// `✓ factorial(5) (0.002ms)`, `✗ add(2, 2): expected 5, got 4 (0.001ms)`
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let call = call_syntax(&self.function, self.input.as_deref());
+        let millis = self.duration.as_secs_f64() * 1000.0;
+        match &self.outcome {
+            TestOutcome::Passed => write!(f, "✓ {} ({:.3}ms)", call, millis),
+            TestOutcome::Failed { expected, actual } => {
+                write!(f, "✗ {}: expected {}, got {} ({:.3}ms)", call, expected, actual, millis)
+            }
+            TestOutcome::Error(message) => write!(f, "✗ {}: {}", call, message),
+        }
+    }
+}
+
+// `f(input)` as the report shows a case
+pub(crate) fn call_syntax(function: &str, input: Option<&str>) -> String {
+    match input {
+        None => format!("{}()", function),
+        Some(input) if input.starts_with('(') && input.ends_with(')') => format!("{}{}", function, input),
+        Some(input) => format!("{}({})", function, input),
+    }
+}
+
+/// Results of every `@test_case` in a program, in declaration order
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+    /// Time spent compiling the program and the test thunks
+    pub compile_time: Duration,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|result| result.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            writeln!(f, "{}", result)?;
+        }
+        write!(
+            f,
+            "{} passed, {} failed (compiled in {:.1}ms)",
+            self.passed(),
+            self.failed(),
+            self.compile_time.as_secs_f64() * 1000.0,
+        )
+    }
+}
+
+// ============================================================================
+// Running
+// ============================================================================
+
+// A test case ready to compile: the thunk calling the function, and the
+// value it should return
+pub(crate) struct PreparedCase {
+    // Index into the report's results
+    pub(crate) result: usize,
+    pub(crate) thunk: FunctionDef,
+    pub(crate) expected: TestValue,
+}
+
+/// Compile the program and run every `@test_case(input=..., expected=...)`
+/// annotation on it. Several arguments are given as a tuple,
+/// `input=(3, 4)`, and record values as `{name="a", age=3}`. A case that
+/// crashes (a failed contract check, a bad memory access) takes the whole
+/// process down with it.
+pub fn run_test_cases(program: &Program) -> Result<TestReport, CodegenError> {
+    let program = flatten_program(program).map_err(CodegenError::from)?;
+    let types = TypeTable::new(&program);
+
+    let mut results = Vec::new();
+    let mut cases = Vec::new();
+    for function in &program.func_defs {
+        let annotations = function.annotations.iter().filter(|annotation| annotation.name == "test_case");
+        for (index, annotation) in annotations.enumerate() {
+            let input = annotation_arg(annotation, "input");
+            let outcome = match prepare_case(function, annotation, results.len(), &types) {
+                Ok(case) => {
+                    cases.push(case);
+                    TestOutcome::Passed
+                }
+                Err(message) => TestOutcome::Error(message),
+            };
+            results.push(TestResult {
+                function: function.name.clone(),
+                index,
+                input: input.map(str::to_string),
+                outcome,
+                duration: Duration::ZERO,
+            });
+        }
+    }
+
+    let compile_time = run_prepared(&program, &types, cases, &mut results)?;
+    Ok(TestReport { results, compile_time })
+}
+
+// Compile the program with the cases' thunks, run them and record in
+// `results` how what they return compares with what is expected; returns
+// the compile time. Cases whose thunks do not compile are marked as errors.
+pub(crate) fn run_prepared(
+    program: &Program,
+    types: &TypeTable,
+    mut cases: Vec<PreparedCase>,
+    results: &mut [TestResult],
+) -> Result<Duration, CodegenError> {
+    let start = Instant::now();
+    let context = Context::create();
+    let arena = Bump::new();
+    let mut codegen = CodeGen::new(&context, "test_cases", &arena);
+    if codegen.compile_program(&with_thunks(program, &cases)).is_err() {
+        // Find the cases whose thunks break compilation, e.g. with inputs of
+        // the wrong type; errors in the program itself are reported as such
+        let scratch = Context::create();
+        let scratch_arena = Bump::new();
+        CodeGen::new(&scratch, "program", &scratch_arena).compile_program(program)?;
+        cases.retain(|case| {
+            let scratch = Context::create();
+            let scratch_arena = Bump::new();
+            let single = with_thunks(program, std::slice::from_ref(case));
+            let compiled = CodeGen::new(&scratch, "test_case", &scratch_arena).compile_program(&single);
+            match compiled {
+                Ok(()) => true,
+                Err(error) => {
+                    results[case.result].outcome = TestOutcome::Error(error.message);
+                    false
+                }
+            }
+        });
+        codegen = CodeGen::new(&context, "test_cases", &arena);
+        codegen.compile_program(&with_thunks(program, &cases))?;
+    }
+    add_result_writers(&context, &codegen, &cases).map_err(|message| CodegenError::new(message, None))?;
+
+    let engine = codegen.get_module()
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .map_err(|e| CodegenError::new(format!("Failed to create execution engine: {}", e), None))?;
+    let compile_time = start.elapsed();
+
+    let reader = Reader {
+        context: &context,
+        codegen: &codegen,
+        target_data: engine.get_target_data(),
+        types,
+    };
+    for case in &cases {
+        let return_type = &case.thunk.return_type;
+        let size = reader.target_data.get_abi_size(&codegen.llvm_type(return_type)) as usize;
+        let mut buffer = vec![0u64; size.div_ceil(8).max(1)];
+
+        let writer = unsafe {
+            engine.get_function::<unsafe extern "C" fn(*mut u8)>(&writer_name(&case.thunk.name))
+        };
+        let result = &mut results[case.result];
+        let writer = match writer {
+            Ok(writer) => writer,
+            Err(e) => {
+                result.outcome = TestOutcome::Error(format!("Failed to get compiled test case: {}", e));
+                continue;
+            }
+        };
+        let start = Instant::now();
+        unsafe { writer.call(buffer.as_mut_ptr().cast()) };
+        result.duration = start.elapsed();
+
+        result.outcome = match unsafe { reader.read(buffer.as_ptr().cast(), return_type) } {
+            Ok(actual) if case.expected.matches(&actual) => TestOutcome::Passed,
+            Ok(actual) => TestOutcome::Failed { expected: case.expected.clone(), actual },
+            Err(message) => TestOutcome::Error(message),
+        };
+    }
+
+    Ok(compile_time)
+}
+
+pub(crate) fn annotation_arg<'a>(annotation: &'a Annotation, key: &str) -> Option<&'a str> {
+    annotation.args.iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.as_str())
+}
+
+fn prepare_case(
+    function: &FunctionDef,
+    annotation: &Annotation,
+    result: usize,
+    types: &TypeTable,
+) -> Result<PreparedCase, String> {
+    let thunk = case_thunk(function, annotation_arg(annotation, "input"), format!("__test_case_{}", result))?;
+    let expected = annotation_arg(annotation, "expected").ok_or("@test_case has no expected value")?;
+    let expected = types.value_of(&parse_test_value(expected)?, &function.return_type)?;
+    Ok(PreparedCase { result, thunk, expected })
+}
+
+// The zero-argument function `name` calling `function` on `input`, an
+// annotation value giving several arguments as a tuple
+pub(crate) fn case_thunk(function: &FunctionDef, input: Option<&str>, name: String) -> Result<FunctionDef, String> {
+    if !function.type_params.is_empty() {
+        return Err("test cases of generic functions are not supported".to_string());
+    }
+    let args = match input {
+        None => vec![],
+        Some(input) => match (function.params.len(), parse_test_value(input)?) {
+            (1, input) => vec![input],
+            (count, Expr::Tuple(tuple)) if count > 1 => tuple.elements,
+            (_, input) => vec![input],
+        },
+    };
+    if args.len() != function.params.len() {
+        return Err(format!(
+            "'{}' takes {} argument(s), but the input gives {}",
+            function.name,
+            function.params.len(),
+            args.len(),
+        ));
+    }
+
+    // `f()` is `f` applied to unit, as the parser builds it
+    let unit = Expr::Literal(Literal {
+        value: LiteralValue::Unit,
+        type_name: "Unit".to_string(),
+        span: Span::default(),
+    });
+    let callee = Expr::Variable(Variable { name: function.name.clone(), span: Span::default() });
+    let args = if args.is_empty() { vec![unit] } else { args };
+    let body = args.into_iter().fold(callee, |func, arg| {
+        Expr::Application(Application { func: Box::new(func), arg: Box::new(arg), span: Span::default() })
+    });
+
+    Ok(FunctionDef {
+        name,
+        type_params: vec![],
+        params: vec![],
+        return_type: function.return_type.clone(),
+        requires: vec![],
+        ensures: vec![],
+        body,
+        annotations: vec![],
+        span: function.span,
+    })
+}
+
+// Parse an annotation value; records there are written `{name="a", age=3}`,
+// so a lone `=` outside string literals stands for `:`
+fn parse_test_value(source: &str) -> Result<Expr, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut converted = String::with_capacity(source.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            converted.push(c);
+            continue;
+        }
+        let before = i.checked_sub(1).map(|j| chars[j]);
+        let after = chars.get(i + 1);
+        let lone_equals = c == '='
+            && !matches!(before, Some('=' | '!' | '<' | '>'))
+            && !matches!(after, Some('=' | '>'));
+        in_string = c == '"';
+        converted.push(if lone_equals { ':' } else { c });
+    }
+    parse_expression(&converted)
+}
+
+fn with_thunks(program: &Program, cases: &[PreparedCase]) -> Program {
+    let mut program = program.clone();
+    program.func_defs.extend(cases.iter().map(|case| case.thunk.clone()));
+    program
+}
+
+pub(crate) fn writer_name(thunk: &str) -> String {
+    format!("{}_write", thunk)
+}
+
+fn add_result_writers<'ctx>(
+    context: &'ctx Context,
+    codegen: &CodeGen<'ctx, '_>,
+    cases: &[PreparedCase],
+) -> Result<(), String> {
+    for case in cases {
+        add_result_writer(context, codegen, &case.thunk.name)?;
+    }
+    Ok(())
+}
+
+// For a thunk `t`, `void t_write(ptr out)` storing what `t` returns at
+// `out`, so values of any type come back through one calling convention
+pub(crate) fn add_result_writer<'ctx>(context: &'ctx Context, codegen: &CodeGen<'ctx, '_>, thunk_name: &str) -> Result<(), String> {
+    let module = codegen.get_module();
+    let builder = context.create_builder();
+    let out_type = context.i8_type().ptr_type(AddressSpace::default());
+    let writer_type = context.void_type().fn_type(&[out_type.into()], false);
+
+    let thunk = module.get_function(thunk_name)
+        .ok_or_else(|| format!("Thunk '{}' was not compiled", thunk_name))?;
+    let writer = module.add_function(&writer_name(thunk_name), writer_type, None);
+    builder.position_at_end(context.append_basic_block(writer, "entry"));
+
+    let value = builder.build_call(thunk, &[], "result")
+        .map_err(|e| e.to_string())?
+        .try_as_basic_value()
+        .left()
+        .ok_or_else(|| format!("Thunk '{}' returns no value", thunk_name))?;
+    let out = writer.get_first_param().unwrap().into_pointer_value();
+    let slot = builder.build_pointer_cast(out, value.get_type().ptr_type(AddressSpace::default()), "slot")
+        .map_err(|e| e.to_string())?;
+    builder.build_store(slot, value).map_err(|e| e.to_string())?;
+    builder.build_return(None).map_err(|e| e.to_string())?;
+
+    if !writer.verify(true) {
+        return Err(format!("Function verification failed: {}", writer_name(thunk_name)));
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Types and Values
+// ============================================================================
+
+// Named types of the program
+pub(crate) struct TypeTable<'p> {
+    defs: HashMap<&'p str, &'p TypeDefKind>,
+}
+
+impl<'p> TypeTable<'p> {
+    pub(crate) fn new(program: &'p Program) -> Self {
+        TypeTable {
+            defs: program.type_defs.iter()
+                .map(|type_def| (type_def.name.as_str(), &type_def.definition))
+                .collect(),
+        }
+    }
+
+    // The type an alias stands for
+    fn unalias<'t>(&'t self, mut ty: &'t Type) -> &'t Type {
+        while let Type::Basic(basic) = ty {
+            match self.defs.get(basic.name.as_str()) {
+                Some(TypeDefKind::Alias(target)) => ty = target,
+                _ => break,
+            }
+        }
+        ty
+    }
+
+    fn record(&self, ty: &Type) -> Option<&'p RecordType> {
+        match ty {
+            Type::Basic(basic) => match self.defs.get(basic.name.as_str()) {
+                Some(TypeDefKind::Record(record)) => Some(record),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn variants(&self, ty: &Type) -> Option<&'p [(String, Vec<Type>)]> {
+        match ty {
+            Type::Basic(basic) => match self.defs.get(basic.name.as_str()) {
+                Some(TypeDefKind::Variant(variants)) => Some(variants),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Value of a literal expression at the given type
+    fn value_of(&self, expr: &Expr, ty: &Type) -> Result<TestValue, String> {
+        let ty = self.unalias(ty);
+        let mismatch = || format!("`{}` is not a value of type {}", expr, ty);
+        let basic = match ty {
+            Type::Basic(basic) => basic.name.as_str(),
+            _ => "",
+        };
+
+        match expr {
+            Expr::Literal(lit) => match (&lit.value, basic) {
+                (LiteralValue::Int(n), "Int" | "Nat") => Ok(TestValue::Int(*n)),
+                (LiteralValue::Int(n), "Float64") => Ok(TestValue::Float(*n as f64)),
+                (LiteralValue::Float(x), "Float64") => Ok(TestValue::Float(*x)),
+                (LiteralValue::Bool(b), "Bool") => Ok(TestValue::Bool(*b)),
+                (LiteralValue::String(s), "String") => Ok(TestValue::String(s.clone())),
+                (LiteralValue::Unit, "Unit") => Ok(TestValue::Unit),
+                _ => Err(mismatch()),
+            },
+            Expr::UnaryOp(unop) if unop.op == "-" => match self.value_of(&unop.operand, ty)? {
+                TestValue::Int(n) => Ok(TestValue::Int(n.wrapping_neg())),
+                TestValue::Float(x) => Ok(TestValue::Float(-x)),
+                _ => Err(mismatch()),
+            },
+            Expr::Tuple(tuple) => match ty {
+                Type::Tuple(tuple_type) if tuple_type.element_types.len() == tuple.elements.len() => {
+                    let elements = tuple.elements.iter()
+                        .zip(&tuple_type.element_types)
+                        .map(|(element, element_type)| self.value_of(element, element_type))
+                        .collect::<Result<_, _>>()?;
+                    Ok(TestValue::Tuple(elements))
+                }
+                _ => Err(mismatch()),
+            },
+            Expr::Record(record) => {
+                let record_type = self.record(ty).ok_or_else(mismatch)?;
+                if let Some((extra, _)) = record.fields.iter()
+                    .find(|(name, _)| !record_type.fields.iter().any(|(field, _)| field == name))
+                {
+                    return Err(format!("{} has no field '{}'", ty, extra));
+                }
+                let fields = record_type.fields.iter()
+                    .map(|(name, field_type)| {
+                        let (_, value) = record.fields.iter()
+                            .find(|(field, _)| field == name)
+                            .ok_or_else(|| format!("Missing field '{}' in `{}`", name, expr))?;
+                        Ok((name.clone(), self.value_of(value, field_type)?))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok(TestValue::Record(fields))
+            }
+            Expr::Constructor(constructor) => self.constructor_value(&constructor.name, &constructor.args, ty, expr),
+            Expr::Variable(var) => self.constructor_value(&var.name, &[], ty, expr),
+            Expr::Application(_) => {
+                let mut func = expr;
+                let mut args = Vec::new();
+                while let Expr::Application(app) = func {
+                    args.push((*app.arg).clone());
+                    func = &app.func;
+                }
+                args.reverse();
+                match func {
+                    Expr::Variable(var) => self.constructor_value(&var.name, &args, ty, expr),
+                    _ => Err(mismatch()),
+                }
+            }
+            _ => Err(mismatch()),
+        }
+    }
+
+    // Types of the arguments constructor `name` of type `ty` takes
+    fn payload(&self, name: &str, ty: &Type) -> Option<Vec<Type>> {
+        match (name, ty) {
+            ("Some", Type::Option(option)) => Some(vec![(*option.inner).clone()]),
+            ("None", Type::Option(_)) => Some(vec![]),
+            ("Ok", Type::Result(result)) => Some(vec![(*result.ok_type).clone()]),
+            ("Err", Type::Result(result)) => Some(vec![(*result.err_type).clone()]),
+            _ => self.variants(ty)
+                .and_then(|variants| variants.iter().find(|(constructor, _)| constructor == name))
+                .map(|(_, payload)| payload.clone()),
+        }
+    }
+
+    fn constructor_value(&self, name: &str, args: &[Expr], ty: &Type, expr: &Expr) -> Result<TestValue, String> {
+        if let ("List", Type::List(list)) = (name, ty) {
+            let elements = args.iter()
+                .map(|arg| self.value_of(arg, &list.element_type))
+                .collect::<Result<_, _>>()?;
+            return Ok(TestValue::List(elements));
+        }
+        let payload = self.payload(name, ty)
+            .ok_or_else(|| format!("`{}` is not a value of type {}", expr, ty))?;
+        if args.len() != payload.len() {
+            return Err(format!("Constructor {} takes {} argument(s), got {}", name, payload.len(), args.len()));
+        }
+        let args = args.iter()
+            .zip(&payload)
+            .map(|(arg, arg_type)| self.value_of(arg, arg_type))
+            .collect::<Result<_, _>>()?;
+        Ok(TestValue::Constructor(name.to_string(), args))
+    }
+
+    // What the interpreter gave as a value of type `ty`: record fields in
+    // declaration order, integers where floats are expected as floats
+    pub(crate) fn interpreted(&self, value: &Value, ty: &Type) -> Result<TestValue, String> {
+        let ty = self.unalias(ty);
+        let mismatch = || format!("`{}` is not a value of type {}", value, ty);
+        let basic = match ty {
+            Type::Basic(basic) => basic.name.as_str(),
+            _ => "",
+        };
+        let all = |values: &[Value], types: &[Type]| -> Result<Vec<TestValue>, String> {
+            if values.len() != types.len() {
+                return Err(mismatch());
+            }
+            values.iter().zip(types).map(|(value, ty)| self.interpreted(value, ty)).collect()
+        };
+
+        match value {
+            // Functions returning Unit return it whatever their body gives
+            _ if basic == "Unit" => Ok(TestValue::Unit),
+            Value::Int(n) if basic == "Float64" => Ok(TestValue::Float(*n as f64)),
+            Value::Int(n) => Ok(TestValue::Int(*n)),
+            Value::Float(x) => Ok(TestValue::Float(*x)),
+            Value::Bool(b) => Ok(TestValue::Bool(*b)),
+            Value::String(s) => Ok(TestValue::String(s.to_string())),
+            Value::Tuple(elements) => match ty {
+                Type::Tuple(tuple_type) => all(elements, &tuple_type.element_types).map(TestValue::Tuple),
+                _ => Err(mismatch()),
+            },
+            Value::Record(fields) => {
+                let record_type = self.record(ty).ok_or_else(mismatch)?;
+                let fields = record_type.fields.iter()
+                    .map(|(name, field_type)| {
+                        let (_, value) = fields.iter()
+                            .find(|(field, _)| field == name)
+                            .ok_or_else(|| format!("Missing field '{}' in `{}`", name, value))?;
+                        Ok((name.clone(), self.interpreted(value, field_type)?))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok(TestValue::Record(fields))
+            }
+            Value::Constructor(name, args) => {
+                let payload = self.payload(name, ty).ok_or_else(mismatch)?;
+                Ok(TestValue::Constructor(name.to_string(), all(args, &payload)?))
+            }
+            Value::List(elements) => match ty {
+                Type::List(list) => elements.iter()
+                    .map(|element| self.interpreted(element, &list.element_type))
+                    .collect::<Result<_, _>>()
+                    .map(TestValue::List),
+                _ => Err(mismatch()),
+            },
+            Value::Unit => Err(mismatch()),
+            Value::HashMap(_) | Value::Function(_) | Value::Pointer(_) => {
+                Err(format!("Cannot compare values of type {}", ty))
+            }
+        }
+    }
+}
+
+// Reads values out of JIT memory, following the layouts CodeGen gives types
+pub(crate) struct Reader<'a, 'ctx, 'arena> {
+    pub(crate) context: &'ctx Context,
+    pub(crate) codegen: &'a CodeGen<'ctx, 'arena>,
+    pub(crate) target_data: &'a TargetData,
+    pub(crate) types: &'a TypeTable<'a>,
+}
+
+impl<'a, 'ctx, 'arena> Reader<'a, 'ctx, 'arena> {
+    // Byte offset of field `index` in a struct-represented type
+    fn offset(&self, ty: BasicTypeEnum<'ctx>, index: u32) -> usize {
+        self.target_data.offset_of_element(&ty.into_struct_type(), index).unwrap_or(0) as usize
+    }
+
+    // Values of `types` laid out as the fields of a struct
+    unsafe fn read_fields(&self, ptr: *const u8, types: &[Type]) -> Result<Vec<TestValue>, String> {
+        let field_types: Vec<BasicTypeEnum> = types.iter().map(|ty| self.codegen.llvm_type(ty)).collect();
+        let layout: BasicTypeEnum = self.context.struct_type(&field_types, false).into();
+        types.iter()
+            .enumerate()
+            .map(|(i, ty)| self.read(ptr.add(self.offset(layout, i as u32)), ty))
+            .collect()
+    }
+
+    // The value of type `ty` stored at `ptr`
+    pub(crate) unsafe fn read(&self, ptr: *const u8, ty: &Type) -> Result<TestValue, String> {
+        let ty = self.types.unalias(ty);
+        let layout = self.codegen.llvm_type(ty);
+        let tag = || ptr.cast::<i32>().read_unaligned();
+
+        match ty {
+            Type::Basic(basic) => match basic.name.as_str() {
+                "Int" | "Nat" => Ok(TestValue::Int(ptr.cast::<i64>().read_unaligned())),
+                "Float64" => Ok(TestValue::Float(ptr.cast::<f64>().read_unaligned())),
+                "Bool" => Ok(TestValue::Bool(ptr.read() != 0)),
+                "Unit" => Ok(TestValue::Unit),
+                "String" => {
+                    let data = ptr.cast::<*const u8>().read_unaligned();
+                    let len = ptr.add(self.offset(layout, 1)).cast::<i64>().read_unaligned();
+                    let bytes = if len > 0 { std::slice::from_raw_parts(data, len as usize) } else { &[] };
+                    Ok(TestValue::String(String::from_utf8_lossy(bytes).into_owned()))
+                }
+                _ => {
+                    if let Some(record) = self.types.record(ty) {
+                        let field_types: Vec<Type> = record.fields.iter().map(|(_, ty)| ty.clone()).collect();
+                        let values = self.read_fields(ptr, &field_types)?;
+                        let names = record.fields.iter().map(|(name, _)| name.clone());
+                        return Ok(TestValue::Record(names.zip(values).collect()));
+                    }
+                    let variants = self.types.variants(ty)
+                        .ok_or_else(|| format!("Cannot read values of type {}", ty))?;
+                    // Variants without payloads are a bare i32 tag
+                    let (name, payload) = variants.get(tag() as usize)
+                        .ok_or_else(|| format!("Invalid {} tag {}", ty, tag()))?;
+                    let args = if layout.is_struct_type() {
+                        self.read_fields(ptr.add(self.offset(layout, 1)), payload)?
+                    } else {
+                        vec![]
+                    };
+                    Ok(TestValue::Constructor(name.clone(), args))
+                }
+            },
+            // { i32 tag, T value }, tag 0 = None
+            Type::Option(option) => Ok(match tag() {
+                0 => TestValue::Constructor("None".to_string(), vec![]),
+                _ => TestValue::Constructor(
+                    "Some".to_string(),
+                    vec![self.read(ptr.add(self.offset(layout, 1)), &option.inner)?],
+                ),
+            }),
+            // { i32 tag, T or E }, tag 0 = Err
+            Type::Result(result) => {
+                let (name, payload) = match tag() {
+                    0 => ("Err", &result.err_type),
+                    _ => ("Ok", &result.ok_type),
+                };
+                let value = self.read(ptr.add(self.offset(layout, 1)), payload)?;
+                Ok(TestValue::Constructor(name.to_string(), vec![value]))
+            }
+            Type::Tuple(tuple) => Ok(TestValue::Tuple(self.read_fields(ptr, &tuple.element_types)?)),
+            // { T* elements, i64 length }
+            Type::List(list) => {
+                let data = ptr.cast::<*const u8>().read_unaligned();
+                let len = ptr.add(self.offset(layout, 1)).cast::<i64>().read_unaligned();
+                let stride = self.target_data.get_abi_size(&self.codegen.llvm_type(&list.element_type)) as usize;
+                (0..len.max(0) as usize)
+                    .map(|i| self.read(data.add(i * stride), &list.element_type))
+                    .collect::<Result<_, _>>()
+                    .map(TestValue::List)
+            }
+            _ => Err(format!("Cannot read values of type {}", ty)),
+        }
+    }
+}
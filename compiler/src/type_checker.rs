@@ -1,10 +1,15 @@
 use std::collections::HashMap;
+use std::fmt;
 use crate::ast::*;
+use crate::exhaustiveness::{check_match, pattern_to_string, Con, Signature};
+use crate::tailrec::{is_tailrec, recursive_calls};
+use crate::linker::{flatten_program, LinkError};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TypeError {
     pub message: String,
     pub location: Option<String>,
+    pub span: Option<Span>,
 }
 
 impl TypeError {
@@ -12,6 +17,7 @@ impl TypeError {
         TypeError {
             message: message.into(),
             location: None,
+            span: None,
         }
     }
     
@@ -19,6 +25,46 @@ impl TypeError {
         TypeError {
             message: message.into(),
             location: Some(location.into()),
+            span: None,
+        }
+    }
+    
+    /// Error pointing at the source range of the offending node
+    pub fn at(message: impl Into<String>, span: Span) -> Self {
+        TypeError {
+            message: message.into(),
+            location: None,
+            span: Some(span),
+        }
+    }
+    
+    /// Format as `file:line:col: message` using the source the program was parsed from
+    pub fn render(&self, file: &str, source: &str) -> String {
+        match self.span {
+            Some(span) => {
+                let (line, col) = span.line_col(source);
+                format!("{}:{}:{}: {}", file, line, col, self.message)
+            }
+            None => format!("{}: {}", file, self.message),
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{} (at {}..{})", self.message, span.start, span.end),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<LinkError> for TypeError {
+    fn from(error: LinkError) -> Self {
+        TypeError {
+            message: error.message,
+            location: error.file,
+            span: error.span,
         }
     }
 }
@@ -45,12 +91,57 @@ impl TypeCheckResult {
     }
 }
 
+/// Types of variables, by the qualified name of the function they appear
+/// in: each variable use and pattern variable's span with its type, for
+/// editor hovers
+pub type VariableTypes = HashMap<String, Vec<(Span, Type)>>;
+
+/// A type with some of its variables universally quantified, as in
+/// `forall a. List<a> -> a -> List<a>`. Each use instantiates `vars` afresh.
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<TypeVar>,
+    ty: Type,
+}
+
+impl Scheme {
+    fn mono(ty: Type) -> Self {
+        Scheme { vars: vec![], ty }
+    }
+}
+
+/// The types an operator takes: `+` numeric ones, `<<` integers, `&` either
+/// integers or Bool
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OperandKind {
+    Numeric,
+    Integer,
+    Bitwise,
+}
+
+// An operator applied to operands whose type was still unknown
+#[derive(Debug, Clone)]
+struct OperandConstraint {
+    ty: Type,
+    kind: OperandKind,
+    op: String,
+    span: Span,
+}
+
 pub struct TypeChecker {
     program: Program,
-    type_env: HashMap<String, Type>,
-    function_types: HashMap<String, FunctionType>,
+    type_env: HashMap<String, Scheme>,
+    function_types: HashMap<String, Scheme>,
     custom_types: HashMap<String, TypeDef>,
+    // What each type variable has been unified with, indexed by id
+    substitution: Vec<Option<Type>>,
     errors: Vec<TypeError>,
+    // Variables of the function being checked, when recording their types
+    recorded: Option<Vec<(Span, Type)>>,
+    // Operand types still to check once inference has resolved them
+    constraints: Vec<OperandConstraint>,
+    // Parameter counts of @variadic externs, which take any further arguments
+    variadic: HashMap<String, usize>,
 }
 
 impl TypeChecker {
@@ -60,7 +151,11 @@ impl TypeChecker {
             type_env: HashMap::new(),
             function_types: HashMap::new(),
             custom_types: HashMap::new(),
+            substitution: Vec::new(),
             errors: Vec::new(),
+            recorded: None,
+            constraints: Vec::new(),
+            variadic: HashMap::new(),
         };
         
         checker.initialize_builtins();
@@ -71,81 +166,64 @@ impl TypeChecker {
     }
     
     fn initialize_builtins(&mut self) {
-        // Helper to create a polymorphic type variable (we'll use "Unknown" to mean "any type")
-        // In a real implementation, we'd need proper type variable support
+        // `a` and `e` are quantified in every builtin below, so each use of a
+        // builtin gets its own element type
+        let a = self.fresh_var();
+        let e = self.fresh_var();
+        let list_a = Type::List(ListType { element_type: Box::new(a.clone()) });
+        let list_list_a = Type::List(ListType { element_type: Box::new(list_a.clone()) });
+        let option_a = Type::Option(OptionType { inner: Box::new(a.clone()) });
+        let result_ae = Type::Result(ResultType {
+            ok_type: Box::new(a.clone()),
+            err_type: Box::new(e.clone()),
+        });
+        let param = |name: &str, ty: &Type| (name.to_string(), ty.clone());
         
-        // List operations - curried form
-        // list_get: List<T> -> Int -> T -> T
-        // First application: List<T> -> (Int -> (T -> T))
-        let list_get_type = FunctionType {
-            param_type: Box::new(Type::List(ListType {
-                element_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-            })),
-            return_type: Box::new(Type::Function(FunctionType {
-                param_type: Box::new(Type::Basic(BasicType { name: "Int".to_string() })),
-                return_type: Box::new(Type::Function(FunctionType {
-                    param_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-                    return_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-                    effect: None,
-                })),
-                effect: None,
-            })),
-            effect: None,
-        };
-        self.function_types.insert("list_get".to_string(), list_get_type);
-
-        // list_set: List<T> -> Int -> T -> List<T>
-        let list_set_type = FunctionType {
-            param_type: Box::new(Type::List(ListType {
-                element_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-            })),
-            return_type: Box::new(Type::Function(FunctionType {
-                param_type: Box::new(Type::Basic(BasicType { name: "Int".to_string() })),
-                return_type: Box::new(Type::Function(FunctionType {
-                    param_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-                    return_type: Box::new(Type::List(ListType {
-                        element_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-                    })),
-                    effect: None,
-                })),
-                effect: None,
-            })),
-            effect: None,
-        };
-        self.function_types.insert("list_set".to_string(), list_set_type);
-
-        // list_push: List<T> -> T -> List<T>
-        let list_push_type = FunctionType {
-            param_type: Box::new(Type::List(ListType {
-                element_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-            })),
-            return_type: Box::new(Type::Function(FunctionType {
-                param_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-                return_type: Box::new(Type::List(ListType {
-                    element_type: Box::new(Type::Basic(BasicType { name: "Unknown".to_string() })),
-                })),
-                effect: None,
-            })),
-            effect: None,
-        };
-        self.function_types.insert("list_push".to_string(), list_push_type);
-
-        // Type conversions
-        // int_to_float: Int -> Float64
-        let int_to_float_type = FunctionType {
-            param_type: Box::new(Type::Basic(BasicType { name: "Int".to_string() })),
-            return_type: Box::new(Type::Basic(BasicType { name: "Float64".to_string() })),
-            effect: None,
-        };
-        self.function_types.insert("int_to_float".to_string(), int_to_float_type);
-
-        // float_to_int: Float64 -> Int
-        let float_to_int_type = FunctionType {
-            param_type: Box::new(Type::Basic(BasicType { name: "Float64".to_string() })),
-            return_type: Box::new(Type::Basic(BasicType { name: "Int".to_string() })),
-            effect: None,
-        };
-        self.function_types.insert("float_to_int".to_string(), float_to_int_type);
+        let builtins = [
+            // list_new: Unit -> List<a>
+            ("list_new", vec![param("unit", &basic_type("Unit"))], list_a.clone()),
+            // list_get: List<a> -> Int -> a -> a, the last argument being the default
+            ("list_get", vec![param("list", &list_a), param("index", &basic_type("Int")), param("default", &a)], a.clone()),
+            // list_set: List<a> -> Int -> a -> List<a>
+            ("list_set", vec![param("list", &list_a), param("index", &basic_type("Int")), param("value", &a)], list_a.clone()),
+            // list_push: List<a> -> a -> List<a>
+            ("list_push", vec![param("list", &list_a), param("value", &a)], list_a.clone()),
+            // List_get: List<a> -> Int -> a, without a default
+            ("List_get", vec![param("list", &list_a), param("index", &basic_type("Int"))], a.clone()),
+            ("List_set", vec![param("list", &list_a), param("index", &basic_type("Int")), param("value", &a)], list_a.clone()),
+            ("List_push", vec![param("list", &list_a), param("value", &a)], list_a.clone()),
+            // List_length: List<a> -> Nat
+            ("List_length", vec![param("list", &list_a)], basic_type("Nat")),
+            // List_concat: List<List<a>> -> List<a>
+            ("List_concat", vec![param("lists", &list_list_a)], list_a.clone()),
+            // String_length: String -> Nat
+            ("String_length", vec![param("text", &basic_type("String"))], basic_type("Nat")),
+            // String_contains: String -> String -> Bool
+            ("String_contains", vec![param("text", &basic_type("String")), param("part", &basic_type("String"))], basic_type("Bool")),
+            // Maps have Int keys and values, and are updated in place; HashMap_new
+            // takes the capacity and HashMap_get gives 0 for missing keys
+            ("HashMap_new", vec![param("capacity", &basic_type("Nat"))], basic_type("HashMap")),
+            ("HashMap_put", vec![param("map", &basic_type("HashMap")), param("key", &basic_type("Int")), param("value", &basic_type("Int"))], basic_type("Unit")),
+            ("HashMap_get", vec![param("map", &basic_type("HashMap")), param("key", &basic_type("Int"))], basic_type("Int")),
+            ("HashMap_size", vec![param("map", &basic_type("HashMap"))], basic_type("Nat")),
+            ("Some", vec![param("value", &a)], option_a.clone()),
+            ("Ok", vec![param("value", &a)], result_ae.clone()),
+            ("Err", vec![param("error", &e)], result_ae),
+            // print, println: String -> Unit, writing to stdout
+            ("print", vec![param("text", &basic_type("String"))], basic_type("Unit")),
+            ("println", vec![param("text", &basic_type("String"))], basic_type("Unit")),
+            // Type conversions
+            ("int_to_float", vec![param("value", &basic_type("Int"))], basic_type("Float64")),
+            ("float_to_int", vec![param("value", &basic_type("Float64"))], basic_type("Int")),
+        ];
+        
+        for (name, params, return_type) in builtins {
+            let scheme = self.generalize(&Type::Function(curried_type(&params, &return_type)));
+            self.function_types.insert(name.to_string(), scheme);
+        }
+        
+        let none_scheme = self.generalize(&option_a);
+        self.type_env.insert("None".to_string(), none_scheme);
     }
     
     fn collect_type_definitions(&mut self) {
@@ -154,40 +232,18 @@ impl TypeChecker {
             
             // Register variant constructors
             if let TypeDefKind::Variant(variants) = &type_def.definition {
+                let variant_type = basic_type(&type_def.name);
                 for (variant_name, param_types) in variants {
                     if param_types.is_empty() {
                         // Nullary constructor: South is a value of type Direction
-                        // Register it in type_env as a constant value
-                        self.type_env.insert(
-                            variant_name.clone(),
-                            Type::Basic(BasicType {
-                                name: type_def.name.clone(),
-                            }),
-                        );
+                        self.type_env.insert(variant_name.clone(), Scheme::mono(variant_type.clone()));
                     } else {
-                        // Constructor with parameters: Some(T) -> T -> Option<T>
-                        // Build curried function type
-                        let mut result_type = Type::Basic(BasicType {
-                            name: type_def.name.clone(),
-                        });
-                        
-                        // Build curried type from right to left
-                        for param_type in param_types.iter().skip(1).rev() {
-                            result_type = Type::Function(FunctionType {
-                                param_type: Box::new(param_type.clone()),
-                                return_type: Box::new(result_type),
-                                effect: None,
-                            });
-                        }
-                        
-                        // Wrap with first parameter
-                        let constructor_type = FunctionType {
-                            param_type: Box::new(param_types[0].clone()),
-                            return_type: Box::new(result_type),
-                            effect: None,
-                        };
-                        
-                        self.function_types.insert(variant_name.clone(), constructor_type);
+                        // Constructor with parameters: Circle(Float64) is Float64 -> Shape
+                        let params: Vec<(String, Type)> = param_types.iter()
+                            .map(|ty| (String::new(), ty.clone()))
+                            .collect();
+                        let constructor_type = Type::Function(curried_type(&params, &variant_type));
+                        self.function_types.insert(variant_name.clone(), Scheme::mono(constructor_type));
                     }
                 }
             }
@@ -195,57 +251,142 @@ impl TypeChecker {
     }
     
     fn collect_function_signatures(&mut self) {
-        for func_def in &self.program.func_defs {
-            let func_type = if func_def.params.is_empty() {
-                FunctionType {
-                    param_type: Box::new(Type::Basic(BasicType {
-                        name: "Unit".to_string(),
-                    })),
-                    return_type: Box::new(func_def.return_type.clone()),
-                    effect: None,
-                }
-            } else if func_def.params.len() == 1 {
-                // Single parameter - simple function type
-                FunctionType {
-                    param_type: Box::new(func_def.params[0].1.clone()),
-                    return_type: Box::new(func_def.return_type.clone()),
-                    effect: None,
-                }
-            } else {
-                // Multiple parameters - build curried function type
-                // params = [(x, Int), (y, String), (z, Bool)]
-                // result type = Int -> (String -> (Bool -> ReturnType))
-                let mut result_type = func_def.return_type.clone();
-                
-                // Iterate params in reverse to build nested function types
-                // Skip the first parameter - it will be the outermost param_type
-                for (_, param_type) in func_def.params.iter().skip(1).rev() {
-                    result_type = Type::Function(FunctionType {
-                        param_type: Box::new(param_type.clone()),
-                        return_type: Box::new(result_type),
-                        effect: None,
-                    });
-                }
-                
-                // Now wrap with the first parameter
-                FunctionType {
-                    param_type: Box::new(func_def.params[0].1.clone()),
-                    return_type: Box::new(result_type),
-                    effect: None,
-                }
+        for func_def in self.program.func_defs.clone() {
+            // `func id<T>(x: T) -> T` is `forall T. T -> T` to its callers
+            let vars: Vec<TypeVar> = func_def.type_params.iter().map(|_| self.fresh_type_var()).collect();
+            let bindings: HashMap<String, Type> = func_def.type_params.iter()
+                .cloned()
+                .zip(vars.iter().map(|var| Type::Var(*var)))
+                .collect();
+            let func_type = Type::Function(curried_type(&func_def.params, &func_def.return_type));
+            let ty = substitute_type_params(&func_type, &bindings);
+            self.function_types.insert(func_def.name.clone(), Scheme { vars, ty });
+        }
+        
+        for extern_func in &self.program.extern_funcs {
+            let func_type = curried_type(&extern_func.params, &extern_func.return_type);
+            self.function_types.insert(extern_func.name.clone(), Scheme::mono(Type::Function(func_type)));
+            if extern_func.annotations.iter().any(|annotation| annotation.name == "variadic") {
+                self.variadic.insert(extern_func.name.clone(), extern_func.params.len());
+            }
+        }
+    }
+    
+    /// Check every module declared `module M : S` against signature `S`.
+    /// Takes the program as written, before flattening.
+    pub fn check_signatures(&mut self, program: &Program) -> Vec<TypeError> {
+        let mut signatures = HashMap::new();
+        collect_signatures(program, &mut signatures);
+
+        let mut errors = vec![];
+        self.check_module_signatures(&program.modules, &signatures, &mut errors);
+        errors
+    }
+
+    fn check_module_signatures(
+        &mut self,
+        modules: &[ModuleDef],
+        signatures: &HashMap<String, SignatureDef>,
+        errors: &mut Vec<TypeError>,
+    ) {
+        for module in modules {
+            self.check_module_signatures(&module.body.modules, signatures, errors);
+
+            let Some(sig_name) = &module.signature else { continue };
+            let Some(signature) = signatures.get(sig_name) else {
+                errors.push(TypeError::at(
+                    format!("Unknown signature '{}' for module '{}'", sig_name, module.name),
+                    module.span,
+                ));
+                continue;
             };
-            
-            self.function_types.insert(func_def.name.clone(), func_type);
+
+            for item in &signature.items {
+                match item {
+                    SignatureItem::Func { name, func_type, span } => {
+                        let Some(func_def) = module.body.func_defs.iter().find(|f| &f.name == name) else {
+                            errors.push(TypeError::at(
+                                format!(
+                                    "Module '{}' does not implement '{}' required by signature '{}'",
+                                    module.name, name, sig_name
+                                ),
+                                *span,
+                            ));
+                            continue;
+                        };
+
+                        let actual = Type::Function(curried_type(&func_def.params, &func_def.return_type));
+                        if !self.unify(&actual, func_type) {
+                            errors.push(TypeError::at(
+                                format!(
+                                    "Module '{}' defines '{}' as {} but signature '{}' requires {}",
+                                    module.name,
+                                    name,
+                                    self.type_to_string(&actual),
+                                    sig_name,
+                                    self.type_to_string(func_type)
+                                ),
+                                func_def.span,
+                            ));
+                        }
+                    }
+                    SignatureItem::Type { name, span, .. } => {
+                        if !module.body.type_defs.iter().any(|t| &t.name == name) {
+                            errors.push(TypeError::at(
+                                format!(
+                                    "Module '{}' does not define type '{}' required by signature '{}'",
+                                    module.name, name, sig_name
+                                ),
+                                *span,
+                            ));
+                        }
+                    }
+                }
+            }
         }
     }
     
     pub fn check(mut self) -> TypeCheckResult {
+        self.check_functions();
+        self.into_result()
+    }
+    
+    /// `check`, also returning the type of every variable in the program
+    pub fn check_recording_types(mut self) -> (TypeCheckResult, VariableTypes) {
+        self.recorded = Some(vec![]);
+        let types = self.check_functions();
+        let types = types.into_iter()
+            .map(|(function, spans)| {
+                let spans = spans.into_iter().map(|(span, ty)| (span, self.apply_substitution(&ty))).collect();
+                (function, spans)
+            })
+            .collect();
+        (self.into_result(), types)
+    }
+    
+    // Types stay unresolved until every function has been checked
+    fn check_functions(&mut self) -> VariableTypes {
         self.errors.clear();
+        let mut types = VariableTypes::new();
         
         for func_def in self.program.func_defs.clone() {
             self.check_function(&func_def);
+            if is_tailrec(&func_def) {
+                for span in recursive_calls(&func_def).non_tail {
+                    self.errors.push(TypeError::at(
+                        format!("Recursive call to '{}' is not in tail position, but the function is @tailrec", func_def.name),
+                        span,
+                    ));
+                }
+            }
+            if let Some(recorded) = &mut self.recorded {
+                types.insert(func_def.name.clone(), std::mem::take(recorded));
+            }
         }
-        
+        types
+    }
+    
+    fn into_result(self) -> TypeCheckResult {
         if self.errors.is_empty() {
             TypeCheckResult::success()
         } else {
@@ -253,91 +394,113 @@ impl TypeChecker {
         }
     }
     
+    fn record(&mut self, span: Span, ty: &Type) {
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push((span, ty.clone()));
+        }
+    }
+    
+    /// Type of `expr` among the program's types, functions and builtins,
+    /// with no local variables in scope, as for an expression typed at a
+    /// REPL prompt. Type variables left unresolved, as in `[]`, stay in the
+    /// result, and record literals have `Type::Record` types.
+    pub fn infer_expression(mut self, expr: &Expr) -> Result<Type, Vec<TypeError>> {
+        self.errors.clear();
+        let ty = self.infer_type(expr);
+        self.check_constraints();
+        if self.errors.is_empty() {
+            Ok(self.apply_substitution(&ty))
+        } else {
+            Err(self.errors)
+        }
+    }
+    
     fn check_function(&mut self, func_def: &FunctionDef) {
         let mut local_env = self.type_env.clone();
         
+        // Inside the body, type parameters are fixed, opaque types: `T` only
+        // unifies with `T`
         for (param_name, param_type) in &func_def.params {
-            local_env.insert(param_name.clone(), param_type.clone());
+            local_env.insert(param_name.clone(), Scheme::mono(param_type.clone()));
         }
         
         let old_env = std::mem::replace(&mut self.type_env, local_env);
         
-        // Pass expected type to help with record literal inference
+        // Pass expected type to help with record and lambda inference
         let body_type = self.infer_type_with_hint(&func_def.body, Some(&func_def.return_type));
         
-        if !self.types_compatible(&body_type, &func_def.return_type) {
-            self.errors.push(TypeError::with_location(
+        if !self.unify(&body_type, &func_def.return_type) {
+            self.errors.push(TypeError::at(
                 format!(
                     "Function '{}' body type {} does not match declared return type {}",
                     func_def.name,
                     self.type_to_string(&body_type),
                     self.type_to_string(&func_def.return_type)
                 ),
-                func_def.name.clone(),
+                func_def.body.span(),
             ));
         }
+        self.check_constraints();
         
         self.type_env = old_env;
     }
     
     fn infer_type_with_hint(&mut self, expr: &Expr, expected: Option<&Type>) -> Type {
-        // Special handling for record literals with expected type
-        if let Expr::Record(record) = expr {
-            if let Some(expected_type) = expected {
-                // Resolve expected type to actual definition
-                let resolved = self.resolve_type(expected_type);
+        match expr {
+            Expr::Lambda(lambda) => self.infer_lambda(lambda, expected),
+            Expr::Record(record) => {
+                // Field types of the expected record reach lambdas in the literal
+                let expected_fields = match expected.map(|t| self.resolve_type(&self.apply_substitution(t))) {
+                    Some(Type::Record(expected_record)) => expected_record.fields,
+                    _ => vec![],
+                };
+                let fields: Vec<(String, Type)> = record.fields.iter()
+                    .map(|(name, expr)| {
+                        let hint = expected_fields.iter().find(|(field, _)| field == name).map(|(_, t)| t);
+                        (name.clone(), self.infer_type_with_hint(expr, hint))
+                    })
+                    .collect();
+                let record_type = Type::Record(RecordType { fields });
                 
-                if let Type::Record(expected_record) = resolved {
-                    // Check if record fields match expected type
-                    if self.record_fields_match(record, &expected_record) {
-                        // Return the expected type (not the inferred anonymous type)
-                        return expected_type.clone();
-                    }
+                // Report the literal as the named record type it was written for
+                match expected {
+                    Some(expected_type) if self.unify(&record_type, expected_type) => expected_type.clone(),
+                    _ => record_type,
                 }
             }
+            _ => self.infer_type(expr),
         }
-        
-        // For all other cases, use regular inference
-        self.infer_type(expr)
     }
     
-    fn record_fields_match(&mut self, record: &RecordExpr, expected: &RecordType) -> bool {
-        // Check if all expected fields are present with compatible types
-        if record.fields.len() != expected.fields.len() {
-            return false;
+    // Parameter types come from annotations, or else from the function type
+    // the context expects, or else are left for unification to find
+    fn infer_lambda(&mut self, lambda: &Lambda, expected: Option<&Type>) -> Type {
+        let mut expected = expected.map(|t| self.resolve_type(&self.apply_substitution(t)));
+        let mut param_types = vec![];
+        
+        for (name, annotation) in &lambda.params {
+            let hint = match &expected {
+                Some(Type::Function(ft)) => Some((*ft.param_type.clone(), *ft.return_type.clone())),
+                _ => None,
+            };
+            expected = hint.as_ref().map(|(_, ret)| self.resolve_type(ret));
+            
+            let param_type = match (annotation, hint) {
+                (Some(annotated), _) => annotated.clone(),
+                (None, Some((param, _))) => param,
+                (None, None) => self.fresh_var(),
+            };
+            param_types.push((name.clone(), param_type));
         }
         
-        for (expected_name, expected_type) in &expected.fields {
-            let mut found = false;
-            for (actual_name, actual_expr) in &record.fields {
-                if actual_name == expected_name {
-                    // Resolve expected type to handle custom types
-                    let resolved_expected = self.resolve_type(expected_type);
-                    
-                    // Recursively pass expected type for nested records
-                    let actual_type = self.infer_type_with_hint(actual_expr, Some(&resolved_expected));
-                    
-                    // For compatibility check, use resolved types
-                    if !self.types_compatible_resolved(&actual_type, expected_type) {
-                        return false;
-                    }
-                    found = true;
-                    break;
-                }
-            }
-            if !found {
-                return false;
-            }
+        let old_env = self.type_env.clone();
+        for (name, param_type) in &param_types {
+            self.type_env.insert(name.clone(), Scheme::mono(param_type.clone()));
         }
+        let body_type = self.infer_type_with_hint(&lambda.body, expected.as_ref());
+        self.type_env = old_env;
         
-        true
-    }
-    
-    fn types_compatible_resolved(&self, t1: &Type, t2: &Type) -> bool {
-        // Resolve both types and check compatibility
-        let resolved1 = self.resolve_type(t1);
-        let resolved2 = self.resolve_type(t2);
-        self.types_compatible(&resolved1, &resolved2)
+        Type::Function(curried_type(&param_types, &body_type))
     }
     
     fn infer_type(&mut self, expr: &Expr) -> Type {
@@ -345,16 +508,22 @@ impl TypeChecker {
             Expr::Literal(lit) => self.literal_type(lit),
             
             Expr::Variable(var) => {
-                if let Some(t) = self.type_env.get(&var.name) {
-                    t.clone()
-                } else if let Some(func_type) = self.function_types.get(&var.name) {
-                    Type::Function(func_type.clone())
-                } else {
-                    self.errors.push(TypeError::with_location(
-                        format!("Undefined variable '{}'", var.name),
-                        var.name.clone(),
-                    ));
-                    Type::Basic(BasicType { name: "Unknown".to_string() })
+                let scheme = self.type_env.get(&var.name)
+                    .or_else(|| self.function_types.get(&var.name))
+                    .cloned();
+                match scheme {
+                    Some(scheme) => {
+                        let ty = self.instantiate(&scheme);
+                        self.record(var.span, &ty);
+                        ty
+                    }
+                    None => {
+                        self.errors.push(TypeError::at(
+                            format!("Undefined variable '{}'", var.name),
+                            var.span,
+                        ));
+                        self.fresh_var()
+                    }
                 }
             }
             
@@ -363,23 +532,81 @@ impl TypeChecker {
                 let right_type = self.infer_type(&binop.right);
                 
                 match binop.op.as_str() {
-                    "+" | "-" | "*" | "/" => {
-                        if self.is_numeric_type(&left_type) && self.is_numeric_type(&right_type) {
-                            left_type
+                    "+" | "-" | "*" | "/" | "%" => {
+                        let operand_type = self.apply_substitution(&left_type);
+                        let numeric = matches!(operand_type, Type::Var(_)) || self.is_numeric_type(&operand_type);
+                        if numeric && self.unify(&left_type, &right_type) {
+                            self.constrain(&operand_type, OperandKind::Numeric, &binop.op, binop.span);
+                            operand_type
                         } else {
-                            self.errors.push(TypeError::new(format!(
+                            self.errors.push(TypeError::at(format!(
                                 "Binary operator '{}' requires numeric types, got {} and {}",
                                 binop.op,
                                 self.type_to_string(&left_type),
                                 self.type_to_string(&right_type)
-                            )));
-                            Type::Basic(BasicType { name: "Unknown".to_string() })
+                            ), binop.span));
+                            self.fresh_var()
                         }
                     }
-                    "==" | "!=" | "<" | ">" | "<=" | ">=" | "and" | "or" | "=>" => {
-                        Type::Basic(BasicType { name: "Bool".to_string() })
+                    "&" | "|" | "^" => {
+                        // Bitwise on Int and Nat, non-short-circuiting on Bool
+                        let operand_type = self.apply_substitution(&left_type);
+                        let bitwise = matches!(operand_type, Type::Var(_))
+                            || self.is_integer_type(&operand_type)
+                            || operand_type == basic_type("Bool");
+                        if bitwise && self.unify(&left_type, &right_type) {
+                            self.constrain(&operand_type, OperandKind::Bitwise, &binop.op, binop.span);
+                            operand_type
+                        } else {
+                            self.errors.push(TypeError::at(format!(
+                                "Operator '{}' requires Int or Bool operands, got {} and {}",
+                                binop.op,
+                                self.type_to_string(&left_type),
+                                self.type_to_string(&right_type)
+                            ), binop.span));
+                            self.fresh_var()
+                        }
+                    }
+                    "<<" | ">>" => {
+                        let operand_type = self.apply_substitution(&left_type);
+                        let integer = matches!(operand_type, Type::Var(_)) || self.is_integer_type(&operand_type);
+                        if integer && self.unify(&left_type, &right_type) {
+                            self.constrain(&operand_type, OperandKind::Integer, &binop.op, binop.span);
+                            operand_type
+                        } else {
+                            self.errors.push(TypeError::at(format!(
+                                "Operator '{}' requires Int operands, got {} and {}",
+                                binop.op,
+                                self.type_to_string(&left_type),
+                                self.type_to_string(&right_type)
+                            ), binop.span));
+                            self.fresh_var()
+                        }
+                    }
+                    "==" | "!=" | "<" | ">" | "<=" | ">=" => {
+                        if !self.unify(&left_type, &right_type) {
+                            self.errors.push(TypeError::at(format!(
+                                "Operator '{}' compares incompatible types {} and {}",
+                                binop.op,
+                                self.type_to_string(&left_type),
+                                self.type_to_string(&right_type)
+                            ), binop.span));
+                        }
+                        basic_type("Bool")
+                    }
+                    "and" | "or" | "&&" | "||" | "=>" => {
+                        let bool_type = basic_type("Bool");
+                        if !self.unify(&left_type, &bool_type) || !self.unify(&right_type, &bool_type) {
+                            self.errors.push(TypeError::at(format!(
+                                "Operator '{}' requires Bool operands, got {} and {}",
+                                binop.op,
+                                self.type_to_string(&left_type),
+                                self.type_to_string(&right_type)
+                            ), binop.span));
+                        }
+                        bool_type
                     }
-                    _ => Type::Basic(BasicType { name: "Unknown".to_string() }),
+                    _ => self.fresh_var(),
                 }
             }
             
@@ -388,41 +615,51 @@ impl TypeChecker {
                 
                 match unop.op.as_str() {
                     "-" => {
-                        if self.is_numeric_type(&operand_type) {
+                        let operand_type = self.apply_substitution(&operand_type);
+                        if matches!(operand_type, Type::Var(_)) || self.is_numeric_type(&operand_type) {
+                            self.constrain(&operand_type, OperandKind::Numeric, "-", unop.span);
                             operand_type
                         } else {
-                            self.errors.push(TypeError::new(format!(
+                            self.errors.push(TypeError::at(format!(
                                 "Unary operator '-' requires numeric type, got {}",
                                 self.type_to_string(&operand_type)
-                            )));
-                            Type::Basic(BasicType { name: "Unknown".to_string() })
+                            ), unop.span));
+                            self.fresh_var()
                         }
                     }
-                    "not" => Type::Basic(BasicType { name: "Bool".to_string() }),
-                    _ => Type::Basic(BasicType { name: "Unknown".to_string() }),
+                    "not" => {
+                        let bool_type = basic_type("Bool");
+                        if !self.unify(&operand_type, &bool_type) {
+                            self.errors.push(TypeError::at(format!(
+                                "Unary operator 'not' requires Bool, got {}",
+                                self.type_to_string(&operand_type)
+                            ), unop.span));
+                        }
+                        bool_type
+                    }
+                    _ => self.fresh_var(),
                 }
             }
             
             Expr::If(if_expr) => {
                 let cond_type = self.infer_type(&if_expr.condition);
                 
-                let bool_type = Type::Basic(BasicType { name: "Bool".to_string() });
-                if !self.types_compatible(&cond_type, &bool_type) {
-                    self.errors.push(TypeError::new(format!(
+                if !self.unify(&cond_type, &basic_type("Bool")) {
+                    self.errors.push(TypeError::at(format!(
                         "If condition must be Bool, got {}",
                         self.type_to_string(&cond_type)
-                    )));
+                    ), if_expr.condition.span()));
                 }
                 
                 let then_type = self.infer_type(&if_expr.then_branch);
                 let else_type = self.infer_type(&if_expr.else_branch);
                 
-                if !self.types_compatible(&then_type, &else_type) {
-                    self.errors.push(TypeError::new(format!(
+                if !self.unify(&then_type, &else_type) {
+                    self.errors.push(TypeError::at(format!(
                         "If branches have incompatible types: {} and {}",
                         self.type_to_string(&then_type),
                         self.type_to_string(&else_type)
-                    )));
+                    ), if_expr.else_branch.span()));
                 }
                 
                 then_type
@@ -430,9 +667,12 @@ impl TypeChecker {
             
             Expr::Let(let_expr) => {
                 let value_type = self.infer_type(&let_expr.value);
+                // `let id = \x -> x in ...` may use `id` at several types,
+                // but `let add = \x y -> x + y in ...` at one, Int or Float64
+                let scheme = self.generalize_unconstrained(&value_type);
                 
                 let old_env = self.type_env.clone();
-                self.type_env.insert(let_expr.var_name.clone(), value_type);
+                self.type_env.insert(let_expr.var_name.clone(), scheme);
                 
                 let body_type = self.infer_type(&let_expr.body);
                 
@@ -442,59 +682,113 @@ impl TypeChecker {
             }
             
             Expr::Match(match_expr) => {
-                let _scrutinee_type = self.infer_type(&match_expr.scrutinee);
+                let scrutinee_type = self.infer_type(&match_expr.scrutinee);
                 
                 if match_expr.arms.is_empty() {
-                    self.errors.push(TypeError::new("Match expression must have at least one arm"));
-                    return Type::Basic(BasicType { name: "Unknown".to_string() });
+                    self.errors.push(TypeError::at("Match expression must have at least one arm", match_expr.span));
+                    return self.fresh_var();
                 }
                 
                 let mut first_arm_type: Option<Type> = None;
+                let mut patterns_typed = true;
                 
-                for (_pattern, body) in &match_expr.arms {
-                    let arm_type = self.infer_type(body);
+                for arm in &match_expr.arms {
+                    let old_env = self.type_env.clone();
+                    let error_count = self.errors.len();
+                    self.bind_pattern(&arm.pattern, &scrutinee_type);
+                    patterns_typed &= self.errors.len() == error_count;
+                    if let Some(guard) = &arm.guard {
+                        let guard_type = self.infer_type(guard);
+                        if !self.unify(&guard_type, &basic_type("Bool")) {
+                            self.errors.push(TypeError::at(format!(
+                                "Match guard must be Bool, got {}",
+                                self.type_to_string(&guard_type)
+                            ), guard.span()));
+                        }
+                    }
+                    let arm_type = self.infer_type(&arm.body);
+                    self.type_env = old_env;
                     
                     match &first_arm_type {
                         None => first_arm_type = Some(arm_type),
                         Some(first_type) => {
-                            if !self.types_compatible(&arm_type, first_type) {
-                                self.errors.push(TypeError::new(format!(
+                            if !self.unify(&arm_type, first_type) {
+                                self.errors.push(TypeError::at(format!(
                                     "Match arms have incompatible types: {} and {}",
                                     self.type_to_string(first_type),
                                     self.type_to_string(&arm_type)
-                                )));
+                                ), arm.body.span()));
                             }
                         }
                     }
                 }
                 
-                first_arm_type.unwrap_or(Type::Basic(BasicType { name: "Unknown".to_string() }))
+                // Coverage is only meaningful once every pattern fits the scrutinee
+                if patterns_typed {
+                    self.check_coverage(match_expr, &scrutinee_type);
+                }
+                
+                first_arm_type.unwrap_or_else(|| self.fresh_var())
             }
             
+            Expr::Application(app) if self.is_variadic_call(app) => self.infer_variadic_call(app),
+            
             Expr::Application(app) => {
                 let func_type = self.infer_type(&app.func);
-                let arg_type = self.infer_type(&app.arg);
+                let func_type = self.resolve_type(&self.apply_substitution(&func_type));
                 
-                if let Type::Function(ft) = func_type {
-                    if !self.types_compatible(&arg_type, &ft.param_type) {
-                        self.errors.push(TypeError::new(format!(
-                            "Function argument type mismatch: expected {}, got {}",
-                            self.type_to_string(&ft.param_type),
-                            self.type_to_string(&arg_type)
-                        )));
+                match func_type {
+                    Type::Function(ft) => {
+                        self.check_argument(&app.arg, &ft.param_type);
+                        *ft.return_type
+                    }
+                    Type::Var(_) => {
+                        // Calling a value of not-yet-known type makes it a function
+                        let arg_type = self.infer_type(&app.arg);
+                        let return_type = self.fresh_var();
+                        let expected = Type::Function(FunctionType {
+                            param_type: Box::new(arg_type),
+                            return_type: Box::new(return_type.clone()),
+                            effect: None,
+                        });
+                        // Only fails the occurs check, as in `\x -> x(x)`
+                        if !self.unify(&func_type, &expected) {
+                            self.errors.push(TypeError::at(format!(
+                                "Cannot construct infinite type {} = {}",
+                                self.type_to_string(&func_type),
+                                self.type_to_string(&expected)
+                            ), app.span));
+                        }
+                        return_type
+                    }
+                    _ => {
+                        self.errors.push(TypeError::at(format!(
+                            "Cannot apply non-function type: {}",
+                            self.type_to_string(&func_type)
+                        ), app.func.span()));
+                        self.fresh_var()
                     }
-                    *ft.return_type
-                } else {
-                    self.errors.push(TypeError::new(format!(
-                        "Cannot apply non-function type: {}",
-                        self.type_to_string(&func_type)
-                    )));
-                    Type::Basic(BasicType { name: "Unknown".to_string() })
                 }
             }
             
             Expr::Constructor(ctor) => {
-                Type::Basic(BasicType { name: ctor.name.clone() })
+                if ctor.name == "List" {
+                    // List literal: every element has the element type
+                    let element_type = self.fresh_var();
+                    for element in &ctor.args {
+                        let actual = self.infer_type(element);
+                        if !self.unify(&actual, &element_type) {
+                            self.errors.push(TypeError::at(format!(
+                                "List elements have incompatible types: {} and {}",
+                                self.type_to_string(&element_type),
+                                self.type_to_string(&actual)
+                            ), element.span()));
+                        }
+                    }
+                    Type::List(ListType { element_type: Box::new(element_type) })
+                } else {
+                    basic_type(&ctor.name)
+                }
             }
             
             Expr::Tuple(tuple) => {
@@ -513,46 +807,334 @@ impl TypeChecker {
             
             Expr::FieldAccess(field_access) => {
                 let record_type = self.infer_type(&field_access.record);
-                let resolved_type = self.resolve_type(&record_type);
+                let mut record_type = self.apply_substitution(&record_type);
+                
+                // A field of a value whose type is still unknown selects the
+                // record type declaring that field, if only one does
+                if let Type::Var(_) = record_type {
+                    if let Some(type_name) = self.record_declaring(&field_access.field) {
+                        let named = basic_type(&type_name);
+                        self.unify(&record_type, &named);
+                        record_type = named;
+                    }
+                }
+                
+                if let Ok(index) = field_access.field.parse::<usize>() {
+                    if let Type::Tuple(tuple) = self.resolve_type(&record_type) {
+                        return match tuple.element_types.get(index) {
+                            Some(element_type) => element_type.clone(),
+                            None => {
+                                self.errors.push(TypeError::at(format!(
+                                    "Tuple index {} out of range for {}",
+                                    index,
+                                    self.type_to_string(&record_type)
+                                ), field_access.span));
+                                self.fresh_var()
+                            }
+                        };
+                    }
+                }
                 
-                if let Type::Record(rec_type) = resolved_type {
+                if let Type::Record(rec_type) = self.resolve_type(&record_type) {
                     for (field_name, field_type) in &rec_type.fields {
                         if field_name == &field_access.field {
                             return field_type.clone();
                         }
                     }
-                    self.errors.push(TypeError::new(format!(
+                    self.errors.push(TypeError::at(format!(
                         "Field '{}' not found in record type",
                         field_access.field
-                    )));
-                    Type::Basic(BasicType { name: "Unknown".to_string() })
+                    ), field_access.span));
+                    self.fresh_var()
                 } else {
-                    self.errors.push(TypeError::new(format!(
+                    self.errors.push(TypeError::at(format!(
                         "Cannot access field on non-record type: {}",
                         self.type_to_string(&record_type)
-                    )));
-                    Type::Basic(BasicType { name: "Unknown".to_string() })
+                    ), field_access.record.span()));
+                    self.fresh_var()
+                }
+            }
+            
+            Expr::Lambda(lambda) => self.infer_lambda(lambda, None),
+        }
+    }
+    
+    fn check_argument(&mut self, arg: &Expr, param_type: &Type) {
+        let arg_type = self.infer_type_with_hint(arg, Some(param_type));
+        if !self.unify(&arg_type, param_type) {
+            self.errors.push(TypeError::at(format!(
+                "Function argument type mismatch: expected {}, got {}",
+                self.type_to_string(param_type),
+                self.type_to_string(&arg_type)
+            ), arg.span()));
+        }
+    }
+    
+    // A call of a @variadic extern with more arguments than it declares
+    fn is_variadic_call(&self, app: &Application) -> bool {
+        let (callee, args) = call_spine(app);
+        match callee {
+            Expr::Variable(var) if !self.type_env.contains_key(&var.name) => {
+                self.variadic.get(&var.name).is_some_and(|&count| args.len() > count)
+            }
+            _ => false,
+        }
+    }
+    
+    // The declared parameters are checked as usual, and the arguments
+    // after them may have any type, as in `printf("%d %s", n, s)`
+    fn infer_variadic_call(&mut self, app: &Application) -> Type {
+        let (callee, args) = call_spine(app);
+        let mut ty = self.infer_type(callee);
+        for arg in args {
+            match self.resolve_type(&self.apply_substitution(&ty)) {
+                Type::Function(ft) => {
+                    self.check_argument(arg, &ft.param_type);
+                    ty = *ft.return_type;
+                }
+                _ => {
+                    self.infer_type(arg);
+                }
+            }
+        }
+        ty
+    }
+    
+    // Bind the variables `pattern` introduces, unifying it with the type of
+    // the value it matches
+    fn bind_pattern(&mut self, pattern: &Pattern, expected: &Type) {
+        match pattern {
+            Pattern::Wildcard(_) => {}
+            
+            Pattern::Variable(var) => {
+                self.record(var.span, expected);
+                self.type_env.insert(var.name.clone(), Scheme::mono(expected.clone()));
+            }
+            
+            Pattern::Literal(lit) => {
+                let lit_type = literal_value_type(&lit.value);
+                self.expect_pattern_type(&lit_type, expected, lit.span);
+            }
+            
+            Pattern::Tuple(tuple) => {
+                let element_types: Vec<Type> = tuple.elements.iter().map(|_| self.fresh_var()).collect();
+                let tuple_type = Type::Tuple(TupleType { element_types: element_types.clone() });
+                self.expect_pattern_type(&tuple_type, expected, tuple.span);
+                for (element, element_type) in tuple.elements.iter().zip(&element_types) {
+                    self.bind_pattern(element, element_type);
+                }
+            }
+            
+            Pattern::Record(record) => {
+                if let Some(type_name) = &record.type_name {
+                    self.expect_pattern_type(&basic_type(type_name), expected, record.span);
+                }
+                let resolved = self.resolve_type(&self.apply_substitution(expected));
+                let field_types = match resolved {
+                    Type::Record(record_type) => record_type.fields,
+                    other => {
+                        self.errors.push(TypeError::at(format!(
+                            "Record pattern cannot match a value of type {}",
+                            self.type_to_string(&other)
+                        ), record.span));
+                        vec![]
+                    }
+                };
+                for (name, field_pattern) in &record.fields {
+                    let field_type = match field_types.iter().find(|(field, _)| field == name) {
+                        Some((_, field_type)) => field_type.clone(),
+                        None => {
+                            if !field_types.is_empty() {
+                                self.errors.push(TypeError::at(
+                                    format!("Field '{}' not found in record type", name),
+                                    field_pattern.span(),
+                                ));
+                            }
+                            self.fresh_var()
+                        }
+                    };
+                    self.bind_pattern(field_pattern, &field_type);
+                }
+            }
+            
+            Pattern::Constructor(ctor) => {
+                let arg_types = match self.constructor_type(&ctor.name, ctor.args.len(), ctor.span) {
+                    Some((arg_types, result_type)) => {
+                        self.expect_pattern_type(&result_type, expected, ctor.span);
+                        arg_types
+                    }
+                    None => ctor.args.iter().map(|_| self.fresh_var()).collect(),
+                };
+                for (arg, arg_type) in ctor.args.iter().zip(&arg_types) {
+                    self.bind_pattern(arg, arg_type);
                 }
             }
             
-            Expr::Lambda(_) => {
-                Type::Basic(BasicType { name: "Function".to_string() })
+            Pattern::Or(or) => {
+                // Every alternative must bind the same variables at the same types
+                let Some((first, rest)) = or.alternatives.split_first() else { return };
+                self.bind_pattern(first, expected);
+                let mut names = vec![];
+                first.collect_bindings(&mut names);
+                let bound: Vec<Scheme> = names.iter().map(|name| self.type_env[name].clone()).collect();
+                
+                for alternative in rest {
+                    let mut alternative_names = vec![];
+                    alternative.collect_bindings(&mut alternative_names);
+                    let mut sorted_names = names.clone();
+                    sorted_names.sort();
+                    alternative_names.sort();
+                    if alternative_names != sorted_names {
+                        self.errors.push(TypeError::at(format!(
+                            "Alternatives of an or-pattern must bind the same variables: {} binds {}, {} binds {}",
+                            pattern_to_string(first),
+                            describe_names(&names),
+                            pattern_to_string(alternative),
+                            describe_names(&alternative_names)
+                        ), alternative.span()));
+                        continue;
+                    }
+                    
+                    self.bind_pattern(alternative, expected);
+                    for (name, scheme) in names.iter().zip(&bound) {
+                        let alternative_type = self.type_env[name].ty.clone();
+                        if !self.unify(&alternative_type, &scheme.ty) {
+                            self.errors.push(TypeError::at(format!(
+                                "Variable '{}' has type {} in one alternative and {} in another",
+                                name,
+                                self.type_to_string(&scheme.ty),
+                                self.type_to_string(&alternative_type)
+                            ), alternative.span()));
+                        }
+                    }
+                }
+                
+                for (name, scheme) in names.into_iter().zip(bound) {
+                    self.type_env.insert(name, scheme);
+                }
             }
         }
     }
     
-    fn literal_type(&self, literal: &Literal) -> Type {
-        let type_name = match &literal.type_name.as_str() {
-            &"Int" => "Int",
-            &"Nat" => "Nat",
-            &"Float64" => "Float64",
-            &"Bool" => "Bool",
-            &"String" => "String",
-            &"Unit" => "Unit",
-            _ => "Unknown",
+    fn check_coverage(&mut self, match_expr: &MatchExpr, scrutinee_type: &Type) {
+        let arms: Vec<(&Pattern, bool)> = match_expr.arms.iter()
+            .map(|arm| (&arm.pattern, arm.guard.is_some()))
+            .collect();
+        let report = check_match(&arms, scrutinee_type, &|ty| self.match_signature(ty));
+        
+        for index in report.unreachable {
+            let pattern = arms[index].0;
+            self.errors.push(TypeError::at(
+                format!("Unreachable match arm: {} is already covered by earlier arms", pattern_to_string(pattern)),
+                pattern.span(),
+            ));
+        }
+        if !report.missing.is_empty() {
+            self.errors.push(TypeError::at(
+                format!("Match is not exhaustive: missing {}", report.missing.join(", ")),
+                match_expr.span,
+            ));
+        }
+    }
+    
+    // Constructors of `ty` for coverage checking
+    fn match_signature(&self, ty: &Type) -> Signature {
+        let variant = |name: &str, args: Vec<Type>| (Con::Variant(name.to_string()), args);
+        match self.resolve_type(&self.apply_substitution(ty)) {
+            Type::Basic(basic) => match basic.name.as_str() {
+                "Bool" => Signature::Complete(vec![variant("true", vec![]), variant("false", vec![])]),
+                "Unit" => Signature::Complete(vec![(Con::Tuple(0), vec![])]),
+                name => match self.custom_types.get(name).map(|def| &def.definition) {
+                    Some(TypeDefKind::Variant(variants)) => Signature::Complete(
+                        variants.iter().map(|(name, args)| variant(name, args.clone())).collect(),
+                    ),
+                    _ => Signature::Open,
+                },
+            },
+            Type::Option(option) => Signature::Complete(vec![
+                variant("None", vec![]),
+                variant("Some", vec![*option.inner]),
+            ]),
+            Type::Result(result) => Signature::Complete(vec![
+                variant("Ok", vec![*result.ok_type]),
+                variant("Err", vec![*result.err_type]),
+            ]),
+            Type::Tuple(tuple) => Signature::Complete(vec![
+                (Con::Tuple(tuple.element_types.len()), tuple.element_types),
+            ]),
+            Type::Record(record) => {
+                let (names, types) = record.fields.into_iter().unzip();
+                Signature::Complete(vec![(Con::Record(names), types)])
+            }
+            _ => Signature::Open,
+        }
+    }
+    
+    fn expect_pattern_type(&mut self, pattern_type: &Type, expected: &Type, span: Span) {
+        if !self.unify(pattern_type, expected) {
+            self.errors.push(TypeError::at(format!(
+                "Pattern of type {} cannot match a value of type {}",
+                self.type_to_string(pattern_type),
+                self.type_to_string(expected)
+            ), span));
+        }
+    }
+    
+    // Argument types and result type of a fresh instance of constructor `name`
+    fn constructor_type(&mut self, name: &str, arg_count: usize, span: Span) -> Option<(Vec<Type>, Type)> {
+        let Some(arity) = self.constructor_arity(name) else {
+            self.errors.push(TypeError::at(format!("Unknown constructor '{}'", name), span));
+            return None;
         };
+        if arity != arg_count {
+            self.errors.push(TypeError::at(
+                format!("Constructor '{}' takes {} arguments, got {}", name, arity, arg_count),
+                span,
+            ));
+            return None;
+        }
         
-        Type::Basic(BasicType { name: type_name.to_string() })
+        let scheme = self.function_types.get(name).or_else(|| self.type_env.get(name)).cloned()?;
+        let mut ty = self.instantiate(&scheme);
+        let mut arg_types = vec![];
+        for _ in 0..arity {
+            let Type::Function(ft) = ty else { return None };
+            arg_types.push(*ft.param_type);
+            ty = *ft.return_type;
+        }
+        Some((arg_types, ty))
+    }
+    
+    fn constructor_arity(&self, name: &str) -> Option<usize> {
+        match name {
+            "Some" | "Ok" | "Err" => Some(1),
+            "None" => Some(0),
+            _ => self.custom_types.values().find_map(|type_def| match &type_def.definition {
+                TypeDefKind::Variant(variants) => variants.iter()
+                    .find(|(variant, _)| variant == name)
+                    .map(|(_, params)| params.len()),
+                _ => None,
+            }),
+        }
+    }
+    
+    // The only record type with a field called `field`
+    fn record_declaring(&self, field: &str) -> Option<String> {
+        let mut declaring = self.custom_types.values().filter(|type_def| match &type_def.definition {
+            TypeDefKind::Record(record) => record.fields.iter().any(|(name, _)| name == field),
+            _ => false,
+        });
+        match (declaring.next(), declaring.next()) {
+            (Some(type_def), None) => Some(type_def.name.clone()),
+            _ => None,
+        }
+    }
+    
+    fn literal_type(&mut self, literal: &Literal) -> Type {
+        match literal.type_name.as_str() {
+            "Int" | "Nat" | "Float64" | "Bool" | "String" | "Unit" => basic_type(&literal.type_name),
+            _ => self.fresh_var(),
+        }
     }
     
     fn resolve_type(&self, t: &Type) -> Type {
@@ -590,97 +1172,383 @@ impl TypeChecker {
         }
     }
     
-    fn types_compatible(&self, t1: &Type, t2: &Type) -> bool {
-        match (t1, t2) {
+    fn is_integer_type(&self, t: &Type) -> bool {
+        if let Type::Basic(basic) = t {
+            matches!(basic.name.as_str(), "Int" | "Nat")
+        } else {
+            false
+        }
+    }
+    
+    // Remember that `ty`, if not yet known, must be of the given kind
+    fn constrain(&mut self, ty: &Type, kind: OperandKind, op: &str, span: Span) {
+        if let Type::Var(_) = self.shallow_resolve(ty) {
+            self.constraints.push(OperandConstraint { ty: ty.clone(), kind, op: op.to_string(), span });
+        }
+    }
+    
+    // `generalize`, leaving the variables of operator operands unquantified:
+    // their uses in scope then settle them, or `check_constraints` defaults
+    // them to Int
+    fn generalize_unconstrained(&self, t: &Type) -> Scheme {
+        let mut scheme = self.generalize(t);
+        let constrained: Vec<TypeVar> = self.constraints.iter()
+            .filter_map(|constraint| match self.shallow_resolve(&constraint.ty) {
+                Type::Var(var) => Some(var),
+                _ => None,
+            })
+            .collect();
+        scheme.vars.retain(|var| !constrained.contains(var));
+        scheme
+    }
+    
+    // Check the operands constrained since the last check against what
+    // inference found; those still unknown default to Int
+    fn check_constraints(&mut self) {
+        for constraint in std::mem::take(&mut self.constraints) {
+            let ty = self.apply_substitution(&constraint.ty);
+            let (accepted, expected) = match constraint.kind {
+                OperandKind::Numeric => (self.is_numeric_type(&ty), "numeric types"),
+                OperandKind::Integer => (self.is_integer_type(&ty), "Int operands"),
+                OperandKind::Bitwise => (self.is_integer_type(&ty) || ty == basic_type("Bool"), "Int or Bool operands"),
+            };
+            if let Type::Var(_) = ty {
+                self.unify(&ty, &basic_type("Int"));
+            } else if !accepted {
+                self.errors.push(TypeError::at(format!(
+                    "Operator '{}' requires {}, got {}",
+                    constraint.op,
+                    expected,
+                    self.type_to_string(&ty)
+                ), constraint.span));
+            }
+        }
+    }
+    
+    // ========================================================================
+    // Unification
+    // ========================================================================
+    
+    fn fresh_type_var(&mut self) -> TypeVar {
+        self.substitution.push(None);
+        TypeVar { id: self.substitution.len() - 1 }
+    }
+    
+    fn fresh_var(&mut self) -> Type {
+        Type::Var(self.fresh_type_var())
+    }
+    
+    /// `t` with every bound type variable replaced by what it is bound to
+    fn apply_substitution(&self, t: &Type) -> Type {
+        map_type(t, &mut |node| match node {
+            Type::Var(var) => self.substitution[var.id].as_ref().map(|bound| self.apply_substitution(bound)),
+            _ => None,
+        })
+    }
+    
+    // Follow variable bindings until reaching an unbound variable or a type constructor
+    fn shallow_resolve(&self, t: &Type) -> Type {
+        let mut current = t;
+        while let Type::Var(var) = current {
+            match &self.substitution[var.id] {
+                Some(bound) => current = bound,
+                None => break,
+            }
+        }
+        current.clone()
+    }
+    
+    /// Make `t1` and `t2` equal by binding type variables. Returns false when
+    /// they cannot be; the caller reports both types.
+    fn unify(&mut self, t1: &Type, t2: &Type) -> bool {
+        let t1 = self.shallow_resolve(t1);
+        let t2 = self.shallow_resolve(t2);
+        
+        if let Some(aliased) = self.expand_alias(&t1) {
+            return self.unify(&aliased, &t2);
+        }
+        if let Some(aliased) = self.expand_alias(&t2) {
+            return self.unify(&t1, &aliased);
+        }
+        
+        match (&t1, &t2) {
+            (Type::Var(v1), Type::Var(v2)) if v1 == v2 => true,
+            (Type::Var(var), other) | (other, Type::Var(var)) => self.bind_var(*var, other),
+            
             (Type::Basic(b1), Type::Basic(b2)) => {
-                if b1.name == "Unknown" || b2.name == "Unknown" {
-                    return true;
-                }
-                if (b1.name == "Nat" && b2.name == "Int") || (b1.name == "Int" && b2.name == "Nat") {
-                    return true;
-                }
                 b1.name == b2.name
+                    || (b1.name == "Nat" && b2.name == "Int")
+                    || (b1.name == "Int" && b2.name == "Nat")
             }
             
-            (Type::Option(o1), Type::Option(o2)) => {
-                self.types_compatible(&o1.inner, &o2.inner)
+            // A record literal against the named record type it was written for
+            (Type::Basic(named), Type::Record(record)) | (Type::Record(record), Type::Basic(named)) => {
+                match self.custom_types.get(&named.name).map(|def| &def.definition) {
+                    Some(TypeDefKind::Record(declared)) => {
+                        let declared = declared.clone();
+                        self.unify_records(&declared, record)
+                    }
+                    _ => false,
+                }
             }
             
+            // Unparameterised `List` in older declarations
+            (Type::Basic(basic), Type::List(_)) | (Type::List(_), Type::Basic(basic)) => basic.name == "List",
+            
+            (Type::Option(o1), Type::Option(o2)) => self.unify(&o1.inner, &o2.inner),
+            
             (Type::Result(r1), Type::Result(r2)) => {
-                self.types_compatible(&r1.ok_type, &r2.ok_type)
-                    && self.types_compatible(&r1.err_type, &r2.err_type)
+                self.unify(&r1.ok_type, &r2.ok_type) && self.unify(&r1.err_type, &r2.err_type)
             }
             
-            (Type::List(l1), Type::List(l2)) => {
-                self.types_compatible(&l1.element_type, &l2.element_type)
-            }
+            (Type::List(l1), Type::List(l2)) => self.unify(&l1.element_type, &l2.element_type),
             
             (Type::Tuple(tu1), Type::Tuple(tu2)) => {
-                if tu1.element_types.len() != tu2.element_types.len() {
-                    return false;
-                }
-                tu1.element_types.iter()
-                    .zip(&tu2.element_types)
-                    .all(|(e1, e2)| self.types_compatible(e1, e2))
+                tu1.element_types.len() == tu2.element_types.len()
+                    && tu1.element_types.iter()
+                        .zip(&tu2.element_types)
+                        .all(|(e1, e2)| self.unify(e1, e2))
             }
             
+            (Type::Record(r1), Type::Record(r2)) => self.unify_records(r1, r2),
+            
             (Type::Function(f1), Type::Function(f2)) => {
-                self.types_compatible(&f1.param_type, &f2.param_type)
-                    && self.types_compatible(&f1.return_type, &f2.return_type)
+                self.unify(&f1.param_type, &f2.param_type)
+                    && self.unify(&f1.return_type, &f2.return_type)
             }
             
+            (Type::Pointer(p1), Type::Pointer(p2)) => self.unify(&p1.pointee_type, &p2.pointee_type),
+            
             _ => false,
         }
     }
     
+    fn unify_records(&mut self, r1: &RecordType, r2: &RecordType) -> bool {
+        r1.fields.len() == r2.fields.len()
+            && r1.fields.iter().all(|(name, t1)| {
+                match r2.fields.iter().find(|(other, _)| other == name) {
+                    Some((_, t2)) => self.unify(t1, t2),
+                    None => false,
+                }
+            })
+    }
+    
+    fn bind_var(&mut self, var: TypeVar, ty: &Type) -> bool {
+        // Occurs check: `'t0 = List<'t0>` has no finite solution
+        let mut vars = vec![];
+        free_type_vars(&self.apply_substitution(ty), &mut vars);
+        if vars.contains(&var) {
+            return false;
+        }
+        self.substitution[var.id] = Some(ty.clone());
+        true
+    }
+    
+    fn expand_alias(&self, t: &Type) -> Option<Type> {
+        let Type::Basic(basic) = t else { return None };
+        match &self.custom_types.get(&basic.name)?.definition {
+            TypeDefKind::Alias(aliased) => Some(aliased.clone()),
+            _ => None,
+        }
+    }
+    
+    /// Quantify the variables of `t` that no binding in scope mentions
+    fn generalize(&self, t: &Type) -> Scheme {
+        let ty = self.apply_substitution(t);
+        
+        let mut env_vars = vec![];
+        for scheme in self.type_env.values() {
+            let mut scheme_vars = vec![];
+            free_type_vars(&self.apply_substitution(&scheme.ty), &mut scheme_vars);
+            env_vars.extend(scheme_vars.into_iter().filter(|var| !scheme.vars.contains(var)));
+        }
+        
+        let mut vars = vec![];
+        free_type_vars(&ty, &mut vars);
+        vars.retain(|var| !env_vars.contains(var));
+        Scheme { vars, ty }
+    }
+    
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        if scheme.vars.is_empty() {
+            return scheme.ty.clone();
+        }
+        let fresh: HashMap<TypeVar, Type> = scheme.vars.iter()
+            .map(|var| (*var, self.fresh_var()))
+            .collect();
+        map_type(&scheme.ty, &mut |node| match node {
+            Type::Var(var) => fresh.get(var).cloned(),
+            _ => None,
+        })
+    }
+    
     fn type_to_string(&self, t: &Type) -> String {
-        match t {
-            Type::Basic(basic) => basic.name.clone(),
-            
-            Type::Option(opt) => {
-                format!("Option<{}>", self.type_to_string(&opt.inner))
-            }
-            
-            Type::Result(res) => {
-                format!("Result<{}, {}>", 
-                    self.type_to_string(&res.ok_type),
-                    self.type_to_string(&res.err_type))
-            }
-            
-            Type::List(list) => {
-                format!("List<{}>", self.type_to_string(&list.element_type))
-            }
-            
-            Type::Tuple(tuple) => {
-                let types: Vec<String> = tuple.element_types.iter()
-                    .map(|t| self.type_to_string(t))
-                    .collect();
-                format!("({})", types.join(", "))
-            }
-            
-            Type::Record(record) => {
-                let fields: Vec<String> = record.fields.iter()
-                    .map(|(name, t)| format!("{}: {}", name, self.type_to_string(t)))
-                    .collect();
-                format!("{{{}}}", fields.join(", "))
-            }
-            
-            Type::Function(func) => {
-                format!("{} -> {}", 
-                    self.type_to_string(&func.param_type),
-                    self.type_to_string(&func.return_type))
-            }
-            
-            Type::Pointer(ptr) => {
-                format!("Ptr<{}>", self.type_to_string(&ptr.pointee_type))
+        self.apply_substitution(t).to_string()
+    }
+}
+
+/// Curried type of a function definition: `(x: A, y: B) -> R` is `A -> B -> R`,
+/// and a function without parameters takes `Unit`
+pub fn curried_type(params: &[(String, Type)], return_type: &Type) -> FunctionType {
+    let unit = Type::Basic(BasicType { name: "Unit".to_string() });
+    let (first, rest) = match params.split_first() {
+        Some(((_, first), rest)) => (first.clone(), rest),
+        None => (unit, &[][..]),
+    };
+
+    let mut result_type = return_type.clone();
+    for (_, param_type) in rest.iter().rev() {
+        result_type = Type::Function(FunctionType {
+            param_type: Box::new(param_type.clone()),
+            return_type: Box::new(result_type),
+            effect: None,
+        });
+    }
+
+    FunctionType {
+        param_type: Box::new(first),
+        return_type: Box::new(result_type),
+        effect: None,
+    }
+}
+
+/// Replace each type parameter named in `bindings` (`T` in `func id<T>`)
+/// with the type it is bound to
+pub fn substitute_type_params(ty: &Type, bindings: &HashMap<String, Type>) -> Type {
+    map_type(ty, &mut |node| match node {
+        Type::Basic(basic) => bindings.get(&basic.name).cloned(),
+        _ => None,
+    })
+}
+
+fn basic_type(name: &str) -> Type {
+    Type::Basic(BasicType { name: name.to_string() })
+}
+
+fn literal_value_type(value: &LiteralValue) -> Type {
+    match value {
+        LiteralValue::Int(_) => basic_type("Int"),
+        LiteralValue::Float(_) => basic_type("Float64"),
+        LiteralValue::Bool(_) => basic_type("Bool"),
+        LiteralValue::String(_) => basic_type("String"),
+        LiteralValue::Unit => basic_type("Unit"),
+    }
+}
+
+// `x, y`, or `nothing`, for messages about the variables a pattern binds
+fn describe_names(names: &[String]) -> String {
+    if names.is_empty() {
+        "nothing".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+// Rebuild `ty` bottom-up, except that nodes `replace` maps to `Some` are
+// swapped for the replacement wholesale
+pub(crate) fn map_type(ty: &Type, replace: &mut dyn FnMut(&Type) -> Option<Type>) -> Type {
+    if let Some(replacement) = replace(ty) {
+        return replacement;
+    }
+    match ty {
+        Type::Basic(_) | Type::Var(_) => ty.clone(),
+        Type::Option(option) => Type::Option(OptionType {
+            inner: Box::new(map_type(&option.inner, replace)),
+        }),
+        Type::Result(result) => Type::Result(ResultType {
+            ok_type: Box::new(map_type(&result.ok_type, replace)),
+            err_type: Box::new(map_type(&result.err_type, replace)),
+        }),
+        Type::List(list) => Type::List(ListType {
+            element_type: Box::new(map_type(&list.element_type, replace)),
+        }),
+        Type::Tuple(tuple) => Type::Tuple(TupleType {
+            element_types: tuple.element_types.iter().map(|t| map_type(t, replace)).collect(),
+        }),
+        Type::Record(record) => Type::Record(RecordType {
+            fields: record.fields.iter()
+                .map(|(name, t)| (name.clone(), map_type(t, replace)))
+                .collect(),
+        }),
+        Type::Function(function) => Type::Function(FunctionType {
+            param_type: Box::new(map_type(&function.param_type, replace)),
+            return_type: Box::new(map_type(&function.return_type, replace)),
+            effect: function.effect.clone(),
+        }),
+        Type::Pointer(pointer) => Type::Pointer(PointerType {
+            pointee_type: Box::new(map_type(&pointer.pointee_type, replace)),
+        }),
+    }
+}
+
+// Type variables in `ty` in order of first appearance, skipping those already in `out`
+fn free_type_vars(ty: &Type, out: &mut Vec<TypeVar>) {
+    map_type(ty, &mut |node| {
+        if let Type::Var(var) = node {
+            if !out.contains(var) {
+                out.push(*var);
             }
         }
+        None
+    });
+}
+
+// The function `f(a)(b)` applies, and its arguments in order
+fn call_spine(app: &Application) -> (&Expr, Vec<&Expr>) {
+    let mut args = vec![app.arg.as_ref()];
+    let mut callee = app.func.as_ref();
+    while let Expr::Application(inner) = callee {
+        args.push(inner.arg.as_ref());
+        callee = inner.func.as_ref();
     }
+    args.reverse();
+    (callee, args)
 }
 
+/// Check `program`, including modules and their signatures. Modules are
+/// flattened into namespaced items first (see `linker::flatten_program`);
+/// imports must already have been loaded with `linker::link_program`.
 pub fn check_types(program: Program) -> TypeCheckResult {
-    let checker = TypeChecker::new(program);
-    checker.check()
+    check_program(program, false).0
+}
+
+/// `check_types`, also returning the type of every variable in the program
+pub fn check_types_recording(program: Program) -> (TypeCheckResult, VariableTypes) {
+    check_program(program, true)
+}
+
+fn check_program(program: Program, record: bool) -> (TypeCheckResult, VariableTypes) {
+    let flat = match flatten_program(&program) {
+        Ok(flat) => flat,
+        Err(errors) => {
+            let errors = errors.into_iter().map(TypeError::from).collect();
+            return (TypeCheckResult::failure(errors), VariableTypes::new());
+        }
+    };
+
+    let mut checker = TypeChecker::new(flat);
+    let mut errors = checker.check_signatures(&program);
+    let (result, types) = if record {
+        checker.check_recording_types()
+    } else {
+        (checker.check(), VariableTypes::new())
+    };
+    errors.extend(result.errors);
+
+    if errors.is_empty() {
+        (TypeCheckResult::success(), types)
+    } else {
+        (TypeCheckResult::failure(errors), types)
+    }
+}
+
+fn collect_signatures(program: &Program, out: &mut HashMap<String, SignatureDef>) {
+    for signature in &program.signatures {
+        out.insert(signature.name.clone(), signature.clone());
+    }
+    for module in &program.modules {
+        collect_signatures(&module.body, out);
+    }
 }
 
 #[cfg(test)]
@@ -752,7 +1620,6 @@ func test_it() -> Int:
         let result = check_types(program);
         assert!(result.success, "Type check failed: {:?}", result.errors);
     }
-}
 
     #[test]
     fn test_variant_constructor() {
@@ -766,3 +1633,344 @@ func test() -> Direction:
         let result = check_types(program);
         assert!(result.success, "Type check failed: {:?}", result.errors);
     }
+    
+    #[test]
+    fn test_error_points_at_expression() {
+        let ir = "func bad (x: Int) -> Int :\n  x + missing\n";
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        assert!(!result.success);
+        
+        let error = &result.errors[0];
+        let span = error.span.expect("error should carry a span");
+        assert_eq!(span.snippet(ir), "missing");
+        assert_eq!(error.render("bad.pole-ir", ir), "bad.pole-ir:2:7: Undefined variable 'missing'");
+    }
+    
+    #[test]
+    fn test_module_signature_conformance() {
+        let ir = r#"
+signature COUNTER :
+  type State
+  func next : Int -> Int
+end
+
+module Good : COUNTER :
+  type State = Int
+
+  func next (n: Int) -> Int :
+    n + 1
+end
+
+module Bad : COUNTER :
+  func next (n: Int) -> Bool :
+    n > 0
+end
+
+func main () -> Int :
+  Good.next(1)
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "Module 'Bad' does not define type 'State' required by signature 'COUNTER'",
+            "Module 'Bad' defines 'next' as Int -> Bool but signature 'COUNTER' requires Int -> Int",
+        ]);
+    }
+
+    #[test]
+    fn test_lambda_parameters_typed_from_context() {
+        let ir = r#"
+func apply_twice (f: Int -> Int, x: Int) -> Int :
+  f(f(x))
+
+func make_adder (n: Int) -> Int -> Int :
+  \x -> x + n
+
+func main () -> Int :
+  let scale = 3 in
+  apply_twice(\x -> x * scale, 2) + make_adder(10)(7)
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        assert!(result.success, "Type check failed: {:?}", result.errors);
+    }
+
+    #[test]
+    fn test_let_bound_lambda_is_generalised() {
+        let ir = r#"
+func main () -> Int :
+  let id = \x -> x in
+  if id(true) then id(1) else id(2)
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        assert!(result.success, "Type check failed: {:?}", result.errors);
+    }
+
+    #[test]
+    fn test_operator_operands_of_unknown_type() {
+        let ir = r#"
+func concat () -> String :
+  let add = \x y -> x + y in
+  add("a", "b")
+
+func flip () -> Bool :
+  let neg = \x -> -x in
+  neg(true)
+
+func sum () -> Int :
+  let add = \x y -> x + y in
+  add(1, 2) + add(3, 4)
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "Operator '+' requires numeric types, got String",
+            "Operator '-' requires numeric types, got Bool",
+        ]);
+    }
+
+    #[test]
+    fn test_let_bound_arithmetic_at_float() {
+        let ir = r#"
+func square () -> Float64 :
+  let sq = \x -> x * x in
+  sq(1.5)
+
+func total () -> Float64 :
+  let add = \a b -> a + b in
+  add(1.0, 2.0)
+
+func mixed () -> Float64 :
+  let add = \a b -> a + b in
+  add(1.0, 2.0) + add(3, 4)
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "Function argument type mismatch: expected Float64, got Int",
+            "Function argument type mismatch: expected Float64, got Int",
+        ]);
+    }
+
+    #[test]
+    fn test_polymorphic_list_builtins() {
+        let ir = r#"
+func first (xs: List<Int>) -> Bool :
+  list_get(xs, 0, 0)
+
+func mixed () -> List<Int> :
+  list_push(list_push(list_new(), 1), true)
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "Function 'first' body type Int does not match declared return type Bool",
+            "Function argument type mismatch: expected Int, got Bool",
+        ]);
+    }
+
+    #[test]
+    fn test_string_map_and_variadic_builtins() {
+        let ir = r#"
+@extern("printf")
+@variadic
+func printf(format: String) -> Int
+
+func count (words: List<List<String>>, word: String) -> Int :
+  let map = HashMap_new(8) in
+  let _ = HashMap_put(map, String_length(word), 1) in
+  let all = List_concat(words) in
+  let _ = printf("%lld %s\n", List_length(all), word) in
+  if String_contains(word, "a") then HashMap_get(map, 3) else HashMap_size(map)
+
+func bad (word: String) -> Int :
+  let _ = printf(word, word, 1) in
+  HashMap_get(word, 1) + String_length(1)
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "Function argument type mismatch: expected HashMap, got String",
+            "Function argument type mismatch: expected String, got Int",
+        ]);
+    }
+
+    #[test]
+    fn test_infer_expression() {
+        let program = parse_ir("func double (x: Int) -> Int :\n  x * 2\n").unwrap();
+        let infer = |source: &str| {
+            let expr = crate::ir_parser::parse_expression(source).unwrap();
+            TypeChecker::new(program.clone()).infer_expression(&expr)
+        };
+        assert_eq!(infer("Some(double(21))").unwrap().to_string(), "Option<Int>");
+        assert_eq!(infer("List_push([], \"a\")").unwrap().to_string(), "List<String>");
+        let errors = infer("double(true)").unwrap_err();
+        assert_eq!(errors[0].message, "Function argument type mismatch: expected Int, got Bool");
+    }
+
+    #[test]
+    fn test_check_types_recording() {
+        let source = "module Shapes :\n  func area (w: Int, h: Int) -> Int :\n    w * h\nend\n\nfunc main () -> Int :\n  match Some(Shapes.area(2, 3)) with\n  | Some(n) -> n\n  | None -> 0\n";
+        let (result, types) = check_types_recording(parse_ir(source).unwrap());
+        assert!(result.success, "Type check failed: {:?}", result.errors);
+
+        // Type of the variable starting where `context` does
+        let at = |function: &str, context: &str| {
+            let start = source.find(context).unwrap();
+            types[function].iter()
+                .find(|(span, _)| span.start == start)
+                .map(|(_, ty)| ty.to_string())
+        };
+        assert_eq!(at("Shapes.area", "w * h").as_deref(), Some("Int"));
+        assert_eq!(at("main", "Shapes.area(").as_deref(), Some("Int -> Int -> Int"));
+        assert_eq!(at("main", "n) ->").as_deref(), Some("Int"));
+        assert_eq!(at("main", "n\n").as_deref(), Some("Int"));
+        assert_eq!(at("main", "Some(Shapes").as_deref(), Some("Int -> Option<Int>"));
+    }
+
+    #[test]
+    fn test_generic_function() {
+        let ir = r#"
+func id<T> (x: T) -> T :
+  x
+
+func pick<A, B> (a: A, b: B, first: Bool) -> Option<A> :
+  if first then Some(a) else None
+
+func main () -> Int :
+  match pick(id(1), "unused", id(true)) with
+  | Some(n) -> n + 1
+  | None -> 0
+
+func leaky<T> (x: T) -> T :
+  1
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Function 'leaky' body type Int does not match declared return type T"]);
+    }
+
+    #[test]
+    fn test_pattern_bindings_are_typed() {
+        let ir = r#"
+func unwrap_or (o: Option<Int>, default: Int) -> Int :
+  match o with
+  | Some(x) -> x && true
+  | None -> default
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        assert_eq!(
+            result.errors[0].message,
+            "Operator '&&' requires Bool operands, got Int and Bool"
+        );
+    }
+
+    #[test]
+    fn test_occurs_check() {
+        let ir = "func f () -> Int :\n  let g = \\x -> x(x) in\n  1\n";
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        assert_eq!(result.errors.len(), 1);
+        assert!(
+            result.errors[0].message.starts_with("Cannot construct infinite type"),
+            "{}", result.errors[0].message
+        );
+    }
+
+    #[test]
+    fn test_or_pattern_alternatives_bind_the_same_variables() {
+        let ir = r#"
+func f (r: Result<Int, Int>) -> Int :
+  match r with
+  | Ok(n) | Err(n) -> n
+
+func g (r: Result<Int, Bool>) -> Int :
+  match r with
+  | Ok(n) | Err(_) -> 0
+  | _ -> 1
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "Alternatives of an or-pattern must bind the same variables: Ok(n) binds n, Err(_) binds nothing",
+        ]);
+    }
+
+    #[test]
+    fn test_match_guard_must_be_bool() {
+        let ir = r#"
+func f (o: Option<Int>) -> Int :
+  match o with
+  | Some(n) if n -> n
+  | Some(n) if n >= 10 -> 10
+  | _ -> 0
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Match guard must be Bool, got Int"]);
+    }
+
+    #[test]
+    fn test_tuple_projection() {
+        let ir = r#"
+func swap (p: (Int, String)) -> (String, Int) :
+  (p.1, p.0)
+
+func third (p: (Int, Int)) -> Int :
+  p.2
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Tuple index 2 out of range for (Int, Int)"]);
+    }
+
+    #[test]
+    fn test_operator_types() {
+        let ir = r#"
+func ok (n: Int, x: Float64, b: Bool) -> Bool :
+  (n % 3) << 2 ^ -n == 0 && not (b | false) && x % 2.0 > -x
+
+func shift_float (x: Float64) -> Float64 :
+  x << 1
+
+func and_string (s: String) -> String :
+  s & s
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec![
+            "Operator '<<' requires Int operands, got Float64 and Int",
+            "Operator '&' requires Int or Bool operands, got String and String",
+        ]);
+    }
+
+    #[test]
+    fn test_tailrec_calls_must_be_tail_calls() {
+        let ir = r#"
+@tailrec
+func sum_to (n: Int, acc: Int) -> Int :
+  if n == 0 then acc else sum_to(n - 1, acc + n)
+
+@tailrec
+func fact (n: Int) -> Int :
+  if n == 0 then 1 else n * fact(n - 1)
+"#;
+        let program = parse_ir(ir).unwrap();
+        let result = check_types(program);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Recursive call to 'fact' is not in tail position, but the function is @tailrec"]);
+    }
+}
@@ -0,0 +1,1321 @@
+// Static Contract Verification
+// Proves `requires`/`ensures` clauses without running the program: each
+// clause is translated, together with the function body, into linear integer
+// arithmetic over booleans, and a built-in decision procedure (case splitting
+// plus Fourier-Motzkin elimination) searches for a counterexample.
+//
+// `ensures` clauses are checked against the body, assuming the `requires`
+// clauses. `requires` clauses are obligations of the callers: they are checked
+// at every call site, under the caller's own `requires` and the conditions on
+// the path to the call. Calls are modular: a callee's result is only known
+// through its `ensures` clauses, which also makes recursion an induction
+// hypothesis (partial correctness). Integers are unbounded, so wrap-around on
+// overflow is not modelled.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use crate::ast::*;
+use crate::linker::flatten_program;
+
+// Case splits per query before giving up
+const SEARCH_LIMIT: usize = 20_000;
+// Constraints per elimination step before giving up
+const CONSTRAINT_LIMIT: usize = 4_000;
+// Largest coefficient or constant kept in a constraint; beyond it a query is undecided
+const MAGNITUDE_LIMIT: i128 = 1 << 100;
+
+// ============================================================================
+// Report
+// ============================================================================
+
+/// Outcome of verifying one clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// Holds for every input satisfying the `requires` clauses
+    Proven,
+    Refuted(Counterexample),
+    /// Neither proven nor refuted, with what the verifier could not model
+    Unknown(String),
+}
+
+/// Inputs under which a clause fails
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    /// For a `requires` clause, the function whose call breaks it
+    pub caller: Option<String>,
+    /// Parameter values (of the caller, for a `requires` clause), then
+    /// `result` for an `ensures` clause
+    pub values: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClauseKind {
+    Requires,
+    Ensures,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClauseReport {
+    pub function: String,
+    pub kind: ClauseKind,
+    /// Position among the function's clauses of the same kind
+    pub index: usize,
+    /// The clause in source syntax
+    pub clause: String,
+    pub span: Span,
+    pub verdict: Verdict,
+}
+
+/// Verdicts for every contract clause, in declaration order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerificationReport {
+    pub clauses: Vec<ClauseReport>,
+}
+
+impl VerificationReport {
+    pub fn all_proven(&self) -> bool {
+        self.clauses.iter().all(|clause| clause.verdict == Verdict::Proven)
+    }
+
+    pub fn refuted(&self) -> impl Iterator<Item = &ClauseReport> {
+        self.clauses.iter().filter(|clause| matches!(clause.verdict, Verdict::Refuted(_)))
+    }
+}
+
+impl fmt::Display for ClauseKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClauseKind::Requires => write!(f, "requires"),
+            ClauseKind::Ensures => write!(f, "ensures"),
+        }
+    }
+}
+
+/// `n = 0, result = 0` or `in call from 'main' with x = 0`
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = self.values.iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        match (&self.caller, values.is_empty()) {
+            (Some(caller), true) => write!(f, "in call from '{}'", caller),
+            (Some(caller), false) => write!(f, "in call from '{}' with {}", caller, values.join(", ")),
+            (None, true) => write!(f, "for every input"),
+            (None, false) => write!(f, "{}", values.join(", ")),
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Proven => write!(f, "proven"),
+            Verdict::Refuted(counterexample) => write!(f, "refuted ({})", counterexample),
+            Verdict::Unknown(reason) => write!(f, "unknown ({})", reason),
+        }
+    }
+}
+
+/// `factorial: ensures result >= 1: proven`
+impl fmt::Display for ClauseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} {}: {}", self.function, self.kind, self.clause, self.verdict)
+    }
+}
+
+// ============================================================================
+// Entry Point
+// ============================================================================
+
+/// Try to prove every `requires`/`ensures` clause of the program
+pub fn verify_contracts(program: &Program) -> VerificationReport {
+    let program = flatten_program(program).unwrap_or_else(|_| program.clone());
+    let functions: HashMap<String, &FunctionDef> = program.func_defs.iter()
+        .map(|def| (def.name.clone(), def))
+        .collect();
+
+    let mut ensures = Vec::new();
+    // Verdict at every call site, per `requires` clause of the callee
+    let mut call_sites: HashMap<(String, usize), Vec<Verdict>> = HashMap::new();
+    for def in &program.func_defs {
+        let checked = check_function(&functions, def);
+        ensures.push(checked.ensures);
+        for (callee, index, verdict) in checked.call_sites {
+            call_sites.entry((callee, index)).or_default().push(verdict);
+        }
+    }
+
+    let mut clauses = Vec::new();
+    for (def, ensures) in program.func_defs.iter().zip(ensures) {
+        for (index, clause) in def.requires.iter().enumerate() {
+            // A clause nobody calls into is vacuously satisfied
+            let verdicts = call_sites.remove(&(def.name.clone(), index)).unwrap_or_default();
+            let verdict = verdicts.iter().find(|v| matches!(v, Verdict::Refuted(_)))
+                .or_else(|| verdicts.iter().find(|v| matches!(v, Verdict::Unknown(_))))
+                .cloned()
+                .unwrap_or(Verdict::Proven);
+            clauses.push(clause_report(def, ClauseKind::Requires, index, clause, verdict));
+        }
+        for ((index, clause), verdict) in def.ensures.iter().enumerate().zip(ensures) {
+            clauses.push(clause_report(def, ClauseKind::Ensures, index, clause, verdict));
+        }
+    }
+    VerificationReport { clauses }
+}
+
+fn clause_report(def: &FunctionDef, kind: ClauseKind, index: usize, clause: &Expr, verdict: Verdict) -> ClauseReport {
+    ClauseReport {
+        function: def.name.clone(),
+        kind,
+        index,
+        clause: clause.to_string(),
+        span: clause.span(),
+        verdict,
+    }
+}
+
+// Verdicts for one function's `ensures` clauses, and for the `requires`
+// clauses of everything it calls, as (callee, clause index, verdict)
+struct CheckedFunction {
+    ensures: Vec<Verdict>,
+    call_sites: Vec<(String, usize, Verdict)>,
+}
+
+fn check_function(functions: &HashMap<String, &FunctionDef>, def: &FunctionDef) -> CheckedFunction {
+    let mut translator = Translator::new(functions);
+    let mut params = Vec::new();
+    for (name, ty) in &def.params {
+        let value = translator.value_of_type(ty);
+        translator.env.insert(name.clone(), value.clone());
+        params.push((name.clone(), value));
+    }
+
+    let mut assumptions = Vec::new();
+    for clause in &def.requires {
+        let value = translator.expr(clause);
+        assumptions.push(translator.bool_of(value, clause));
+    }
+    translator.in_body = true;
+    let result = translator.expr(&def.body);
+    translator.in_body = false;
+
+    let calls = std::mem::take(&mut translator.calls);
+    let mut call_sites = Vec::new();
+    for call in calls {
+        let callee = functions[&call.callee];
+        let saved_env = std::mem::replace(
+            &mut translator.env,
+            callee.params.iter().map(|(name, _)| name.clone()).zip(call.args).collect(),
+        );
+        for (index, clause) in callee.requires.iter().enumerate() {
+            let value = translator.expr(clause);
+            let goal = translator.bool_of(value, clause);
+            let mut context = assumptions.clone();
+            context.push(call.path.clone());
+            let verdict = translator.verdict(&context, goal, &params, Some(&def.name));
+            call_sites.push((call.callee.clone(), index, verdict));
+        }
+        translator.env = saved_env;
+    }
+
+    let mut shown = params;
+    shown.push(("result".to_string(), result.clone()));
+    translator.env.insert("result".to_string(), result);
+    let mut ensures = Vec::new();
+    for clause in &def.ensures {
+        let value = translator.expr(clause);
+        let goal = translator.bool_of(value, clause);
+        ensures.push(translator.verdict(&assumptions, goal, &shown, None));
+    }
+
+    CheckedFunction { ensures, call_sites }
+}
+
+// ============================================================================
+// Linear Arithmetic
+// ============================================================================
+
+type Var = usize;
+
+// Σ coefficient·variable + constant, over the integers; no zero coefficients
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Linear {
+    terms: BTreeMap<Var, i128>,
+    constant: i128,
+}
+
+impl Linear {
+    fn constant(constant: i128) -> Self {
+        Linear { terms: BTreeMap::new(), constant }
+    }
+
+    fn var(var: Var) -> Self {
+        Linear { terms: BTreeMap::from([(var, 1)]), constant: 0 }
+    }
+
+    fn as_constant(&self) -> Option<i128> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    fn coefficient(&self, var: Var) -> i128 {
+        self.terms.get(&var).copied().unwrap_or(0)
+    }
+
+    // self + k·other, or None once a number leaves MAGNITUDE_LIMIT
+    fn add_scaled(&self, other: &Linear, k: i128) -> Option<Linear> {
+        let bounded = |n: i128| (n.abs() <= MAGNITUDE_LIMIT).then_some(n);
+        let mut terms = self.terms.clone();
+        for (&var, &coefficient) in &other.terms {
+            let sum = bounded(terms.get(&var).copied().unwrap_or(0).checked_add(coefficient.checked_mul(k)?)?)?;
+            if sum == 0 {
+                terms.remove(&var);
+            } else {
+                terms.insert(var, sum);
+            }
+        }
+        let constant = bounded(self.constant.checked_add(other.constant.checked_mul(k)?)?)?;
+        Some(Linear { terms, constant })
+    }
+
+    fn add(&self, other: &Linear) -> Option<Linear> {
+        self.add_scaled(other, 1)
+    }
+
+    fn sub(&self, other: &Linear) -> Option<Linear> {
+        self.add_scaled(other, -1)
+    }
+
+    fn scale(&self, k: i128) -> Option<Linear> {
+        Linear::constant(0).add_scaled(self, k)
+    }
+
+    fn eval(&self, ints: &HashMap<Var, i128>) -> Option<i128> {
+        self.terms.iter().try_fold(self.constant, |sum, (var, coefficient)| {
+            sum.checked_add(coefficient.checked_mul(ints.get(var).copied().unwrap_or(0))?)
+        })
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// Rounding divisions by a positive divisor
+fn floor_div(a: i128, b: i128) -> i128 {
+    a.div_euclid(b)
+}
+
+fn ceil_div(a: i128, b: i128) -> i128 {
+    -(-a).div_euclid(b)
+}
+
+// ============================================================================
+// Formulas
+// ============================================================================
+
+// Negation normal form over linear constraints and boolean variables
+#[derive(Debug, Clone, PartialEq)]
+enum Formula {
+    True,
+    False,
+    /// `linear <= 0`
+    AtMost(Linear),
+    /// Boolean variable, or its negation when the flag is false
+    Bool(Var, bool),
+    And(Vec<Formula>),
+    Or(Vec<Formula>),
+}
+
+// `linear <= 0`, divided through by the gcd of the coefficients; rounding the
+// constant up is exact because the left-hand side only takes integer values
+fn at_most(linear: Linear) -> Formula {
+    if linear.terms.is_empty() {
+        return if linear.constant <= 0 { Formula::True } else { Formula::False };
+    }
+    let divisor = linear.terms.values().fold(0, |g, &c| gcd(g, c.abs()));
+    Formula::AtMost(Linear {
+        terms: linear.terms.into_iter().map(|(var, c)| (var, c / divisor)).collect(),
+        constant: ceil_div(linear.constant, divisor),
+    })
+}
+
+fn and(parts: Vec<Formula>) -> Formula {
+    let mut flat = Vec::new();
+    for part in parts {
+        match part {
+            Formula::True => {}
+            Formula::False => return Formula::False,
+            Formula::And(inner) => flat.extend(inner),
+            other => flat.push(other),
+        }
+    }
+    match flat.len() {
+        0 => Formula::True,
+        1 => flat.pop().unwrap(),
+        _ => Formula::And(flat),
+    }
+}
+
+fn or(parts: Vec<Formula>) -> Formula {
+    let mut flat = Vec::new();
+    for part in parts {
+        match part {
+            Formula::False => {}
+            Formula::True => return Formula::True,
+            Formula::Or(inner) => flat.extend(inner),
+            other => flat.push(other),
+        }
+    }
+    match flat.len() {
+        0 => Formula::False,
+        1 => flat.pop().unwrap(),
+        _ => Formula::Or(flat),
+    }
+}
+
+fn negate(formula: Formula) -> Formula {
+    match formula {
+        Formula::True => Formula::False,
+        Formula::False => Formula::True,
+        // not (l <= 0)  <=>  l >= 1  <=>  -l + 1 <= 0
+        Formula::AtMost(linear) => at_most(Linear {
+            terms: linear.terms.into_iter().map(|(var, c)| (var, -c)).collect(),
+            constant: 1 - linear.constant,
+        }),
+        Formula::Bool(var, value) => Formula::Bool(var, !value),
+        Formula::And(parts) => or(parts.into_iter().map(negate).collect()),
+        Formula::Or(parts) => and(parts.into_iter().map(negate).collect()),
+    }
+}
+
+fn implies(premise: Formula, conclusion: Formula) -> Formula {
+    or(vec![negate(premise), conclusion])
+}
+
+fn iff(a: Formula, b: Formula) -> Formula {
+    or(vec![and(vec![a.clone(), b.clone()]), and(vec![negate(a), negate(b)])])
+}
+
+// Integer comparison `a op b`
+fn compare(op: &str, a: &Linear, b: &Linear) -> Option<Formula> {
+    let difference = a.sub(b)?;
+    let one = Linear::constant(1);
+    Some(match op {
+        "<=" => at_most(difference),
+        "<" => at_most(difference.add(&one)?),
+        ">=" => at_most(difference.scale(-1)?),
+        ">" => at_most(difference.scale(-1)?.add(&one)?),
+        "==" => and(vec![at_most(difference.clone()), at_most(difference.scale(-1)?)]),
+        "!=" => negate(compare("==", a, b)?),
+        _ => return None,
+    })
+}
+
+// Assignment found for a satisfiable formula; unmentioned variables are 0/false
+struct Model {
+    ints: HashMap<Var, i128>,
+    bools: HashMap<Var, bool>,
+}
+
+impl Formula {
+    fn vars(&self) -> BTreeSet<Var> {
+        match self {
+            Formula::True | Formula::False => BTreeSet::new(),
+            Formula::AtMost(linear) => linear.terms.keys().copied().collect(),
+            Formula::Bool(var, _) => BTreeSet::from([*var]),
+            Formula::And(parts) | Formula::Or(parts) => parts.iter().flat_map(Formula::vars).collect(),
+        }
+    }
+
+    fn holds_in(&self, model: &Model) -> bool {
+        match self {
+            Formula::True => true,
+            Formula::False => false,
+            Formula::AtMost(linear) => linear.eval(&model.ints).is_some_and(|value| value <= 0),
+            Formula::Bool(var, value) => model.bools.get(var).copied().unwrap_or(false) == *value,
+            Formula::And(parts) => parts.iter().all(|part| part.holds_in(model)),
+            Formula::Or(parts) => parts.iter().any(|part| part.holds_in(model)),
+        }
+    }
+}
+
+// ============================================================================
+// Decision Procedure
+// ============================================================================
+
+// Why a query was neither proven nor refuted
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Undecided {
+    /// Search or elimination limits were exceeded
+    Limit,
+    /// The rational relaxation is feasible but no integer point was found
+    Incomplete,
+}
+
+// Find a model of the formula, or None when it is unsatisfiable
+fn satisfiable(formula: Formula) -> Result<Option<Model>, Undecided> {
+    let mut search = Search { steps: 0 };
+    search.run(vec![formula], Vec::new(), HashMap::new(), Vec::new())
+}
+
+struct Search {
+    steps: usize,
+}
+
+impl Search {
+    // DPLL-style case splitting: conjunctions are decomposed eagerly, the
+    // constraints collected so far are checked after every split, and the
+    // remaining disjunctions are tried one alternative at a time
+    fn run(
+        &mut self,
+        mut pending: Vec<Formula>,
+        mut atoms: Vec<Linear>,
+        mut bools: HashMap<Var, bool>,
+        mut splits: Vec<Vec<Formula>>,
+    ) -> Result<Option<Model>, Undecided> {
+        self.steps += 1;
+        if self.steps > SEARCH_LIMIT {
+            return Err(Undecided::Limit);
+        }
+
+        while let Some(formula) = pending.pop() {
+            match formula {
+                Formula::True => {}
+                Formula::False => return Ok(None),
+                Formula::AtMost(linear) => atoms.push(linear),
+                Formula::Bool(var, value) => {
+                    if bools.insert(var, value).is_some_and(|old| old != value) {
+                        return Ok(None);
+                    }
+                }
+                Formula::And(parts) => pending.extend(parts),
+                Formula::Or(alternatives) => splits.push(alternatives),
+            }
+        }
+
+        atoms.sort();
+        atoms.dedup();
+        let ints = match solve(&atoms) {
+            Ok(None) => return Ok(None),
+            Ok(Some(ints)) => Ok(ints),
+            Err(Undecided::Limit) => return Err(Undecided::Limit),
+            Err(undecided) => Err(undecided),
+        };
+
+        let literal_is = |formula: &Formula, wanted: bool| match formula {
+            Formula::Bool(var, value) => bools.get(var).is_some_and(|b| (b == value) == wanted),
+            _ => false,
+        };
+        while let Some(alternatives) = splits.pop() {
+            if alternatives.iter().any(|alternative| literal_is(alternative, true)) {
+                continue;
+            }
+            let mut undecided = None;
+            for alternative in alternatives {
+                if literal_is(&alternative, false) {
+                    continue;
+                }
+                match self.run(vec![alternative], atoms.clone(), bools.clone(), splits.clone()) {
+                    Ok(Some(model)) => return Ok(Some(model)),
+                    Ok(None) => {}
+                    Err(Undecided::Limit) => return Err(Undecided::Limit),
+                    Err(other) => undecided = Some(other),
+                }
+            }
+            return undecided.map_or(Ok(None), Err);
+        }
+
+        Ok(Some(Model { ints: ints?, bools }))
+    }
+}
+
+// Integer solution of a conjunction of `linear <= 0` constraints. Equalities
+// with a unit coefficient are substituted away first, which is exact over the
+// integers; the remaining variables are eliminated one by one (Fourier-Motzkin,
+// tightening every combination to integers), then given values back to front
+// within the bounds each stage leaves. Infeasible combinations prove there is
+// no solution.
+fn solve(constraints: &[Linear]) -> Result<Option<HashMap<Var, i128>>, Undecided> {
+    let mut constraints = constraints.to_vec();
+    // (var, its value in terms of the variables left), in substitution order
+    let mut substitutions = Vec::new();
+    while let Some((var, value)) = unit_equality(&constraints) {
+        let mut next = Vec::new();
+        for constraint in &constraints {
+            let coefficient = constraint.coefficient(var);
+            if coefficient == 0 {
+                next.push(constraint.clone());
+                continue;
+            }
+            let mut rest = constraint.clone();
+            rest.terms.remove(&var);
+            match at_most(rest.add_scaled(&value, coefficient).ok_or(Undecided::Incomplete)?) {
+                Formula::True => {}
+                Formula::AtMost(linear) => next.push(linear),
+                _ => return Ok(None),
+            }
+        }
+        next.sort();
+        next.dedup();
+        constraints = next;
+        substitutions.push((var, value));
+    }
+
+    let mut ints = match eliminate_all(&constraints)? {
+        Some(ints) => ints,
+        None => return Ok(None),
+    };
+    for (var, value) in substitutions.iter().rev() {
+        ints.insert(*var, value.eval(&ints).ok_or(Undecided::Incomplete)?);
+    }
+    Ok(Some(ints))
+}
+
+// `a·var + rest = 0` with a = ±1, present as a pair of opposite constraints,
+// as var = -a·rest
+fn unit_equality(constraints: &[Linear]) -> Option<(Var, Linear)> {
+    for constraint in constraints {
+        for (&var, &coefficient) in &constraint.terms {
+            if coefficient.abs() != 1 {
+                continue;
+            }
+            let opposite = constraint.scale(-1)?;
+            if constraints.contains(&opposite) {
+                let mut rest = constraint.clone();
+                rest.terms.remove(&var);
+                return Some((var, rest.scale(-coefficient)?));
+            }
+        }
+    }
+    None
+}
+
+fn eliminate_all(constraints: &[Linear]) -> Result<Option<HashMap<Var, i128>>, Undecided> {
+    let vars: BTreeSet<Var> = constraints.iter().flat_map(|c| c.terms.keys().copied()).collect();
+    let vars: Vec<Var> = vars.into_iter().collect();
+
+    let mut stages = vec![constraints.to_vec()];
+    for &var in &vars {
+        match eliminate(stages.last().unwrap(), var)? {
+            Some(next) => stages.push(next),
+            None => return Ok(None),
+        }
+    }
+
+    let mut ints = HashMap::new();
+    for (stage, &var) in stages.iter().zip(&vars).rev() {
+        let (mut lower, mut upper): (Option<i128>, Option<i128>) = (None, None);
+        for constraint in stage {
+            let coefficient = constraint.coefficient(var);
+            if coefficient == 0 {
+                continue;
+            }
+            // coefficient·var + rest <= 0, every other variable already chosen
+            let rest = constraint.eval(&ints).ok_or(Undecided::Incomplete)?;
+            if coefficient > 0 {
+                let bound = floor_div(-rest, coefficient);
+                upper = Some(upper.map_or(bound, |u| u.min(bound)));
+            } else {
+                let bound = ceil_div(rest, -coefficient);
+                lower = Some(lower.map_or(bound, |l| l.max(bound)));
+            }
+        }
+        let value = match (lower, upper) {
+            (Some(lower), Some(upper)) if lower > upper => return Err(Undecided::Incomplete),
+            (Some(lower), _) if lower > 0 => lower,
+            (_, Some(upper)) if upper < 0 => upper,
+            _ => 0,
+        };
+        ints.insert(var, value);
+    }
+    Ok(Some(ints))
+}
+
+// Constraints without `var` implied by the given ones; None when they include
+// a contradiction
+fn eliminate(constraints: &[Linear], var: Var) -> Result<Option<Vec<Linear>>, Undecided> {
+    let mut lower = Vec::new();
+    let mut upper = Vec::new();
+    let mut rest = Vec::new();
+    for constraint in constraints {
+        match constraint.coefficient(var).signum() {
+            1 => upper.push(constraint),
+            -1 => lower.push(constraint),
+            _ => rest.push(constraint.clone()),
+        }
+    }
+
+    for low in &lower {
+        for up in &upper {
+            // b·low + a·up cancels var, with a = -coefficient in low, b = coefficient in up
+            let combined = low.scale(up.coefficient(var))
+                .and_then(|scaled| scaled.add_scaled(up, -low.coefficient(var)))
+                .ok_or(Undecided::Incomplete)?;
+            match at_most(combined) {
+                Formula::True => {}
+                Formula::AtMost(linear) => rest.push(linear),
+                _ => return Ok(None),
+            }
+        }
+        if rest.len() > CONSTRAINT_LIMIT {
+            return Err(Undecided::Limit);
+        }
+    }
+    rest.sort();
+    rest.dedup();
+    Ok(Some(rest))
+}
+
+// ============================================================================
+// Translation
+// ============================================================================
+
+// Symbolic value of an expression
+#[derive(Debug, Clone)]
+enum Value {
+    Int(Linear),
+    Bool(Formula),
+    /// Anything outside the fragment: strings, lists, records, floats, ...
+    Opaque,
+}
+
+// Call to a user function, recorded to check the callee's `requires` clauses
+struct CallSite {
+    callee: String,
+    args: Vec<Value>,
+    /// Condition under which the call is made
+    path: Formula,
+}
+
+struct Translator<'p> {
+    functions: &'p HashMap<String, &'p FunctionDef>,
+    // Number of variables created so far
+    vars: usize,
+    env: HashMap<String, Value>,
+    // Facts about fresh variables, assumed in every query
+    facts: Vec<Formula>,
+    // Conditions under which the expression being translated is evaluated
+    path: Vec<Formula>,
+    // Variables standing for something modelled only approximately, with
+    // what; models that depend on one may be spurious, so they are not
+    // reported as counterexamples
+    approximations: BTreeMap<Var, String>,
+    // Fresh variables defined by facts, with the variables those facts mention
+    definitions: Vec<(Var, BTreeSet<Var>)>,
+    calls: Vec<CallSite>,
+    // Translating a function body rather than a contract clause: calls are
+    // recorded and their callees' `ensures` assumed
+    in_body: bool,
+}
+
+impl<'p> Translator<'p> {
+    fn new(functions: &'p HashMap<String, &'p FunctionDef>) -> Self {
+        Translator {
+            functions,
+            vars: 0,
+            env: HashMap::new(),
+            facts: Vec::new(),
+            path: Vec::new(),
+            approximations: BTreeMap::new(),
+            definitions: Vec::new(),
+            calls: Vec::new(),
+            in_body: false,
+        }
+    }
+
+    fn fresh(&mut self) -> Var {
+        self.vars += 1;
+        self.vars - 1
+    }
+
+    // Fresh variable for a value the translation cannot pin down
+    fn approximate(&mut self, reason: String) -> Var {
+        let var = self.fresh();
+        self.approximations.insert(var, reason);
+        var
+    }
+
+    // Facts fixing the value of fresh variables
+    fn define(&mut self, defined: &[Var], facts: Vec<Formula>) {
+        let mentioned: BTreeSet<Var> = facts.iter().flat_map(Formula::vars).collect();
+        for &var in defined {
+            self.definitions.push((var, mentioned.clone()));
+        }
+        self.facts.extend(facts);
+    }
+
+    // Unconstrained value of the given type; Nat values are non-negative
+    fn value_of_type(&mut self, ty: &Type) -> Value {
+        match ty {
+            Type::Basic(basic) if basic.name == "Int" || basic.name == "Nat" => {
+                let var = Linear::var(self.fresh());
+                if basic.name == "Nat" {
+                    self.facts.push(at_most(var.scale(-1).unwrap()));
+                }
+                Value::Int(var)
+            }
+            Type::Basic(basic) if basic.name == "Bool" => Value::Bool(Formula::Bool(self.fresh(), true)),
+            _ => Value::Opaque,
+        }
+    }
+
+    // Integer view of a value; anything else becomes an unconstrained integer
+    fn int_of(&mut self, value: Value, source: &dyn fmt::Display) -> Linear {
+        match value {
+            Value::Int(linear) => linear,
+            _ => {
+                Linear::var(self.approximate(format!("cannot model `{}`", source)))
+            }
+        }
+    }
+
+    fn bool_of(&mut self, value: Value, source: &dyn fmt::Display) -> Formula {
+        match value {
+            Value::Bool(formula) => formula,
+            _ => {
+                Formula::Bool(self.approximate(format!("cannot model `{}`", source)), true)
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Value {
+        match expr {
+            Expr::Literal(lit) => match lit.value {
+                LiteralValue::Int(n) => Value::Int(Linear::constant(n as i128)),
+                LiteralValue::Bool(true) => Value::Bool(Formula::True),
+                LiteralValue::Bool(false) => Value::Bool(Formula::False),
+                _ => Value::Opaque,
+            },
+            Expr::Variable(var) => self.env.get(&var.name).cloned().unwrap_or(Value::Opaque),
+            Expr::BinaryOp(binop) => self.binary_op(binop, expr),
+            Expr::UnaryOp(unop) => {
+                let operand = self.expr(&unop.operand);
+                match unop.op.as_str() {
+                    "-" => {
+                        let operand = self.int_of(operand, &unop.operand);
+                        self.arithmetic(operand.scale(-1), expr)
+                    }
+                    "not" => Value::Bool(negate(self.bool_of(operand, &unop.operand))),
+                    _ => Value::Opaque,
+                }
+            }
+            Expr::If(if_expr) => {
+                let condition = self.expr(&if_expr.condition);
+                let condition = self.bool_of(condition, &if_expr.condition);
+                let then_value = self.under(condition.clone(), &if_expr.then_branch);
+                let else_value = self.under(negate(condition.clone()), &if_expr.else_branch);
+                self.join(vec![(condition.clone(), then_value), (negate(condition), else_value)], expr)
+            }
+            Expr::Let(let_expr) => {
+                let value = self.expr(&let_expr.value);
+                let shadowed = self.env.insert(let_expr.var_name.clone(), value);
+                let body = self.expr(&let_expr.body);
+                match shadowed {
+                    Some(old) => self.env.insert(let_expr.var_name.clone(), old),
+                    None => self.env.remove(&let_expr.var_name),
+                };
+                body
+            }
+            Expr::Match(match_expr) => self.match_expr(match_expr, expr),
+            Expr::Application(_) => self.application(expr),
+            Expr::Lambda(lambda) => {
+                // The body runs wherever the closure is called: no path
+                // condition applies, and its parameters are unknown
+                let saved_env = self.env.clone();
+                let saved_path = std::mem::take(&mut self.path);
+                for (name, _) in &lambda.params {
+                    self.env.insert(name.clone(), Value::Opaque);
+                }
+                self.expr(&lambda.body);
+                self.env = saved_env;
+                self.path = saved_path;
+                Value::Opaque
+            }
+            // Still walked, for the calls inside them
+            Expr::Constructor(constructor) => {
+                for arg in &constructor.args {
+                    self.expr(arg);
+                }
+                Value::Opaque
+            }
+            Expr::Tuple(tuple) => {
+                for element in &tuple.elements {
+                    self.expr(element);
+                }
+                Value::Opaque
+            }
+            Expr::Record(record) => {
+                for (_, field) in &record.fields {
+                    self.expr(field);
+                }
+                Value::Opaque
+            }
+            Expr::FieldAccess(access) => {
+                self.expr(&access.record);
+                Value::Opaque
+            }
+        }
+    }
+
+    // Translate an expression evaluated only when `condition` holds
+    fn under(&mut self, condition: Formula, expr: &Expr) -> Value {
+        self.path.push(condition);
+        let value = self.expr(expr);
+        self.path.pop();
+        value
+    }
+
+    fn arithmetic(&mut self, result: Option<Linear>, expr: &Expr) -> Value {
+        match result {
+            Some(linear) => Value::Int(linear),
+            None => Value::Int(Linear::var(self.approximate(format!("numbers too large in `{}`", expr)))),
+        }
+    }
+
+    fn binary_op(&mut self, binop: &BinaryOp, expr: &Expr) -> Value {
+        let left = self.expr(&binop.left);
+        let right = self.expr(&binop.right);
+        match binop.op.as_str() {
+            "+" | "-" | "*" | "/" | "%" => {
+                let left = self.int_of(left, &binop.left);
+                let right = self.int_of(right, &binop.right);
+                match binop.op.as_str() {
+                    "+" => self.arithmetic(left.add(&right), expr),
+                    "-" => self.arithmetic(left.sub(&right), expr),
+                    "*" => match (left.as_constant(), right.as_constant()) {
+                        (Some(k), _) => self.arithmetic(right.scale(k), expr),
+                        (_, Some(k)) => self.arithmetic(left.scale(k), expr),
+                        _ => self.product(&left, &right, expr),
+                    },
+                    op => self.division(op, &left, &right, expr),
+                }
+            }
+            "<" | "<=" | ">" | ">=" => {
+                let left = self.int_of(left, &binop.left);
+                let right = self.int_of(right, &binop.right);
+                self.comparison(&binop.op, &left, &right, expr)
+            }
+            "==" | "!=" => {
+                let equal = match (left, right) {
+                    (Value::Bool(a), Value::Bool(b)) => iff(a, b),
+                    (left @ Value::Int(_), right) | (left, right @ Value::Int(_)) => {
+                        let left = self.int_of(left, &binop.left);
+                        let right = self.int_of(right, &binop.right);
+                        match self.comparison("==", &left, &right, expr) {
+                            Value::Bool(formula) => formula,
+                            _ => unreachable!("comparisons are boolean"),
+                        }
+                    }
+                    (left, right) => {
+                        let left = self.bool_of(left, &binop.left);
+                        let right = self.bool_of(right, &binop.right);
+                        iff(left, right)
+                    }
+                };
+                Value::Bool(if binop.op == "==" { equal } else { negate(equal) })
+            }
+            "&&" | "||" | "=>" => {
+                let left = self.bool_of(left, &binop.left);
+                let right = self.bool_of(right, &binop.right);
+                Value::Bool(match binop.op.as_str() {
+                    "&&" => and(vec![left, right]),
+                    "||" => or(vec![left, right]),
+                    _ => implies(left, right),
+                })
+            }
+            // Bitwise and shift operators
+            _ => Value::Opaque,
+        }
+    }
+
+    fn comparison(&mut self, op: &str, left: &Linear, right: &Linear, expr: &Expr) -> Value {
+        match compare(op, left, right) {
+            Some(formula) => Value::Bool(formula),
+            None => Value::Bool(Formula::Bool(self.approximate(format!("numbers too large in `{}`", expr)), true)),
+        }
+    }
+
+    // `x * y` with neither side constant: a fresh variable, with the sign and
+    // monotonicity facts that hold for every product
+    fn product(&mut self, x: &Linear, y: &Linear, expr: &Expr) -> Value {
+        let p_var = self.approximate(format!("non-linear term `{}`", expr));
+        let p = Linear::var(p_var);
+        let zero = Linear::constant(0);
+        let one = Linear::constant(1);
+        let lemmas = (|| {
+            let at_least = |a: &Linear, b: &Linear| compare(">=", a, b);
+            let at_most = |a: &Linear, b: &Linear| compare("<=", a, b);
+            Some(vec![
+                implies(compare("==", x, &zero)?, compare("==", &p, &zero)?),
+                implies(compare("==", y, &zero)?, compare("==", &p, &zero)?),
+                implies(and(vec![at_least(x, &zero)?, at_least(y, &zero)?]), at_least(&p, &zero)?),
+                implies(and(vec![at_most(x, &zero)?, at_most(y, &zero)?]), at_least(&p, &zero)?),
+                implies(and(vec![at_least(x, &zero)?, at_most(y, &zero)?]), at_most(&p, &zero)?),
+                implies(and(vec![at_most(x, &zero)?, at_least(y, &zero)?]), at_most(&p, &zero)?),
+                implies(and(vec![at_least(x, &one)?, at_least(y, &zero)?]), at_least(&p, y)?),
+                implies(and(vec![at_least(y, &one)?, at_least(x, &zero)?]), at_least(&p, x)?),
+            ])
+        })();
+        self.define(&[p_var], lemmas.unwrap_or_default());
+        Value::Int(p)
+    }
+
+    // Truncating `/` and `%` by a non-zero constant k: x = k·q + r with
+    // |r| < |k| and r taking the sign of x
+    fn division(&mut self, op: &str, x: &Linear, divisor: &Linear, expr: &Expr) -> Value {
+        let k = match divisor.as_constant() {
+            Some(k) if k != 0 => k,
+            _ => {
+                let var = self.approximate(format!("division by a non-constant in `{}`", expr));
+                return Value::Int(Linear::var(var));
+            }
+        };
+        let (q_var, r_var) = (self.fresh(), self.fresh());
+        let (q, r) = (Linear::var(q_var), Linear::var(r_var));
+        let zero = Linear::constant(0);
+        let facts = (|| {
+            let bound = Linear::constant(k.abs() - 1);
+            Some(vec![
+                compare("==", x, &q.scale(k)?.add(&r)?)?,
+                compare("<=", &r, &bound)?,
+                compare(">=", &r, &bound.scale(-1)?)?,
+                implies(compare(">=", x, &zero)?, compare(">=", &r, &zero)?),
+                implies(compare("<", x, &zero)?, compare("<=", &r, &zero)?),
+            ])
+        })();
+        match facts {
+            Some(facts) => {
+                self.define(&[q_var, r_var], facts);
+                Value::Int(if op == "/" { q } else { r })
+            }
+            None => self.arithmetic(None, expr),
+        }
+    }
+
+    // Value that is `value_i` when `condition_i` holds; the conditions are
+    // exclusive and one of them always holds
+    fn join(&mut self, branches: Vec<(Formula, Value)>, expr: &Expr) -> Value {
+        let has_int = branches.iter().any(|(_, value)| matches!(value, Value::Int(_)));
+        let has_bool = branches.iter().any(|(_, value)| matches!(value, Value::Bool(_)));
+        if has_int && !has_bool {
+            let var = self.fresh();
+            let joined = Linear::var(var);
+            let mut facts = Vec::new();
+            for (condition, value) in branches {
+                let value = self.int_of(value, expr);
+                match compare("==", &joined, &value) {
+                    Some(equal) => facts.push(implies(condition, equal)),
+                    None => {
+                        self.approximations.insert(var, format!("numbers too large in `{}`", expr));
+                    }
+                }
+            }
+            self.define(&[var], facts);
+            Value::Int(joined)
+        } else if has_bool && !has_int {
+            let mut cases = Vec::new();
+            for (condition, value) in branches {
+                let value = self.bool_of(value, expr);
+                cases.push(and(vec![condition, value]));
+            }
+            Value::Bool(or(cases))
+        } else {
+            Value::Opaque
+        }
+    }
+
+    fn match_expr(&mut self, match_expr: &MatchExpr, expr: &Expr) -> Value {
+        let scrutinee = self.expr(&match_expr.scrutinee);
+        // No earlier arm was taken
+        let mut unmatched = Formula::True;
+        let mut branches = Vec::new();
+        for arm in &match_expr.arms {
+            let saved_env = self.env.clone();
+            let mut matches = self.pattern(&arm.pattern, &scrutinee);
+            if let Some(guard) = &arm.guard {
+                let guard_value = self.under(and(vec![unmatched.clone(), matches.clone()]), guard);
+                let guard_holds = self.bool_of(guard_value, guard);
+                matches = and(vec![matches, guard_holds]);
+            }
+            let taken = and(vec![unmatched.clone(), matches.clone()]);
+            let value = self.under(taken.clone(), &arm.body);
+            self.env = saved_env;
+            unmatched = and(vec![unmatched, negate(matches)]);
+            branches.push((taken, value));
+        }
+        // The type checker only accepts exhaustive matches
+        self.facts.push(negate(unmatched));
+        self.join(branches, expr)
+    }
+
+    // Condition for the pattern to match, binding its variables
+    fn pattern(&mut self, pattern: &Pattern, scrutinee: &Value) -> Formula {
+        match pattern {
+            Pattern::Wildcard(_) => Formula::True,
+            Pattern::Variable(var) => {
+                self.env.insert(var.name.clone(), scrutinee.clone());
+                Formula::True
+            }
+            Pattern::Literal(lit) => match &lit.value {
+                LiteralValue::Int(n) => {
+                    let value = self.int_of(scrutinee.clone(), pattern);
+                    match compare("==", &value, &Linear::constant(*n as i128)) {
+                        Some(equal) => equal,
+                        None => self.unknown_pattern(pattern),
+                    }
+                }
+                LiteralValue::Bool(b) => {
+                    let value = self.bool_of(scrutinee.clone(), pattern);
+                    if *b { value } else { negate(value) }
+                }
+                _ => self.unknown_pattern(pattern),
+            },
+            Pattern::Or(or_pattern) => {
+                let mut names = Vec::new();
+                pattern.collect_bindings(&mut names);
+                if !names.is_empty() {
+                    return self.unknown_pattern(pattern);
+                }
+                let alternatives = or_pattern.alternatives.iter()
+                    .map(|alternative| self.pattern(alternative, scrutinee))
+                    .collect();
+                or(alternatives)
+            }
+            Pattern::Constructor(_) | Pattern::Tuple(_) | Pattern::Record(_) => self.unknown_pattern(pattern),
+        }
+    }
+
+    fn unknown_pattern(&mut self, pattern: &Pattern) -> Formula {
+        let mut names = Vec::new();
+        pattern.collect_bindings(&mut names);
+        for name in names {
+            self.env.insert(name, Value::Opaque);
+        }
+        Formula::Bool(self.approximate(format!("cannot model pattern `{}`", pattern)), true)
+    }
+
+    // Call to a user function: its result is a fresh value constrained by
+    // the callee's `ensures`, assumed whenever the call is made
+    fn application(&mut self, expr: &Expr) -> Value {
+        let mut func = expr;
+        let mut arg_exprs = Vec::new();
+        while let Expr::Application(app) = func {
+            arg_exprs.push(&*app.arg);
+            func = &app.func;
+        }
+        arg_exprs.reverse();
+        let args: Vec<Value> = arg_exprs.iter().map(|arg| self.expr(arg)).collect();
+
+        let callee = match func {
+            Expr::Variable(var) => self.functions.get(&var.name).copied(),
+            _ => None,
+        };
+        let callee = match callee {
+            Some(callee) if callee.params.len() == args.len() => callee,
+            _ => {
+                self.expr(func);
+                return Value::Opaque;
+            }
+        };
+
+        let result = self.value_of_type(&callee.return_type);
+        let result_vars: Vec<Var> = match &result {
+            Value::Int(linear) => linear.terms.keys().copied().collect(),
+            Value::Bool(formula) => formula.vars().into_iter().collect(),
+            Value::Opaque => Vec::new(),
+        };
+        for &var in &result_vars {
+            self.approximations.insert(var, format!("call to '{}'", callee.name));
+        }
+        if !self.in_body {
+            // Inside a contract clause the callee's own clauses are not
+            // unfolded, which would not terminate for self-reference
+            return result;
+        }
+
+        let path = and(self.path.clone());
+        self.calls.push(CallSite {
+            callee: callee.name.clone(),
+            args: args.clone(),
+            path: path.clone(),
+        });
+
+        let mut env: HashMap<String, Value> = callee.params.iter()
+            .map(|(name, _)| name.clone())
+            .zip(args)
+            .collect();
+        env.insert("result".to_string(), result.clone());
+        let saved_env = std::mem::replace(&mut self.env, env);
+        let saved_path = std::mem::take(&mut self.path);
+        self.in_body = false;
+        let mut ensures = Vec::new();
+        for clause in &callee.ensures {
+            let value = self.expr(clause);
+            ensures.push(self.bool_of(value, clause));
+        }
+        self.in_body = true;
+        self.env = saved_env;
+        self.path = saved_path;
+
+        self.define(&result_vars, vec![implies(path, and(ensures))]);
+        result
+    }
+
+    // Prove `assumptions => goal` by refuting its negation together with the facts
+    fn verdict(
+        &self,
+        assumptions: &[Formula],
+        goal: Formula,
+        shown: &[(String, Value)],
+        caller: Option<&str>,
+    ) -> Verdict {
+        let mut parts = self.facts.clone();
+        parts.extend(assumptions.iter().cloned());
+        let goal = negate(goal);
+
+        // A model is a real counterexample when the goal and assumptions only
+        // mention variables the parameters determine exactly: approximations
+        // taint every variable defined in terms of them
+        let mut tainted = self.approximations.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (defined, mentioned) in &self.definitions {
+                if tainted.contains_key(defined) {
+                    continue;
+                }
+                if let Some(reason) = mentioned.iter().find_map(|var| tainted.get(var)) {
+                    tainted.insert(*defined, reason.clone());
+                    changed = true;
+                }
+            }
+        }
+        let mut mentioned = goal.vars();
+        for assumption in assumptions {
+            mentioned.extend(assumption.vars());
+        }
+        let approximation = mentioned.iter().find_map(|var| tainted.get(var));
+
+        parts.push(goal);
+        match satisfiable(and(parts)) {
+            Ok(None) => Verdict::Proven,
+            Ok(Some(model)) => match approximation {
+                Some(reason) => Verdict::Unknown(reason.clone()),
+                None => Verdict::Refuted(Counterexample {
+                    caller: caller.map(str::to_string),
+                    values: shown.iter()
+                        .filter_map(|(name, value)| {
+                            let value = match value {
+                                Value::Int(linear) => linear.eval(&model.ints)?.to_string(),
+                                Value::Bool(formula) => formula.holds_in(&model).to_string(),
+                                Value::Opaque => return None,
+                            };
+                            Some((name.clone(), value))
+                        })
+                        .collect(),
+                }),
+            },
+            Err(Undecided::Limit) => Verdict::Unknown("search limit reached".to_string()),
+            Err(Undecided::Incomplete) => Verdict::Unknown("no integer solution found for the constraints".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir_parser::parse_ir;
+
+    fn verdicts(ir: &str) -> Vec<String> {
+        let program = parse_ir(ir).unwrap();
+        verify_contracts(&program).clauses.iter().map(ClauseReport::to_string).collect()
+    }
+
+    #[test]
+    fn test_recursive_contracts_are_proven() {
+        let ir = r#"
+func factorial (n: Nat) -> Nat
+  requires n >= 0
+  ensures result >= 1
+  ensures (n == 0) => (result == 1)
+:
+  match n with
+  | 0 -> 1
+  | n -> n * factorial (n - 1)
+"#;
+        assert_eq!(verdicts(ir), vec![
+            "factorial: requires n >= 0: proven",
+            "factorial: ensures result >= 1: proven",
+            "factorial: ensures n == 0 => result == 1: proven",
+        ]);
+    }
+
+    #[test]
+    fn test_refuted_ensures_has_counterexample() {
+        let ir = r#"
+func abs (n: Int) -> Int
+  ensures result > 0
+:
+  if n < 0 then -n else n
+"#;
+        let program = parse_ir(ir).unwrap();
+        let report = verify_contracts(&program);
+        assert_eq!(report.clauses[0].verdict, Verdict::Refuted(Counterexample {
+            caller: None,
+            values: vec![("n".to_string(), "0".to_string()), ("result".to_string(), "0".to_string())],
+        }));
+        assert!(!report.all_proven());
+    }
+
+    #[test]
+    fn test_requires_checked_at_call_sites() {
+        let ir = r#"
+func safe_div (a: Int, b: Int) -> Int
+  requires b != 0
+:
+  a / b
+
+func halve (x: Int) -> Int :
+  safe_div(x, 2)
+
+func ratio (x: Int, y: Int) -> Int :
+  if y > 0 then safe_div(x, y) else safe_div(y, x)
+"#;
+        assert_eq!(verdicts(ir), vec![
+            "safe_div: requires b != 0: refuted (in call from 'ratio' with x = 0, y = 0)",
+        ]);
+    }
+
+    #[test]
+    fn test_division_by_constant_is_exact() {
+        let ir = r#"
+func half (n: Nat) -> Nat
+  ensures result * 2 <= n
+  ensures n - result * 2 <= 1
+  ensures n % 2 == 1 => result * 2 + 1 == n
+:
+  n / 2
+"#;
+        assert_eq!(verdicts(ir), vec![
+            "half: ensures result * 2 <= n: proven",
+            "half: ensures n - result * 2 <= 1: proven",
+            "half: ensures n % 2 == 1 => result * 2 + 1 == n: proven",
+        ]);
+    }
+
+    #[test]
+    fn test_non_linear_clause_is_unknown() {
+        let ir = r#"
+func square (x: Int) -> Int
+  ensures result >= 0
+  ensures result != 2
+:
+  x * x
+"#;
+        assert_eq!(verdicts(ir), vec![
+            "square: ensures result >= 0: proven",
+            "square: ensures result != 2: unknown (non-linear term `x * x`)",
+        ]);
+    }
+}
@@ -1,9 +1,21 @@
 import sys
 from pathlib import Path
-from typing import Any
+from typing import Any, Optional
 
 sys.path.insert(0, str(Path(__file__).parent.parent.parent))
 
+try:
+    rust_lib_path = str(Path(__file__).parent.parent.parent.parent / "compiler" / "target" / "release")
+    if rust_lib_path not in sys.path:
+        sys.path.insert(0, rust_lib_path)
+
+    import pole_compiler
+
+    RUST_AVAILABLE = True
+except ImportError:
+    RUST_AVAILABLE = False
+    pole_compiler = None
+
 from pole.common.errors import PoleError, RuntimeError as PoleRuntimeError
 from pole.runtime.interpreter import Interpreter
 from pole.runtime.ir_ast import FunctionDef, Program
@@ -143,3 +155,20 @@ def verify_contracts(program: Program, func_name: str, *args: Any) -> tuple[bool
         raise ContractViolation(f"Contract violation in function '{func_name}':\n{violation_msg}")
 
     return (success, result)
+
+
+def verify_contracts_statically(ir_source: str) -> Optional[list[dict[str, Any]]]:
+    """
+    Prove requires/ensures clauses without running the program, using the
+    native verifier in the Rust compiler.
+
+    Returns:
+        One dict per clause with "function", "kind", "clause" and "status"
+        ("proven", "refuted" with a "counterexample", or "unknown" with a
+        "reason"), or None when the Rust extension is not available
+    """
+    if not RUST_AVAILABLE:
+        return None
+
+    report = pole_compiler.verify_contracts_py(ir_source)
+    return report["clauses"]